@@ -0,0 +1,130 @@
+/* Benchmarks for the sample processing that runs in the output callback.
+ *
+ * alplay is a binary, so the modules under test are pulled in by path. They
+ * only depend on the sample byte order from the argument module, which gets
+ * stood in for here. Their unit tests don't get built without the test
+ * harness, which leaves the imports of the test modules unused. */
+#![allow(
+	dead_code,
+	unused_imports,
+	clippy::tabs_in_doc_comments,
+	clippy::redundant_static_lifetimes,
+	clippy::from_str_radix_10)]
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use cpal::SampleFormat;
+
+mod arg {
+	#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+	pub enum Endianness {
+		Little,
+		Big,
+		Native
+	}
+}
+
+#[path = "../src/convert.rs"]
+mod convert;
+
+#[path = "../src/mix.rs"]
+mod mix;
+
+use arg::Endianness;
+use convert::{Dither, Quantizer};
+
+/** Number of frames in every buffer, about as many as a second of audio. */
+const FRAMES: usize = 48000;
+/** Number of channels in every buffer. */
+const CHANNELS: usize = 2;
+
+/** A buffer of samples in the given format, holding something other than
+ * silence. */
+fn buffer(format: SampleFormat) -> Vec<u8> {
+	let mut quantizer = Quantizer::new(Dither::None);
+	let mut bytes = vec![0; FRAMES * CHANNELS * format.sample_size()];
+	for (i, sample) in bytes.chunks_exact_mut(format.sample_size()).enumerate() {
+		let value = (i as f32 * 0.01).sin() * 0.5;
+		convert::encode(&mut quantizer, format, Endianness::Native, value, sample);
+	}
+
+	bytes
+}
+
+/** Byte order of the samples that have to be swapped on this machine. */
+fn foreign() -> Endianness {
+	if cfg!(target_endian = "little") { Endianness::Big } else { Endianness::Little }
+}
+
+fn bench_decode_encode(c: &mut Criterion) {
+	let mut group = c.benchmark_group("decode_encode");
+	for &format in &[SampleFormat::I16, SampleFormat::U16, SampleFormat::F32] {
+		let mut bytes = buffer(format);
+		let mut quantizer = Quantizer::new(Dither::None);
+		group.throughput(Throughput::Bytes(bytes.len() as u64));
+		group.bench_function(format!("{:?}", format), |b| b.iter(|| {
+			for sample in bytes.chunks_exact_mut(format.sample_size()) {
+				let value = convert::decode(format, Endianness::Native, sample);
+				convert::encode(&mut quantizer, format, Endianness::Native, black_box(value), sample);
+			}
+		}));
+	}
+	group.finish();
+}
+
+fn bench_to_native(c: &mut Criterion) {
+	let mut group = c.benchmark_group("to_native");
+	for &format in &[SampleFormat::I16, SampleFormat::F32] {
+		let mut bytes = buffer(format);
+		group.throughput(Throughput::Bytes(bytes.len() as u64));
+		group.bench_function(format!("{:?}", format), |b| b.iter(|| {
+			convert::to_native(format, foreign(), black_box(&mut bytes));
+		}));
+	}
+	group.finish();
+}
+
+fn bench_scale_f32(c: &mut Criterion) {
+	let mut bytes = buffer(SampleFormat::F32);
+	let mut group = c.benchmark_group("scale_f32");
+	group.throughput(Throughput::Bytes(bytes.len() as u64));
+	group.bench_function("F32", |b| b.iter(|| {
+		convert::scale_f32(black_box(&mut bytes), black_box(0.5));
+	}));
+	group.finish();
+}
+
+fn bench_fade_out(c: &mut Criterion) {
+	let mut group = c.benchmark_group("fade_out");
+	for &format in &[SampleFormat::I16, SampleFormat::F32] {
+		let mut bytes = buffer(format);
+		let mut quantizer = Quantizer::new(Dither::None);
+		let frame = CHANNELS * format.sample_size();
+		group.throughput(Throughput::Bytes(bytes.len() as u64));
+		group.bench_function(format!("{:?}", format), |b| b.iter(|| {
+			let mut remaining = FRAMES;
+			convert::fade_out(&mut quantizer, format, frame, &mut remaining, FRAMES, black_box(&mut bytes));
+		}));
+	}
+	group.finish();
+}
+
+fn bench_matrix(c: &mut Criterion) {
+	let mut group = c.benchmark_group("matrix");
+	let matrix = mix::Matrix::parse("0.5*0+0.5*1;0.5*0+0.5*1").unwrap();
+	for &format in &[SampleFormat::I16, SampleFormat::F32] {
+		let bytes = buffer(format);
+		let mut quantizer = Quantizer::new(Dither::None);
+		let mut output = Vec::with_capacity(bytes.len());
+		group.throughput(Throughput::Bytes(bytes.len() as u64));
+		group.bench_function(format!("{:?}", format), |b| b.iter(|| {
+			output.clear();
+			for frame in bytes.chunks_exact(CHANNELS * format.sample_size()) {
+				matrix.mix(&mut quantizer, format, Endianness::Native, black_box(frame), &mut output);
+			}
+		}));
+	}
+	group.finish();
+}
+
+criterion_group!(benches, bench_decode_encode, bench_to_native, bench_scale_f32, bench_fade_out, bench_matrix);
+criterion_main!(benches);
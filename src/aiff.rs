@@ -0,0 +1,349 @@
+use crate::arg::{Endianness, InputFormat};
+use crate::detect::{Seeker, Skip, Span};
+use crate::error::Error;
+use crate::src::Source;
+use std::io::{ErrorKind, Read};
+
+/** Maximum number of bytes we're willing to go through looking for the `SSND`
+ * chunk before giving up on the file. */
+const MAX_HEADER: u64 = 16 * 1024 * 1024;
+/** Maximum length of a `COMM` chunk we're willing to read. */
+const MAX_COMM: u64 = 1024;
+
+/** Format of the data in an AIFF file, as described by its `COMM` chunk. */
+#[derive(Debug, Clone, PartialEq)]
+pub struct AiffFormat {
+	/** Number of interleaved channels. */
+	pub channels: u16,
+	/** Number of sample frames in the file. */
+	pub frames: u32,
+	/** Number of bits in every sample. */
+	pub bits: u16,
+	/** Number of frames per second. */
+	pub sample_rate: f64,
+	/** Compression type of AIFF-C files. Plain AIFF files are always `NONE`. */
+	pub compression: [u8; 4],
+}
+impl AiffFormat {
+	/** Description of this format for the purposes of playback, if it's one we
+	 * know how to play. */
+	pub fn input_format(&self) -> Result<InputFormat, Error> {
+		let endian = match &self.compression {
+			b"NONE" => Endianness::Big,
+			/* Byte swapped data, as produced by some tools on x86 machines. */
+			b"sowt" => Endianness::Little,
+			_ => unreachable!()
+		};
+
+		let sample_format = match self.bits {
+			16 => cpal::SampleFormat::I16,
+			bits =>
+				return Err(Error::UnsupportedAiff(
+					format!("{} bit samples are not supported", bits)))
+		};
+
+		if !(self.sample_rate >= 1.0 && self.sample_rate <= f64::from(u32::MAX)) {
+			return Err(Error::MalformedAiff(
+				format!("the sample rate of {}Hz is not valid", self.sample_rate)))
+		}
+
+		Ok(InputFormat {
+			channels: self.channels,
+			sample_rate: self.sample_rate.round() as u32,
+			sample_format,
+			endian
+		})
+	}
+}
+
+/** Decodes an 80-bit IEEE 754 extended precision number, as used by AIFF for
+ * its sample rates. */
+pub fn extended(bytes: [u8; 10]) -> f64 {
+	let negative = bytes[0] & 0x80 != 0;
+	let exponent = i32::from(u16::from_be_bytes([bytes[0] & 0x7f, bytes[1]]));
+
+	let mut mantissa = [0; 8];
+	mantissa.copy_from_slice(&bytes[2..10]);
+	let mantissa = u64::from_be_bytes(mantissa);
+
+	let value = if exponent == 0 && mantissa == 0 {
+		0.0
+	} else if exponent == 0x7fff {
+		if mantissa << 1 == 0 { f64::INFINITY } else { f64::NAN }
+	} else {
+		/* The mantissa has an explicit integer bit, so it's to be read as a
+		 * fixed point number with 63 fractional bits. */
+		mantissa as f64 * 2f64.powi(exponent - 16383 - 63)
+	};
+
+	if negative { -value } else { value }
+}
+
+/** Opens the AIFF or AIFF-C file in the given source, whose magic number has
+ * already been read, skipping over chunks with the given function. Returns a
+ * reader over the samples in the file, along with their format. */
+pub fn open<R>(mut source: R, skip: Skip<R>, seek: Option<Seeker<R>>, compressed: bool)
+	-> Result<(Box<dyn Source>, InputFormat), Error>
+	where R: Read + Send + 'static {
+
+	let mut format = None;
+	let mut scanned = 12u64;
+	loop {
+		if scanned > MAX_HEADER {
+			return Err(Error::MalformedAiff(
+				format!("no SSND chunk was found within the first {} bytes", MAX_HEADER)))
+		}
+
+		let mut header = [0; 8];
+		read_header(&mut source, &mut header, "before the SSND chunk")?;
+
+		let id = [header[0], header[1], header[2], header[3]];
+		let size = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+
+		/* Chunks are always aligned to two bytes, so the ones with an odd
+		 * length are followed by a padding byte. */
+		let padded = u64::from(size) + u64::from(size & 1);
+		scanned += 8 + padded;
+
+		match &id {
+			b"COMM" => {
+				let minimum = if compressed { 22 } else { 18 };
+				if size < minimum {
+					return Err(Error::MalformedAiff(
+						format!("the COMM chunk is too short ({} bytes)", size)))
+				}
+				if u64::from(size) > MAX_COMM {
+					return Err(Error::MalformedAiff(
+						format!("the COMM chunk is implausibly long ({} bytes)", size)))
+				}
+
+				let mut chunk = vec![0; padded as usize];
+				read_header(&mut source, &mut chunk, "within the COMM chunk")?;
+
+				let mut rate = [0; 10];
+				rate.copy_from_slice(&chunk[8..18]);
+
+				let mut compression = *b"NONE";
+				if compressed {
+					compression.copy_from_slice(&chunk[18..22]);
+					if &compression != b"NONE" && &compression != b"sowt" {
+						/* The type is followed by its human readable name. */
+						let name = chunk.get(22)
+							.map(|len| {
+								let end = usize::min(23 + usize::from(*len), chunk.len());
+								String::from_utf8_lossy(&chunk[23..end]).into_owned()
+							})
+							.unwrap_or_default();
+
+						return Err(Error::UnsupportedAiff(
+							format!("the \"{}\" ({}) compression type is not supported",
+								String::from_utf8_lossy(&compression),
+								name)))
+					}
+				}
+
+				format = Some(AiffFormat {
+					channels: u16::from_be_bytes([chunk[0], chunk[1]]),
+					frames: u32::from_be_bytes([chunk[2], chunk[3], chunk[4], chunk[5]]),
+					bits: u16::from_be_bytes([chunk[6], chunk[7]]),
+					sample_rate: extended(rate),
+					compression
+				});
+			},
+			b"SSND" => {
+				let format = format.ok_or(Error::MalformedAiff(
+					"the SSND chunk comes before the COMM chunk".to_owned()))?;
+				let input = format.input_format()?;
+
+				if size < 8 {
+					return Err(Error::MalformedAiff(
+						format!("the SSND chunk is too short ({} bytes)", size)))
+				}
+
+				let mut fields = [0; 8];
+				read_header(&mut source, &mut fields, "within the SSND chunk")?;
+				let offset = u32::from_be_bytes([fields[0], fields[1], fields[2], fields[3]]);
+
+				/* The samples start `offset` bytes into the chunk data. */
+				if offset > size - 8 {
+					return Err(Error::MalformedAiff(
+						format!("the SSND data offset ({}) is past the end of the chunk", offset)))
+				}
+				skip(&mut source, u64::from(offset))
+					.map_err(|what| if what.kind() == ErrorKind::UnexpectedEof {
+						Error::MalformedAiff("the file ends within the SSND chunk".to_owned())
+					} else {
+						Error::InputError(what)
+					})?;
+
+				let length = u64::from(size - 8 - offset);
+				let data = Span::new(source, length, seek).map_err(Error::InputError)?;
+				return Ok((Box::new(data), input))
+			},
+			_ => {
+				/* We don't care about any other chunks. */
+				skip(&mut source, padded)
+					.map_err(|what| if what.kind() == ErrorKind::UnexpectedEof {
+						Error::MalformedAiff(format!(
+							"the file ends within the \"{}\" chunk",
+							String::from_utf8_lossy(&id)))
+					} else {
+						Error::InputError(what)
+					})?;
+			}
+		}
+	}
+}
+
+/** Fills the given buffer with header data, with the given description of
+ * where in the header we are in case the file ends early. */
+fn read_header<R>(source: &mut R, buf: &mut [u8], place: &str) -> Result<(), Error>
+	where R: Read {
+
+	source.read_exact(buf)
+		.map_err(|what| if what.kind() == ErrorKind::UnexpectedEof {
+			Error::MalformedAiff(format!("the file ends {}", place))
+		} else {
+			Error::InputError(what)
+		})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::{Cursor, Seek, SeekFrom};
+
+	/** A chunk with the given ID and contents, padded out to an even length. */
+	fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+		let mut chunk = id.to_vec();
+		chunk.extend(&(data.len() as u32).to_be_bytes());
+		chunk.extend(data);
+		if data.len() & 1 != 0 { chunk.push(0) }
+		chunk
+	}
+
+	/** Sample rate of 44100Hz, as an extended precision number. */
+	const RATE_44100: [u8; 10] = [0x40, 0x0e, 0xac, 0x44, 0, 0, 0, 0, 0, 0];
+
+	/** Contents of a `COMM` chunk, with the given compression type for AIFF-C
+	 * files. */
+	fn comm(channels: u16, bits: u16, compression: Option<&[u8; 4]>) -> Vec<u8> {
+		let mut comm = channels.to_be_bytes().to_vec();
+		comm.extend(&0u32.to_be_bytes());
+		comm.extend(&bits.to_be_bytes());
+		comm.extend(&RATE_44100);
+		if let Some(compression) = compression {
+			comm.extend(compression);
+			comm.extend(b"\x04name");
+		}
+		comm
+	}
+
+	/** Contents of an `SSND` chunk, with the samples the given number of bytes
+	 * into it. */
+	fn ssnd(offset: u32, data: &[u8]) -> Vec<u8> {
+		let mut ssnd = offset.to_be_bytes().to_vec();
+		ssnd.extend(&0u32.to_be_bytes());
+		ssnd.extend(vec![0xee; offset as usize]);
+		ssnd.extend(data);
+		ssnd
+	}
+
+	fn skip(source: &mut Cursor<Vec<u8>>, count: u64) -> std::io::Result<()> {
+		source.seek(SeekFrom::Current(count as i64)).map(|_| ())
+	}
+
+	/** Opens a file made up of the given chunks, past where its magic number
+	 * would be. */
+	fn open_chunks(chunks: &[Vec<u8>], compressed: bool) -> Result<(Box<dyn Source>, InputFormat), Error> {
+		let mut file = vec![0; 12];
+		file.extend(chunks.concat());
+		let mut source = Cursor::new(file);
+		source.set_position(12);
+		open(source, skip, Some(<Cursor<Vec<u8>> as Seek>::seek), compressed)
+	}
+
+	#[test]
+	fn extended_precision_numbers_are_decoded() {
+		assert_eq!(extended(RATE_44100), 44100.0);
+		assert_eq!(extended([0x40, 0x0b, 0xfa, 0, 0, 0, 0, 0, 0, 0]), 8000.0);
+		assert_eq!(extended([0x3f, 0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0]), 0.5);
+		assert_eq!(extended([0xc0, 0x0e, 0xac, 0x44, 0, 0, 0, 0, 0, 0]), -44100.0);
+		assert_eq!(extended([0; 10]), 0.0);
+		assert_eq!(extended([0x7f, 0xff, 0x80, 0, 0, 0, 0, 0, 0, 0]), f64::INFINITY);
+		assert!(extended([0x7f, 0xff, 0xc0, 0, 0, 0, 0, 0, 0, 0]).is_nan());
+	}
+
+	#[test]
+	fn aiff_samples_are_big_endian() {
+		let (mut reader, format) = open_chunks(&[
+			chunk(b"COMM", &comm(2, 16, None)),
+			chunk(b"SSND", &ssnd(0, b"abcd"))
+		], false).unwrap();
+		assert_eq!(format, InputFormat {
+			channels: 2,
+			sample_rate: 44100,
+			sample_format: cpal::SampleFormat::I16,
+			endian: Endianness::Big
+		});
+
+		let mut data = Vec::new();
+		reader.read_to_end(&mut data).unwrap();
+		assert_eq!(data, b"abcd");
+	}
+
+	#[test]
+	fn byte_swapped_aifc_is_little_endian() {
+		let (_, format) = open_chunks(&[
+			chunk(b"FVER", &[0; 4]),
+			chunk(b"COMM", &comm(1, 16, Some(b"sowt"))),
+			chunk(b"SSND", &ssnd(0, b"ab"))
+		], true).unwrap();
+		assert_eq!(format.endian, Endianness::Little);
+	}
+
+	#[test]
+	fn samples_start_past_the_data_offset() {
+		let (mut reader, _) = open_chunks(&[
+			chunk(b"COMM", &comm(1, 16, None)),
+			chunk(b"SSND", &ssnd(6, b"abcd"))
+		], false).unwrap();
+
+		let mut data = Vec::new();
+		reader.read_to_end(&mut data).unwrap();
+		assert_eq!(data, b"abcd");
+	}
+
+	#[test]
+	fn compressed_aifc_is_rejected() {
+		let error = open_chunks(&[
+			chunk(b"COMM", &comm(1, 16, Some(b"ima4"))),
+			chunk(b"SSND", &ssnd(0, b"ab"))
+		], true).err().unwrap();
+		assert!(matches!(error, Error::UnsupportedAiff(_)));
+	}
+
+	#[test]
+	fn malformed_files_are_rejected() {
+		let before = open_chunks(&[
+			chunk(b"SSND", &ssnd(0, b"ab")),
+			chunk(b"COMM", &comm(1, 16, None))
+		], false).err().unwrap();
+		assert!(matches!(before, Error::MalformedAiff(_)));
+
+		let mut offset = ssnd(0, b"ab");
+		offset[..4].copy_from_slice(&8u32.to_be_bytes());
+		let offset = open_chunks(&[chunk(b"COMM", &comm(1, 16, None)), chunk(b"SSND", &offset)], false)
+			.err().unwrap();
+		assert!(matches!(offset, Error::MalformedAiff(_)));
+
+		let short = open_chunks(&[chunk(b"COMM", &comm(1, 16, None)[..16])], false).err().unwrap();
+		assert!(matches!(short, Error::MalformedAiff(_)));
+
+		let bits = open_chunks(&[
+			chunk(b"COMM", &comm(1, 24, None)),
+			chunk(b"SSND", &ssnd(0, b"abc"))
+		], false).err().unwrap();
+		assert!(matches!(bits, Error::UnsupportedAiff(_)));
+	}
+}
@@ -0,0 +1,276 @@
+use crate::arg::InputFormat;
+use crate::detect::Detected;
+use std::io::{ErrorKind, Read};
+
+/** Size of the chunks the input gets read in. */
+const CHUNK: usize = 64 * 1024;
+
+/** Statistics gathered over the samples of a single channel. */
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct ChannelStats {
+	/** Largest magnitude of any sample, relative to full scale. */
+	pub peak: f64,
+	/** Sum of every sample, from which the DC offset comes. */
+	pub sum: f64,
+	/** Sum of the squares of every sample, from which the RMS level comes. */
+	pub squares: f64,
+	/** Number of samples at or past full scale. */
+	pub clipped: u64,
+}
+
+/** Everything measured over an input. */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+	/** Format the samples were read as. */
+	pub format: InputFormat,
+	/** Number of complete frames in the input. */
+	pub frames: u64,
+	/** Statistics of every channel, in order. */
+	pub channels: Vec<ChannelStats>,
+	/** Number of bytes at the end of the input that didn't make up a whole
+	 * frame, and were left out. */
+	pub trailing: usize,
+}
+impl Analysis {
+	/** Length of the input, in seconds. */
+	pub fn seconds(&self) -> f64 {
+		self.frames as f64 / f64::from(self.format.sample_rate)
+	}
+
+	/** RMS level of the given channel, relative to full scale. */
+	pub fn rms(&self, channel: usize) -> f64 {
+		if self.frames == 0 { return 0.0 }
+		(self.channels[channel].squares / self.frames as f64).sqrt()
+	}
+
+	/** DC offset of the given channel, relative to full scale. */
+	pub fn dc_offset(&self, channel: usize) -> f64 {
+		if self.frames == 0 { return 0.0 }
+		self.channels[channel].sum / self.frames as f64
+	}
+}
+
+/** Reads the whole of the given source, as samples in the given format, and
+ * measures them. Samples get decoded and checked for clipping the same way
+ * they are on their way to the device. */
+pub fn measure<R>(mut source: R, format: InputFormat) -> std::io::Result<Analysis>
+	where R: Read {
+
+	let size = format.sample_format.sample_size();
+	let channels = usize::from(format.channels);
+	let frame = size * channels;
+
+	let mut stats = vec![ChannelStats::default(); channels];
+	let mut frames = 0u64;
+	let mut buffer = vec![0; (CHUNK - CHUNK % frame).max(frame)];
+	let mut filled = 0;
+	loop {
+		let read = match source.read(&mut buffer[filled..]) {
+			Ok(0) => break,
+			Ok(read) => read,
+			Err(what) if what.kind() == ErrorKind::Interrupted => continue,
+			Err(what) => return Err(what)
+		};
+		filled += read;
+
+		/* Whatever's left of a frame that got cut short waits for the rest of
+		 * it at the start of the buffer. */
+		let whole = filled - filled % frame;
+		for samples in buffer[..whole].chunks_exact(frame) {
+			for (stats, sample) in stats.iter_mut().zip(samples.chunks_exact(size)) {
+				let value = crate::convert::decode(format.sample_format, format.endian, sample);
+				if crate::convert::clipped(format.sample_format, value) {
+					stats.clipped += 1;
+				}
+
+				let value = f64::from(value);
+				stats.peak = stats.peak.max(value.abs());
+				stats.sum += value;
+				stats.squares += value * value;
+			}
+		}
+		frames += (whole / frame) as u64;
+
+		buffer.copy_within(whole..filled, 0);
+		filled -= whole;
+	}
+
+	Ok(Analysis {
+		format,
+		frames,
+		channels: stats,
+		trailing: filled
+	})
+}
+
+/** Converts the given level, relative to full scale, into dBFS. */
+fn dbfs(level: f64) -> f64 {
+	20.0 * level.log10()
+}
+
+/** Prints the given analysis of an input in the given container, if any, to
+ * the standard output, either as lines of text or as a JSON object. */
+pub fn print(analysis: &Analysis, detected: Option<&Detected>, json: bool) {
+	print!("{}", report(analysis, detected, json));
+	if analysis.trailing > 0 && !json {
+		eprintln!("warning: the input ends {} bytes into a frame, which were left out",
+			analysis.trailing);
+	}
+}
+
+/** Writes the given analysis of an input in the given container, if any, out
+ * either as lines of text or as a JSON object. Levels of silent channels are
+ * negative infinity, which JSON has no way to hold, and which get written out
+ * as null. */
+fn report(analysis: &Analysis, detected: Option<&Detected>, json: bool) -> String {
+	use std::fmt::Write;
+
+	let detected = detected.copied().unwrap_or(Detected {
+		container: "raw",
+		format: analysis.format,
+		loop_points: None
+	});
+
+	if json {
+		let level = |level: f64| if level > 0.0 {
+			format!("{:.3}", dbfs(level))
+		} else {
+			"null".to_owned()
+		};
+		let channels = analysis.channels.iter()
+			.enumerate()
+			.map(|(index, stats)| format!(
+				"{{\"peak_dbfs\":{},\"rms_dbfs\":{},\"dc_offset\":{:.9},\"clipped\":{}}}",
+				level(stats.peak),
+				level(analysis.rms(index)),
+				analysis.dc_offset(index),
+				stats.clipped))
+			.collect::<Vec<_>>();
+
+		return format!("{{\"format\":\"{}\",\"frames\":{},\"seconds\":{:.6},\"trailing_bytes\":{},\"channels\":[{}]}}\n",
+			detected,
+			analysis.frames,
+			analysis.seconds(),
+			analysis.trailing,
+			channels.join(","))
+	}
+
+	let mut report = String::new();
+	let _ = writeln!(report, "format:  {}", detected);
+	let _ = writeln!(report, "length:  {} frames ({:.3} seconds)", analysis.frames, analysis.seconds());
+	for (index, stats) in analysis.channels.iter().enumerate() {
+		let _ = writeln!(report, "channel {}: peak {:.2}dBFS, rms {:.2}dBFS, dc offset {:+.6}, {} clipped",
+			index,
+			dbfs(stats.peak),
+			dbfs(analysis.rms(index)),
+			analysis.dc_offset(index),
+			stats.clipped);
+	}
+
+	report
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::arg::Endianness;
+	use std::io::Cursor;
+
+	fn format(channels: u16, sample_format: cpal::SampleFormat) -> InputFormat {
+		InputFormat {
+			channels,
+			sample_rate: 8000,
+			sample_format,
+			endian: Endianness::Little
+		}
+	}
+
+	fn f32s(samples: &[f32]) -> Vec<u8> {
+		samples.iter().flat_map(|sample| sample.to_le_bytes()).collect()
+	}
+
+	/** A source handing out three bytes at a time, so frames get split across
+	 * reads. */
+	struct Dribble(Cursor<Vec<u8>>);
+	impl Read for Dribble {
+		fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+			let len = usize::min(buf.len(), 3);
+			self.0.read(&mut buf[..len])
+		}
+	}
+
+	#[test]
+	fn known_signals_measure_as_expected() {
+		/* A square wave of ±0.5 riding on 0.25 on the left, and a full scale
+		 * sine on the right. */
+		let samples = (0..8000)
+			.flat_map(|i| {
+				let square = if i / 4 % 2 == 0 { 0.75 } else { -0.25 };
+				let sine = (2.0 * std::f32::consts::PI * i as f32 / 16.0).sin();
+				vec![square, sine]
+			})
+			.collect::<Vec<_>>();
+		let analysis = measure(Dribble(Cursor::new(f32s(&samples))), format(2, cpal::SampleFormat::F32)).unwrap();
+
+		assert_eq!(analysis.frames, 8000);
+		assert_eq!(analysis.seconds(), 1.0);
+		assert_eq!(analysis.trailing, 0);
+
+		assert_eq!(analysis.channels[0].peak, 0.75);
+		assert!((analysis.dc_offset(0) - 0.25).abs() < 1e-9);
+		assert!((analysis.rms(0) - (0.3125f64).sqrt()).abs() < 1e-9);
+		assert_eq!(analysis.channels[0].clipped, 0);
+
+		assert!((analysis.channels[1].peak - 1.0).abs() < 1e-6);
+		assert!(analysis.dc_offset(1).abs() < 1e-6);
+		assert!((analysis.rms(1) - 0.5f64.sqrt()).abs() < 1e-6);
+		assert_eq!(analysis.channels[1].clipped, 1000);
+	}
+
+	#[test]
+	fn integer_samples_clip_at_either_end() {
+		let samples = [i16::MIN, 0, i16::MAX, -16384, 16384]
+			.iter()
+			.flat_map(|sample| sample.to_le_bytes())
+			.collect::<Vec<_>>();
+		let analysis = measure(Cursor::new(samples), format(1, cpal::SampleFormat::I16)).unwrap();
+
+		assert_eq!(analysis.frames, 5);
+		assert_eq!(analysis.channels[0].peak, 1.0);
+		assert_eq!(analysis.channels[0].clipped, 2);
+		assert!((analysis.dc_offset(0) - (-1.0 + 32767.0 / 32768.0) / 5.0).abs() < 1e-9);
+	}
+
+	#[test]
+	fn partial_frames_at_the_end_are_left_out() {
+		let mut data = f32s(&[0.5, -0.5, 0.5]);
+		data.push(0);
+		let analysis = measure(Cursor::new(data), format(2, cpal::SampleFormat::F32)).unwrap();
+
+		assert_eq!(analysis.frames, 1);
+		assert_eq!(analysis.trailing, 5);
+	}
+
+	#[test]
+	fn reports_have_a_fixed_layout() {
+		let analysis = Analysis {
+			format: format(2, cpal::SampleFormat::I16),
+			frames: 4000,
+			channels: vec![
+				ChannelStats { peak: 0.5, sum: 40.0, squares: 250.0, clipped: 0 },
+				ChannelStats::default()
+			],
+			trailing: 1
+		};
+
+		assert_eq!(report(&analysis, None, false), "\
+			format:  raw: 2ch 8000Hz s16le\n\
+			length:  4000 frames (0.500 seconds)\n\
+			channel 0: peak -6.02dBFS, rms -12.04dBFS, dc offset +0.010000, 0 clipped\n\
+			channel 1: peak -infdBFS, rms -infdBFS, dc offset +0.000000, 0 clipped\n");
+		assert_eq!(report(&analysis, None, true),
+			"{\"format\":\"raw: 2ch 8000Hz s16le\",\"frames\":4000,\"seconds\":0.500000,\"trailing_bytes\":1,\"channels\":[\
+			{\"peak_dbfs\":-6.021,\"rms_dbfs\":-12.041,\"dc_offset\":0.010000000,\"clipped\":0},\
+			{\"peak_dbfs\":null,\"rms_dbfs\":null,\"dc_offset\":0.000000000,\"clipped\":0}]}\n");
+	}
+}
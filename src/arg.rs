@@ -1,16 +1,471 @@
 use clap::ArgMatches;
+use crate::convert::Dither;
 use crate::error::Error;
+use crate::mix::Matrix;
 use cpal::traits::DeviceTrait;
 use cpal::{StreamConfig, SupportedStreamConfig};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/** Lowest sample rate that may be requested, in Hz. */
+pub const MIN_SAMPLE_RATE: u32 = 1000;
+/** Highest sample rate that may be requested, in Hz. */
+pub const MAX_SAMPLE_RATE: u32 = 768000;
+/** Longest the playback thread may spin for before going to sleep, in
+ * microseconds. That's as long as it sleeps for at a time, so spinning any
+ * longer would have it never sleep at all. */
+pub const MAX_SPIN_US: u64 = 10_000;
+/** Number of channels past which a request is more likely a typo than not. */
+const MANY_CHANNELS: u16 = 64;
+/** Environment variable picking the host when none is given on the command
+ * line. */
+const ENV_HOST: &'static str = "ALPLAY_HOST";
+/** Environment variable picking the device when none is given on the command
+ * line. */
+const ENV_DEVICE: &'static str = "ALPLAY_DEVICE";
+
+/** Value of the given environment variable, if it's set to anything. Empty
+ * values count as unset, so that a variable can be blanked out for a single
+ * invocation. */
+fn env(name: &str) -> Option<String> {
+	std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/** Name of the host that was asked for, either on the command line or, failing
+ * that, in the environment. */
+fn host_name(matches: &ArgMatches) -> Option<String> {
+	matches.value_of(crate::ARG_HOST)
+		.map(str::to_owned)
+		.or_else(|| env(ENV_HOST))
+}
+
+/** Name of the device that was asked for, either on the command line or,
+ * failing that, in the environment. */
+fn device_name(matches: &ArgMatches) -> Option<String> {
+	matches.value_of(crate::ARG_DEVICE)
+		.map(str::to_owned)
+		.or_else(|| env(ENV_DEVICE))
+}
+
+/** Picks the host given by the arguments, returning it along with its
+ * selection parameters. */
+pub fn pick_host(matches: &ArgMatches)
+	-> Result<(cpal::Host, Option<(usize, String)>), Error> {
+
+	/* The command line takes precedence over the environment, which takes
+	 * precedence over the defaults. */
+	Ok(match host_name(matches).as_deref() {
+		Some(host) => {
+			let name = host.to_owned();
+
+			let index = usize::from_str_radix(host, 10)
+				.map_err(|what| Error::MalformedHost {
+					what,
+					value: host.to_owned()
+				})?;
+			let host_id = cpal::available_hosts().get(index)
+				.cloned()
+				.ok_or(Error::NoSuchHost {
+					name: index
+				})?;
+			let host = cpal::host_from_id(host_id)
+				.map_err(|what| Error::HostUnavailable {
+					what,
+					name: index,
+					id: host_id
+				})?;
+
+			(host, Some((index, name)))
+		},
+		None =>
+			/* Just pick the default audio host. */
+			(cpal::default_host(), None)
+	})
+}
+
+/** Picks the output device of the given host that matches the given
+ * specification, returning it along with its selection parameters. */
+fn pick_device(host: &cpal::Host, spec: &str)
+	-> Result<(cpal::Device, (usize, String)), Error> {
+
+	use cpal::traits::HostTrait;
+
+	let index = usize::from_str_radix(spec, 10)
+		.map_err(|what| Error::MalformedDevice {
+			what,
+			value: spec.to_owned()
+		})?;
+	let device = host.output_devices()?
+		.nth(index)
+		.ok_or(Error::NoSuchDevice {
+			name: index
+		})?;
+
+	Ok((device, (index, spec.to_owned())))
+}
+
+/** Picks the input device given by the arguments out of the input devices of
+ * the given host, or its default input device, returning it along with its
+ * selection parameters. Unlike output devices, these only ever get picked on
+ * the command line, as the environment names an output device. */
+pub fn pick_input_device(
+	matches: &ArgMatches,
+	host: &cpal::Host,
+	host_pick: Option<(usize, String)>)
+	-> Result<(cpal::Device, Option<(usize, String)>), Error> {
+
+	use cpal::traits::HostTrait;
+
+	match matches.value_of(crate::ARG_DEVICE) {
+		Some(spec) => {
+			let index = usize::from_str_radix(spec, 10)
+				.map_err(|what| Error::MalformedDevice {
+					what,
+					value: spec.to_owned()
+				})?;
+			let device = host.input_devices()?
+				.nth(index)
+				.ok_or(Error::NoSuchDevice {
+					name: index
+				})?;
+
+			Ok((device, Some((index, spec.to_owned()))))
+		},
+		None => {
+			let device = host.default_input_device()
+				.ok_or(Error::NoInputDevice { host_pick })?;
+
+			Ok((device, None))
+		}
+	}
+}
+
+/** What we know about a sample format, beyond what cpal tells us. */
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SampleFormatInfo {
+	pub format: cpal::SampleFormat,
+	/** Name the format goes by on the command line and in the output. */
+	pub name: &'static str,
+	/** Indefinite article that goes before the name, when read out loud. */
+	pub article: &'static str,
+	/** Size of a single sample, in bytes. */
+	pub size: usize,
+}
+
+/** Every sample format that cpal exposes.
+ *
+ * The version of cpal we use only knows about these three. Newer versions add
+ * 8, 32 and 64 bit integers, as well as double precision floats, all of which
+ * will need a row here, and a way to be converted to and from in [`convert`],
+ * before they can be played.
+ *
+ * [`convert`]: crate::convert */
+pub const SAMPLE_FORMATS: &[SampleFormatInfo] = &[
+	SampleFormatInfo {
+		format: cpal::SampleFormat::F32,
+		name: "f32",
+		article: "an",
+		size: 4
+	},
+	SampleFormatInfo {
+		format: cpal::SampleFormat::I16,
+		name: "s16",
+		article: "an",
+		size: 2
+	},
+	SampleFormatInfo {
+		format: cpal::SampleFormat::U16,
+		name: "u16",
+		article: "a",
+		size: 2
+	},
+];
+
+/** Names of the sample formats that may be given on the command line. */
+const SAMPLE_FORMAT_NAMES: &[&str] = &[
+	"f32le",
+	"s16le",
+	"u16le",
+	"f32be",
+	"s16be",
+	"u16be",
+	"f32",
+	"s16",
+	"u16"
+];
+
+/** What we know about the given sample format. */
+pub fn sample_format_info(format: cpal::SampleFormat) -> &'static SampleFormatInfo {
+	SAMPLE_FORMATS.iter()
+		.find(|info| info.format == format)
+		.expect("sample format missing from the table")
+}
+
+/** Parses the name of a sample format, along with the endianness it gives as a
+ * suffix, if any. */
+pub fn parse_sample_format(name: &str)
+	-> Result<(cpal::SampleFormat, Option<Endianness>), Error> {
+
+	let name = name.to_ascii_lowercase();
+	let (base, endian) = if let Some(base) = name.strip_suffix("le") {
+		(base, Some(Endianness::Little))
+	} else if let Some(base) = name.strip_suffix("be") {
+		(base, Some(Endianness::Big))
+	} else {
+		(name.as_str(), None)
+	};
+
+	match SAMPLE_FORMATS.iter().find(|info| info.name == base) {
+		Some(info) => Ok((info.format, endian)),
+		None => Err(Error::MalformedSampleFormat {
+			expected: SAMPLE_FORMAT_NAMES,
+			got: name
+		})
+	}
+}
+
+/** Parses the name of a byte order. */
+pub fn parse_endianness(name: &str) -> Result<Endianness, Error> {
+	match name.to_ascii_lowercase().as_str() {
+		"little" => Ok(Endianness::Little),
+		"big"    => Ok(Endianness::Big),
+		"native" => Ok(Endianness::Native),
+		n => Err(Error::MalformedEndianness {
+			expected: &["little", "big", "native"],
+			got: n.to_string()
+		})
+	}
+}
+
+/** Rejects a number of channels that can't be played, warning about one that
+ * likely wasn't meant. This catches nonsense up front, rather than having it
+ * go through negotiation and come out as some cryptic error from the backend. */
+pub fn check_channels(channels: u16) -> Result<(), Error> {
+	if channels == 0 {
+		return Err(Error::InvalidChannels)
+	}
+	if channels > MANY_CHANNELS {
+		eprintln!("warning: {} channels is a lot, are you sure that's right?", channels);
+	}
+
+	Ok(())
+}
+
+/** Rejects a sample rate outside of the range that may be requested. */
+pub fn check_sample_rate(sample_rate: u32) -> Result<(), Error> {
+	if !(MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE).contains(&sample_rate) {
+		return Err(Error::InvalidSampleRate { sample_rate })
+	}
+
+	Ok(())
+}
+
+/** Reconciles the endianness given as a suffix to the sample format with the
+ * one given on its own, failing if they disagree. */
+pub fn reconcile_endianness(suffix: Option<Endianness>, flag: Option<Endianness>)
+	-> Result<Option<Endianness>, Error> {
+
+	match (suffix, flag) {
+		(Some(suffix), Some(flag)) if suffix != flag =>
+			Err(Error::ConflictingEndianness { suffix, flag }),
+		(suffix, flag) => Ok(suffix.or(flag))
+	}
+}
+
+/** Reads the format of the samples described by the channel, sample rate,
+ * sample format and byte order flags, filling in whatever of it isn't given
+ * from the given defaults, and checks it. This is for the commands that take
+ * a description of some samples on their own, with none of the rest of what
+ * goes into [`Arguments`]. */
+pub fn sample_description(matches: &ArgMatches, defaults: InputFormat)
+	-> Result<InputFormat, Error> {
+
+	let channels = matches.value_of(crate::ARG_CHANNELS)
+		.map(|channels| u16::from_str_radix(channels, 10))
+		.transpose()
+		.map_err(Error::MalformedChannels)?
+		.unwrap_or(defaults.channels);
+	let sample_rate = matches.value_of(crate::ARG_SAMPLE_RATE)
+		.map(|sample_rate| u32::from_str_radix(sample_rate, 10))
+		.transpose()
+		.map_err(Error::MalformedSampleRate)?
+		.unwrap_or(defaults.sample_rate);
+	let (sample_format, suffix) = match matches.value_of(crate::ARG_SAMPLE_FORMAT) {
+		Some(sample_format) => parse_sample_format(sample_format)?,
+		None => (defaults.sample_format, None)
+	};
+	let endian = matches.value_of(crate::ARG_ENDIAN)
+		.map(parse_endianness)
+		.transpose()?;
+	let endian = reconcile_endianness(suffix, endian)?
+		.unwrap_or(defaults.endian);
+
+	check_channels(channels)?;
+	check_sample_rate(sample_rate)?;
+
+	Ok(InputFormat {
+		channels,
+		sample_rate,
+		sample_format,
+		endian
+	})
+}
 
 /** A sample can be in multiple different endians. */
-#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum Endianness {
 	Little,
 	Big,
 	Native
 }
 
+/** Layout of raw integer samples of a width none of the sample formats have,
+ * which get unpacked into single precision samples as they're read. */
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Packing {
+	/** Number of bytes every sample takes up. */
+	pub size: usize,
+	/** Number of bits in every sample that carry data, which are the lowest
+	 * ones. */
+	pub valid_bits: u32,
+	/** Byte order of every sample. */
+	pub endian: Endianness,
+}
+
+/** Widest samples that can be unpacked, in bytes. */
+pub const MAX_PACKED_SIZE: usize = 4;
+
+/** A range of configurations an output device supports. This mirrors
+ * [`cpal::SupportedStreamConfigRange`], which cpal gives no way of building by
+ * hand. */
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SupportedRange {
+	pub channels: u16,
+	/** Lowest sample rate in the range, in Hz. */
+	pub min_sample_rate: u32,
+	/** Highest sample rate in the range, in Hz. */
+	pub max_sample_rate: u32,
+	pub sample_format: cpal::SampleFormat,
+}
+impl From<&cpal::SupportedStreamConfigRange> for SupportedRange {
+	fn from(range: &cpal::SupportedStreamConfigRange) -> Self {
+		Self {
+			channels: range.channels(),
+			min_sample_rate: range.min_sample_rate().0,
+			max_sample_rate: range.max_sample_rate().0,
+			sample_format: range.sample_format()
+		}
+	}
+}
+
+/** The ranges of configurations the given device supports. */
+fn supported_ranges(device: &cpal::Device) -> Result<Vec<SupportedRange>, Error> {
+	Ok(device.supported_output_configs()?
+		.map(|range| SupportedRange::from(&range))
+		.collect())
+}
+
+/** Configuration of an output stream, either negotiated with the device or
+ * picked outright when there is no device to negotiate with. This mirrors
+ * [`SupportedStreamConfig`], which cpal gives no way of building by hand. */
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct OutputConfig {
+	channels: u16,
+	sample_rate: cpal::SampleRate,
+	sample_format: cpal::SampleFormat,
+}
+impl OutputConfig {
+	pub fn new(
+		channels: u16,
+		sample_rate: cpal::SampleRate,
+		sample_format: cpal::SampleFormat) -> Self {
+
+		Self {
+			channels,
+			sample_rate,
+			sample_format
+		}
+	}
+
+	pub fn channels(&self) -> u16 {
+		self.channels
+	}
+
+	pub fn sample_rate(&self) -> cpal::SampleRate {
+		self.sample_rate
+	}
+
+	pub fn sample_format(&self) -> cpal::SampleFormat {
+		self.sample_format
+	}
+
+	/** Configuration streams get built with, leaving the size of the buffer
+	 * up to the device. */
+	pub fn config(&self) -> StreamConfig {
+		StreamConfig {
+			channels: self.channels,
+			sample_rate: self.sample_rate,
+			buffer_size: cpal::BufferSize::Default
+		}
+	}
+}
+impl From<SupportedStreamConfig> for OutputConfig {
+	fn from(config: SupportedStreamConfig) -> Self {
+		Self::new(config.channels(), config.sample_rate(), config.sample_format())
+	}
+}
+
+/** Description of the format of the input data, as given by the input
+ * itself, rather than by the user. */
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct InputFormat {
+	/** Number of interleaved channels. */
+	pub channels: u16,
+	/** Number of frames per second. */
+	pub sample_rate: u32,
+	/** Format of every sample. */
+	pub sample_format: cpal::SampleFormat,
+	/** Byte order of every sample. */
+	pub endian: Endianness,
+}
+impl InputFormat {
+	/** Whether samples in this format can be played back to back with samples
+	 * in the given one, without having to be converted. */
+	pub fn compatible(&self, other: &InputFormat) -> bool {
+		let resolve = |endian| match endian {
+			Endianness::Native if cfg!(target_endian = "big") => Endianness::Big,
+			Endianness::Native => Endianness::Little,
+			endian => endian
+		};
+
+		self.channels == other.channels
+			&& self.sample_rate == other.sample_rate
+			&& self.sample_format == other.sample_format
+			&& resolve(self.endian) == resolve(other.endian)
+	}
+}
+impl std::fmt::Display for InputFormat {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "{}ch {}Hz {}",
+			self.channels,
+			self.sample_rate,
+			sample_format_name(self.sample_format, self.endian))
+	}
+}
+
+/** The name of the given sample format, as it would be given to
+ * [`parse_sample_format`]. */
+pub fn sample_format_name(format: cpal::SampleFormat, endian: Endianness) -> String {
+	let format = sample_format_info(format).name;
+	let endian = match endian {
+		Endianness::Little => "le",
+		Endianness::Big    => "be",
+		Endianness::Native => ""
+	};
+
+	format!("{}{}", format, endian)
+}
+
 pub struct Arguments {
 	/** The audio host we are going to be using. */
 	host: cpal::Host,
@@ -19,8 +474,9 @@ pub struct Arguments {
 	 * [`cpal::default_host()`]. */
 	host_pick: Option<(usize, String)>,
 
-	/** The audio output device we are going to be using. */
-	device: cpal::Device,
+	/** The audio output device we are going to be using, unless we're
+	 * rendering, which has no use for one. */
+	device: Option<cpal::Device>,
 	/** The name given by the user to pick this device, if any. */
 	device_pick: Option<(usize, String)>,
 
@@ -28,97 +484,232 @@ pub struct Arguments {
 	channels: Option<u16>,
 	/** Requested output sample rate. */
 	sample_rate: Option<u32>,
+	/** Sample rate the input is played as being at, whatever it says. */
+	assume_rate: Option<u32>,
 	/** Requested output sample format. */
-	sample_format: Option<(cpal::SampleFormat, Endianness)>
+	sample_format: Option<(cpal::SampleFormat, Endianness)>,
+	/** Layout of the input samples, when they're of an unusual width. */
+	packing: Option<Packing>,
+	/** Requested byte order of the input samples. This is only kept apart from
+	 * the sample format for when no format was given, in which case it gets
+	 * applied to whichever format the input turns out to have. */
+	endian: Option<Endianness>,
+	/** Whether to play as many channels as the device would rather have,
+	 * rather than as many as the input has. */
+	channels_from_device: bool,
+	/** Whether to build the stream with exactly the requested configuration,
+	 * without checking it against the ones the device claims to support. */
+	force_config: bool,
+
+	/** Whether to fade the output out when playback is paused or interrupted,
+	 * and back in when it's resumed. */
+	ramp_on_pause: bool,
+	/** How long to spin for before going to sleep while waiting for playback
+	 * to end, if at all. */
+	spin: Option<Duration>,
+	/** Number of underruns after which playback gets aborted, if any. */
+	max_underruns: Option<u64>,
+	/** Whether to wait for the output device to come back, should it go away
+	 * during playback, rather than giving up. */
+	reconnect: bool,
+
+	/** Whether to give up on inputs that are broken in any way, rather than
+	 * playing what can be played of them. */
+	strict: bool,
+
+	/** Whether to print what is going to be played, and how. */
+	banner: bool,
+	/** Whether to print progress through the input as it's played. */
+	progress: bool,
+	/** Whether to print a message once the input is over. */
+	eof_message: bool,
+
+	/** Wall-clock time at which playback should start, if any. */
+	start_at: Option<SystemTime>,
+
+	/** Matrix the channels get mixed through, if any. */
+	matrix: Option<Matrix>,
+	/** Dither applied to samples quantized on their way to the output. */
+	dither: Dither,
+
+	/** File the timing of every callback gets logged to, if any. */
+	timestamps: Option<PathBuf>,
+
+	/** File every clipped sample gets logged to, if any. */
+	clip_report: Option<PathBuf>,
+
+	/** File everything handed to the device gets captured to, if any. */
+	tee: Option<PathBuf>,
+
+	/** File the output gets rendered to, instead of being played, if any. */
+	render_to: Option<PathBuf>,
+	/** Whether to play the output to nowhere at all, rather than to the
+	 * device. */
+	null_output: bool,
+
+	/** Additional devices the output gets mirrored to, along with the names
+	 * given by the user to pick them. */
+	mirrors: Vec<(cpal::Device, (usize, String))>,
 }
 impl Arguments {
 	/** Creates a new instance of the arguments structure from the parsed
 	 * argument strings provided by `clap`. */
 	pub fn new(matches: &ArgMatches) -> Result<Self, Error> {
-		/* Pick the host and its name. */
-		let (host, host_pick) = match matches.value_of(crate::ARG_HOST) {
-			Some(host) => {
-				let name = host.to_owned();
-
-				let index = usize::from_str_radix(host, 10)
-					.map_err(|what| Error::MalformedHost {
-						what,
-						value: host.to_owned()
-					})?;
-				let host_id = cpal::available_hosts().get(index)
-					.cloned()
-					.ok_or(Error::NoSuchHost {
-						name: index
-					})?;
-				let host = cpal::host_from_id(host_id)
-					.map_err(|what| Error::HostUnavailable {
-						what,
-						name: index,
-						id: host_id
-					})?;
-
-				(host, Some((index, name)))
-			},
-			None =>
-				/* Just pick the default audio host. */
-				(cpal::default_host(), None)
-		};
+		let (host, host_pick) = pick_host(matches)?;
 
 		/* Pick the device and specify its name. */
 		use cpal::traits::HostTrait;
-		let (device, device_pick) = match matches.value_of(crate::ARG_DEVICE) {
+		let render_to = matches.value_of(crate::ARG_RENDER_TO).map(PathBuf::from);
+		let null_output = matches.value_of(crate::ARG_OUTPUT) == Some("null");
+		let (device, device_pick) = match device_name(matches).as_deref() {
+			/* Rendering and the null output work just the same without a
+			 * device around. */
+			_ if render_to.is_some() || null_output => (None, None),
 			Some(device) => {
-				unimplemented!()
+				let (device, pick) = pick_device(&host, device)?;
+				(Some(device), Some(pick))
 			},
 			None =>
 				/* Just pick the default audio output. */
 				(
-					host.default_output_device()
+					Some(host.default_output_device()
 						.ok_or(Error::NoOutputDevice {
 							host_pick: host_pick.clone()
-						})?,
+						})?),
 					None
 				)
 		};
 
+		/* Whatever needs the device had better not be asked of the null
+		 * output. */
+		if null_output {
+			let needs_device = [
+				(crate::ARG_CHANNELS_FROM_DEVICE, "channels-from-device"),
+				(crate::ARG_ALSO_DEVICE, "also-device"),
+				(crate::ARG_RECONNECT, "reconnect"),
+			];
+			if let Some((_, flag)) = needs_device.iter().find(|(id, _)| matches.is_present(id)) {
+				return Err(Error::NeedsDevice { flag })
+			}
+		}
+
+		/* Pick the devices the output gets mirrored to. */
+		let mirrors = matches.values_of(crate::ARG_ALSO_DEVICE)
+			.map(|specs| specs
+				.map(|spec| pick_device(&host, spec))
+				.collect::<Result<Vec<_>, _>>())
+			.transpose()?
+			.unwrap_or_default();
+
+		/* A sidecar file describes the input just like the flags do, but any
+		 * flag that's given still takes precedence over what it says. */
+		let sidecar = matches.value_of(crate::ARG_SIDECAR)
+			.map(|path| crate::sidecar::load(Path::new(path)))
+			.transpose()?
+			.unwrap_or_default();
+
 		/* Get the values for the channels and sample rate. */
 		let channels = matches.value_of(crate::ARG_CHANNELS)
 			.map(|channels| u16::from_str_radix(channels, 10))
 			.transpose()
-			.map_err(|what| Error::MalformedChannels(what))?;
+			.map_err(Error::MalformedChannels)?
+			.or(sidecar.channels);
 		let sample_rate = matches.value_of(crate::ARG_SAMPLE_RATE)
 			.map(|sample_rate| u32::from_str_radix(sample_rate, 10))
 			.transpose()
-			.map_err(|what| Error::MalformedSampleRate(what))?;
+			.map_err(Error::MalformedSampleRate)?
+			.or(sidecar.sample_rate);
+		let assume_rate = matches.value_of(crate::ARG_ASSUME_RATE)
+			.map(|sample_rate| u32::from_str_radix(sample_rate, 10))
+			.transpose()
+			.map_err(Error::MalformedSampleRate)?;
+		let sample_rate = assume_rate.or(sample_rate);
+
+		channels.map(check_channels).transpose()?;
+		sample_rate.map(check_sample_rate).transpose()?;
 
-		/* Get the endianness and format specification for the sample. */
+		/* Get the endianness and format specification for the sample. The
+		 * format may carry its own endianness as a suffix. */
 		let sample_format = matches.value_of(crate::ARG_SAMPLE_FORMAT)
-			.map(|sample_format| match sample_format.to_ascii_lowercase().as_str() {
-				"f32le" => Ok((cpal::SampleFormat::F32, Endianness::Little)),
-				"s16le" => Ok((cpal::SampleFormat::I16, Endianness::Little)),
-				"u16le" => Ok((cpal::SampleFormat::U16, Endianness::Little)),
-				"f32be" => Ok((cpal::SampleFormat::F32, Endianness::Big)),
-				"s16be" => Ok((cpal::SampleFormat::I16, Endianness::Big)),
-				"u16be" => Ok((cpal::SampleFormat::U16, Endianness::Big)),
-				"f32"   => Ok((cpal::SampleFormat::F32, Endianness::Native)),
-				"s16"   => Ok((cpal::SampleFormat::I16, Endianness::Native)),
-				"u16"   => Ok((cpal::SampleFormat::U16, Endianness::Native)),
-				n @ _ => Err(Error::MalformedSampleFormat {
-					expected: &[
-						"f32le",
-						"s16le",
-						"u16le",
-						"f32be",
-						"s16be",
-						"u16be",
-						"f32",
-						"s16",
-						"u16"
-					],
-					got: n.to_string()
+			.map(parse_sample_format)
+			.transpose()?;
+		let endian = matches.value_of(crate::ARG_ENDIAN)
+			.map(parse_endianness)
+			.transpose()?;
+
+		let endian = reconcile_endianness(
+			sample_format.and_then(|(_, suffix)| suffix),
+			endian)?
+			.or(sidecar.endian);
+		let sample_format = sample_format
+			.map(|(format, _)| format)
+			.or(sidecar.sample_format)
+			.map(|format| (format, endian.unwrap_or(Endianness::Native)));
+
+		/* Samples of unusual widths get unpacked into native single precision
+		 * samples, which is what everything past that works with. */
+		let packing = matches.value_of(crate::ARG_SAMPLE_SIZE)
+			.map(|value| -> Result<_, Error> {
+				let size = usize::from_str_radix(value, 10).ok()
+					.filter(|size| (1..=MAX_PACKED_SIZE).contains(size))
+					.ok_or_else(|| Error::MalformedSampleSize { value: value.to_owned() })?;
+
+				/* The valid bits have to fit in the stride, which is to say
+				 * that it can't be any narrower than ceil(bits / 8) bytes. */
+				let valid_bits = match matches.value_of(crate::ARG_VALID_BITS) {
+					Some(value) => u32::from_str_radix(value, 10).ok()
+						.filter(|bits| *bits > 0 && (*bits as usize).div_ceil(8) <= size)
+						.ok_or_else(|| Error::MalformedValidBits {
+							value: value.to_owned(),
+							size
+						})?,
+					None => size as u32 * 8
+				};
+
+				Ok(Packing {
+					size,
+					valid_bits,
+					endian: endian.unwrap_or(Endianness::Native)
 				})
 			})
 			.transpose()?;
+		let sample_format = match packing {
+			Some(_) => Some((cpal::SampleFormat::F32, Endianness::Native)),
+			None => sample_format
+		};
+
+		let start_at = matches.value_of(crate::ARG_START_AT)
+			.map(|value| crate::clock::parse(value)
+				.ok_or(Error::MalformedStartTime {
+					value: value.to_owned()
+				}))
+			.transpose()?;
+
+		let spin = matches.value_of(crate::ARG_SPIN_US)
+			.map(|spin| u64::from_str_radix(spin, 10))
+			.transpose()
+			.map_err(Error::MalformedSpin)?
+			.filter(|spin| *spin > 0);
+		if let Some(spin) = spin.filter(|spin| *spin > MAX_SPIN_US) {
+			return Err(Error::InvalidSpin { spin })
+		}
+		let spin = spin.map(Duration::from_micros);
+
+		let max_underruns = matches.value_of(crate::ARG_MAX_UNDERRUNS)
+			.map(|limit| u64::from_str_radix(limit, 10))
+			.transpose()
+			.map_err(Error::MalformedMaxUnderruns)?;
+
+		let matrix = matches.value_of(crate::ARG_MATRIX)
+			.map(Matrix::parse)
+			.transpose()
+			.map_err(Error::MalformedMatrix)?;
+
+		let dither = match matches.value_of(crate::ARG_DITHER) {
+			Some("rectangular") => Dither::Rectangular,
+			Some("triangular") => Dither::Triangular,
+			_ => Dither::None
+		};
 
 		Ok(Self {
 			host,
@@ -127,10 +718,106 @@ impl Arguments {
 			device_pick,
 			channels,
 			sample_rate,
-			sample_format
+			assume_rate,
+			sample_format,
+			packing,
+			endian,
+			channels_from_device: matches.is_present(crate::ARG_CHANNELS_FROM_DEVICE),
+			force_config: matches.is_present(crate::ARG_FORCE_CONFIG),
+			ramp_on_pause: matches.is_present(crate::ARG_RAMP_ON_PAUSE),
+			spin,
+			reconnect: matches.is_present(crate::ARG_RECONNECT),
+			max_underruns,
+			strict: matches.is_present(crate::ARG_STRICT),
+			banner: !matches.is_present(crate::ARG_NO_BANNER),
+			progress: !matches.is_present(crate::ARG_NO_PROGRESS),
+			eof_message: !matches.is_present(crate::ARG_NO_EOF_MESSAGE),
+			start_at,
+			matrix,
+			dither,
+			timestamps: matches.value_of(crate::ARG_TIMESTAMPS).map(PathBuf::from),
+			clip_report: matches.value_of(crate::ARG_CLIP_REPORT).map(PathBuf::from),
+			tee: matches.value_of(crate::ARG_TEE).map(PathBuf::from),
+			render_to,
+			null_output,
+			mirrors
 		})
 	}
 
+	/** Uses the format the input describes itself as having for all of the
+	 * parameters the user did not specify. Parameters that were specified but
+	 * that disagree with the input are kept, with a warning. An assumed sample
+	 * rate replaces the one the input has outright. */
+	pub fn apply_input_format(&mut self, mut input: InputFormat) {
+		if let Some(assume_rate) = self.assume_rate {
+			if self.banner && assume_rate != input.sample_rate {
+				eprintln!("note: the input has a sample rate of {}Hz, playing it as if it were {}Hz",
+					input.sample_rate,
+					assume_rate);
+			}
+			input.sample_rate = assume_rate;
+		}
+
+		match self.channels {
+			Some(channels) => if channels != input.channels {
+				eprintln!("warning: the input has {} channels, playing it with {} instead",
+					input.channels,
+					channels);
+			},
+			None => self.channels = Some(input.channels)
+		}
+
+		match self.sample_rate {
+			Some(sample_rate) => if sample_rate != input.sample_rate {
+				eprintln!("warning: the input has a sample rate of {}Hz, playing it at {}Hz instead",
+					input.sample_rate,
+					sample_rate);
+			},
+			None => self.sample_rate = Some(input.sample_rate)
+		}
+
+		match self.sample_format {
+			Some(format) => if format != (input.sample_format, input.endian) {
+				eprintln!("warning: the input has {:?} {:?} endian samples, playing them as {:?} {:?} endian instead",
+					input.sample_format,
+					input.endian,
+					format.0,
+					format.1);
+			},
+			None => {
+				let endian = match self.endian {
+					Some(endian) => {
+						if endian != input.endian {
+							eprintln!("warning: the input has {:?} endian samples, playing them as {:?} endian instead",
+								input.endian,
+								endian);
+						}
+						endian
+					},
+					None => input.endian
+				};
+				self.sample_format = Some((input.sample_format, endian))
+			}
+		}
+	}
+
+	/** Uses the given sample format, which the name of the input suggests, if
+	 * the user did not specify one. The endianness given by the user, if any,
+	 * still has to agree with the one in the name. */
+	pub fn assume_sample_format(
+		&mut self,
+		format: cpal::SampleFormat,
+		suffix: Option<Endianness>) -> Result<(), Error> {
+
+		if self.sample_format.is_some() { return Ok(()) }
+
+		let endian = reconcile_endianness(suffix, self.endian)?;
+		self.sample_format = Some((format, endian.unwrap_or(Endianness::Native)));
+		self.endian = endian;
+
+		Ok(())
+	}
+
 	/** Pick an audio host that matches the given settings. */
 	pub fn host(&self) -> &cpal::Host {
 		&self.host
@@ -144,9 +831,11 @@ impl Arguments {
 			.map(|(a, b)| (*a, b.as_str()))
 	}
 
-	/** Pick an audio output device that matches the given settings. */
+	/** Pick an audio output device that matches the given settings. There's
+	 * none when rendering or playing to the null output, which never get as
+	 * far as asking for one. */
 	pub fn device(&self) -> &cpal::Device {
-		&self.device
+		self.device.as_ref().expect("no output device is picked without a device to play to")
 	}
 
 	/** The selection parameters used to pick the current device. If no
@@ -158,9 +847,134 @@ impl Arguments {
 			.map(|(a, b)| (*a, b.as_str()))
 	}
 
+	/** Additional audio output devices the output gets mirrored to, along with
+	 * the selection parameters used to pick them. */
+	pub fn mirrors(&self) -> &[(cpal::Device, (usize, String))] {
+		&self.mirrors
+	}
+
 	/** Format for interpret the input data as. */
 	pub fn endianness(&self) -> Option<Endianness> {
-		self.sample_format.map(|(_, a)| a)
+		self.sample_format.map(|(_, a)| a).or(self.endian)
+	}
+
+	/** Sample rate the input should be taken to be at, whatever it says. */
+	pub fn assume_rate(&self) -> Option<u32> {
+		self.assume_rate
+	}
+
+	/** Number of channels in the input, if known. */
+	pub fn input_channels(&self) -> Option<u16> {
+		self.channels
+	}
+
+	/** Layout of the samples in the input, if they're of an unusual width and
+	 * have to be unpacked before anything else is done with them. Once they
+	 * are, they're native single precision samples. */
+	pub fn packing(&self) -> Option<Packing> {
+		self.packing
+	}
+
+	/** Format of the samples in the input, if known. When it isn't, the input
+	 * is taken to be in whatever format the output gets. */
+	pub fn input_sample_format(&self) -> Option<cpal::SampleFormat> {
+		self.sample_format.map(|(format, _)| format)
+	}
+
+	/** Whether the output plays as many channels as the device would rather
+	 * have, rather than as many as the input has. */
+	pub fn channels_from_device(&self) -> bool {
+		self.channels_from_device
+	}
+
+	/** Whether the output should be faded out when playback is paused or
+	 * interrupted, and back in when it's resumed, rather than being cut off. */
+	pub fn ramp_on_pause(&self) -> bool {
+		self.ramp_on_pause
+	}
+
+	/** Number of underruns past which playback should be aborted. Playback
+	 * carries on through any number of them if this is not set. */
+	pub fn max_underruns(&self) -> Option<u64> {
+		self.max_underruns
+	}
+
+	/** Whether playback should wait for the output device to come back, should
+	 * it go away, and then carry on from where it was. */
+	pub fn reconnect(&self) -> bool {
+		self.reconnect
+	}
+
+	/** How long to spin for before going to sleep while waiting for playback
+	 * to end. Playback is waited for by sleeping alone if this is not set. */
+	pub fn spin(&self) -> Option<Duration> {
+		self.spin
+	}
+
+	/** Whether what is going to be played, and how, should be printed. */
+	pub fn banner(&self) -> bool {
+		self.banner
+	}
+
+	/** Whether broken inputs should fail playback, rather than having as much
+	 * of them as can be played played. */
+	pub fn strict(&self) -> bool {
+		self.strict
+	}
+
+	/** Whether progress through the input should be printed. */
+	pub fn progress(&self) -> bool {
+		self.progress
+	}
+
+	/** Whether a message should be printed once the input is over. */
+	pub fn eof_message(&self) -> bool {
+		self.eof_message
+	}
+
+	/** Wall-clock time at which playback should start. Playback starts as
+	 * soon as possible if this is not set. */
+	pub fn start_at(&self) -> Option<SystemTime> {
+		self.start_at
+	}
+
+	/** Matrix the channels should be mixed through before being played. */
+	pub fn matrix(&self) -> Option<&Matrix> {
+		self.matrix.as_ref()
+	}
+
+	/** Dither applied to samples that get mixed or converted into integer
+	 * ones on their way to the output. */
+	pub fn dither(&self) -> Dither {
+		self.dither
+	}
+
+	/** File the timing of every callback should be logged to, as CSV. */
+	pub fn timestamps(&self) -> Option<&Path> {
+		self.timestamps.as_deref()
+	}
+
+	/** File every sample played at or past full scale should be logged to, as
+	 * CSV. */
+	pub fn clip_report(&self) -> Option<&Path> {
+		self.clip_report.as_deref()
+	}
+
+	/** File the samples handed to the device should be captured to, exactly
+	 * as they were handed over. */
+	pub fn tee(&self) -> Option<&Path> {
+		self.tee.as_deref()
+	}
+
+	/** Whether the output should be played to nowhere at all, rather than to
+	 * the device. */
+	pub fn null_output(&self) -> bool {
+		self.null_output
+	}
+
+	/** File the output should be rendered to, rather than being played. */
+	pub fn render_to(&self) -> Option<&Path> {
+		self.render_to.as_deref()
 	}
 
 	/** Find the best suited output stream configuration, if any is possible. */
@@ -169,44 +983,228 @@ impl Arguments {
 		preferred_sample_rate: u32,
 		preferred_channels: u16,
 		preferred_sample_format: cpal::SampleFormat)
-		-> Result<SupportedStreamConfig, Error> {
+		-> Result<OutputConfig, Error> {
+
+		/* Without a device, there's nothing to negotiate with, so the output
+		 * is in whatever format was asked for, or else the preferred one. */
+		if self.render_to.is_some() || self.null_output {
+			return Ok(OutputConfig::new(
+				self.channels.unwrap_or(preferred_channels),
+				cpal::SampleRate(self.sample_rate.unwrap_or(preferred_sample_rate)),
+				self.input_sample_format().unwrap_or(preferred_sample_format)))
+		}
+
+		/* Some drivers misreport what they support, but do fine when asked
+		 * for the right thing directly. Whatever goes wrong here is left to
+		 * building the stream to find out. */
+		if self.force_config {
+			let config = OutputConfig::new(
+				self.channels.unwrap_or(preferred_channels),
+				cpal::SampleRate(self.sample_rate.unwrap_or(preferred_sample_rate)),
+				self.input_sample_format().unwrap_or(preferred_sample_format));
+			if self.banner {
+				eprintln!("forcing {} channels of {} at {}Hz, without checking whether the device supports it",
+					config.channels(),
+					sample_format_info(config.sample_format()).name.to_ascii_uppercase(),
+					config.sample_rate().0);
+			}
+
+			return Ok(config)
+		}
+
+		if !self.channels_from_device {
+			return self.config_for(
+				self.device(),
+				preferred_sample_rate,
+				preferred_channels,
+				preferred_sample_format)
+		}
+
+		let channels = self.device().default_output_config()?.channels();
+		if self.banner {
+			eprintln!("playing the {} channels the device defaults to", channels);
+		}
+
+		self.negotiate(
+			&supported_ranges(self.device())?,
+			Some(channels),
+			preferred_sample_rate,
+			preferred_channels,
+			preferred_sample_format)
+	}
+
+	/** Find the best suited output stream configuration for the given device,
+	 * if any is possible. */
+	pub fn config_for(
+		&self,
+		device: &cpal::Device,
+		preferred_sample_rate: u32,
+		preferred_channels: u16,
+		preferred_sample_format: cpal::SampleFormat)
+		-> Result<OutputConfig, Error> {
+
+		self.config_within(
+			&supported_ranges(device)?,
+			preferred_sample_rate,
+			preferred_channels,
+			preferred_sample_format)
+	}
+
+	/** Find the output stream configuration for a device the output gets
+	 * mirrored to. The output gets converted into whatever the mirror plays,
+	 * so nothing is required of it, it just gets as close to the given
+	 * configuration as it can. */
+	pub fn mirror_config_for(&self, device: &cpal::Device, config: &OutputConfig)
+		-> Result<OutputConfig, Error> {
+
+		self.negotiate_with(
+			&supported_ranges(device)?,
+			None,
+			None,
+			None,
+			config.sample_rate().0,
+			config.channels(),
+			config.sample_format())
+	}
+
+	/** Find the best suited output stream configuration among the given ones
+	 * a device supports, if any is possible. Surround input that can't be
+	 * played as it is gets played in stereo, to be downmixed into it. */
+	pub fn config_within(
+		&self,
+		supported: &[SupportedRange],
+		preferred_sample_rate: u32,
+		preferred_channels: u16,
+		preferred_sample_format: cpal::SampleFormat)
+		-> Result<OutputConfig, Error> {
+
+		let config = self.negotiate(
+			supported,
+			self.channels,
+			preferred_sample_rate,
+			preferred_channels,
+			preferred_sample_format);
+
+		match (config, self.channels) {
+			(Err(error @ Error::NoSuitableStreamConfig { .. }), Some(channels))
+				if Matrix::surround(channels, 2, false).is_some() => {
+
+				let config = self.negotiate(
+					supported,
+					Some(2),
+					preferred_sample_rate,
+					preferred_channels,
+					preferred_sample_format);
+				match config {
+					Ok(config) => {
+						if self.banner {
+							eprintln!("the device can't play {} channels, playing them in stereo instead",
+								channels);
+						}
+						Ok(config)
+					},
+					Err(_) => Err(error)
+				}
+			},
+			(config, _) => config
+		}
+	}
+
+	/** Find the best suited output stream configuration among the given ones
+	 * a device supports, for the given number of channels, if given, if any
+	 * is possible. Outputs in the format of the input are preferred, but when
+	 * the device has none, the input gets played through whichever one fits
+	 * best otherwise, and is converted into it during playback. */
+	fn negotiate(
+		&self,
+		supported: &[SupportedRange],
+		channels: Option<u16>,
+		preferred_sample_rate: u32,
+		preferred_channels: u16,
+		preferred_sample_format: cpal::SampleFormat)
+		-> Result<OutputConfig, Error> {
+
+		let required_format = self.input_sample_format();
+		let strict = self.negotiate_with(
+			supported,
+			channels,
+			required_format,
+			self.sample_rate,
+			preferred_sample_rate,
+			preferred_channels,
+			preferred_sample_format);
+
+		match (strict, required_format) {
+			(Err(Error::NoSuitableStreamConfig { .. }), Some(format)) => {
+				let config = self.negotiate_with(
+					supported,
+					channels,
+					None,
+					self.sample_rate,
+					preferred_sample_rate,
+					preferred_channels,
+					format)?;
+				if self.banner {
+					eprintln!("the device can't play {} samples, converting them to {}",
+						sample_format_info(format).name.to_ascii_uppercase(),
+						sample_format_info(config.sample_format()).name.to_ascii_uppercase());
+				}
+
+				Ok(config)
+			},
+			(strict, _) => strict
+		}
+	}
+
+	/** Find the best suited output stream configuration among the given ones
+	 * a device supports, for the given number of channels, sample format and
+	 * sample rate, if given, if any is possible. */
+	#[allow(clippy::too_many_arguments)]
+	fn negotiate_with(
+		&self,
+		supported: &[SupportedRange],
+		channels: Option<u16>,
+		required_format: Option<cpal::SampleFormat>,
+		required_sample_rate: Option<u32>,
+		preferred_sample_rate: u32,
+		preferred_channels: u16,
+		preferred_sample_format: cpal::SampleFormat)
+		-> Result<OutputConfig, Error> {
+
+		let required_channels = channels;
 
 		let mut best = None;
-		for output in self.device.supported_output_configs()? {
-			let channels = if let Some(channels) = self.channels {
-				if output.channels() != channels { continue }
+		for output in supported.iter() {
+			let channels = if let Some(channels) = required_channels {
+				if output.channels != channels { continue }
 				channels
 			} else {
 				preferred_channels
 			};
 
-			let format = if let Some((format, _)) = self.sample_format {
-				if output.sample_format() != format { continue }
+			let format = if let Some(format) = required_format {
+				if output.sample_format != format { continue }
 				format
 			} else {
 				preferred_sample_format
 			};
 
-			let sample_rate = if let Some(sample_rate) = self.sample_rate {
-				if sample_rate < output.min_sample_rate().0 { continue }
-				if sample_rate > output.max_sample_rate().0 { continue }
+			let sample_rate = if let Some(sample_rate) = required_sample_rate {
+				if sample_rate < output.min_sample_rate { continue }
+				if sample_rate > output.max_sample_rate { continue }
 
 				sample_rate
 			} else {
 				preferred_sample_rate
 			};
 
-			let config = if output.min_sample_rate().0 > sample_rate {
-				let max = output.min_sample_rate();
-				output.with_sample_rate(max)
-			} else if output.max_sample_rate().0 < sample_rate {
-				let min = output.min_sample_rate();
-				output.with_sample_rate(min)
-			} else {
-				output.with_sample_rate(cpal::SampleRate(sample_rate))
-			};
+			let rate = closest_sample_rate(
+				output.min_sample_rate,
+				output.max_sample_rate,
+				sample_rate);
+			let config = OutputConfig::new(output.channels, cpal::SampleRate(rate), output.sample_format);
 
-			let better = |best: &SupportedStreamConfig, candidate: &SupportedStreamConfig| {
+			let better = |best: &OutputConfig, candidate: &OutputConfig| {
 				let a =
 					  u32::max(best.sample_rate().0, sample_rate)
 					- u32::min(best.sample_rate().0, sample_rate);
@@ -239,9 +1237,414 @@ impl Arguments {
 		}
 
 		best.ok_or(Error::NoSuitableStreamConfig {
-			required_format: self.sample_format.map(|(a, _)| a),
-			required_sample_rate: self.sample_rate,
-			required_channels: self.channels
+			required_format,
+			required_sample_rate,
+			required_channels,
+			supported: supported.to_vec()
 		})
 	}
 }
+
+/** The sample rate closest to the given one out of the range a configuration
+ * supports, which is the nearest end of the range for rates outside of it. */
+fn closest_sample_rate(min: u32, max: u32, sample_rate: u32) -> u32 {
+	if sample_rate < min {
+		min
+	} else if sample_rate > max {
+		max
+	} else {
+		sample_rate
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn sample_rates_get_clamped_to_the_nearest_end_of_the_range() {
+		assert_eq!(closest_sample_rate(8000, 48000, 44100), 44100);
+		assert_eq!(closest_sample_rate(8000, 48000, 4000), 8000);
+		assert_eq!(closest_sample_rate(8000, 48000, 96000), 48000);
+		assert_eq!(closest_sample_rate(44100, 44100, 192000), 44100);
+	}
+
+	fn stereo_s16() -> InputFormat {
+		InputFormat {
+			channels: 2,
+			sample_rate: 48000,
+			sample_format: cpal::SampleFormat::I16,
+			endian: Endianness::Little
+		}
+	}
+
+	#[test]
+	fn mixed_rates_are_not_compatible() {
+		let format = stereo_s16();
+		assert!(format.compatible(&format));
+		assert!(!format.compatible(&InputFormat { sample_rate: 44100, ..format }));
+		assert!(!format.compatible(&InputFormat { channels: 1, ..format }));
+		assert!(!format.compatible(&InputFormat { endian: Endianness::Big, ..format }));
+		assert!(!format.compatible(&InputFormat {
+			sample_format: cpal::SampleFormat::F32,
+			..format
+		}));
+
+		let native = InputFormat { endian: Endianness::Native, ..format };
+		assert_eq!(native.compatible(&format), cfg!(target_endian = "little"));
+	}
+
+	#[test]
+	fn mismatched_items_get_tabled() {
+		let format = stereo_s16();
+		let error = Error::MismatchedItems {
+			items: vec![
+				("(list)".to_owned(), format),
+				("b.raw".to_owned(), InputFormat { sample_rate: 44100, ..format })
+			]
+		};
+
+		let message = error.to_string();
+		let lines = message.lines().collect::<Vec<_>>();
+		assert_eq!(lines.len(), 4);
+		assert!(lines[0].contains("--force-concat"));
+		assert_eq!(lines[2], "  (list)         2      48000   s16le ");
+		assert_eq!(lines[3], "  b.raw          2      44100*  s16le ");
+	}
+
+	#[test]
+	fn endianness_suffix_must_agree_with_the_flag() {
+		let (little, big) = (Some(Endianness::Little), Some(Endianness::Big));
+		assert!(matches!(reconcile_endianness(little, little), Ok(Some(Endianness::Little))));
+		assert!(matches!(reconcile_endianness(None, big), Ok(Some(Endianness::Big))));
+		assert!(matches!(reconcile_endianness(big, None), Ok(Some(Endianness::Big))));
+		assert!(matches!(reconcile_endianness(None, None), Ok(None)));
+		assert!(matches!(
+			reconcile_endianness(little, big),
+			Err(Error::ConflictingEndianness { suffix: Endianness::Little, flag: Endianness::Big })));
+
+		/* Native is a byte order of its own as far as flags go. */
+		assert!(matches!(
+			reconcile_endianness(little, Some(Endianness::Native)),
+			Err(Error::ConflictingEndianness { .. })));
+	}
+
+	#[test]
+	fn unsuitable_configs_list_the_supported_ones() {
+		let error = Error::NoSuitableStreamConfig {
+			required_format: Some(cpal::SampleFormat::F32),
+			required_sample_rate: None,
+			required_channels: Some(8),
+			supported: vec![
+				SupportedRange {
+					channels: 2,
+					min_sample_rate: 44100,
+					max_sample_rate: 48000,
+					sample_format: cpal::SampleFormat::I16
+				},
+				SupportedRange {
+					channels: 6,
+					min_sample_rate: 48000,
+					max_sample_rate: 48000,
+					sample_format: cpal::SampleFormat::F32
+				}
+			]
+		};
+		assert_eq!(error.to_string(),
+			"failed to find a suitable output format with an F32 format and 8 channels. \
+			the device supports I16 with 2 channels at 44100-48000Hz, F32 with 6 channels at 48000Hz");
+	}
+
+	#[test]
+	fn unsuitable_configs_list_every_requirement() {
+		let error = |format, rate, channels| Error::NoSuitableStreamConfig {
+			required_format: format,
+			required_sample_rate: rate,
+			required_channels: channels,
+			supported: Vec::new()
+		}.to_string();
+
+		assert_eq!(error(None, Some(96000), None),
+			"failed to find a suitable output format with a sample rate of 96000Hz");
+		assert_eq!(error(Some(cpal::SampleFormat::U16), Some(96000), Some(2)),
+			"failed to find a suitable output format with a U16 format, a sample rate of 96000Hz and 2 channels");
+		assert_eq!(error(None, None, None), "the device supports no output formats");
+	}
+
+	#[test]
+	fn sample_formats_parse_with_their_suffix() {
+		assert!(matches!(
+			parse_sample_format("s16le"),
+			Ok((cpal::SampleFormat::I16, Some(Endianness::Little)))));
+		assert!(matches!(
+			parse_sample_format("F32BE"),
+			Ok((cpal::SampleFormat::F32, Some(Endianness::Big)))));
+		assert!(matches!(parse_sample_format("u16"), Ok((cpal::SampleFormat::U16, None))));
+		assert!(matches!(parse_sample_format("s24le"), Err(Error::MalformedSampleFormat { .. })));
+		assert!(matches!(parse_sample_format("le"), Err(Error::MalformedSampleFormat { .. })));
+	}
+
+	#[test]
+	fn channels_must_not_be_zero() {
+		assert!(matches!(check_channels(0), Err(Error::InvalidChannels)));
+		assert!(check_channels(1).is_ok());
+	}
+
+	#[test]
+	fn sample_rate_must_be_in_range() {
+		assert!(matches!(
+			check_sample_rate(999),
+			Err(Error::InvalidSampleRate { sample_rate: 999 })));
+		assert!(check_sample_rate(1000).is_ok());
+		assert!(check_sample_rate(768000).is_ok());
+		assert!(matches!(
+			check_sample_rate(768001),
+			Err(Error::InvalidSampleRate { sample_rate: 768001 })));
+	}
+
+	#[test]
+	fn command_line_wins_over_the_environment() {
+		let matches = |args: &[&str]| clap::App::new("alplay")
+			.args(&crate::device_args())
+			.get_matches_from(std::iter::once("alplay").chain(args.iter().copied()));
+
+		/* Everything touching the variables lives in this one test, as the
+		 * environment is shared by every test running alongside it. */
+		std::env::remove_var(ENV_HOST);
+		std::env::remove_var(ENV_DEVICE);
+		assert_eq!(host_name(&matches(&[])), None);
+		assert_eq!(device_name(&matches(&[])), None);
+
+		std::env::set_var(ENV_HOST, "1");
+		std::env::set_var(ENV_DEVICE, "speakers");
+		assert_eq!(host_name(&matches(&[])).as_deref(), Some("1"));
+		assert_eq!(device_name(&matches(&[])).as_deref(), Some("speakers"));
+
+		let given = matches(&["--host", "0", "--device", "headphones"]);
+		assert_eq!(host_name(&given).as_deref(), Some("0"));
+		assert_eq!(device_name(&given).as_deref(), Some("headphones"));
+
+		/* Blank variables count as unset. */
+		std::env::set_var(ENV_HOST, "");
+		std::env::set_var(ENV_DEVICE, "");
+		assert_eq!(host_name(&matches(&[])), None);
+		assert_eq!(device_name(&matches(&[])), None);
+
+		std::env::remove_var(ENV_HOST);
+		std::env::remove_var(ENV_DEVICE);
+	}
+
+	#[test]
+	fn endianness_parses_in_any_case() {
+		assert!(matches!(parse_endianness("Little"), Ok(Endianness::Little)));
+		assert!(matches!(parse_endianness("BIG"), Ok(Endianness::Big)));
+		assert!(matches!(parse_endianness("native"), Ok(Endianness::Native)));
+		assert!(matches!(parse_endianness("middle"), Err(Error::MalformedEndianness { .. })));
+	}
+
+	/** Arguments for playback to the null output, which needs no device, as
+	 * given on the command line. */
+	fn null_playback(args: &[&str]) -> Result<Arguments, Error> {
+		let matches = clap::App::new("alplay")
+			.args(&crate::device_args())
+			.args(&crate::input_args())
+			.args(&crate::playback_args())
+			.get_matches_from(["alplay", "--output", "null"].iter().chain(args));
+		Arguments::new(&matches)
+	}
+
+	#[test]
+	fn flags_win_over_the_sidecar_one_by_one() {
+		let path = std::env::temp_dir().join(format!("alplay-sidecar-{}.meta", std::process::id()));
+		std::fs::write(&path, "channels=6\nrate=96000\nformat=s16be\n").unwrap();
+		let sidecar = path.to_str().unwrap();
+
+		let args = null_playback(&["--header", sidecar]).unwrap();
+		assert_eq!(args.input_channels(), Some(6));
+		assert_eq!(args.sample_rate, Some(96000));
+		assert_eq!(args.input_sample_format(), Some(cpal::SampleFormat::I16));
+		assert_eq!(args.endianness(), Some(Endianness::Big));
+
+		let args = null_playback(&["--header", sidecar, "-c", "2", "-f", "f32"]).unwrap();
+		assert_eq!(args.input_channels(), Some(2));
+		assert_eq!(args.sample_rate, Some(96000));
+		assert_eq!(args.input_sample_format(), Some(cpal::SampleFormat::F32));
+		assert_eq!(args.endianness(), Some(Endianness::Big));
+
+		let args = null_playback(&["--header", sidecar, "-r", "44100", "--endian", "little"]).unwrap();
+		assert_eq!(args.input_channels(), Some(6));
+		assert_eq!(args.sample_rate, Some(44100));
+		assert_eq!(args.input_sample_format(), Some(cpal::SampleFormat::I16));
+		assert_eq!(args.endianness(), Some(Endianness::Little));
+
+		/* What the sidecar says gets checked like the flags are. */
+		std::fs::write(&path, "channels=0\n").unwrap();
+		assert!(matches!(
+			null_playback(&["--header", sidecar]).err().unwrap(),
+			Error::InvalidChannels));
+
+		std::fs::remove_file(&path).unwrap();
+		assert!(matches!(
+			null_playback(&["--header", sidecar]).err().unwrap(),
+			Error::SidecarRead { .. }));
+	}
+
+	/** What a device that can only play stereo supports. */
+	fn stereo_only() -> Vec<SupportedRange> {
+		[cpal::SampleFormat::I16, cpal::SampleFormat::F32].iter()
+			.map(|&sample_format| SupportedRange {
+				channels: 2,
+				min_sample_rate: 8000,
+				max_sample_rate: 192000,
+				sample_format
+			})
+			.collect()
+	}
+
+	#[test]
+	fn surround_gets_played_in_stereo_when_the_device_cant_play_it() {
+		let stereo = OutputConfig::new(2, cpal::SampleRate(48000), cpal::SampleFormat::I16);
+		for channels in &["6", "8"] {
+			let args = null_playback(&["-c", channels, "-r", "48000", "-f", "s16le"]).unwrap();
+			let config = args.config_within(&stereo_only(), 44100, 2, cpal::SampleFormat::F32).unwrap();
+			assert_eq!(config, stereo);
+			assert_eq!(args.input_channels().map(|c| c.to_string()).as_deref(), Some(*channels));
+		}
+
+		/* It's the same for surround the input itself says it has. */
+		let mut args = null_playback(&[]).unwrap();
+		args.apply_input_format(InputFormat { channels: 6, ..stereo_s16() });
+		assert_eq!(args.config_within(&stereo_only(), 44100, 2, cpal::SampleFormat::F32).unwrap(), stereo);
+
+		/* Devices that can play surround get it as it is. */
+		let mut supported = stereo_only();
+		supported.push(SupportedRange { channels: 6, ..supported[0] });
+		let config = args.config_within(&supported, 44100, 2, cpal::SampleFormat::F32).unwrap();
+		assert_eq!(config.channels(), 6);
+
+		/* Other layouts have no downmix to fall back on. */
+		let args = null_playback(&["-c", "4", "-r", "48000", "-f", "s16le"]).unwrap();
+		assert!(matches!(
+			args.config_within(&stereo_only(), 44100, 2, cpal::SampleFormat::F32),
+			Err(Error::NoSuitableStreamConfig { required_channels: Some(4), .. })));
+	}
+
+	/** The description of samples given on the command line, filled in from
+	 * the preferred format for playback, but in native byte order. */
+	fn described(args: &[&str]) -> Result<InputFormat, Error> {
+		let matches = clap::App::new("alplay")
+			.args(&crate::input_args())
+			.get_matches_from(["alplay"].iter().chain(args));
+		sample_description(&matches, InputFormat {
+			channels: 2,
+			sample_rate: 48000,
+			sample_format: cpal::SampleFormat::I16,
+			endian: Endianness::Native
+		})
+	}
+
+	#[test]
+	fn sample_descriptions_fill_in_what_is_left_out() {
+		assert_eq!(described(&[]).unwrap(), InputFormat {
+			channels: 2,
+			sample_rate: 48000,
+			sample_format: cpal::SampleFormat::I16,
+			endian: Endianness::Native
+		});
+		assert_eq!(described(&["-c", "6", "-r", "96000", "-f", "f32be"]).unwrap(), InputFormat {
+			channels: 6,
+			sample_rate: 96000,
+			sample_format: cpal::SampleFormat::F32,
+			endian: Endianness::Big
+		});
+		assert_eq!(described(&["-f", "u16", "--endian", "little"]).unwrap().endian, Endianness::Little);
+	}
+
+	#[test]
+	fn sample_descriptions_are_checked() {
+		assert!(matches!(described(&["-c", "0"]), Err(Error::InvalidChannels)));
+		assert!(matches!(described(&["-c", "two"]), Err(Error::MalformedChannels(_))));
+		assert!(matches!(described(&["-r", "999"]), Err(Error::InvalidSampleRate { sample_rate: 999 })));
+		assert!(matches!(described(&["-r", "fast"]), Err(Error::MalformedSampleRate(_))));
+		assert!(matches!(described(&["-f", "s16le", "--endian", "big"]), Err(Error::ConflictingEndianness { .. })));
+	}
+
+	#[test]
+	fn formats_named_by_the_input_give_way_to_flags() {
+		let assumed = |args: &[&str], format, suffix| {
+			let mut args = null_playback(args)?;
+			args.assume_sample_format(format, suffix)?;
+			Ok::<_, Error>((args.input_sample_format(), args.endianness()))
+		};
+
+		assert_eq!(assumed(&[], cpal::SampleFormat::I16, Some(Endianness::Big)).unwrap(),
+			(Some(cpal::SampleFormat::I16), Some(Endianness::Big)));
+		assert_eq!(assumed(&[], cpal::SampleFormat::F32, None).unwrap(),
+			(Some(cpal::SampleFormat::F32), Some(Endianness::Native)));
+
+		/* A byte order given on its own fills in for a name without one, and
+		 * has to agree with a name that has one. */
+		assert_eq!(assumed(&["--endian", "big"], cpal::SampleFormat::U16, None).unwrap(),
+			(Some(cpal::SampleFormat::U16), Some(Endianness::Big)));
+		assert_eq!(assumed(&["--endian", "little"], cpal::SampleFormat::U16, Some(Endianness::Little)).unwrap(),
+			(Some(cpal::SampleFormat::U16), Some(Endianness::Little)));
+		assert!(matches!(
+			assumed(&["--endian", "little"], cpal::SampleFormat::I16, Some(Endianness::Big)),
+			Err(Error::ConflictingEndianness { suffix: Endianness::Big, flag: Endianness::Little })));
+
+		/* A sample format given by the user wins over the name outright. */
+		assert_eq!(assumed(&["-f", "f32be"], cpal::SampleFormat::I16, Some(Endianness::Little)).unwrap(),
+			(Some(cpal::SampleFormat::F32), Some(Endianness::Big)));
+	}
+
+	#[test]
+	fn packings_need_their_bits_to_fit_the_stride() {
+		let packing = |args: &[&str]| {
+			let args = null_playback(&[&["--raw", "--endian", "big"], args].concat())?;
+			Ok::<_, Error>(args.packing())
+		};
+
+		assert_eq!(packing(&[]).unwrap(), None);
+		assert_eq!(packing(&["--sample-size", "3", "--valid-bits", "20"]).unwrap(), Some(Packing {
+			size: 3,
+			valid_bits: 20,
+			endian: Endianness::Big
+		}));
+		assert_eq!(packing(&["--sample-size", "2", "--valid-bits", "9"]).unwrap().unwrap().valid_bits, 9);
+		assert_eq!(packing(&["--sample-size", "2"]).unwrap().unwrap().valid_bits, 16);
+		assert_eq!(packing(&["--sample-size", "4", "--valid-bits", "32"]).unwrap().unwrap().valid_bits, 32);
+
+		for size in ["0", "5", "1.5", "-2", "two"] {
+			let sample_size = format!("--sample-size={}", size);
+			match packing(&[&sample_size]) {
+				Err(Error::MalformedSampleSize { value }) => assert_eq!(value, size),
+				other => panic!("expected {} to be refused, got {:?}", size, other)
+			}
+		}
+
+		/* A stride of two bytes holds up to 16 bits. */
+		for bits in ["0", "17", "24", "-1", "12.5"] {
+			let valid_bits = format!("--valid-bits={}", bits);
+			match packing(&["--sample-size", "2", &valid_bits]) {
+				Err(Error::MalformedValidBits { value, size: 2 }) => assert_eq!(value, bits),
+				other => panic!("expected {} to be refused, got {:?}", bits, other)
+			}
+		}
+	}
+
+	#[test]
+	fn spins_are_whole_microseconds_up_to_a_cap() {
+		let spin = |value: &str| null_playback(&[&format!("--spin-us={}", value)]).map(|args| args.spin());
+
+		assert_eq!(spin("0").unwrap(), None);
+		assert_eq!(spin("1").unwrap(), Some(Duration::from_micros(1)));
+		assert_eq!(spin("250").unwrap(), Some(Duration::from_micros(250)));
+		assert_eq!(spin(&MAX_SPIN_US.to_string()).unwrap(), Some(Duration::from_micros(MAX_SPIN_US)));
+		assert_eq!(null_playback(&[]).unwrap().spin(), None);
+
+		for value in ["", "-5", "1.5", "10ms", "lots", "99999999999999999999"] {
+			assert!(matches!(spin(value), Err(Error::MalformedSpin(_))), "{:?} was taken for a spin", value);
+		}
+		assert!(matches!(spin(&(MAX_SPIN_US + 1).to_string()), Err(Error::InvalidSpin { spin }) if spin == MAX_SPIN_US + 1));
+	}
+}
@@ -0,0 +1,402 @@
+use crate::arg::{Endianness, InputFormat};
+use crate::detect::{Seeker, Skip, Span};
+use crate::error::Error;
+use crate::src::Source;
+use std::io::{ErrorKind, Read};
+
+/** Size of the fixed part of the header, magic number included. */
+const HEADER: u32 = 24;
+/** Maximum length of the annotation field we're willing to skip over. */
+const MAX_ANNOTATION: u32 = 16 * 1024 * 1024;
+/** Data size used by files that don't know how long their data is. */
+const UNKNOWN_SIZE: u32 = u32::MAX;
+
+/** Encoding of 8-bit G.711 µ-law samples. */
+const ENCODING_MULAW: u32 = 1;
+/** Encoding of 8-bit linear samples. */
+const ENCODING_LINEAR_8: u32 = 2;
+/** Encoding of 16-bit linear samples. */
+const ENCODING_LINEAR_16: u32 = 3;
+/** Encoding of 24-bit linear samples. */
+const ENCODING_LINEAR_24: u32 = 4;
+/** Encoding of 32-bit linear samples. */
+const ENCODING_LINEAR_32: u32 = 5;
+/** Encoding of single precision floating point samples. */
+const ENCODING_FLOAT: u32 = 6;
+/** Encoding of double precision floating point samples. */
+const ENCODING_DOUBLE: u32 = 7;
+/** Encoding of 8-bit G.711 A-law samples. */
+const ENCODING_ALAW: u32 = 27;
+
+/** Format of the data in an AU file, as described by its header. */
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct AuFormat {
+	/** How the samples are encoded. */
+	pub encoding: u32,
+	/** Number of frames per second. */
+	pub sample_rate: u32,
+	/** Number of interleaved channels. */
+	pub channels: u32,
+}
+impl AuFormat {
+	/** Description of this format for the purposes of playback, if it's one we
+	 * know how to play. */
+	pub fn input_format(&self) -> Result<InputFormat, Error> {
+		let sample_format = match self.encoding {
+			/* Eight bit samples get expanded to 16 bits as they're read. */
+			ENCODING_MULAW
+				| ENCODING_ALAW
+				| ENCODING_LINEAR_8
+				| ENCODING_LINEAR_16 => cpal::SampleFormat::I16,
+			ENCODING_FLOAT => cpal::SampleFormat::F32,
+			ENCODING_LINEAR_24 | ENCODING_LINEAR_32 | ENCODING_DOUBLE =>
+				return Err(Error::UnsupportedAu(
+					format!("{} samples are not supported", encoding_name(self.encoding)))),
+			encoding =>
+				return Err(Error::UnsupportedAu(
+					format!("unknown encoding {}", encoding)))
+		};
+
+		if self.channels == 0 || self.channels > u32::from(u16::MAX) {
+			return Err(Error::MalformedAu(
+				format!("{} channels is not a valid channel count", self.channels)))
+		}
+		if self.sample_rate == 0 {
+			return Err(Error::MalformedAu("the sample rate is zero".to_owned()))
+		}
+
+		Ok(InputFormat {
+			channels: self.channels as u16,
+			sample_rate: self.sample_rate,
+			sample_format,
+			endian: Endianness::Big
+		})
+	}
+}
+
+/** Human readable name of the given encoding. */
+fn encoding_name(encoding: u32) -> &'static str {
+	match encoding {
+		ENCODING_MULAW => "µ-law",
+		ENCODING_LINEAR_8 => "8 bit linear",
+		ENCODING_LINEAR_16 => "16 bit linear",
+		ENCODING_LINEAR_24 => "24 bit linear",
+		ENCODING_LINEAR_32 => "32 bit linear",
+		ENCODING_FLOAT => "floating point",
+		ENCODING_DOUBLE => "double precision floating point",
+		ENCODING_ALAW => "A-law",
+		_ => "unknown"
+	}
+}
+
+/** Expands a G.711 µ-law sample into a 16-bit linear sample. */
+pub fn mulaw(sample: u8) -> i16 {
+	/* Samples are stored with all of their bits inverted. */
+	let sample = !sample;
+	let exponent = (sample >> 4) & 0x07;
+	let mantissa = sample & 0x0f;
+
+	let magnitude = ((i16::from(mantissa) << 3) + 0x84) << exponent;
+	if sample & 0x80 != 0 {
+		0x84 - magnitude
+	} else {
+		magnitude - 0x84
+	}
+}
+
+/** Expands a G.711 A-law sample into a 16-bit linear sample. */
+pub fn alaw(sample: u8) -> i16 {
+	/* Samples are stored with every other bit inverted. */
+	let sample = sample ^ 0x55;
+	let exponent = (sample >> 4) & 0x07;
+	let mantissa = i16::from(sample & 0x0f);
+
+	let magnitude = if exponent == 0 {
+		(mantissa << 4) + 8
+	} else {
+		((mantissa << 4) + 0x108) << (exponent - 1)
+	};
+
+	/* Unlike in µ-law, a set sign bit means the sample is positive. */
+	if sample & 0x80 != 0 {
+		magnitude
+	} else {
+		-magnitude
+	}
+}
+
+/** Expands a signed 8-bit linear sample into a 16-bit linear sample. */
+fn linear_8(sample: u8) -> i16 {
+	i16::from(sample as i8) << 8
+}
+
+/** A reader that expands 8-bit samples into big endian, 16-bit samples using a
+ * lookup table. */
+struct Expand<R> {
+	source: R,
+	/** Expanded value of every possible sample. */
+	table: [i16; 256],
+	/** Expanded samples that have yet to be handed out. */
+	output: Vec<u8>,
+	/** How much of the expanded samples has been handed out already. */
+	offset: usize,
+}
+impl<R> Expand<R> {
+	/** Creates a new reader expanding samples with the given function. */
+	fn new(source: R, expand: fn(u8) -> i16) -> Self {
+		let mut table = [0; 256];
+		for (i, value) in table.iter_mut().enumerate() {
+			*value = expand(i as u8);
+		}
+
+		Self {
+			source,
+			table,
+			output: Vec::new(),
+			offset: 0
+		}
+	}
+}
+impl<R> Read for Expand<R>
+	where R: Read {
+
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		if buf.is_empty() { return Ok(0) }
+		if self.offset == self.output.len() {
+			self.output.clear();
+			self.offset = 0;
+
+			/* Read about as many samples as the caller wants. */
+			let mut chunk = vec![0; usize::max(buf.len() / 2, 1)];
+			let read = self.source.read(&mut chunk)?;
+			if read == 0 { return Ok(0) }
+
+			for sample in &chunk[..read] {
+				self.output.extend(&self.table[usize::from(*sample)].to_be_bytes());
+			}
+		}
+
+		let len = usize::min(buf.len(), self.output.len() - self.offset);
+		buf[..len].copy_from_slice(&self.output[self.offset..self.offset + len]);
+		self.offset += len;
+
+		Ok(len)
+	}
+}
+impl<R> Source for Expand<R>
+	where R: Source {
+
+	fn rewind(&mut self) -> std::io::Result<bool> {
+		if !self.source.rewind()? { return Ok(false) }
+
+		self.output.clear();
+		self.offset = 0;
+
+		Ok(true)
+	}
+}
+
+/** Opens the AU file in the given source, whose magic number has already been
+ * read, along with the data offset and data size fields that follow it, which
+ * are passed in. Data we're not interested in is skipped over with the given
+ * function. Returns a reader over the samples in the file, along with their
+ * format. */
+pub fn open<R>(mut source: R, skip: Skip<R>, seek: Option<Seeker<R>>, fields: [u8; 8])
+	-> Result<(Box<dyn Source>, InputFormat), Error>
+	where R: Read + Send + 'static {
+
+	let offset = u32::from_be_bytes([fields[0], fields[1], fields[2], fields[3]]);
+	let size = u32::from_be_bytes([fields[4], fields[5], fields[6], fields[7]]);
+
+	let mut header = [0; 12];
+	source.read_exact(&mut header)
+		.map_err(|what| if what.kind() == ErrorKind::UnexpectedEof {
+			Error::MalformedAu("the file ends within the header".to_owned())
+		} else {
+			Error::InputError(what)
+		})?;
+
+	let format = AuFormat {
+		encoding: u32::from_be_bytes([header[0], header[1], header[2], header[3]]),
+		sample_rate: u32::from_be_bytes([header[4], header[5], header[6], header[7]]),
+		channels: u32::from_be_bytes([header[8], header[9], header[10], header[11]])
+	};
+	let input = format.input_format()?;
+
+	/* The header is followed by a free form annotation, which runs up to the
+	 * start of the data. */
+	if offset < HEADER {
+		return Err(Error::MalformedAu(
+			format!("the data offset ({}) is within the header", offset)))
+	}
+	if offset - HEADER > MAX_ANNOTATION {
+		return Err(Error::MalformedAu(
+			format!("the annotation is implausibly long ({} bytes)", offset - HEADER)))
+	}
+	skip(&mut source, u64::from(offset - HEADER))
+		.map_err(|what| if what.kind() == ErrorKind::UnexpectedEof {
+			Error::MalformedAu("the file ends within the annotation".to_owned())
+		} else {
+			Error::InputError(what)
+		})?;
+
+	/* Streams don't know how long their data is going to be. Just read those
+	 * until the end. */
+	let length = if size == UNKNOWN_SIZE { u64::MAX } else { u64::from(size) };
+	let data = Span::new(source, length, seek).map_err(Error::InputError)?;
+
+	Ok(match format.encoding {
+		ENCODING_MULAW => (Box::new(Expand::new(data, mulaw)), input),
+		ENCODING_ALAW => (Box::new(Expand::new(data, alaw)), input),
+		ENCODING_LINEAR_8 => (Box::new(Expand::new(data, linear_8)), input),
+		_ => (Box::new(data), input)
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::{Cursor, Seek, SeekFrom};
+
+	/** Compresses a 16-bit linear sample into G.711 µ-law, the way the
+	 * reference implementation does it. */
+	fn compress_mulaw(sample: i16) -> u8 {
+		let (sign, magnitude) = if sample < 0 {
+			(0x80, -i32::from(sample))
+		} else {
+			(0x00, i32::from(sample))
+		};
+		let magnitude = i32::min(magnitude, 32635) + 0x84;
+
+		let exponent = (0..8).rev()
+			.find(|exponent| magnitude & (0x80 << exponent) != 0)
+			.unwrap_or(0);
+		let mantissa = (magnitude >> (exponent + 3)) & 0x0f;
+		!(sign | (exponent << 4) as u8 | mantissa as u8)
+	}
+
+	/** Compresses a 16-bit linear sample into G.711 A-law, the way the
+	 * reference implementation does it. */
+	fn compress_alaw(sample: i16) -> u8 {
+		let sample = i32::from(sample) >> 3;
+		let (mask, magnitude) = if sample >= 0 {
+			(0xd5, sample)
+		} else {
+			(0x55, -sample - 1)
+		};
+
+		let segment = (0..8u8)
+			.find(|segment| magnitude <= (0x1f << segment) | ((1 << segment) - 1))
+			.unwrap_or(8);
+		if segment == 8 { return 0x7f ^ mask }
+
+		let shift = if segment < 2 { 1 } else { segment };
+		let mantissa = ((magnitude >> shift) & 0x0f) as u8;
+		((segment << 4) | mantissa) ^ mask
+	}
+
+	#[test]
+	fn mulaw_round_trips() {
+		for code in 0..=255u8 {
+			/* Negative zero comes back as positive zero. */
+			let expected = if code == 0x7f { 0xff } else { code };
+			assert_eq!(compress_mulaw(mulaw(code)), expected, "code {:#x}", code);
+		}
+		assert_eq!(mulaw(0xff), 0);
+		assert_eq!(mulaw(0x80), 32124);
+		assert_eq!(mulaw(0x00), -32124);
+	}
+
+	#[test]
+	fn alaw_round_trips() {
+		for code in 0..=255u8 {
+			assert_eq!(compress_alaw(alaw(code)), code, "code {:#x}", code);
+		}
+		assert_eq!(alaw(0xd5), 8);
+		assert_eq!(alaw(0x55), -8);
+		assert_eq!(alaw(0xaa), 32256);
+		assert_eq!(alaw(0x2a), -32256);
+	}
+
+	fn skip(source: &mut Cursor<Vec<u8>>, count: u64) -> std::io::Result<()> {
+		source.seek(SeekFrom::Current(count as i64)).map(|_| ())
+	}
+
+	/** Opens an AU file with the given header fields, annotation and data,
+	 * past its magic number. */
+	fn open_file(encoding: u32, channels: u32, annotation: &[u8], size: u32, data: &[u8])
+		-> Result<(Box<dyn Source>, InputFormat), Error> {
+
+		let offset = HEADER + annotation.len() as u32;
+		let mut fields = [0; 8];
+		fields[..4].copy_from_slice(&offset.to_be_bytes());
+		fields[4..].copy_from_slice(&size.to_be_bytes());
+
+		let mut file = vec![0; 12];
+		file.extend(&encoding.to_be_bytes());
+		file.extend(&8000u32.to_be_bytes());
+		file.extend(&channels.to_be_bytes());
+		file.extend(annotation);
+		file.extend(data);
+		let mut source = Cursor::new(file);
+		source.set_position(12);
+
+		open(source, skip, Some(<Cursor<Vec<u8>> as Seek>::seek), fields)
+	}
+
+	fn read_all(mut reader: Box<dyn Source>) -> Vec<u8> {
+		let mut data = Vec::new();
+		reader.read_to_end(&mut data).unwrap();
+		data
+	}
+
+	#[test]
+	fn linear_samples_are_big_endian() {
+		let (reader, format) = open_file(ENCODING_LINEAR_16, 2, b"note", 4, b"abcdef").unwrap();
+		assert_eq!(format, InputFormat {
+			channels: 2,
+			sample_rate: 8000,
+			sample_format: cpal::SampleFormat::I16,
+			endian: Endianness::Big
+		});
+		assert_eq!(read_all(reader), b"abcd");
+	}
+
+	#[test]
+	fn companded_samples_get_expanded() {
+		let (reader, format) = open_file(ENCODING_MULAW, 1, b"", 2, &[0x80, 0x00]).unwrap();
+		assert_eq!(format.sample_format, cpal::SampleFormat::I16);
+
+		let mut expected = 32124i16.to_be_bytes().to_vec();
+		expected.extend(&(-32124i16).to_be_bytes());
+		assert_eq!(read_all(reader), expected);
+	}
+
+	#[test]
+	fn unknown_sizes_are_read_to_the_end() {
+		let (reader, _) = open_file(ENCODING_LINEAR_16, 1, b"", UNKNOWN_SIZE, b"abcdef").unwrap();
+		assert_eq!(reader.len_hint(), None);
+		assert_eq!(read_all(reader), b"abcdef");
+	}
+
+	#[test]
+	fn malformed_files_are_rejected() {
+		assert!(matches!(
+			open_file(ENCODING_LINEAR_24, 1, b"", 3, b"abc").err().unwrap(),
+			Error::UnsupportedAu(_)));
+		assert!(matches!(
+			open_file(ENCODING_LINEAR_16, 0, b"", 2, b"ab").err().unwrap(),
+			Error::MalformedAu(_)));
+
+		/* Data can't start within the header. */
+		let mut fields = [0; 8];
+		fields[..4].copy_from_slice(&16u32.to_be_bytes());
+		let mut file = vec![0; 12];
+		file.extend(&ENCODING_LINEAR_16.to_be_bytes());
+		file.extend(&8000u32.to_be_bytes());
+		file.extend(&1u32.to_be_bytes());
+		let mut source = Cursor::new(file);
+		source.set_position(12);
+		assert!(matches!(open(source, skip, None, fields).err().unwrap(), Error::MalformedAu(_)));
+	}
+}
@@ -0,0 +1,228 @@
+use crate::arg::InputFormat;
+use crate::convert::{self, Dither, Quantizer};
+use std::io::{ErrorKind, Read};
+use std::time::{Duration, Instant};
+
+/** Number of frames every stage gets asked for at a time, which is as many as
+ * the null output asks the callback for. */
+const BUFFER_FRAMES: usize = crate::play::NULL_BUFFER_FRAMES;
+
+/** Number of frames of generated samples the stages get fed, over and over. */
+const GENERATED_FRAMES: usize = 48000;
+
+/** Size of the buffer the skipper gets measured with, which is the one it gets
+ * played with. */
+const SKIPPER_CAPACITY: usize = 16 * 1024 * 1024;
+
+/** A stage of the pipeline samples go through on their way to the device,
+ * which can be measured on its own. */
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Stage {
+	/** The buffer external sync reads the input through. */
+	Skipper,
+	/** The conversion into the format of the device. */
+	Conversion,
+	/** The remapping of the channels of the input onto those of the device. */
+	Remap,
+}
+impl Stage {
+	/** Every stage, in the order they get measured in. */
+	pub const ALL: &'static [Stage] = &[Self::Skipper, Self::Conversion, Self::Remap];
+
+	/** Name the stage goes by on the command line and in the output. */
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Skipper => "skipper",
+			Self::Conversion => "conversion",
+			Self::Remap => "remap"
+		}
+	}
+
+	/** The stage going by the given name, if any. */
+	pub fn from_name(name: &str) -> Option<Self> {
+		Self::ALL.iter().copied().find(|stage| stage.name() == name)
+	}
+}
+
+/** How fast a stage went. */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Measurement {
+	pub stage: Stage,
+	/** Number of bytes the stage handed out. */
+	pub bytes: u64,
+	/** How long the stage was kept busy for. */
+	pub elapsed: Duration,
+	/** How long every buffer took to be filled in, from quickest to slowest. */
+	pub latencies: Vec<Duration>,
+}
+impl Measurement {
+	/** Number of megabytes handed out every second. */
+	pub fn throughput(&self) -> f64 {
+		self.bytes as f64 / 1e6 / self.elapsed.as_secs_f64()
+	}
+
+	/** How long the given fraction of the buffers took to be filled in, at
+	 * most. */
+	pub fn percentile(&self, fraction: f64) -> Duration {
+		if self.latencies.is_empty() { return Duration::from_secs(0) }
+
+		let index = (fraction * (self.latencies.len() - 1) as f64).round() as usize;
+		self.latencies[index]
+	}
+}
+
+/** A source going over the same samples of a sine, in the given format, over
+ * and over, without ever running out. */
+struct Generated {
+	data: Vec<u8>,
+	position: usize,
+}
+impl Generated {
+	fn new(format: InputFormat) -> Self {
+		let mut quantizer = Quantizer::new(Dither::None);
+		let size = format.sample_format.sample_size();
+		let mut data = vec![0; GENERATED_FRAMES * usize::from(format.channels) * size];
+		for (i, sample) in data.chunks_exact_mut(size).enumerate() {
+			let value = (i as f32 * 0.01).sin() * 0.5;
+			convert::encode(&mut quantizer, format.sample_format, format.endian, value, sample);
+		}
+
+		Self {
+			data,
+			position: 0
+		}
+	}
+}
+impl Read for Generated {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		let len = usize::min(buf.len(), self.data.len() - self.position);
+		buf[..len].copy_from_slice(&self.data[self.position..self.position + len]);
+		self.position = (self.position + len) % self.data.len();
+
+		Ok(len)
+	}
+}
+
+/** Reads from the given source until the given buffer is full. */
+fn fill<R>(source: &mut R, buffer: &mut [u8]) -> std::io::Result<usize>
+	where R: Read {
+
+	let mut filled = 0;
+	while filled < buffer.len() {
+		match source.read(&mut buffer[filled..]) {
+			Ok(0) => break,
+			Ok(read) => filled += read,
+			Err(what) if what.kind() == ErrorKind::Interrupted => continue,
+			Err(what) => return Err(what)
+		}
+	}
+
+	Ok(filled)
+}
+
+/** Keeps asking the given stage to fill buffers in for the given duration,
+ * timing every one of them. */
+fn time<F>(stage: Stage, duration: Duration, mut step: F) -> std::io::Result<Measurement>
+	where F: FnMut() -> std::io::Result<usize> {
+
+	let mut bytes = 0;
+	let mut latencies = Vec::new();
+	let start = Instant::now();
+	while start.elapsed() < duration {
+		let entered = Instant::now();
+		bytes += step()? as u64;
+		latencies.push(entered.elapsed());
+	}
+	let elapsed = start.elapsed();
+	latencies.sort();
+
+	Ok(Measurement {
+		stage,
+		bytes,
+		elapsed,
+		latencies
+	})
+}
+
+/** Measures the given stage for the given duration, feeding it generated
+ * samples in the given format, and converting them into the given one, for
+ * the stages that convert them. Stages that remap the channels map them onto
+ * stereo, downmixing surround just like playback would. */
+pub fn measure(
+	stage: Stage,
+	format: InputFormat,
+	output: cpal::SampleFormat,
+	duration: Duration) -> std::io::Result<Measurement> {
+
+	let source = Generated::new(format);
+	let frame = format.sample_format.sample_size() * usize::from(format.channels);
+	match stage {
+		Stage::Skipper => {
+			let mut skipper = crate::src::Skipper::new_with_capacity(source, SKIPPER_CAPACITY);
+			let mut buffer = vec![0; BUFFER_FRAMES * frame];
+			time(stage, duration, || fill(&mut skipper, &mut buffer))
+		},
+		Stage::Conversion => {
+			let mut source = source;
+			let mut conversion = crate::play::Conversion::with(
+				format.sample_format,
+				format.endian,
+				format.channels);
+			let frame = output.sample_size() * usize::from(format.channels);
+			let mut buffer = vec![0; BUFFER_FRAMES * frame];
+			time(stage, duration, || conversion
+				.fill(&mut source, output, &mut buffer)
+				.map(|frames| frames * frame))
+		},
+		Stage::Remap => {
+			let remap = crate::src::Remap::new(
+				source,
+				format.channels,
+				2,
+				format.sample_format,
+				format.endian);
+			let mut remap = match crate::mix::Matrix::surround(format.channels, 2, false) {
+				Some(matrix) => remap.downmix(matrix),
+				None => remap
+			};
+			let mut buffer = vec![0; BUFFER_FRAMES * format.sample_format.sample_size() * 2];
+			time(stage, duration, || fill(&mut remap, &mut buffer))
+		}
+	}
+}
+
+/** Prints the given measurements to the standard output, either as a table or
+ * as a JSON array. Latencies are in microseconds. */
+pub fn print(measurements: &[Measurement], json: bool) {
+	let micros = |duration: Duration| duration.as_secs_f64() * 1e6;
+
+	if json {
+		let entries = measurements.iter()
+			.map(|measurement| format!(
+				"{{\"stage\":\"{}\",\"bytes\":{},\"seconds\":{:.6},\"mb_per_s\":{:.3},\"buffers\":{},\"p50_us\":{:.3},\"p99_us\":{:.3},\"max_us\":{:.3}}}",
+				measurement.stage.name(),
+				measurement.bytes,
+				measurement.elapsed.as_secs_f64(),
+				measurement.throughput(),
+				measurement.latencies.len(),
+				micros(measurement.percentile(0.5)),
+				micros(measurement.percentile(0.99)),
+				micros(measurement.percentile(1.0))))
+			.collect::<Vec<_>>();
+
+		println!("[{}]", entries.join(","));
+		return
+	}
+
+	println!("{:<12} {:>12} {:>10} {:>10} {:>10} {:>10}",
+		"stage", "MB/s", "buffers", "p50 us", "p99 us", "max us");
+	for measurement in measurements {
+		println!("{:<12} {:>12.3} {:>10} {:>10.3} {:>10.3} {:>10.3}",
+			measurement.stage.name(),
+			measurement.throughput(),
+			measurement.latencies.len(),
+			micros(measurement.percentile(0.5)),
+			micros(measurement.percentile(0.99)),
+			micros(measurement.percentile(1.0)));
+	}
+}
@@ -0,0 +1,289 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/** How long before the deadline we stop sleeping and start spinning instead,
+ * since the scheduler may well oversleep by about this much. */
+const SPIN_WINDOW: Duration = Duration::from_millis(5);
+
+/** Parses a wall-clock time, given either as a number of seconds since the
+ * Unix epoch, possibly fractional, or as an RFC 3339 timestamp. */
+pub fn parse(value: &str) -> Option<SystemTime> {
+	let value = value.trim();
+	if value.bytes().all(|c| c.is_ascii_digit() || c == b'.') {
+		let secs = value.parse::<f64>().ok()?;
+		return UNIX_EPOCH.checked_add(Duration::try_from_secs_f64(secs).ok()?)
+	}
+
+	rfc3339(value)
+}
+
+/** Parses an offset into the input, given as `[[HH:]MM:]SS[.fraction]`, where
+ * every field but the first may only go up to 59. */
+pub fn parse_offset(value: &str) -> Option<Duration> {
+	let fields = value.trim().split(':').collect::<Vec<_>>();
+	if fields.is_empty() || fields.len() > 3 { return None }
+
+	let (seconds, whole) = fields.split_last()?;
+	if seconds.is_empty() || !seconds.bytes().all(|c| c.is_ascii_digit() || c == b'.') {
+		return None
+	}
+	let seconds = seconds.parse::<f64>().ok().filter(|seconds| seconds.is_finite())?;
+	if !whole.is_empty() && seconds >= 60.0 { return None }
+
+	let mut total = 0u64;
+	for (i, field) in whole.iter().enumerate() {
+		if field.is_empty() || !field.bytes().all(|c| c.is_ascii_digit()) { return None }
+		let field = field.parse::<u64>().ok()?;
+		if i > 0 && field > 59 { return None }
+
+		total = total.checked_mul(60)?.checked_add(field)?;
+	}
+
+	let seconds = Duration::try_from_secs_f64(seconds).ok()?;
+	Duration::from_secs(total.checked_mul(60)?).checked_add(seconds)
+}
+
+/** Parses an RFC 3339 timestamp, in the form of
+ * `YYYY-MM-DDTHH:MM:SS[.fraction](Z|+HH:MM|-HH:MM)`. */
+fn rfc3339(value: &str) -> Option<SystemTime> {
+	let bytes = value.as_bytes();
+	if bytes.len() < 20 { return None }
+
+	let number = |range: std::ops::Range<usize>| -> Option<i64> {
+		let digits = value.get(range)?;
+		if !digits.bytes().all(|c| c.is_ascii_digit()) { return None }
+		digits.parse().ok()
+	};
+	let separators = [(4, b'-'), (7, b'-'), (13, b':'), (16, b':')];
+	if separators.iter().any(|(i, c)| bytes[*i] != *c) { return None }
+	if !matches!(bytes[10], b'T' | b't' | b' ') { return None }
+
+	let year = number(0..4)?;
+	let month = number(5..7)?;
+	let day = number(8..10)?;
+	let hour = number(11..13)?;
+	let minute = number(14..16)?;
+	let second = number(17..19)?;
+	if !(1..=12).contains(&month) || !(1..=31).contains(&day)
+		|| hour > 23 || minute > 59 || second > 60 {
+		return None
+	}
+
+	/* Fractional seconds, if any. */
+	let mut rest = &value[19..];
+	let mut nanos = 0u32;
+	if let Some(fraction) = rest.strip_prefix('.') {
+		let len = fraction.bytes().take_while(|c| c.is_ascii_digit()).count();
+		if len == 0 { return None }
+
+		for (i, digit) in fraction.bytes().take(len).enumerate() {
+			if i < 9 {
+				nanos += u32::from(digit - b'0') * 10u32.pow(8 - i as u32);
+			}
+		}
+		rest = &fraction[len..];
+	}
+
+	let offset = match rest {
+		"Z" | "z" => 0,
+		_ => {
+			let sign = match rest.as_bytes().first()? {
+				b'+' => 1,
+				b'-' => -1,
+				_ => return None
+			};
+			if rest.len() != 6 || rest.as_bytes()[3] != b':' { return None }
+
+			let parse = |digits: &str| -> Option<i64> {
+				if !digits.bytes().all(|c| c.is_ascii_digit()) { return None }
+				digits.parse().ok()
+			};
+			sign * (parse(&rest[1..3])? * 3600 + parse(&rest[4..6])? * 60)
+		}
+	};
+
+	let secs = days_from_civil(year, month, day) * 86400
+		+ hour * 3600
+		+ minute * 60
+		+ second
+		- offset;
+	if secs < 0 { return None }
+
+	UNIX_EPOCH.checked_add(Duration::new(secs as u64, nanos))
+}
+
+/** Number of days between the Unix epoch and the given date in the proleptic
+ * Gregorian calendar. */
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+	/* Shift the year so that it starts in March, which puts the leap day at
+	 * its very end. */
+	let year = if month <= 2 { year - 1 } else { year };
+	let era = year.div_euclid(400);
+	let year_of_era = year - era * 400;
+	let month = (month + 9) % 12;
+	let day_of_year = (153 * month + 2) / 5 + day - 1;
+	let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+	era * 146097 + day_of_era - 719468
+}
+
+/** Date in the proleptic Gregorian calendar that lies the given number of days
+ * after the Unix epoch, as its year, month and day. */
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+	/* Same as above, in reverse. */
+	let days = days + 719468;
+	let era = days.div_euclid(146097);
+	let day_of_era = days - era * 146097;
+	let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+	let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	let month = (5 * day_of_year + 2) / 153;
+	let day = day_of_year - (153 * month + 2) / 5 + 1;
+	let month = if month < 10 { month + 3 } else { month - 9 };
+	let year = year_of_era + era * 400;
+
+	(if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+/** Formats the given time as an RFC 3339 timestamp in UTC, down to the
+ * millisecond. */
+pub fn format(time: SystemTime) -> String {
+	let since = time.duration_since(UNIX_EPOCH).unwrap_or(Duration::from_secs(0));
+	let secs = since.as_secs() as i64;
+	let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+	let secs = secs.rem_euclid(86400);
+
+	format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+		year,
+		month,
+		day,
+		secs / 3600,
+		secs / 60 % 60,
+		secs % 60,
+		since.subsec_millis())
+}
+
+/** Blocks the current thread until the system clock reaches the given time.
+ * Most of the wait is spent sleeping, but the last few milliseconds are spun
+ * through, so that we wake up as close to the deadline as we can.
+ *
+ * This is only ever as good as the system clock. Machines that are meant to
+ * start together have to agree on the time to within the precision that's
+ * wanted, by way of NTP or PTP, say, or the skew between their clocks will show
+ * up as-is in their playback. The clock stepping while we wait will also move
+ * the wake up time along with it. */
+pub fn wait_until(deadline: SystemTime) {
+	loop {
+		let left = match deadline.duration_since(SystemTime::now()) {
+			Ok(left) => left,
+			Err(_) => return
+		};
+
+		if left > SPIN_WINDOW {
+			std::thread::sleep(left - SPIN_WINDOW);
+		} else {
+			std::thread::yield_now();
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn since_epoch(time: SystemTime) -> Duration {
+		time.duration_since(UNIX_EPOCH).unwrap()
+	}
+
+	#[test]
+	fn seconds_since_the_epoch_get_parsed() {
+		assert_eq!(since_epoch(parse("0").unwrap()), Duration::from_secs(0));
+		assert_eq!(since_epoch(parse(" 1700000000 ").unwrap()), Duration::from_secs(1700000000));
+		assert_eq!(since_epoch(parse("1.5").unwrap()), Duration::from_millis(1500));
+
+		assert!(parse("").is_none());
+		assert!(parse("1.2.3").is_none());
+		assert!(parse("-1").is_none());
+	}
+
+	#[test]
+	fn seconds_too_far_out_are_refused_rather_than_panicking() {
+		assert!(parse("99999999999999999999999").is_none());
+		assert!(parse(&"9".repeat(400)).is_none());
+	}
+
+	#[test]
+	fn offsets_get_parsed_field_by_field() {
+		assert_eq!(parse_offset("90"), Some(Duration::from_secs(90)));
+		assert_eq!(parse_offset("1.5"), Some(Duration::from_millis(1500)));
+		assert_eq!(parse_offset("2:03"), Some(Duration::from_secs(123)));
+		assert_eq!(parse_offset("90:00"), Some(Duration::from_secs(5400)));
+		assert_eq!(parse_offset("1:02:03.25"), Some(Duration::from_millis(3723250)));
+		assert_eq!(parse_offset(" 0:00:59.999 "), Some(Duration::from_millis(59999)));
+
+		for value in ["", ":", "1:", ":30", "1:2:3:4", "1:-2", "1e3", "a:00", "1.5:00"] {
+			assert!(parse_offset(value).is_none(), "{}", value);
+		}
+	}
+
+	#[test]
+	fn offset_fields_after_the_first_stop_at_59() {
+		assert!(parse_offset("1:60").is_none());
+		assert!(parse_offset("1:59.9999").is_some());
+		assert!(parse_offset("1:60:00").is_none());
+		assert!(parse_offset("100:59:59").is_some());
+	}
+
+	#[test]
+	fn offsets_too_far_out_are_refused_rather_than_panicking() {
+		assert!(parse_offset("99999999999999999999999").is_none());
+		assert!(parse_offset(&"9".repeat(400)).is_none());
+		assert!(parse_offset("99999999999999999999:00").is_none());
+
+		/* Whole minutes that only just fit, plus seconds that push them
+		 * past what a Duration holds. */
+		let minutes = u64::MAX / 60;
+		assert!(parse_offset(&format!("{}:00", minutes)).is_some());
+		assert!(parse_offset(&format!("{}:59", minutes)).is_none());
+	}
+
+	#[test]
+	fn rfc3339_timestamps_get_parsed() {
+		let parsed = |value| since_epoch(rfc3339(value).unwrap());
+
+		assert_eq!(parsed("1970-01-01T00:00:00Z"), Duration::from_secs(0));
+		assert_eq!(parsed("2023-11-14T22:13:20Z"), Duration::from_secs(1700000000));
+		assert_eq!(parsed("2023-11-14t22:13:20.25z"), Duration::from_millis(1700000000250));
+		assert_eq!(parsed("2023-11-14 22:13:20.123456789123Z"), Duration::new(1700000000, 123456789));
+		assert_eq!(parsed("2023-11-15T00:13:20+02:00"), Duration::from_secs(1700000000));
+		assert_eq!(parsed("2023-11-14T21:13:20-01:00"), Duration::from_secs(1700000000));
+		assert_eq!(parsed("2024-02-29T00:00:00Z"), Duration::from_secs(1709164800));
+
+		/* Timestamps parse the same through the general parser. */
+		assert_eq!(parse("2023-11-14T22:13:20Z"), rfc3339("2023-11-14T22:13:20Z"));
+	}
+
+	#[test]
+	fn malformed_rfc3339_timestamps_are_refused() {
+		for value in [
+			"2023-11-14T22:13:20",
+			"2023-11-14T22:13:20+0200",
+			"2023-11-14X22:13:20Z",
+			"2023/11/14T22:13:20Z",
+			"2023-13-14T22:13:20Z",
+			"2023-11-00T22:13:20Z",
+			"2023-11-14T24:13:20Z",
+			"2023-11-14T22:60:20Z",
+			"2023-11-14T22:13:20.Z",
+			"2023-11-14T22:13:20Zulu",
+			"1969-12-31T23:59:59Z",
+		] {
+			assert!(rfc3339(value).is_none(), "{}", value);
+		}
+	}
+
+	#[test]
+	fn times_get_formatted_back_the_way_they_were_parsed() {
+		let time = parse("2023-11-14T22:13:20.250Z").unwrap();
+		assert_eq!(format(time), "2023-11-14T22:13:20.250Z");
+		assert_eq!(format(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+	}
+}
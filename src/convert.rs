@@ -0,0 +1,744 @@
+use crate::arg::Endianness;
+use cpal::SampleFormat;
+
+/** Dithering applied to a sample right before it gets quantized. */
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Dither {
+	/** Samples get quantized as they are. */
+	None,
+	/** Uniform noise with an amplitude of one LSB. */
+	Rectangular,
+	/** Triangular noise with an amplitude of two LSBs. This decorrelates the
+	 * quantization error from the signal completely, at the cost of a little
+	 * more noise than the rectangular variant. */
+	Triangular,
+}
+
+/** Quantizes normalized floating point samples into integer samples.
+ *
+ * This is the one place in the program where floating point samples get turned
+ * into integer ones, so that all of the features writing integer samples share
+ * the same, well-behaved, rounding. Samples are rounded half to even, so that
+ * no bias is accumulated over repeated conversions, and saturate instead of
+ * wrapping around when they go past full scale. */
+pub struct Quantizer {
+	/** Dithering applied before rounding. */
+	dither: Dither,
+	/** State of the noise generator used for dithering. */
+	state: u32,
+}
+impl Quantizer {
+	/** Creates a new quantizer using the given dithering. */
+	pub fn new(dither: Dither) -> Self {
+		Self {
+			dither,
+			state: 0x9e37_79b9
+		}
+	}
+
+	/** Quantizes a normalized sample into a signed 16-bit sample. */
+	#[allow(clippy::wrong_self_convention)]
+	pub fn to_i16(&mut self, sample: f32) -> i16 {
+		self.quantize(sample, 32768.0, -32768.0, 32767.0) as i16
+	}
+
+	/** Quantizes a normalized sample into an unsigned 16-bit sample. */
+	#[allow(clippy::wrong_self_convention)]
+	pub fn to_u16(&mut self, sample: f32) -> u16 {
+		(i32::from(self.to_i16(sample)) + 32768) as u16
+	}
+
+	/** Scales the given sample by the given factor, applies dither to it,
+	 * rounds it and clamps it to the given range. */
+	fn quantize(&mut self, sample: f32, scale: f32, min: f32, max: f32) -> f32 {
+		/* Anything that isn't a number gets turned into silence, rather than
+		 * into whatever the cast would make of it. */
+		if sample.is_nan() { return 0.0 }
+
+		let sample = sample * scale + match self.dither {
+			Dither::None => 0.0,
+			Dither::Rectangular => self.noise() - 0.5,
+			Dither::Triangular => self.noise() - self.noise(),
+		};
+
+		let rounded = round_half_even(sample);
+		if rounded < min {
+			min
+		} else if rounded > max {
+			max
+		} else {
+			rounded
+		}
+	}
+
+	/** Produces uniform noise in the range `[0; 1)`. */
+	fn noise(&mut self) -> f32 {
+		/* A xorshift generator is more than good enough for dithering. */
+		self.state ^= self.state << 13;
+		self.state ^= self.state >> 17;
+		self.state ^= self.state << 5;
+
+		(self.state >> 8) as f32 / (1u32 << 24) as f32
+	}
+}
+
+/** Rounds a number to the closest integer, with ties going to the even one. */
+fn round_half_even(value: f32) -> f32 {
+	if (value - value.trunc()).abs() == 0.5 {
+		2.0 * (value / 2.0).round()
+	} else {
+		value.round()
+	}
+}
+
+/** Swaps the bytes of all of the samples in the given buffer from the given
+ * byte order into the native byte order. */
+pub fn to_native(format: SampleFormat, endian: Endianness, bytes: &mut [u8]) {
+	let foreign = match endian {
+		Endianness::Little => cfg!(target_endian = "big"),
+		Endianness::Big    => cfg!(target_endian = "little"),
+		Endianness::Native => false
+	};
+	if !foreign { return }
+
+	swap_bytes(format.sample_size(), bytes);
+}
+
+/** Reverses the byte order of every sample of the given size in the given
+ * buffer. Bytes past the last whole sample are left as they are. 16 and 32-bit
+ * samples get swapped in bulk, where the processor lets us. */
+pub fn swap_bytes(size: usize, bytes: &mut [u8]) {
+	#[cfg(target_arch = "x86_64")] {
+		if (size == 2 || size == 4) && is_x86_feature_detected!("ssse3") {
+			return unsafe { swap_bytes_ssse3(size, bytes) }
+		}
+	}
+	#[cfg(target_arch = "aarch64")] {
+		if size == 2 || size == 4 {
+			return unsafe { swap_bytes_neon(size, bytes) }
+		}
+	}
+
+	swap_bytes_scalar(size, bytes)
+}
+
+/** Same as [`swap_bytes`], one sample at a time. */
+fn swap_bytes_scalar(size: usize, bytes: &mut [u8]) {
+	match size {
+		2 => for sample in bytes.chunks_exact_mut(2) {
+			sample.swap(0, 1);
+		},
+		4 => for sample in bytes.chunks_exact_mut(4) {
+			sample.swap(0, 3);
+			sample.swap(1, 2);
+		},
+		size => for sample in bytes.chunks_exact_mut(size) {
+			sample.reverse();
+		}
+	}
+}
+
+/** Same as [`swap_bytes`], sixteen bytes at a time, for 16 and 32-bit
+ * samples only. */
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "ssse3")]
+unsafe fn swap_bytes_ssse3(size: usize, bytes: &mut [u8]) {
+	use std::arch::x86_64::*;
+
+	let mask = if size == 2 {
+		_mm_setr_epi8(1, 0, 3, 2, 5, 4, 7, 6, 9, 8, 11, 10, 13, 12, 15, 14)
+	} else {
+		_mm_setr_epi8(3, 2, 1, 0, 7, 6, 5, 4, 11, 10, 9, 8, 15, 14, 13, 12)
+	};
+
+	let mut chunks = bytes.chunks_exact_mut(16);
+	for chunk in &mut chunks {
+		let pointer = chunk.as_mut_ptr() as *mut __m128i;
+		_mm_storeu_si128(pointer, _mm_shuffle_epi8(_mm_loadu_si128(pointer), mask));
+	}
+
+	/* Chunks hold a whole number of samples, so the rest starts on one. */
+	swap_bytes_scalar(size, chunks.into_remainder());
+}
+
+/** Same as [`swap_bytes`], sixteen bytes at a time, for 16 and 32-bit
+ * samples only. */
+#[cfg(target_arch = "aarch64")]
+unsafe fn swap_bytes_neon(size: usize, bytes: &mut [u8]) {
+	use std::arch::aarch64::*;
+
+	let mut chunks = bytes.chunks_exact_mut(16);
+	for chunk in &mut chunks {
+		let value = vld1q_u8(chunk.as_ptr());
+		let value = if size == 2 { vrev16q_u8(value) } else { vrev32q_u8(value) };
+		vst1q_u8(chunk.as_mut_ptr(), value);
+	}
+
+	/* Chunks hold a whole number of samples, so the rest starts on one. */
+	swap_bytes_scalar(size, chunks.into_remainder());
+}
+
+/** Multiplies every single precision sample in the given buffer, in native
+ * byte order, by the given gain. Bytes past the last whole sample are left as
+ * they are. The samples get multiplied in bulk, where the processor lets us,
+ * which gives the exact same results. */
+pub fn scale_f32(bytes: &mut [u8], gain: f32) {
+	#[cfg(target_arch = "x86_64")] {
+		if is_x86_feature_detected!("avx") {
+			unsafe { scale_f32_avx(bytes, gain) }
+		} else {
+			/* SSE is always there on this architecture. */
+			unsafe { scale_f32_sse(bytes, gain) }
+		}
+	}
+	#[cfg(target_arch = "aarch64")] {
+		unsafe { scale_f32_neon(bytes, gain) }
+	}
+	#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))] {
+		scale_f32_scalar(bytes, gain)
+	}
+}
+
+/** Same as [`scale_f32`], one sample at a time. */
+fn scale_f32_scalar(bytes: &mut [u8], gain: f32) {
+	for sample in bytes.chunks_exact_mut(4) {
+		let value = f32::from_ne_bytes([sample[0], sample[1], sample[2], sample[3]]);
+		sample.copy_from_slice(&(value * gain).to_ne_bytes());
+	}
+}
+
+/** Same as [`scale_f32`], four samples at a time. */
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse")]
+unsafe fn scale_f32_sse(bytes: &mut [u8], gain: f32) {
+	use std::arch::x86_64::*;
+
+	let gains = _mm_set1_ps(gain);
+	let mut chunks = bytes.chunks_exact_mut(16);
+	for chunk in &mut chunks {
+		let pointer = chunk.as_mut_ptr() as *mut f32;
+		_mm_storeu_ps(pointer, _mm_mul_ps(_mm_loadu_ps(pointer), gains));
+	}
+	scale_f32_scalar(chunks.into_remainder(), gain);
+}
+
+/** Same as [`scale_f32`], eight samples at a time. */
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+unsafe fn scale_f32_avx(bytes: &mut [u8], gain: f32) {
+	use std::arch::x86_64::*;
+
+	let gains = _mm256_set1_ps(gain);
+	let mut chunks = bytes.chunks_exact_mut(32);
+	for chunk in &mut chunks {
+		let pointer = chunk.as_mut_ptr() as *mut f32;
+		_mm256_storeu_ps(pointer, _mm256_mul_ps(_mm256_loadu_ps(pointer), gains));
+	}
+	scale_f32_scalar(chunks.into_remainder(), gain);
+}
+
+/** Same as [`scale_f32`], four samples at a time. */
+#[cfg(target_arch = "aarch64")]
+unsafe fn scale_f32_neon(bytes: &mut [u8], gain: f32) {
+	use std::arch::aarch64::*;
+
+	/* Samples are loaded as bytes, as the buffer need not be aligned. */
+	let mut chunks = bytes.chunks_exact_mut(16);
+	for chunk in &mut chunks {
+		let value = vreinterpretq_f32_u8(vld1q_u8(chunk.as_ptr()));
+		let value = vmulq_n_f32(value, gain);
+		vst1q_u8(chunk.as_mut_ptr(), vreinterpretq_u8_f32(value));
+	}
+	scale_f32_scalar(chunks.into_remainder(), gain);
+}
+
+/** Fades out the frames of the given size in the given buffer, holding
+ * samples in the given format, in native byte order. The gain goes down by one
+ * step of the given number of them every frame, starting from the given
+ * remaining number of steps, which gets updated to where the fade is left off.
+ * Frames past the end of the fade come out silent. */
+pub fn fade_out(
+	quantizer: &mut Quantizer,
+	format: SampleFormat,
+	frame: usize,
+	remaining: &mut usize,
+	length: usize,
+	bytes: &mut [u8]) {
+
+	let size = format.sample_size();
+	for frame in bytes.chunks_exact_mut(frame) {
+		let gain = *remaining as f32 / length as f32;
+		*remaining = remaining.saturating_sub(1);
+
+		/* Frames with plenty of channels are worth scaling in bulk. */
+		if format == SampleFormat::F32 {
+			scale_f32(frame, gain);
+			continue
+		}
+		for sample in frame.chunks_exact_mut(size) {
+			let value = decode(format, Endianness::Native, sample);
+			encode(quantizer, format, Endianness::Native, value * gain, sample);
+		}
+	}
+}
+
+/** Fades in the frames of the given size in the given buffer, holding samples
+ * in the given format, in native byte order. The gain goes up by one step of
+ * the given number of them every frame, starting from the given number of steps
+ * already reached, which gets updated to where the fade is left off. Frames
+ * past the end of the fade are left as they are. */
+pub fn fade_in(
+	quantizer: &mut Quantizer,
+	format: SampleFormat,
+	frame: usize,
+	reached: &mut usize,
+	length: usize,
+	bytes: &mut [u8]) {
+
+	let size = format.sample_size();
+	for frame in bytes.chunks_exact_mut(frame) {
+		if *reached >= length { break }
+		*reached += 1;
+		let gain = *reached as f32 / length as f32;
+
+		if format == SampleFormat::F32 {
+			scale_f32(frame, gain);
+			continue
+		}
+		for sample in frame.chunks_exact_mut(size) {
+			let value = decode(format, Endianness::Native, sample);
+			encode(quantizer, format, Endianness::Native, value * gain, sample);
+		}
+	}
+}
+
+/** Fills the given buffer with silent samples in the given format. Bytes past
+ * the last whole sample are left as they are. */
+pub fn silence(format: SampleFormat, endian: Endianness, bytes: &mut [u8]) {
+	let mut quantizer = Quantizer::new(Dither::None);
+	for sample in bytes.chunks_exact_mut(format.sample_size()) {
+		encode(&mut quantizer, format, endian, 0.0, sample);
+	}
+}
+
+/** Narrows a double precision sample down to single precision. Samples that
+ * aren't numbers are silenced and samples past full scale, including infinite
+ * ones, are clamped, as there's no telling what the output device would do
+ * with them otherwise. */
+pub fn narrow(sample: f64) -> f32 {
+	if sample.is_nan() {
+		0.0
+	} else {
+		sample.clamp(-1.0, 1.0) as f32
+	}
+}
+
+/** Unpacks a single signed integer sample, laid out over the given bytes in
+ * the given byte order, of which only the lowest given number of bits carry
+ * data, normalized to the `[-1; 1]` range. */
+pub fn unpack(bytes: &[u8], endian: Endianness, valid_bits: u32) -> f32 {
+	let big = match endian {
+		Endianness::Little => false,
+		Endianness::Big    => true,
+		Endianness::Native => cfg!(target_endian = "big")
+	};
+	let value = if big {
+		bytes.iter().fold(0u32, |value, byte| (value << 8) | u32::from(*byte))
+	} else {
+		bytes.iter().rev().fold(0u32, |value, byte| (value << 8) | u32::from(*byte))
+	};
+
+	/* Moving the significant bits up to the top and shifting them back down
+	 * sign-extends them, and gets rid of whatever was above them. */
+	let shift = 32 - valid_bits;
+	let value = ((value << shift) as i32) >> shift;
+
+	value as f32 / (1u64 << (valid_bits - 1)) as f32
+}
+
+/** Whether the given sample, as decoded from the given format, is at or past
+ * full scale. Integer samples can't go past it, so the highest and lowest values
+ * they can hold are taken to be clipped. */
+pub fn clipped(format: SampleFormat, value: f32) -> bool {
+	match format {
+		SampleFormat::I16 | SampleFormat::U16 => value <= -1.0 || value >= 32767.0 / 32768.0,
+		SampleFormat::F32 => value.abs() >= 1.0
+	}
+}
+
+/** Reads a single sample in the given format from the start of the given
+ * buffer, normalized to the `[-1; 1]` range. */
+pub fn decode(format: SampleFormat, endian: Endianness, bytes: &[u8]) -> f32 {
+	match format {
+		SampleFormat::I16 => {
+			let bytes = [bytes[0], bytes[1]];
+			let value = match endian {
+				Endianness::Little => i16::from_le_bytes(bytes),
+				Endianness::Big    => i16::from_be_bytes(bytes),
+				Endianness::Native => i16::from_ne_bytes(bytes),
+			};
+			f32::from(value) / 32768.0
+		},
+		SampleFormat::U16 => {
+			let bytes = [bytes[0], bytes[1]];
+			let value = match endian {
+				Endianness::Little => u16::from_le_bytes(bytes),
+				Endianness::Big    => u16::from_be_bytes(bytes),
+				Endianness::Native => u16::from_ne_bytes(bytes),
+			};
+			(f32::from(value) - 32768.0) / 32768.0
+		},
+		SampleFormat::F32 => {
+			let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+			match endian {
+				Endianness::Little => f32::from_le_bytes(bytes),
+				Endianness::Big    => f32::from_be_bytes(bytes),
+				Endianness::Native => f32::from_ne_bytes(bytes),
+			}
+		}
+	}
+}
+
+/** Converts samples in native byte order from one format into another, one by
+ * one, for as many samples as both buffers hold. Integer formats go through the
+ * given quantizer. Any pair of formats can be converted between, with samples
+ * going through their normalized values, which no format we know of has more
+ * precision than. */
+pub fn convert(
+	quantizer: &mut Quantizer,
+	input: SampleFormat,
+	source: &[u8],
+	output: SampleFormat,
+	target: &mut [u8]) {
+
+	if input == output {
+		let len = usize::min(source.len(), target.len());
+		target[..len].copy_from_slice(&source[..len]);
+		return
+	}
+
+	let samples = source.chunks_exact(input.sample_size())
+		.zip(target.chunks_exact_mut(output.sample_size()));
+	for (source, target) in samples {
+		let value = decode(input, Endianness::Native, source);
+		encode(quantizer, output, Endianness::Native, value, target);
+	}
+}
+
+/** Writes a single normalized sample in the given format to the start of the
+ * given buffer. Integer formats go through the given quantizer. */
+pub fn encode(
+	quantizer: &mut Quantizer,
+	format: SampleFormat,
+	endian: Endianness,
+	sample: f32,
+	bytes: &mut [u8]) {
+
+	match format {
+		SampleFormat::I16 => {
+			let value = quantizer.to_i16(sample);
+			bytes[..2].copy_from_slice(&match endian {
+				Endianness::Little => value.to_le_bytes(),
+				Endianness::Big    => value.to_be_bytes(),
+				Endianness::Native => value.to_ne_bytes(),
+			});
+		},
+		SampleFormat::U16 => {
+			let value = quantizer.to_u16(sample);
+			bytes[..2].copy_from_slice(&match endian {
+				Endianness::Little => value.to_le_bytes(),
+				Endianness::Big    => value.to_be_bytes(),
+				Endianness::Native => value.to_ne_bytes(),
+			});
+		},
+		SampleFormat::F32 => {
+			bytes[..4].copy_from_slice(&match endian {
+				Endianness::Little => sample.to_le_bytes(),
+				Endianness::Big    => sample.to_be_bytes(),
+				Endianness::Native => sample.to_ne_bytes(),
+			});
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn quantizer_saturates_at_full_scale() {
+		let mut quantizer = Quantizer::new(Dither::None);
+		assert_eq!(quantizer.to_i16(1.0), 32767);
+		assert_eq!(quantizer.to_i16(-1.0), -32768);
+		assert_eq!(quantizer.to_u16(1.0), 65535);
+		assert_eq!(quantizer.to_u16(-1.0), 0);
+	}
+
+	#[test]
+	fn quantizer_never_wraps_around() {
+		let mut quantizer = Quantizer::new(Dither::None);
+		for sample in [2.0, 1e30, f32::MAX, f32::INFINITY] {
+			assert_eq!(quantizer.to_i16(sample), 32767);
+			assert_eq!(quantizer.to_i16(-sample), -32768);
+		}
+
+		/* Dither pushes samples at full scale past it, but never around. */
+		let mut quantizer = Quantizer::new(Dither::Triangular);
+		for _ in 0..10_000 {
+			assert!(quantizer.to_i16(1.0) > 32000);
+			assert!(quantizer.to_i16(-1.0) < -32000);
+		}
+	}
+
+	#[test]
+	fn quantizer_silences_nan() {
+		let mut quantizer = Quantizer::new(Dither::None);
+		assert_eq!(quantizer.to_i16(f32::NAN), 0);
+		assert_eq!(quantizer.to_u16(f32::NAN), 32768);
+
+		let mut quantizer = Quantizer::new(Dither::Triangular);
+		assert_eq!(quantizer.to_i16(f32::NAN), 0);
+	}
+
+	#[test]
+	fn quantizer_rounds_ties_to_even() {
+		let lsb = 1.0 / 32768.0;
+		let mut quantizer = Quantizer::new(Dither::None);
+		assert_eq!(quantizer.to_i16(0.5 * lsb), 0);
+		assert_eq!(quantizer.to_i16(1.5 * lsb), 2);
+		assert_eq!(quantizer.to_i16(2.5 * lsb), 2);
+		assert_eq!(quantizer.to_i16(-0.5 * lsb), 0);
+		assert_eq!(quantizer.to_i16(-1.5 * lsb), -2);
+		assert_eq!(quantizer.to_i16(-2.5 * lsb), -2);
+
+		assert_eq!(round_half_even(0.4), 0.0);
+		assert_eq!(round_half_even(0.6), 1.0);
+		assert_eq!(round_half_even(3.5), 4.0);
+		assert_eq!(round_half_even(4.5), 4.0);
+	}
+
+	#[test]
+	fn quantizer_is_monotonic() {
+		let mut quantizer = Quantizer::new(Dither::None);
+		let mut last_i16 = i16::MIN;
+		let mut last_u16 = u16::MIN;
+		for step in -300_000..=300_000 {
+			let sample = step as f32 / 200_000.0;
+			let (i, u) = (quantizer.to_i16(sample), quantizer.to_u16(sample));
+			assert!(i >= last_i16, "{} went down to {} at {}", last_i16, i, sample);
+			assert!(u >= last_u16, "{} went down to {} at {}", last_u16, u, sample);
+			last_i16 = i;
+			last_u16 = u;
+		}
+	}
+
+	/** Bytes that don't repeat for a good while, so that any of them ending up
+	 * out of place gets noticed. */
+	fn pattern(len: usize) -> Vec<u8> {
+		(0..len).map(|i| (i * 37 % 251) as u8).collect()
+	}
+
+	/** Single precision samples covering the awkward cases, such as subnormals,
+	 * infinities and NaN, as native bytes. Comparing them as bytes tells apart
+	 * every bit of them, NaN included. */
+	fn floats(len: usize) -> Vec<u8> {
+		let special = [
+			0.0, -0.0, 1.0, -1.0, 0.333, f32::MIN_POSITIVE, 1e-40, -1e-40,
+			f32::MAX, f32::MIN, f32::INFINITY, f32::NEG_INFINITY, f32::NAN
+		];
+		(0..len)
+			.map(|i| special.get(i).copied().unwrap_or((i as f32 * 0.37).sin()))
+			.flat_map(f32::to_ne_bytes)
+			.collect()
+	}
+
+	#[test]
+	fn bulk_swap_matches_the_scalar_one() {
+		/* Lengths that leave every possible remainder past the bulk chunks. */
+		for &size in &[2, 3, 4, 8] {
+			for len in 0..100 {
+				let mut bulk = pattern(len);
+				let mut scalar = bulk.clone();
+				swap_bytes(size, &mut bulk);
+				swap_bytes_scalar(size, &mut scalar);
+				assert_eq!(bulk, scalar, "{} byte samples, {} bytes", size, len);
+			}
+		}
+	}
+
+	#[test]
+	fn swap_reverses_whole_samples_only() {
+		let mut bytes = vec![1, 2, 3, 4, 5, 6, 7];
+		swap_bytes(2, &mut bytes);
+		assert_eq!(bytes, [2, 1, 4, 3, 6, 5, 7]);
+
+		let mut bytes = vec![1, 2, 3, 4, 5, 6, 7];
+		swap_bytes(4, &mut bytes);
+		assert_eq!(bytes, [4, 3, 2, 1, 5, 6, 7]);
+
+		let mut bytes = vec![1, 2, 3, 4, 5, 6, 7];
+		swap_bytes(3, &mut bytes);
+		assert_eq!(bytes, [3, 2, 1, 6, 5, 4, 7]);
+	}
+
+	#[cfg(target_arch = "x86_64")]
+	#[test]
+	fn every_x86_swap_matches_the_scalar_one() {
+		if !is_x86_feature_detected!("ssse3") { return }
+		for &size in &[2, 4] {
+			for len in 0..100 {
+				let mut bulk = pattern(len);
+				let mut scalar = bulk.clone();
+				unsafe { swap_bytes_ssse3(size, &mut bulk) }
+				swap_bytes_scalar(size, &mut scalar);
+				assert_eq!(bulk, scalar, "{} byte samples, {} bytes", size, len);
+			}
+		}
+	}
+
+	#[test]
+	fn bulk_gain_matches_the_scalar_one() {
+		for &gain in &[0.0, 0.5, 1.0, -2.0, 1e-30, f32::INFINITY] {
+			/* Lengths that leave every possible remainder, including partial
+			 * samples past the last whole one. */
+			for len in 0..130 {
+				let mut bulk = floats(len / 4 + 1);
+				bulk.truncate(len);
+				let mut scalar = bulk.clone();
+				scale_f32(&mut bulk, gain);
+				scale_f32_scalar(&mut scalar, gain);
+				assert_eq!(bulk, scalar, "gain of {}, {} bytes", gain, len);
+			}
+		}
+	}
+
+	#[cfg(target_arch = "x86_64")]
+	#[test]
+	fn every_x86_gain_matches_the_scalar_one() {
+		for &gain in &[0.5, -2.0, 1e-30] {
+			for len in 0..130 {
+				let mut scalar = floats(len / 4 + 1);
+				scalar.truncate(len);
+				scale_f32_scalar(&mut scalar, gain);
+
+				let mut sse = floats(len / 4 + 1);
+				sse.truncate(len);
+				unsafe { scale_f32_sse(&mut sse, gain) }
+				assert_eq!(sse, scalar, "gain of {}, {} bytes", gain, len);
+
+				if is_x86_feature_detected!("avx") {
+					let mut avx = floats(len / 4 + 1);
+					avx.truncate(len);
+					unsafe { scale_f32_avx(&mut avx, gain) }
+					assert_eq!(avx, scalar, "gain of {}, {} bytes", gain, len);
+				}
+			}
+		}
+	}
+
+	/** The same few levels in every format, from full scale down to full
+	 * scale up, as native bytes. */
+	fn levels(format: SampleFormat) -> Vec<u8> {
+		match format {
+			SampleFormat::I16 => [-32768i16, -16384, 0, 8192, 32767].iter()
+				.flat_map(|value| value.to_ne_bytes())
+				.collect(),
+			SampleFormat::U16 => [0u16, 16384, 32768, 40960, 65535].iter()
+				.flat_map(|value| value.to_ne_bytes())
+				.collect(),
+			SampleFormat::F32 => [-1.0f32, -0.5, 0.0, 0.25, 32767.0 / 32768.0].iter()
+				.flat_map(|value| value.to_ne_bytes())
+				.collect(),
+		}
+	}
+
+	#[test]
+	fn every_format_converts_into_every_other() {
+		let formats = [SampleFormat::I16, SampleFormat::U16, SampleFormat::F32];
+		for &input in &formats {
+			for &output in &formats {
+				let source = levels(input);
+				let mut target = vec![0; 5 * output.sample_size()];
+				convert(&mut Quantizer::new(Dither::None), input, &source, output, &mut target);
+				assert_eq!(target, levels(output), "{:?} into {:?}", input, output);
+			}
+		}
+	}
+
+	#[test]
+	fn conversion_saturates_out_of_range_floats() {
+		let source = [1.5f32, -2.0].iter()
+			.flat_map(|value| value.to_ne_bytes())
+			.collect::<Vec<_>>();
+
+		let mut target = [0; 4];
+		convert(&mut Quantizer::new(Dither::None), SampleFormat::F32, &source, SampleFormat::I16, &mut target);
+		assert_eq!(target[..2], 32767i16.to_ne_bytes());
+		assert_eq!(target[2..], (-32768i16).to_ne_bytes());
+
+		convert(&mut Quantizer::new(Dither::None), SampleFormat::F32, &source, SampleFormat::U16, &mut target);
+		assert_eq!(target[..2], 65535u16.to_ne_bytes());
+		assert_eq!(target[2..], 0u16.to_ne_bytes());
+	}
+
+	#[test]
+	fn fades_go_down_a_step_every_frame() {
+		/* Two channels at a quarter of full scale, faded over four steps
+		 * from the third one on, and silent past the end of the fade. */
+		for &format in &[SampleFormat::I16, SampleFormat::F32] {
+			let mut quantizer = Quantizer::new(Dither::None);
+			let size = format.sample_size();
+			let mut bytes = vec![0; 6 * 2 * size];
+			for sample in bytes.chunks_exact_mut(size) {
+				encode(&mut quantizer, format, Endianness::Native, 0.25, sample);
+			}
+
+			let mut remaining = 3;
+			fade_out(&mut quantizer, format, 2 * size, &mut remaining, 4, &mut bytes);
+			assert_eq!(remaining, 0);
+
+			let faded = bytes.chunks_exact(size)
+				.map(|sample| decode(format, Endianness::Native, sample))
+				.collect::<Vec<_>>();
+			let expected = [0.1875, 0.1875, 0.125, 0.125, 0.0625, 0.0625, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+			for (faded, expected) in faded.iter().zip(&expected) {
+				assert!((faded - expected).abs() < 1e-4, "{:?}: {:?}", format, faded);
+			}
+		}
+	}
+
+	#[test]
+	fn fades_go_up_a_step_every_frame() {
+		/* Two channels at a quarter of full scale, faded in over four steps
+		 * from the first one on, and left alone past the end of the fade. */
+		for &format in &[SampleFormat::I16, SampleFormat::F32] {
+			let mut quantizer = Quantizer::new(Dither::None);
+			let size = format.sample_size();
+			let mut bytes = vec![0; 6 * 2 * size];
+			for sample in bytes.chunks_exact_mut(size) {
+				encode(&mut quantizer, format, Endianness::Native, 0.25, sample);
+			}
+
+			let mut reached = 1;
+			fade_in(&mut quantizer, format, 2 * size, &mut reached, 4, &mut bytes);
+			assert_eq!(reached, 4);
+
+			let faded = bytes.chunks_exact(size)
+				.map(|sample| decode(format, Endianness::Native, sample))
+				.collect::<Vec<_>>();
+			let expected = [0.125, 0.125, 0.1875, 0.1875, 0.25, 0.25, 0.25, 0.25, 0.25, 0.25, 0.25, 0.25];
+			for (faded, expected) in faded.iter().zip(&expected) {
+				assert!((faded - expected).abs() < 1e-4, "{:?}: {:?}", format, faded);
+			}
+		}
+	}
+
+	#[test]
+	fn conversion_stops_at_the_shorter_buffer() {
+		let source = levels(SampleFormat::I16);
+		let mut target = vec![0xaa; 3 * 4 + 2];
+		convert(&mut Quantizer::new(Dither::None), SampleFormat::I16, &source, SampleFormat::F32, &mut target);
+		assert_eq!(target[..12], levels(SampleFormat::F32)[..12]);
+		assert_eq!(target[12..], [0xaa, 0xaa]);
+	}
+}
@@ -0,0 +1,515 @@
+use crate::arg::InputFormat;
+use crate::error::Error;
+use crate::src::{LoopPoints, Source};
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+
+/** Function used by container readers to skip over data they're not
+ * interested in. */
+pub type Skip<R> = fn(&mut R, u64) -> std::io::Result<()>;
+
+/** Function used by container readers to move around sources that can seek,
+ * so that they can find their way back to the start of their samples. */
+pub type Seeker<R> = fn(&mut R, SeekFrom) -> std::io::Result<u64>;
+
+/** A reader over a stretch of data in a source. When the source can seek, the
+ * reader can go back to the start of that stretch. */
+pub struct Span<R> {
+	source: R,
+	/** How to seek around the source, if it can. */
+	seek: Option<Seeker<R>>,
+	/** Where in the source the stretch starts. Only known when it can seek. */
+	start: u64,
+	/** Length of the stretch, in bytes. */
+	length: u64,
+	/** How much of the stretch is left to be read. */
+	remaining: u64,
+}
+impl<R> Span<R> {
+	/** Creates a reader over the given number of bytes, starting where the
+	 * source currently is. */
+	pub fn new(mut source: R, length: u64, seek: Option<Seeker<R>>) -> std::io::Result<Self> {
+		let start = match seek {
+			Some(seek) => seek(&mut source, SeekFrom::Current(0))?,
+			None => 0
+		};
+
+		Ok(Self {
+			source,
+			seek,
+			start,
+			length,
+			remaining: length
+		})
+	}
+
+	/** How much of the stretch is left to be read, in bytes. */
+	pub fn remaining(&self) -> u64 {
+		self.remaining
+	}
+}
+impl<R> Read for Span<R>
+	where R: Read {
+
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		let len = u64::min(buf.len() as u64, self.remaining) as usize;
+		let read = self.source.read(&mut buf[..len])?;
+		self.remaining -= read as u64;
+
+		Ok(read)
+	}
+}
+impl<R> Source for Span<R>
+	where R: Read + Send {
+
+	fn rewind(&mut self) -> std::io::Result<bool> {
+		let seek = match self.seek {
+			Some(seek) => seek,
+			None => return Ok(false)
+		};
+
+		seek(&mut self.source, SeekFrom::Start(self.start))?;
+		self.remaining = self.length;
+
+		Ok(true)
+	}
+
+	fn seek_forward(&mut self, bytes: u64) -> std::io::Result<bool> {
+		let seek = match self.seek {
+			Some(seek) => seek,
+			None => return Ok(false)
+		};
+
+		/* Going past the end of the stretch is the same as going to it. */
+		let bytes = u64::min(bytes, self.remaining);
+		let position = self.start + (self.length - self.remaining) + bytes;
+		seek(&mut self.source, SeekFrom::Start(position))?;
+		self.remaining -= bytes;
+
+		Ok(true)
+	}
+
+	/** Stretches as long as they can be run until the end of the source, and
+	 * don't know how much of it is left. */
+	fn len_hint(&self) -> Option<u64> {
+		Some(self.remaining).filter(|_| self.length != u64::MAX)
+	}
+}
+
+/** Length of the header of an ID3v2 tag, as well as of its footer. */
+const ID3_HEADER: usize = 10;
+
+/** Decodes a syncsafe integer, in which only the lower seven bits of every
+ * byte are used, so that it can never be mistaken for an MPEG sync pattern.
+ * Integers with any of the top bits set are not valid. */
+pub fn syncsafe(bytes: [u8; 4]) -> Option<u32> {
+	if bytes.iter().any(|byte| byte & 0x80 != 0) { return None }
+
+	Some(bytes.iter().fold(0, |value, byte| (value << 7) | u32::from(*byte)))
+}
+
+/** Total length of the ID3v2 tag with the given header, header and footer
+ * included, if the header is a valid one. */
+fn id3_length(header: [u8; ID3_HEADER]) -> Option<u32> {
+	/* Versions are never 0xff, neither are revisions. */
+	if header[3] == 0xff || header[4] == 0xff { return None }
+
+	let size = syncsafe([header[6], header[7], header[8], header[9]])?;
+	let footer = if header[5] & 0x10 != 0 { ID3_HEADER as u32 } else { 0 };
+
+	Some(ID3_HEADER as u32 + size + footer)
+}
+
+/** A container found at the start of an input, along with the format of the
+ * samples in it. */
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Detected {
+	/** Name of the container format. */
+	pub container: &'static str,
+	/** Format of the samples in the container. */
+	pub format: InputFormat,
+	/** Loop embedded in the container, if any. */
+	pub loop_points: Option<LoopPoints>,
+}
+impl std::fmt::Display for Detected {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "{}: {}", self.container, self.format)
+	}
+}
+
+/** A reader that lets data be looked at before it gets read, without consuming
+ * it. This is what lets us sniff the format of inputs that can't seek back. */
+pub struct Peeker<R> {
+	source: R,
+	/** Data that has been peeked at, but not yet read. */
+	buffer: Vec<u8>,
+	/** How much of the peeked data has been read already. */
+	offset: usize,
+}
+impl<R> Peeker<R>
+	where R: Read {
+
+	pub fn new(source: R) -> Self {
+		Self {
+			source,
+			buffer: Vec::new(),
+			offset: 0
+		}
+	}
+
+	/** Looks at up to the given number of bytes coming up next in the source,
+	 * without consuming them. Fewer bytes are only returned if the source ends
+	 * before that. */
+	pub fn peek(&mut self, len: usize) -> std::io::Result<&[u8]> {
+		while self.buffer.len() - self.offset < len {
+			let mut chunk = vec![0; len - (self.buffer.len() - self.offset)];
+			match self.source.read(&mut chunk) {
+				Ok(0) => break,
+				Ok(read) => self.buffer.extend(&chunk[..read]),
+				Err(what) if what.kind() == ErrorKind::Interrupted => continue,
+				Err(what) => return Err(what)
+			}
+		}
+
+		let end = usize::min(self.offset + len, self.buffer.len());
+		Ok(&self.buffer[self.offset..end])
+	}
+
+	/** Throws away the given number of bytes that have been peeked at. */
+	pub fn consume(&mut self, len: usize) {
+		self.offset = usize::min(self.offset + len, self.buffer.len());
+	}
+
+	/** Gives the source back, along with whatever peeked data was not read or
+	 * consumed yet. */
+	pub fn into_parts(self) -> (R, Vec<u8>) {
+		let Self { source, mut buffer, offset } = self;
+		buffer.drain(..offset);
+
+		(source, buffer)
+	}
+}
+impl<R> Read for Peeker<R>
+	where R: Read {
+
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		if self.offset < self.buffer.len() {
+			let len = usize::min(buf.len(), self.buffer.len() - self.offset);
+			buf[..len].copy_from_slice(&self.buffer[self.offset..self.offset + len]);
+			self.offset += len;
+
+			return Ok(len)
+		}
+
+		self.source.read(buf)
+	}
+}
+impl<R> Source for Peeker<R>
+	where R: Read + Send {}
+
+/** Checks whether the given source holds a container format we know of. If it
+ * does, its header is parsed and a reader over its samples is returned, along
+ * with their format. Otherwise, the source is returned as it is. */
+pub fn detect<R>(source: R)
+	-> Result<(Box<dyn Read + Send>, Option<Detected>), Error>
+	where R: Read + Send + 'static {
+
+	let (reader, detected) = detect_with(source, skip_by_reading, None)?;
+	Ok((Box::new(reader), detected))
+}
+
+/** Same as [`detect`], but data we're not interested in gets seeked over,
+ * rather than read and discarded. Readers of containers that know where their
+ * samples are can go back to the start of them. */
+pub fn detect_seekable<R>(source: R)
+	-> Result<(Box<dyn Source>, Option<Detected>), Error>
+	where R: Read + Seek + Send + 'static {
+
+	detect_with(source, skip_by_seeking, Some(R::seek))
+}
+
+/** Skips over data by reading it and throwing it away. */
+fn skip_by_reading<R>(source: &mut R, count: u64) -> std::io::Result<()>
+	where R: Read {
+
+	let skipped = std::io::copy(&mut source.take(count), &mut std::io::sink())?;
+	if skipped < count {
+		Err(ErrorKind::UnexpectedEof.into())
+	} else {
+		Ok(())
+	}
+}
+
+/** Skips over data by seeking past it. Seeking past the end is not an error,
+ * but the next read will then fail, which is just as good. */
+fn skip_by_seeking<R>(source: &mut R, count: u64) -> std::io::Result<()>
+	where R: Seek {
+
+	source.seek(SeekFrom::Current(count as i64)).map(|_| ())
+}
+
+/** Implementation of format detection, using the given functions to skip over
+ * data the container readers are not interested in and, if the source can
+ * seek, to move around it. */
+fn detect_with<R>(source: R, skip: Skip<R>, seek: Option<Seeker<R>>)
+	-> Result<(Box<dyn Source>, Option<Detected>), Error>
+	where R: Read + Send + 'static {
+
+	let mut peeker = Peeker::new(source);
+
+	/* Compressed input gets decompressed, and whatever comes out of it goes
+	 * through detection all over again. */
+	#[cfg(feature = "gzip")] {
+		if peeker.peek(2).map_err(Error::InputError)? == [0x1f, 0x8b] {
+			eprintln!("gzip-compressed input");
+
+			let source: Box<dyn Read + Send> = Box::new(crate::gzip::Gunzip::new(peeker));
+			return detect_with(source, skip_by_reading, None)
+		}
+	}
+
+	#[cfg(feature = "zstd")] {
+		let peeked = peeker.peek(5).map_err(Error::InputError)?;
+		if crate::zst::is_frame(peeked) {
+			if crate::zst::needs_dictionary(peeked) {
+				return Err(Error::UnsupportedContainer("dictionary compressed zstd"))
+			}
+			eprintln!("zstd-compressed input");
+
+			let source = crate::zst::Unzstd::new(peeker).map_err(Error::InputError)?;
+			let source: Box<dyn Read + Send> = Box::new(source);
+			return detect_with(source, skip_by_reading, None)
+		}
+	}
+
+	/* Some tools put an ID3v2 tag in front of whatever they write, which we
+	 * have no use for and which would otherwise get played as noise. */
+	let mut header = [0; ID3_HEADER];
+	let peeked = peeker.peek(ID3_HEADER).map_err(Error::InputError)?;
+	if peeked.len() == ID3_HEADER && &peeked[0..3] == b"ID3" {
+		header.copy_from_slice(peeked);
+		match id3_length(header) {
+			Some(length) => {
+				peeker.consume(ID3_HEADER);
+				let (mut source, rest) = peeker.into_parts();
+				debug_assert!(rest.is_empty());
+
+				match skip(&mut source, u64::from(length) - ID3_HEADER as u64) {
+					Ok(_) => eprintln!("skipped an ID3 tag of {} bytes", length),
+					Err(what) if what.kind() == ErrorKind::UnexpectedEof =>
+						eprintln!("warning: the ID3 tag of {} bytes runs past the end of the input", length),
+					Err(what) => return Err(Error::InputError(what))
+				}
+				peeker = Peeker::new(source);
+			},
+			None => eprintln!("warning: the input starts with a malformed ID3 tag, playing it as it is")
+		}
+	}
+
+	let mut magic = [0; crate::registry::MAGIC];
+	let filled = {
+		let peeked = peeker.peek(magic.len()).map_err(Error::InputError)?;
+		magic[..peeked.len()].copy_from_slice(peeked);
+		peeked.len()
+	};
+
+	/* The general purpose decoder, when there is one, gets first dibs on
+	 * everything it recognizes. */
+	#[cfg(feature = "symphonia")] {
+		if let Some(container) = crate::universal::recognize(&magic[..filled]) {
+			let (reader, format) = crate::universal::open(peeker)?;
+			return Ok((Box::new(reader), Some(Detected { container, format, loop_points: None })))
+		}
+	}
+
+	for container in crate::registry::containers::<R>() {
+		if (container.probe)(&magic[..filled]) {
+			let (reader, format, loop_points) = (container.open)(peeker, skip, seek)?;
+			return Ok((reader, Some(Detected {
+				container: container.name,
+				format,
+				loop_points
+			})))
+		}
+	}
+
+	/* Not a format we know, so the data is played as it is, starting with
+	 * whatever we've peeked at. Sources that can seek get to do so over the
+	 * data, from where we've peeked at it on. */
+	if let Some(seek) = seek {
+		let (mut source, peeked) = peeker.into_parts();
+		let start = seek(&mut source, SeekFrom::Current(-(peeked.len() as i64)))
+			.map_err(Error::InputError)?;
+		let end = seek(&mut source, SeekFrom::End(0)).map_err(Error::InputError)?;
+		seek(&mut source, SeekFrom::Start(start)).map_err(Error::InputError)?;
+
+		let span = Span::new(source, end.saturating_sub(start), Some(seek))
+			.map_err(Error::InputError)?;
+		return Ok((Box::new(span), None))
+	}
+	Ok((Box::new(peeker), None))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Cursor;
+
+	/** Name of the first container that takes the given start of an input
+	 * for its own, if any. */
+	fn probe(magic: &[u8]) -> Option<&'static str> {
+		crate::registry::containers::<Cursor<Vec<u8>>>()
+			.into_iter()
+			.find(|container| (container.probe)(magic))
+			.map(|container| container.name)
+	}
+
+	/** A WAV file holding the given mono 16-bit samples at 8000Hz. */
+	fn wav(data: &[u8]) -> Vec<u8> {
+		let mut file = b"RIFF".to_vec();
+		file.extend(&(36 + data.len() as u32).to_le_bytes());
+		file.extend(b"WAVEfmt ");
+		file.extend(&16u32.to_le_bytes());
+		file.extend(&[1, 0, 1, 0]);
+		file.extend(&8000u32.to_le_bytes());
+		file.extend(&16000u32.to_le_bytes());
+		file.extend(&[2, 0, 16, 0]);
+		file.extend(b"data");
+		file.extend(&(data.len() as u32).to_le_bytes());
+		file.extend(data);
+		file
+	}
+
+	fn read_all(mut reader: impl Read) -> Vec<u8> {
+		let mut data = Vec::new();
+		reader.read_to_end(&mut data).unwrap();
+		data
+	}
+
+	#[test]
+	fn every_magic_number_is_recognized() {
+		assert_eq!(probe(b"RIFF\0\0\0\0WAVE"), Some("WAV"));
+		assert_eq!(probe(b"FORM\0\0\0\0AIFF"), Some("AIFF"));
+		assert_eq!(probe(b"FORM\0\0\0\0AIFC"), Some("AIFF-C"));
+		assert_eq!(probe(b".snd\0\0\0\x18\0\0\0\x03"), Some("AU"));
+		assert_eq!(probe(b"fLaC\0\0\0\x22\0\0\0\0"), Some("FLAC"));
+		assert_eq!(probe(b"OggS\0\x02\0\0\0\0\0\0"), Some("Ogg Vorbis"));
+
+		/* Other kinds of RIFF and FORM files are not ours to play. */
+		assert_eq!(probe(b"RIFF\0\0\0\0AVI "), None);
+		assert_eq!(probe(b"FORM\0\0\0\08SVX"), None);
+		assert_eq!(probe(b"RIFF"), None);
+	}
+
+	#[test]
+	fn unknown_input_is_played_raw() {
+		let data = b"these are raw samples".to_vec();
+		let (reader, detected) = detect(Cursor::new(data.clone())).unwrap();
+		assert_eq!(detected, None);
+		assert_eq!(read_all(reader), data);
+
+		/* Inputs shorter than any magic number are left alone too. */
+		let (reader, detected) = detect(Cursor::new(b"RIFF".to_vec())).unwrap();
+		assert_eq!(detected, None);
+		assert_eq!(read_all(reader), b"RIFF");
+	}
+
+	#[test]
+	fn unknown_files_can_be_gone_back_through() {
+		let data = b"these are raw samples".to_vec();
+		let (mut reader, detected) = detect_seekable(Cursor::new(data.clone())).unwrap();
+		assert_eq!(detected, None);
+		assert_eq!(reader.len_hint(), Some(data.len() as u64));
+
+		let mut read = Vec::new();
+		reader.read_to_end(&mut read).unwrap();
+		assert_eq!(read, data);
+
+		assert!(reader.rewind().unwrap());
+		assert!(reader.seek_forward(10).unwrap());
+		assert_eq!(read_all(reader), &data[10..]);
+	}
+
+	#[test]
+	fn containers_are_opened_from_streams_and_files() {
+		let (reader, detected) = detect(Cursor::new(wav(b"abcd"))).unwrap();
+		let detected = detected.unwrap();
+		assert_eq!(detected.container, "WAV");
+		assert_eq!(detected.format.sample_rate, 8000);
+
+		let (mut seekable, detected) = detect_seekable(Cursor::new(wav(b"abcd"))).unwrap();
+		assert_eq!(detected.unwrap().container, "WAV");
+
+		/* The general purpose decoder, when there is one, hands out samples
+		 * of its own making. */
+		if cfg!(not(feature = "symphonia")) {
+			assert_eq!(read_all(reader), b"abcd");
+			assert_eq!(read_all(&mut seekable), b"abcd");
+			assert!(seekable.rewind().unwrap());
+			assert_eq!(read_all(seekable), b"abcd");
+		}
+	}
+
+	/** Header of an ID3v2.4 tag with the given syncsafe size and flags. */
+	fn id3(size: [u8; 4], flags: u8) -> Vec<u8> {
+		let mut header = b"ID3\x04\x00".to_vec();
+		header.push(flags);
+		header.extend(&size);
+		header
+	}
+
+	#[test]
+	fn syncsafe_integers_use_seven_bits_a_byte() {
+		assert_eq!(syncsafe([0, 0, 0, 0x7f]), Some(0x7f));
+		assert_eq!(syncsafe([0, 0, 1, 0]), Some(0x80));
+		assert_eq!(syncsafe([0x7f, 0x7f, 0x7f, 0x7f]), Some(0x0fff_ffff));
+		assert_eq!(syncsafe([0, 0, 0, 0x80]), None);
+	}
+
+	#[test]
+	fn id3_tags_are_skipped() {
+		let mut input = id3([0, 0, 0, 5], 0);
+		input.extend(&[0xaa; 5]);
+		input.extend(wav(b"abcd"));
+
+		for seekable in [false, true] {
+			let detected = if seekable {
+				detect_seekable(Cursor::new(input.clone())).unwrap().1
+			} else {
+				detect(Cursor::new(input.clone())).unwrap().1
+			};
+			assert_eq!(detected.unwrap().container, "WAV");
+		}
+	}
+
+	#[test]
+	fn id3_footers_are_skipped_too() {
+		let mut input = id3([0, 0, 0, 2], 0x10);
+		input.extend(&[0xaa; 2]);
+		input.extend(b"3DI\x04\x00\x10\x00\x00\x00\x02");
+		input.extend(b"raw samples");
+
+		let (reader, detected) = detect(Cursor::new(input)).unwrap();
+		assert_eq!(detected, None);
+		assert_eq!(read_all(reader), b"raw samples");
+	}
+
+	#[test]
+	fn truncated_id3_tags_leave_nothing() {
+		let mut input = id3([0, 0, 1, 0], 0);
+		input.extend(&[0xaa; 16]);
+
+		let (reader, detected) = detect(Cursor::new(input)).unwrap();
+		assert_eq!(detected, None);
+		assert!(read_all(reader).is_empty());
+	}
+
+	#[test]
+	fn malformed_id3_tags_are_played_as_they_are() {
+		let mut input = id3([0, 0, 0, 0x80], 0);
+		input.extend(b"samples");
+
+		let (reader, detected) = detect(Cursor::new(input.clone())).unwrap();
+		assert_eq!(detected, None);
+		assert_eq!(read_all(reader), input);
+	}
+}
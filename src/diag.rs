@@ -1,9 +1,9 @@
-use crate::arg::Arguments;
+use crate::arg::{Arguments, Endianness, OutputConfig, sample_format_info};
 use cpal::traits::{HostTrait, DeviceTrait};
 use crate::error::Error;
-use cpal::SampleFormat;
 
-/** Lists all of the hosts available in this device. */
+/** Lists all of the hosts available in this device. The header is written to
+ * the standard error, and the hosts themselves to the standard output. */
 pub fn list_hosts() {
 	eprintln!("**** Lists of AVAILABLE audio hosts ****");
 
@@ -11,16 +11,18 @@ pub fn list_hosts() {
 	let default = cpal::default_host();
 
 	for (i, host) in hosts.iter().enumerate() {
-		print!("host {}: {:?}", i, host);
 		if default.id() == *host {
-			println!(" [default]")
+			println!("host {}: {:?} [default]", i, host)
 		} else {
-			println!()
+			println!("host {}: {:?}", i, host)
 		}
 	}
 }
 
-/** List all of the output devices for a given host. */
+/** List all of the output devices for a given host. The header and anything
+ * that goes wrong while gathering the list are written to the standard error,
+ * and the devices themselves, along with their configurations, to the standard
+ * output. */
 pub fn list_devices(arg: &Arguments) -> Result<(), Error> {
 	eprintln!("**** List of audio output devices for {} ({:?}) ****",
 		match arg.host_pick() {
@@ -32,32 +34,27 @@ pub fn list_devices(arg: &Arguments) -> Result<(), Error> {
 		arg.host().id());
 
 	let default = arg.host().default_output_device();
-	for (i, device) in arg.host().output_devices()?.enumerate() {
-		print!("device {}: ", i);
-		match device.name() {
-			Ok(name) => {
-				print!("{}", name);
-
-				/* We have no real way of checking whether two devices are the
-				 * same so, as a workaround, we just check whether their names
-				 * are the same, and call that good enough. */
-				if let Some(dev) = default.as_ref() {
-					if let Ok(d) = dev.name() {
-						if d == name { print!(" [default]"); }
-					}
-				}
-			},
+	let devices = arg.host().output_devices()?.collect::<Vec<_>>();
+	let (default, ambiguous) = find_default(default.as_ref(), &devices);
+
+	for (i, device) in devices.iter().enumerate() {
+		let name = match device.name() {
+			Ok(name) => name,
 			Err(what) => {
-				println!();
-				print!("    ! error while retrieving device name: {}", what)
+				eprintln!("warning: could not retrieve the name of device {}: {}", i, what);
+				"(unknown)".to_owned()
 			}
+		};
+		if default == Some(i) {
+			println!("device {}: {} [default]", i, name);
+		} else {
+			println!("device {}: {}", i, name);
 		}
-		println!();
 
 		let outputs = match device.supported_output_configs() {
 			Ok(outputs) => outputs,
 			Err(what) => {
-				println!("    ! error while retrieving supported configurations: {}", what);
+				eprintln!("warning: could not retrieve the configurations supported by device {}: {}", i, what);
 				continue
 			}
 		};
@@ -65,16 +62,154 @@ pub fn list_devices(arg: &Arguments) -> Result<(), Error> {
 			println!("    output {}: ", i);
 			println!("        channels: {}", output.channels());
 			println!("        format:   {} ({} bytes)",
-				match output.sample_format() {
-					SampleFormat::I16 => "S16",
-					SampleFormat::U16 => "U16",
-					SampleFormat::F32 => "F32"
-				},
-				output.sample_format().sample_size());
+				sample_format_info(output.sample_format()).name.to_ascii_uppercase(),
+				sample_format_info(output.sample_format()).size);
 			println!("        min rate: {}Hz", output.min_sample_rate().0);
 			println!("        max rate: {}Hz", output.max_sample_rate().0);
 		}
 	}
 
+	if ambiguous {
+		eprintln!("note: more than one device shares the name of the default \
+			device, the first one of them was marked as the default");
+	}
+
 	Ok(())
 }
+
+/** Finds the position of the given default device in the given list of
+ * devices, along with whether that position is ambiguous.
+ *
+ * We have no real way of checking whether two devices are the same so, as a
+ * workaround, we check whether their names are the same, and pick the first
+ * match if there's more than one. */
+fn find_default(
+	default: Option<&cpal::Device>,
+	devices: &[cpal::Device]) -> (Option<usize>, bool) {
+
+	let default = match default {
+		Some(default) => default,
+		None => return (None, false)
+	};
+
+	let name = match default.name() {
+		Ok(name) => name,
+		Err(_) => return (None, false)
+	};
+	position_by_name(&name, devices.iter().map(|device| device.name().ok()))
+}
+
+/** Finds the position of the first of the given names that matches the given
+ * one, along with whether any other one matches it too. Names that could not
+ * be retrieved match nothing. */
+fn position_by_name<I>(name: &str, names: I) -> (Option<usize>, bool)
+	where I: IntoIterator<Item = Option<String>> {
+
+	let mut matches = names.into_iter()
+		.enumerate()
+		.filter(|(_, candidate)| candidate.as_deref() == Some(name))
+		.map(|(i, _)| i);
+
+	let first = matches.next();
+	(first, matches.next().is_some())
+}
+
+/** Builds the arguments that pin down the host, the device and the stream
+ * configuration that were picked, so that the same ones get picked again on
+ * this machine, whatever the defaults may be by then. */
+pub fn invocation(arg: &Arguments, format: &OutputConfig) -> String {
+	let mut invocation = Vec::new();
+
+	let host = match arg.host_pick() {
+		Some((index, _)) => Some(index),
+		None => cpal::available_hosts()
+			.iter()
+			.position(|host| *host == arg.host().id())
+	};
+	if let Some(host) = host {
+		invocation.push(format!("--host {}", host));
+	}
+
+	let device = match arg.device_pick() {
+		Some((index, _)) => Some(index),
+		None => arg.host().output_devices()
+			.ok()
+			.and_then(|devices| {
+				let devices = devices.collect::<Vec<_>>();
+				match find_default(arg.host().default_output_device().as_ref(), &devices) {
+					(Some(index), false) => Some(index),
+					_ => None
+				}
+			})
+	};
+	match device {
+		Some(device) => invocation.push(format!("--device {}", device)),
+		None => eprintln!("warning: could not tell which device is the default one, leaving it out")
+	}
+
+	invocation.push(format!("--rate {}", format.sample_rate().0));
+
+	/* With the device picking the number of channels, the ones given are the
+	 * ones in the input, so that's what has to be given again. */
+	if arg.channels_from_device() {
+		invocation.push("--channels-from-device".to_owned());
+		if let Some(channels) = arg.input_channels() {
+			invocation.push(format!("--channels {}", channels));
+		}
+	} else {
+		/* Surround played in stereo is still given by its own channels, for
+		 * it to be downmixed again. */
+		let channels = arg.input_channels().unwrap_or(format.channels());
+		invocation.push(format!("--channels {}", channels));
+	}
+
+	invocation.push(format!("--format {}", sample_format_info(format.sample_format()).name));
+	let endian = arg.endianness().unwrap_or(crate::play::PREFERRED_SAMPLE_ENDIAN);
+	invocation.push(format!("--endian {}", match endian {
+		Endianness::Little => "little",
+		Endianness::Big    => "big",
+		Endianness::Native => "native"
+	}));
+
+	invocation.join(" ")
+}
+
+/** Describes how the input would be played, without playing it. */
+pub fn probe(arg: &Arguments, format: &OutputConfig) {
+	println!("device:   {}", match arg.device_pick() {
+		Some((index, name)) => format!("{} ({})", index, name),
+		None => "default".to_owned()
+	});
+	println!("channels: {}", format.channels());
+	println!("format:   {} ({} bytes)",
+		sample_format_info(format.sample_format()).name.to_ascii_uppercase(),
+		sample_format_info(format.sample_format()).size);
+	println!("rate:     {}Hz", format.sample_rate().0);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn names(names: &[Option<&str>]) -> Vec<Option<String>> {
+		names.iter().map(|name| name.map(str::to_owned)).collect()
+	}
+
+	#[test]
+	fn duplicate_names_are_ambiguous() {
+		let devices = names(&[Some("HDMI"), Some("Speakers"), Some("Speakers")]);
+		assert_eq!(position_by_name("Speakers", devices), (Some(1), true));
+	}
+
+	#[test]
+	fn unique_names_are_not_ambiguous() {
+		let devices = names(&[Some("HDMI"), None, Some("Speakers")]);
+		assert_eq!(position_by_name("Speakers", devices), (Some(2), false));
+	}
+
+	#[test]
+	fn missing_names_match_nothing() {
+		let devices = names(&[None, Some("HDMI")]);
+		assert_eq!(position_by_name("Speakers", devices), (None, false));
+	}
+}
@@ -0,0 +1,384 @@
+use crate::arg::{Arguments, sample_format_info};
+use crate::error::Error;
+use cpal::traits::{DeviceTrait, HostTrait};
+
+/** How a check went. Outcomes are ordered from best to worst. */
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Outcome {
+	Pass,
+	Warn,
+	Fail,
+}
+impl Outcome {
+	fn name(self) -> &'static str {
+		match self {
+			Self::Pass => "pass",
+			Self::Warn => "warn",
+			Self::Fail => "fail"
+		}
+	}
+}
+
+/** What a check found, along with a short explanation of it. */
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Report {
+	pub outcome: Outcome,
+	pub explanation: String,
+}
+impl Report {
+	fn pass(explanation: String) -> Self {
+		Self { outcome: Outcome::Pass, explanation }
+	}
+
+	fn warn(explanation: String) -> Self {
+		Self { outcome: Outcome::Warn, explanation }
+	}
+
+	fn fail(explanation: String) -> Self {
+		Self { outcome: Outcome::Fail, explanation }
+	}
+}
+
+/** A check, which can be run on its own. */
+pub struct Check<'a> {
+	/** Name the check is reported under. */
+	pub name: &'static str,
+	pub run: Box<dyn Fn() -> Report + 'a>,
+}
+
+/** The checks to be run, in order, on the host and device picked by the given
+ * arguments. Picking them may have failed, which the checks that need them
+ * report. */
+pub fn checks(args: &Result<Arguments, Error>) -> Vec<Check<'_>> {
+	/* Checks on the device fail alike when there's no device to check. */
+	let picked = move || args.as_ref().map_err(|what| Report::fail(format!(
+		"could not pick the output device: {}",
+		what)));
+
+	vec![
+		Check {
+			name: "hosts",
+			run: Box::new(hosts)
+		},
+		Check {
+			name: "device",
+			run: Box::new(move || match picked() {
+				Ok(args) => device(args),
+				Err(report) => report
+			})
+		},
+		Check {
+			name: "config",
+			run: Box::new(move || match picked() {
+				Ok(args) => config(args),
+				Err(report) => report
+			})
+		},
+		Check {
+			name: "stream",
+			run: Box::new(move || match picked() {
+				Ok(args) => stream(args),
+				Err(report) => report
+			})
+		},
+		Check {
+			name: "stdin",
+			run: Box::new(stdin)
+		},
+		Check {
+			name: "build",
+			run: Box::new(build)
+		},
+	]
+}
+
+/** Runs the given checks, printing how every one of them went to the standard
+ * output, either as lines of text or as a JSON array. Returns the worst of the
+ * outcomes. */
+pub fn run(checks: &[Check], json: bool) -> Outcome {
+	let results = checks.iter()
+		.map(|check| (check.name, (check.run)()))
+		.collect::<Vec<_>>();
+	print!("{}", render(&results, json));
+
+	results.iter()
+		.map(|(_, report)| report.outcome)
+		.max()
+		.unwrap_or(Outcome::Pass)
+}
+
+/** Puts how the given checks went into words, one line per check, or into a
+ * JSON array with an object per check, on a line of its own. */
+pub fn render(results: &[(&str, Report)], json: bool) -> String {
+	if !json {
+		return results.iter()
+			.map(|(name, report)| format!("{}: {}: {}\n", report.outcome.name(), name, report.explanation))
+			.collect()
+	}
+
+	let entries = results.iter()
+		.map(|(name, report)| format!("{{\"check\":\"{}\",\"result\":\"{}\",\"explanation\":\"{}\"}}",
+			name,
+			report.outcome.name(),
+			escape(&report.explanation)))
+		.collect::<Vec<_>>();
+	format!("[{}]\n", entries.join(","))
+}
+
+/** Escapes the given text for use in a JSON string. */
+fn escape(text: &str) -> String {
+	let mut escaped = String::with_capacity(text.len());
+	for character in text.chars() {
+		match character {
+			'"' => escaped.push_str("\\\""),
+			'\\' => escaped.push_str("\\\\"),
+			'\n' => escaped.push_str("\\n"),
+			'\r' => escaped.push_str("\\r"),
+			'\t' => escaped.push_str("\\t"),
+			character if (character as u32) < 0x20 =>
+				escaped.push_str(&format!("\\u{:04x}", character as u32)),
+			character => escaped.push(character)
+		}
+	}
+
+	escaped
+}
+
+/** Checks that every host available on this platform initializes. */
+pub fn hosts() -> Report {
+	let hosts = cpal::available_hosts();
+	let failed = hosts.iter()
+		.filter_map(|host| cpal::host_from_id(*host).err().map(|what| format!("{:?} ({})", host, what)))
+		.collect::<Vec<_>>();
+
+	if hosts.is_empty() {
+		Report::fail("no audio hosts are available on this platform".to_owned())
+	} else if failed.len() == hosts.len() {
+		Report::fail(format!("none of the hosts initialize: {}", failed.join(", ")))
+	} else if !failed.is_empty() {
+		Report::warn(format!("{} of {} hosts initialize, but not {}",
+			hosts.len() - failed.len(),
+			hosts.len(),
+			failed.join(", ")))
+	} else {
+		Report::pass(format!("all {} hosts initialize: {:?}", hosts.len(), hosts))
+	}
+}
+
+/** Checks on the output device that was picked, which is the default one
+ * unless another was asked for. */
+pub fn device(args: &Arguments) -> Report {
+	let name = match args.device().name() {
+		Ok(name) => name,
+		Err(what) => return Report::warn(format!("the device was found, but its name could not be retrieved: {}", what))
+	};
+
+	match args.device_pick() {
+		Some((index, _)) => Report::pass(format!("device {} is {}", index, name)),
+		None if args.host().default_output_device().is_none() =>
+			Report::fail("the host has no default output device".to_owned()),
+		None => Report::pass(format!("the default output device is {}", name))
+	}
+}
+
+/** Checks on the default configuration of the output device. */
+pub fn config(args: &Arguments) -> Report {
+	match args.device().default_output_config() {
+		Ok(config) => Report::pass(format!("the device defaults to {} channels of {} at {}Hz",
+			config.channels(),
+			sample_format_info(config.sample_format()).name,
+			config.sample_rate().0)),
+		Err(what) => Report::fail(format!("could not retrieve the default configuration of the device: {}", what))
+	}
+}
+
+/** Checks that a stream can be built with the configuration playback would
+ * pick, dropping it right away, without ever playing it. */
+pub fn stream(args: &Arguments) -> Report {
+	let format = match crate::play::negotiate(args) {
+		Ok(format) => format,
+		Err(what) => return Report::fail(format!("no configuration could be negotiated: {}", what))
+	};
+
+	let stream = args.device().build_output_stream_raw(
+		&format.config(),
+		format.sample_format(),
+		|data, _: &cpal::OutputCallbackInfo| crate::convert::silence(
+			data.sample_format(),
+			crate::arg::Endianness::Native,
+			data.bytes_mut()),
+		|_| {});
+	match stream {
+		Ok(stream) => {
+			std::mem::drop(stream);
+			Report::pass(format!("a stream of {} channels of {} at {}Hz can be built",
+				format.channels(),
+				sample_format_info(format.sample_format()).name,
+				format.sample_rate().0))
+		},
+		Err(what) => Report::fail(format!("could not build a stream of {} channels of {} at {}Hz: {}",
+			format.channels(),
+			sample_format_info(format.sample_format()).name,
+			format.sample_rate().0,
+			what))
+	}
+}
+
+/** Checks whether samples can be piped in through the standard input, which
+ * is what gets played when no input is given. Being a terminal is only worth a
+ * mention, as that's how this gets run by hand. */
+pub fn stdin() -> Report {
+	stdin_report(stdin_kind())
+}
+
+/** What there is to say about the standard input being connected to the given
+ * kind of thing, if we could tell. */
+fn stdin_report(kind: Option<Stdin>) -> Report {
+	match kind {
+		Some(Stdin::Closed) => Report::warn("the standard input is closed, so playing without an input given has nothing to read".to_owned()),
+		Some(Stdin::Terminal) => Report::pass("the standard input is a terminal, so samples have to be piped in or inputs given".to_owned()),
+		Some(Stdin::Piped) => Report::pass("the standard input is piped or redirected".to_owned()),
+		None => Report::pass("could not tell what the standard input is on this platform".to_owned())
+	}
+}
+
+/** What the standard input is connected to. */
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(not(unix), allow(dead_code))]
+enum Stdin {
+	Closed,
+	Terminal,
+	Piped,
+}
+
+/** Finds out what the standard input is connected to. */
+#[cfg(unix)]
+fn stdin_kind() -> Option<Stdin> {
+	if unsafe { libc::fcntl(libc::STDIN_FILENO, libc::F_GETFD) } == -1 {
+		Some(Stdin::Closed)
+	} else if unsafe { libc::isatty(libc::STDIN_FILENO) } == 1 {
+		Some(Stdin::Terminal)
+	} else {
+		Some(Stdin::Piped)
+	}
+}
+
+/** There's no telling what the standard input is connected to here. */
+#[cfg(not(unix))]
+fn stdin_kind() -> Option<Stdin> {
+	None
+}
+
+/** Reports the version and the optional features this build was made with. */
+pub fn build() -> Report {
+	let features = [
+		("http", cfg!(feature = "http")),
+		("flac", cfg!(feature = "flac")),
+		("vorbis", cfg!(feature = "vorbis")),
+		("mp3", cfg!(feature = "mp3")),
+		("symphonia", cfg!(feature = "symphonia")),
+		("gzip", cfg!(feature = "gzip")),
+		("zstd", cfg!(feature = "zstd")),
+		("mmap", cfg!(feature = "mmap")),
+		("json", cfg!(feature = "json")),
+	];
+	let enabled = features.iter()
+		.filter(|(_, enabled)| *enabled)
+		.map(|(name, _)| *name)
+		.collect::<Vec<_>>();
+
+	Report::pass(format!("{} {}, with {}",
+		env!("CARGO_PKG_NAME"),
+		env!("CARGO_PKG_VERSION"),
+		if enabled.is_empty() {
+			"no optional features".to_owned()
+		} else {
+			format!("features {}", enabled.join(", "))
+		}))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn results() -> Vec<(&'static str, Report)> {
+		vec![
+			("hosts", Report::pass("all 1 hosts initialize: [Alsa]".to_owned())),
+			("stdin", Report::warn("the standard input is closed".to_owned())),
+			("device", Report::fail("could not pick the output device: \"hw:0\"\tis busy".to_owned())),
+		]
+	}
+
+	#[test]
+	fn reports_render_as_lines_of_text() {
+		assert_eq!(render(&results(), false),
+			"pass: hosts: all 1 hosts initialize: [Alsa]\n\
+			warn: stdin: the standard input is closed\n\
+			fail: device: could not pick the output device: \"hw:0\"\tis busy\n");
+		assert_eq!(render(&[], false), "");
+	}
+
+	#[test]
+	fn reports_render_as_json() {
+		assert_eq!(render(&results(), true),
+			"[{\"check\":\"hosts\",\"result\":\"pass\",\"explanation\":\"all 1 hosts initialize: [Alsa]\"},\
+			{\"check\":\"stdin\",\"result\":\"warn\",\"explanation\":\"the standard input is closed\"},\
+			{\"check\":\"device\",\"result\":\"fail\",\"explanation\":\"could not pick the output device: \\\"hw:0\\\"\\tis busy\"}]\n");
+		assert_eq!(render(&[], true), "[]\n");
+	}
+
+	#[cfg(feature = "json")]
+	#[test]
+	fn json_reports_parse_back() {
+		let parsed: serde_json::Value = serde_json::from_str(&render(&results(), true)).unwrap();
+		assert_eq!(parsed[2]["check"], "device");
+		assert_eq!(parsed[2]["result"], "fail");
+		assert_eq!(parsed[2]["explanation"], "could not pick the output device: \"hw:0\"\tis busy");
+	}
+
+	#[test]
+	fn control_characters_get_escaped() {
+		assert_eq!(escape("a\\b\r\n\u{1}"), "a\\\\b\\r\\n\\u0001");
+	}
+
+	#[test]
+	fn the_worst_outcome_wins() {
+		let check = |outcome| Check {
+			name: "check",
+			run: Box::new(move || Report { outcome, explanation: String::new() })
+		};
+
+		assert_eq!(run(&[], false), Outcome::Pass);
+		assert_eq!(run(&[check(Outcome::Pass), check(Outcome::Warn), check(Outcome::Pass)], false), Outcome::Warn);
+		assert_eq!(run(&[check(Outcome::Fail), check(Outcome::Warn)], true), Outcome::Fail);
+	}
+
+	#[test]
+	fn device_checks_fail_without_a_device() {
+		let args = Err(Error::InvalidChannels);
+		let checks = checks(&args);
+
+		for name in ["device", "config", "stream"] {
+			let check = checks.iter().find(|check| check.name == name).unwrap();
+			let report = (check.run)();
+			assert_eq!(report.outcome, Outcome::Fail);
+			assert!(report.explanation.starts_with("could not pick the output device: "), "{}", report.explanation);
+		}
+	}
+
+	#[test]
+	fn only_a_closed_stdin_gets_warned_about() {
+		assert_eq!(stdin_report(Some(Stdin::Closed)).outcome, Outcome::Warn);
+		assert_eq!(stdin_report(Some(Stdin::Terminal)).outcome, Outcome::Pass);
+		assert_eq!(stdin_report(Some(Stdin::Piped)).outcome, Outcome::Pass);
+		assert_eq!(stdin_report(None).outcome, Outcome::Pass);
+	}
+
+	#[test]
+	fn the_build_gets_reported() {
+		let report = build();
+		assert_eq!(report.outcome, Outcome::Pass);
+		assert!(report.explanation.starts_with(concat!("alplay ", env!("CARGO_PKG_VERSION"), ", with ")));
+		assert_eq!(report.explanation.contains("json"), cfg!(feature = "json"));
+	}
+}
@@ -1,7 +1,9 @@
-use std::num::ParseIntError;
-use cpal::SampleFormat;
+use std::num::{ParseFloatError, ParseIntError};
+use std::path::PathBuf;
+use crate::arg::InputFormat;
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
 	MalformedHost {
 		what: ParseIntError,
@@ -18,18 +20,279 @@ pub enum Error {
 	NoOutputDevice {
 		host_pick: Option<(usize, String)>
 	},
+	NoInputDevice {
+		host_pick: Option<(usize, String)>
+	},
 	DevicesError(cpal::DevicesError),
+	MalformedDevice {
+		what: ParseIntError,
+		value: String,
+	},
+	NoSuchDevice {
+		name: usize,
+	},
 	MalformedChannels(ParseIntError),
 	MalformedSampleRate(ParseIntError),
+	MalformedSampleSize {
+		value: String,
+	},
+	MalformedValidBits {
+		value: String,
+		size: usize,
+	},
+	InvalidChannels,
+	InvalidSampleRate {
+		sample_rate: u32,
+	},
 	MalformedSampleFormat {
 		expected: &'static [&'static str],
 		got: String
 	},
+	MalformedMatrix(String),
+	MismatchedMatrix(String),
+	MalformedStartTime {
+		value: String,
+	},
+	MalformedEndianness {
+		expected: &'static [&'static str],
+		got: String
+	},
+	ConflictingEndianness {
+		suffix: crate::arg::Endianness,
+		flag: crate::arg::Endianness,
+	},
 	SupportedStreamConfigsError(cpal::SupportedStreamConfigsError),
+	DefaultStreamConfigError(cpal::DefaultStreamConfigError),
 	NoSuitableStreamConfig {
 		required_format: Option<cpal::SampleFormat>,
 		required_sample_rate: Option<u32>,
 		required_channels: Option<u16>,
+		supported: Vec<crate::arg::SupportedRange>,
+	},
+	MalformedShuffleSeed(ParseIntError),
+	MalformedCrossfade(ParseIntError),
+	MismatchedItems {
+		/** Name and format of every item that isn't in the format of the
+		 * list, which comes first. */
+		items: Vec<(String, InputFormat)>,
+	},
+	MalformedAntiAliasOrder {
+		value: String,
+	},
+	MalformedSpin(ParseIntError),
+	InvalidSpin {
+		spin: u64,
+	},
+	MalformedMaxUnderruns(ParseIntError),
+	MalformedResync(ParseIntError),
+	MalformedSyncChunk(ParseIntError),
+	MalformedDelay(ParseIntError),
+	MalformedSweep {
+		value: String,
+		what: String,
+	},
+	MalformedSignal {
+		value: String,
+		what: String,
+	},
+	MalformedIdleTimeout(ParseIntError),
+	MalformedBenchDuration(ParseIntError),
+	InvalidBenchDuration,
+	MalformedPreloadLimit(ParseIntError),
+	PreloadTooLarge {
+		limit: u64,
+	},
+	MalformedLoopBuffer {
+		value: String,
+	},
+	MalformedIoBuffer {
+		value: String,
+	},
+	MalformedFd(ParseIntError),
+	#[cfg(unix)]
+	BadFd {
+		fd: i32,
+		what: std::io::Error,
+	},
+	#[cfg(not(unix))]
+	FdUnsupported,
+	MalformedAcceptTimeout(ParseIntError),
+	#[cfg(unix)]
+	SocketExists {
+		path: PathBuf,
+	},
+	#[cfg(unix)]
+	Socket {
+		path: PathBuf,
+		what: std::io::Error,
+	},
+	AcceptTimeout {
+		address: String,
+	},
+	Listen {
+		address: String,
+		what: std::io::Error,
+	},
+	MalformedRecvBuffer(ParseIntError),
+	Network {
+		address: String,
+		what: std::io::Error,
+	},
+	MalformedConnectTimeout(ParseIntError),
+	#[cfg(not(unix))]
+	SocketUnsupported,
+	MalformedTrimThreshold(ParseFloatError),
+	MalformedSegments {
+		value: String,
+		what: String,
+	},
+	MalformedTrimEnd {
+		value: String,
+	},
+	MalformedAbLoop {
+		value: String,
+		what: String,
+	},
+	MalformedLoopCount {
+		value: String,
+	},
+	TrimNeedsFiles,
+	Follow {
+		path: PathBuf,
+		what: std::io::Error,
+	},
+	FollowNeedsOneFile,
+	MalformedFollowLimit(ParseIntError),
+	MalformedFollowTimeout(ParseIntError),
+	#[cfg(feature = "http")]
+	Http {
+		url: String,
+		what: String,
+	},
+	#[cfg(feature = "http")]
+	HttpStatus {
+		url: String,
+		status: u16,
+	},
+	#[cfg(not(feature = "http"))]
+	HttpUnsupported {
+		url: String,
+	},
+	InputError(std::io::Error),
+	SidecarRead {
+		path: PathBuf,
+		what: std::io::Error,
+	},
+	MalformedSidecar {
+		path: PathBuf,
+		what: String,
+	},
+	#[cfg(not(feature = "json"))]
+	SidecarJsonUnsupported {
+		path: PathBuf,
+	},
+	MalformedHeader(String),
+	HeaderOutput(std::io::Error),
+	Render {
+		path: PathBuf,
+		what: std::io::Error,
+	},
+	RenderEndless,
+	Record {
+		path: PathBuf,
+		what: std::io::Error,
+	},
+	MalformedRecordDuration {
+		value: String,
+	},
+	NoSuitableRecordConfig {
+		format: InputFormat,
+	},
+	AuditLog {
+		path: PathBuf,
+		what: std::io::Error,
+	},
+	#[cfg(not(feature = "json"))]
+	AuditLogJsonUnsupported,
+	NeedsDevice {
+		flag: &'static str,
+	},
+	MalformedWav(String),
+	UnsupportedWav {
+		tag: u16,
+		bits: u16,
+	},
+	UnsupportedWavSubFormat {
+		guid: String,
+	},
+	UnsupportedWavCodec {
+		tag: u16,
+		name: Option<&'static str>,
+	},
+	MalformedAiff(String),
+	UnsupportedAiff(String),
+	MalformedAu(String),
+	UnsupportedContainer(&'static str),
+	#[cfg(not(all(feature = "flac", feature = "vorbis")))]
+	ContainerNotBuilt {
+		container: &'static str,
+		feature: &'static str,
+	},
+	#[cfg(feature = "flac")]
+	MalformedFlac(String),
+	#[cfg(feature = "vorbis")]
+	MalformedVorbis(String),
+	#[cfg(feature = "mp3")]
+	MalformedMp3(String),
+	#[cfg(feature = "symphonia")]
+	UnsupportedDecode(String),
+	UnsupportedAu(String),
+	PlaylistUnreadable {
+		path: PathBuf,
+		what: std::io::Error,
+	},
+	MalformedPlaylist {
+		path: PathBuf,
+		line: Option<usize>,
+		what: String,
+	},
+	Mirror {
+		index: usize,
+		what: String,
+	},
+	MirrorStream {
+		index: usize,
+		what: cpal::BuildStreamError,
+	},
+	TimestampLog {
+		path: PathBuf,
+		what: std::io::Error,
+	},
+	ClipReport {
+		path: PathBuf,
+		what: std::io::Error,
+	},
+	Tee {
+		path: PathBuf,
+		what: std::io::Error,
+	},
+	OutputStream(cpal::BuildStreamError),
+	InputStream(cpal::BuildStreamError),
+	RecordStream(cpal::PlayStreamError),
+	InputStreamFailed(cpal::StreamError),
+	PlayStream(cpal::PlayStreamError),
+	StreamFailed(cpal::StreamError),
+	MirrorFailed(cpal::StreamError),
+	ReadFailed(std::io::Error),
+	TruncatedData {
+		expected: u64,
+		got: u64,
+	},
+	IdleTimeout {
+		idle: std::time::Duration,
+	},
+	TooManyUnderruns {
+		limit: u64,
 	},
 }
 impl std::fmt::Display for Error {
@@ -47,12 +310,38 @@ impl std::fmt::Display for Error {
 				None =>
 					write!(f, "the default host has no audio output devices")
 			},
+			Self::NoInputDevice { host_pick } => match host_pick {
+				Some((index, name)) =>
+					write!(f, "host {} ({}) has no audio input devices", index, name),
+				None =>
+					write!(f, "the default host has no audio input devices")
+			},
 			Self::DevicesError(what) =>
 				write!(f, "{}", what),
+			Self::MalformedDevice { what, value } =>
+				write!(f, "the given device name \"{}\" is malformed: {}", value, what),
+			Self::NoSuchDevice { name } =>
+				write!(f, "no such device {}", name),
 			Self::MalformedChannels(what) =>
 				write!(f, "the given channel count is malformed: {}", what),
 			Self::MalformedSampleRate(what) =>
 				write!(f, "the given sample rate is malformed: {}", what),
+			Self::MalformedSampleSize { value } =>
+				write!(f, "the given sample size \"{}\" is malformed, expected a whole number of bytes from 1 to {}",
+					value,
+					crate::arg::MAX_PACKED_SIZE),
+			Self::MalformedValidBits { value, size } =>
+				write!(f, "the given number of valid bits \"{}\" is malformed, expected from 1 to {} bits for samples {} bytes wide",
+					value,
+					size * 8,
+					size),
+			Self::InvalidChannels =>
+				write!(f, "at least one channel is needed for playback"),
+			Self::InvalidSampleRate { sample_rate } =>
+				write!(f, "a sample rate of {}Hz is out of range, it must be between {}Hz and {}Hz",
+					sample_rate,
+					crate::arg::MIN_SAMPLE_RATE,
+					crate::arg::MAX_SAMPLE_RATE),
 			Self::MalformedSampleFormat { expected, got } => {
 				write!(f, "the given sample format \"{}\" is malformed. expected one of {{", got)?;
 				for (i, expect) in expected.iter().enumerate() {
@@ -63,61 +352,354 @@ impl std::fmt::Display for Error {
 				}
 				write!(f, "}}")
 			},
+			Self::MalformedMatrix(what) =>
+				write!(f, "the given mixing matrix is malformed: {}", what),
+			Self::MismatchedMatrix(what) =>
+				write!(f, "the given mixing matrix does not fit the channels being played: {}", what),
+			Self::MalformedStartTime { value } =>
+				write!(f, "the given start time \"{}\" is neither an RFC 3339 timestamp nor a number of seconds since the epoch", value),
+			Self::MalformedEndianness { expected, got } => {
+				write!(f, "the given endianness \"{}\" is malformed. expected one of {{", got)?;
+				for (i, expect) in expected.iter().enumerate() {
+					write!(f, "\"{}\"", expect)?;
+					if i + 1 < expected.len() {
+						write!(f, ", ")?;
+					}
+				}
+				write!(f, "}}")
+			},
+			Self::ConflictingEndianness { suffix, flag } =>
+				write!(f, "the sample format is {:?} endian, but {:?} endian was requested", suffix, flag),
 			Self::SupportedStreamConfigsError(what) =>
 				write!(f, "{}", what),
+			Self::DefaultStreamConfigError(what) =>
+				write!(f, "could not get the default configuration of the device: {}", what),
 			Self::NoSuitableStreamConfig {
 				required_format,
 				required_sample_rate,
-				required_channels } => {
+				required_channels,
+				supported } => {
 
-				let count =
-					  if required_channels.is_some()    { 1 } else { 0 }
-					+ if required_format.is_some()      { 1 } else { 0 }
-					+ if required_sample_rate.is_some() { 1 } else { 0 };
+				let mut required = Vec::new();
+				if let Some(format) = required_format.as_ref() {
+					let info = crate::arg::sample_format_info(*format);
+					required.push(format!("{} {} format",
+						info.article,
+						info.name.to_ascii_uppercase()));
+				}
+				if let Some(sample_rate) = required_sample_rate.as_ref() {
+					required.push(format!("a sample rate of {}Hz", sample_rate));
+				}
+				if let Some(channels) = required_channels.as_ref() {
+					required.push(format!("{} channels", channels));
+				}
 
-				if count == 0 {
+				if required.is_empty() {
 					write!(f, "the device supports no output formats")
 				} else {
 					write!(f, "failed to find a suitable output format with ")?;
-
-					let mut written = 0;
-					if let Some(format) = required_format.as_ref() {
-						write!(f, "{} format",
-							match format {
-								SampleFormat::I16 => "an S16",
-								SampleFormat::U16 => "a U16",
-								SampleFormat::F32 => "an F32"
-							})?;
-						written += 1;
+					for (i, requirement) in required.iter().enumerate() {
+						if i + 1 == required.len() && i > 0 {
+							write!(f, " and ")?;
+						} else if i > 0 {
+							write!(f, ", ")?;
+						}
+						write!(f, "{}", requirement)?;
 					}
 
-					if let Some(sample_rate) = required_sample_rate.as_ref() {
-						if written > 0 {
-							if count == 2 {
+					/* Point the user to what they could have asked for. */
+					if !supported.is_empty() {
+						write!(f, ". the device supports ")?;
+						for (i, config) in supported.iter().enumerate() {
+							if i > 0 {
 								write!(f, ", ")?;
-							} else if count == 3 {
-								write!(f, " and ")?;
 							}
 
-							write!(f, "a sample rate of {}Hz", sample_rate)?;
-							written += 1;
-						}
-					}
-
-					if let Some(channels) = required_channels.as_ref() {
-						if written > 0 {
-							write!(f, " and ")?;
+							let min = config.min_sample_rate;
+							let max = config.max_sample_rate;
+							write!(f, "{:?} with {} channels at ", config.sample_format, config.channels)?;
+							if min == max {
+								write!(f, "{}Hz", min)?;
+							} else {
+								write!(f, "{}-{}Hz", min, max)?;
+							}
 						}
-						write!(f, "{} channels", channels)?;
 					}
 
 					Ok(())
 				}
-			}
+			},
+			Self::MalformedShuffleSeed(what) =>
+				write!(f, "the given shuffle seed is malformed: {}", what),
+			Self::MalformedMaxUnderruns(what) =>
+				write!(f, "the given maximum number of underruns is malformed: {}", what),
+			Self::MalformedSpin(what) =>
+				write!(f, "the given spin time is malformed: {}", what),
+			Self::InvalidSpin { spin } =>
+				write!(f, "spinning for {}us is too long, it can't be any longer than {}us",
+					spin,
+					crate::arg::MAX_SPIN_US),
+			Self::MalformedCrossfade(what) =>
+				write!(f, "the given crossfade length is malformed: {}", what),
+			Self::MismatchedItems { items } => {
+				write!(f, "the input files are not all in the same format, \
+					pass --force-concat to have them converted into that of the list:")?;
+
+				/* Mark the columns that differ from the format of the list. */
+				let width = items.iter()
+					.map(|(name, _)| name.chars().count())
+					.max()
+					.unwrap_or(0);
+				write!(f, "\n  {:<width$}  {:>9}  {:>9}  format", "file", "channels", "rate", width = width)?;
+				let first = items[0].1;
+				let mark = |same: bool| if same { ' ' } else { '*' };
+				for (name, format) in items {
+					let sample_format = InputFormat {
+						channels: first.channels,
+						sample_rate: first.sample_rate,
+						..*format
+					};
+					write!(f, "\n  {:<width$}  {:>8}{}  {:>8}{}  {}{}",
+						name,
+						format.channels,
+						mark(format.channels == first.channels),
+						format.sample_rate,
+						mark(format.sample_rate == first.sample_rate),
+						crate::arg::sample_format_name(format.sample_format, format.endian),
+						mark(sample_format.compatible(&first)),
+						width = width)?;
+				}
+
+				Ok(())
+			},
+			Self::MalformedAntiAliasOrder { value } =>
+				write!(f, "the given filter order \"{}\" is malformed, expected an even number up to {}",
+					value,
+					crate::filter::MAX_ORDER),
+			Self::MalformedPreloadLimit(what) =>
+				write!(f, "the given preload limit is malformed: {}", what),
+			Self::PreloadTooLarge { limit } =>
+				write!(f, "the input is larger than the preload limit of {} MiB", limit / (1024 * 1024)),
+			Self::MalformedLoopBuffer { value } =>
+				write!(f, "the given loop buffer size \"{}\" is malformed, expected a number of bytes, optionally followed by K, M or G", value),
+			Self::MalformedIoBuffer { value } =>
+				write!(f, "the given I/O buffer size \"{}\" is malformed, expected a number of bytes other than zero, optionally followed by K, M or G, or \"off\"", value),
+			Self::MalformedFd(what) =>
+				write!(f, "the given file descriptor is malformed: {}", what),
+			#[cfg(unix)]
+			Self::BadFd { fd, what } =>
+				write!(f, "could not read from file descriptor {}: {}", fd, what),
+			#[cfg(not(unix))]
+			Self::FdUnsupported =>
+				write!(f, "reading from a file descriptor is not supported on this platform"),
+			Self::MalformedAcceptTimeout(what) =>
+				write!(f, "the given accept timeout is malformed: {}", what),
+			#[cfg(unix)]
+			Self::SocketExists { path } =>
+				write!(f, "could not listen on {}: the path already exists, remove it if it's a stale socket", path.display()),
+			#[cfg(unix)]
+			Self::Socket { path, what } =>
+				write!(f, "could not listen on {}: {}", path.display(), what),
+			Self::AcceptTimeout { address } =>
+				write!(f, "nobody connected to {} in time", address),
+			Self::Listen { address, what } =>
+				write!(f, "could not listen on {}: {}", address, what),
+			Self::MalformedRecvBuffer(what) =>
+				write!(f, "the given receive buffer size is malformed: {}", what),
+			Self::Network { address, what } =>
+				write!(f, "could not connect to {}: {}", address, what),
+			Self::MalformedConnectTimeout(what) =>
+				write!(f, "the given connect timeout is malformed: {}", what),
+			#[cfg(not(unix))]
+			Self::SocketUnsupported =>
+				write!(f, "listening on a Unix domain socket is not supported on this platform"),
+			Self::MalformedSyncChunk(what) =>
+				write!(f, "the given sync chunk size is malformed: {}", what),
+			Self::MalformedDelay(what) =>
+				write!(f, "the given delay is malformed: {}", what),
+			Self::MalformedSweep { value, what } =>
+				write!(f, "the given sweep \"{}\" is malformed: {}", value, what),
+			Self::MalformedSignal { value, what } =>
+				write!(f, "the given signal parameter \"{}\" is malformed: {}", value, what),
+			Self::MalformedIdleTimeout(what) =>
+				write!(f, "the given idle timeout is malformed: {}", what),
+			Self::MalformedBenchDuration(what) =>
+				write!(f, "the given benchmark duration is malformed: {}", what),
+			Self::InvalidBenchDuration =>
+				write!(f, "every stage must be benchmarked for at least one second"),
+			Self::MalformedResync(what) =>
+				write!(f, "the given resynchronization interval is malformed: {}", what),
+			#[cfg(feature = "http")]
+			Self::Http { url, what } =>
+				write!(f, "could not open {}: {}", url, what),
+			#[cfg(feature = "http")]
+			Self::HttpStatus { url, status } =>
+				write!(f, "could not open {}: the server responded with status {}", url, status),
+			#[cfg(not(feature = "http"))]
+			Self::HttpUnsupported { url } =>
+				write!(f, "could not open {}: alplay was built without HTTP support \
+					(enable the \"http\" feature)", url),
+			Self::SidecarRead { path, what } =>
+				write!(f, "could not read the header file {}: {}", path.display(), what),
+			Self::MalformedSidecar { path, what } =>
+				write!(f, "the header file {} is malformed: {}", path.display(), what),
+			#[cfg(not(feature = "json"))]
+			Self::SidecarJsonUnsupported { path } =>
+				write!(f, "could not read {}: alplay was built without JSON support \
+					(enable the \"json\" feature)", path.display()),
+			Self::MalformedHeader(what) =>
+				write!(f, "the header of the input is malformed: {}", what),
+			Self::Render { path, what } =>
+				write!(f, "could not render to {}: {}", path.display(), what),
+			Self::RenderEndless =>
+				write!(f, "the signal plays until interrupted, so it can't be rendered: \
+					give it a --duration, or a number of times to --loop"),
+			Self::Record { path, what } =>
+				write!(f, "could not record to {}: {}", path.display(), what),
+			Self::MalformedRecordDuration { value } =>
+				write!(f, "the given duration \"{}\" is malformed: it must be a positive number of seconds", value),
+			Self::NoSuitableRecordConfig { format } =>
+				write!(f, "the input device can't record {} channels of {} samples at {}Hz",
+					format.channels,
+					crate::arg::sample_format_info(format.sample_format).name,
+					format.sample_rate),
+			Self::AuditLog { path, what } =>
+				write!(f, "could not append to the audit log at {}: {}", path.display(), what),
+			#[cfg(not(feature = "json"))]
+			Self::AuditLogJsonUnsupported =>
+				write!(f, "could not write the audit log: alplay was built without JSON support \
+					(enable the \"json\" feature)"),
+			Self::NeedsDevice { flag } =>
+				write!(f, "--{} needs an audio device, which the null output does without", flag),
+			Self::HeaderOutput(what) =>
+				write!(f, "could not write the header out: {}", what),
+			Self::InputError(what) =>
+				write!(f, "could not read the input: {}", what),
+			Self::MalformedWav(what) =>
+				write!(f, "malformed WAV file: {}", what),
+			Self::UnsupportedWav { tag, bits } =>
+				write!(f, "unsupported WAV file: format tag {} with {} bits per sample", tag, bits),
+			Self::UnsupportedWavSubFormat { guid } =>
+				write!(f, "unsupported WAV file: unknown sub-format {}", guid),
+			Self::UnsupportedWavCodec { tag, name } => match name {
+				Some(name) =>
+					write!(f, "unsupported WAV file: the samples are compressed with {} (format tag {:#06x}), \
+						only PCM and floating point samples can be played", name, tag),
+				None =>
+					write!(f, "unsupported WAV file: the samples are compressed with an unknown codec \
+						(format tag {:#06x}), only PCM and floating point samples can be played", tag)
+			},
+			Self::MalformedAiff(what) =>
+				write!(f, "malformed AIFF file: {}", what),
+			Self::UnsupportedAiff(what) =>
+				write!(f, "unsupported AIFF file: {}", what),
+			Self::MalformedAu(what) =>
+				write!(f, "malformed AU file: {}", what),
+			Self::UnsupportedContainer(name) =>
+				write!(f, "{} input is not supported. use --raw to play it as raw samples anyway", name),
+			#[cfg(not(all(feature = "flac", feature = "vorbis")))]
+			Self::ContainerNotBuilt { container, feature } =>
+				write!(f, "could not open {} input: alplay was built without {} support \
+					(enable the \"{}\" feature)", container, container, feature),
+			#[cfg(feature = "flac")]
+			Self::MalformedFlac(what) =>
+				write!(f, "malformed FLAC stream: {}", what),
+			#[cfg(feature = "vorbis")]
+			Self::MalformedVorbis(what) =>
+				write!(f, "malformed Ogg Vorbis stream: {}", what),
+			#[cfg(feature = "mp3")]
+			Self::MalformedMp3(what) =>
+				write!(f, "malformed MP3 stream: {}", what),
+			#[cfg(feature = "symphonia")]
+			Self::UnsupportedDecode(what) =>
+				write!(f, "could not decode the input: {}", what),
+			Self::UnsupportedAu(what) =>
+				write!(f, "unsupported AU file: {}", what),
+			Self::MalformedTrimThreshold(what) =>
+				write!(f, "the given silence threshold is malformed: {}", what),
+			Self::MalformedSegments { value, what } =>
+				write!(f, "the given segments \"{}\" are malformed: {}", value, what),
+			Self::MalformedTrimEnd { value } =>
+				write!(f, "the given amount to trim off the end \"{}\" is malformed, expected a number of bytes, optionally followed by K, M or G, or a time ending in s or containing a colon", value),
+			Self::MalformedAbLoop { value, what } =>
+				write!(f, "the given loop \"{}\" is malformed: {}", value, what),
+			Self::MalformedLoopCount { value } =>
+				write!(f, "the given number of loops \"{}\" is malformed, expected a positive number", value),
+			Self::TrimNeedsFiles =>
+				write!(f, "silence can only be trimmed off input files"),
+			Self::Follow { path, what } =>
+				write!(f, "could not open {}: {}", path.display(), what),
+			Self::FollowNeedsOneFile =>
+				write!(f, "only a single input file can be followed"),
+			Self::MalformedFollowLimit(what) =>
+				write!(f, "the given reopen limit is malformed: {}", what),
+			Self::MalformedFollowTimeout(what) =>
+				write!(f, "the given follow timeout is malformed: {}", what),
+			Self::PlaylistUnreadable { path, what } =>
+				write!(f, "could not read playlist {}: {}", path.display(), what),
+			Self::MalformedPlaylist { path, line, what } => match line {
+				Some(line) =>
+					write!(f, "malformed playlist {}, line {}: {}", path.display(), line, what),
+				None =>
+					write!(f, "malformed playlist {}: {}", path.display(), what)
+			},
+			Self::Mirror { index, what } =>
+				write!(f, "could not mirror to device {}: {}", index, what),
+			Self::MirrorStream { index, what } =>
+				write!(f, "could not initialize output stream for device {}: {}", index, what),
+			Self::TimestampLog { path, what } =>
+				write!(f, "could not write the timestamp log {}: {}", path.display(), what),
+			Self::ClipReport { path, what } =>
+				write!(f, "could not write the clip report {}: {}", path.display(), what),
+			Self::Tee { path, what } =>
+				write!(f, "could not capture the output to {}: {}", path.display(), what),
+			Self::OutputStream(what) =>
+				write!(f, "could not initialize output stream: {}", what),
+			Self::PlayStream(what) =>
+				write!(f, "could not start the output stream: {}", what),
+			Self::InputStream(what) =>
+				write!(f, "could not initialize input stream: {}", what),
+			Self::RecordStream(what) =>
+				write!(f, "could not start the input stream: {}", what),
+			Self::InputStreamFailed(what) =>
+				write!(f, "input stream failed: {}", what),
+			Self::StreamFailed(what) =>
+				write!(f, "output stream failed: {}", what),
+			Self::MirrorFailed(what) =>
+				write!(f, "mirror output stream failed: {}", what),
+			Self::ReadFailed(what) =>
+				write!(f, "data read failed: {}", what),
+			Self::TruncatedData { expected, got } =>
+				write!(f, "the input is truncated: its data chunk says it holds {} bytes, but only {} are there",
+					expected,
+					got),
+			Self::IdleTimeout { idle } =>
+				write!(f, "no input came in for {:.1}s, giving up on it", idle.as_secs_f64()),
+			Self::TooManyUnderruns { limit } =>
+				write!(f, "the output ran out of samples more than {} times, giving up on playback", limit),
 		}
 	}
 }
 impl std::error::Error for Error {}
+impl Error {
+	/** Turns an error reading the input into one of ours. Sources that fail
+	 * with one of ours have it wrapped in the I/O error, which gets unwrapped
+	 * here. */
+	pub fn read_failed(what: std::io::Error) -> Self {
+		match what.get_ref().map(|inner| inner.is::<Self>()) {
+			Some(true) => *what.into_inner().unwrap().downcast::<Self>().unwrap(),
+			_ => Self::ReadFailed(what)
+		}
+	}
+
+	/** Whether reading the input failed because it turned out to be shorter
+	 * than it said it was, rather than because of anything else. */
+	pub fn is_truncation(what: &std::io::Error) -> bool {
+		matches!(
+			what.get_ref().and_then(|inner| inner.downcast_ref::<Self>()),
+			Some(Self::TruncatedData { .. }))
+	}
+}
 impl From<cpal::DevicesError> for Error {
 	fn from(what: cpal::DevicesError) -> Self {
 		Self::DevicesError(what)
@@ -127,4 +709,9 @@ impl From<cpal::SupportedStreamConfigsError> for Error {
 	fn from(what: cpal::SupportedStreamConfigsError) -> Self {
 		Self::SupportedStreamConfigsError(what)
 	}
-}
\ No newline at end of file
+}
+impl From<cpal::DefaultStreamConfigError> for Error {
+	fn from(what: cpal::DefaultStreamConfigError) -> Self {
+		Self::DefaultStreamConfigError(what)
+	}
+}
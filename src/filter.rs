@@ -0,0 +1,145 @@
+use std::f64::consts::PI;
+
+/** Highest order a low-pass filter may have. */
+pub const MAX_ORDER: usize = 16;
+
+/** A second order section, in transposed direct form II. */
+#[derive(Debug, Copy, Clone)]
+struct Biquad {
+	b0: f64,
+	b1: f64,
+	b2: f64,
+	a1: f64,
+	a2: f64,
+}
+impl Biquad {
+	/** The low-pass section with the given cutoff, as a fraction of the sample
+	 * rate, and quality factor. */
+	fn low_pass(cutoff: f64, q: f64) -> Self {
+		let w0 = 2.0 * PI * cutoff;
+		let (sin, cos) = w0.sin_cos();
+		let alpha = sin / (2.0 * q);
+		let a0 = 1.0 + alpha;
+
+		Self {
+			b0: (1.0 - cos) / 2.0 / a0,
+			b1: (1.0 - cos) / a0,
+			b2: (1.0 - cos) / 2.0 / a0,
+			a1: -2.0 * cos / a0,
+			a2: (1.0 - alpha) / a0,
+		}
+	}
+
+	/** Runs a single sample through the section, given its state. */
+	fn run(&self, state: &mut [f64; 2], input: f64) -> f64 {
+		let output = self.b0 * input + state[0];
+		state[0] = self.b1 * input - self.a1 * output + state[1];
+		state[1] = self.b2 * input - self.a2 * output;
+
+		output
+	}
+}
+
+/** A Butterworth low-pass filter, run over interleaved frames with every
+ * channel kept apart. It's a cascade of second order sections, one for every
+ * two orders, so odd orders get rounded up. */
+pub struct LowPass {
+	sections: Vec<Biquad>,
+	/** State of every section, for every channel in turn. */
+	state: Vec<[f64; 2]>,
+	channels: usize,
+}
+impl LowPass {
+	/** Creates a filter of the given order, cutting off at the given frequency
+	 * for samples at the given rate. */
+	pub fn new(order: usize, cutoff: f64, sample_rate: u32, channels: u16) -> Self {
+		let pairs = order.div_ceil(2);
+		let poles = 2 * pairs;
+		let cutoff = cutoff / f64::from(sample_rate);
+
+		let sections = (0..pairs)
+			.map(|k| {
+				/* The poles of a Butterworth filter are evenly spread around
+				 * the unit circle, which puts every pair at this Q. */
+				let angle = PI * (2 * k + 1) as f64 / (2 * poles) as f64;
+				Biquad::low_pass(cutoff, 1.0 / (2.0 * angle.cos()))
+			})
+			.collect::<Vec<_>>();
+		let channels = usize::from(channels);
+
+		Self {
+			state: vec![[0.0; 2]; sections.len() * channels],
+			sections,
+			channels
+		}
+	}
+
+	/** Filters a single frame, in place. */
+	pub fn run(&mut self, frame: &mut [f32]) {
+		let sections = self.sections.len();
+		for (channel, sample) in frame.iter_mut().enumerate().take(self.channels) {
+			let state = &mut self.state[channel * sections..(channel + 1) * sections];
+			let mut value = f64::from(*sample);
+			for (section, state) in self.sections.iter().zip(state) {
+				value = section.run(state, value);
+			}
+			*sample = value as f32;
+		}
+	}
+
+	/** Forgets everything that went through the filter. */
+	pub fn reset(&mut self) {
+		self.state.iter_mut().for_each(|state| *state = [0.0; 2]);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn low_passes_leave_a_constant_alone() {
+		for order in [2, 3, 8, MAX_ORDER] {
+			let mut filter = LowPass::new(order, 7200.0, 48000, 2);
+			let mut frame = [0.0; 2];
+			for _ in 0..4800 {
+				frame = [0.5, -0.25];
+				filter.run(&mut frame);
+			}
+			assert!((frame[0] - 0.5).abs() < 1e-4, "order {}: {}", order, frame[0]);
+			assert!((frame[1] + 0.25).abs() < 1e-4, "order {}: {}", order, frame[1]);
+		}
+	}
+
+	/** Level of a tone at the given frequency once it's gone through a filter
+	 * of the given order cutting off at 1kHz, as a fraction of the level it
+	 * went in at, leaving out the start, where the filter is yet to settle. */
+	fn response(frequency: f64, order: usize) -> f64 {
+		let mut filter = LowPass::new(order, 1000.0, 48000, 1);
+		let mut power = 0.0;
+		for i in 0..48000 {
+			let mut frame = [(2.0 * PI * frequency * f64::from(i) / 48000.0).sin() as f32];
+			filter.run(&mut frame);
+			if i >= 4800 {
+				power += f64::from(frame[0]) * f64::from(frame[0]);
+			}
+		}
+
+		(power / 43200.0 * 2.0).sqrt()
+	}
+
+	#[test]
+	fn low_passes_follow_the_butterworth_response() {
+		for order in [2, 4, 8] {
+			/* Flat well below the cutoff, 3dB down right at it, and falling
+			 * off by 6dB an octave for every order past it. */
+			let expected = |frequency: f64| 1.0 / (1.0 + (frequency / 1000.0).powi(2 * order as i32)).sqrt();
+			for frequency in [100.0, 1000.0, 2000.0, 4000.0] {
+				let level = response(frequency, order);
+				assert!(
+					(level - expected(frequency)).abs() < 0.01,
+					"order {} at {}Hz: {} rather than {}", order, frequency, level, expected(frequency));
+			}
+		}
+	}
+}
@@ -0,0 +1,166 @@
+use crate::arg::{Endianness, InputFormat};
+use crate::error::Error;
+use claxon::FlacReader;
+use std::io::{ErrorKind, Read};
+
+/** A reader that decodes the frames of a FLAC stream as they're needed, handing
+ * out their samples interleaved and in native byte order. */
+pub struct FlacSource<R>
+	where R: Read {
+
+	reader: FlacReader<R>,
+	/** Format the samples get handed out in. */
+	format: cpal::SampleFormat,
+	/** Number of bits in every sample of the stream. */
+	bits: u32,
+	/** Buffer the last block got decoded into, kept around for the next one. */
+	buffer: Vec<i32>,
+	/** Decoded samples that have yet to be handed out. */
+	output: Vec<u8>,
+	/** How much of the decoded samples has been handed out already. */
+	offset: usize,
+}
+impl<R> FlacSource<R>
+	where R: Read {
+
+	/** Decodes the next block of the stream into the output buffer. Returns
+	 * whether there was any block left to decode. */
+	fn decode(&mut self) -> std::io::Result<bool> {
+		let buffer = std::mem::take(&mut self.buffer);
+		let block = self.reader.blocks()
+			.read_next_or_eof(buffer)
+			.map_err(|what| std::io::Error::new(ErrorKind::InvalidData, what.to_string()))?;
+		let block = match block {
+			Some(block) => block,
+			None => return Ok(false)
+		};
+
+		self.output.clear();
+		self.offset = 0;
+		for i in 0..block.duration() {
+			for channel in 0..block.channels() {
+				let sample = block.sample(channel, i);
+				match self.format {
+					cpal::SampleFormat::I16 => {
+						/* Narrower samples get scaled up to 16 bits. */
+						let sample = (sample << (16 - self.bits)) as i16;
+						self.output.extend(&sample.to_ne_bytes());
+					},
+					_ => {
+						let scale = (1u64 << (self.bits - 1)) as f32;
+						let sample = sample as f32 / scale;
+						self.output.extend(&sample.to_ne_bytes());
+					}
+				}
+			}
+		}
+
+		self.buffer = block.into_buffer();
+		Ok(true)
+	}
+}
+impl<R> Read for FlacSource<R>
+	where R: Read {
+
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		if buf.is_empty() { return Ok(0) }
+		while self.offset == self.output.len() {
+			if !self.decode()? { return Ok(0) }
+		}
+
+		let len = usize::min(buf.len(), self.output.len() - self.offset);
+		buf[..len].copy_from_slice(&self.output[self.offset..self.offset + len]);
+		self.offset += len;
+
+		Ok(len)
+	}
+}
+
+/** Opens the FLAC stream in the given source, magic number included, as FLAC
+ * streams are always read from the very start. Nothing ever gets seeked over,
+ * so this works just as well for pipes as it does for files. Returns a reader
+ * over the decoded samples, along with their format. */
+pub fn open<R>(source: R) -> Result<(Box<dyn Read + Send>, InputFormat), Error>
+	where R: Read + Send + 'static {
+
+	let reader = FlacReader::new(source)
+		.map_err(|what| Error::MalformedFlac(what.to_string()))?;
+	let info = reader.streaminfo();
+
+	/* Samples that fit in 16 bits are played as they are. Anything wider would
+	 * lose precision, so it gets played as floating point instead. */
+	let format = match info.bits_per_sample {
+		1..=16  => cpal::SampleFormat::I16,
+		17..=32 => cpal::SampleFormat::F32,
+		bits => return Err(Error::MalformedFlac(
+			format!("{} bit samples are not valid", bits)))
+	};
+	if info.channels == 0 || info.channels > u32::from(u16::MAX) {
+		return Err(Error::MalformedFlac(
+			format!("{} channels is not a valid channel count", info.channels)))
+	}
+
+	let input = InputFormat {
+		channels: info.channels as u16,
+		sample_rate: info.sample_rate,
+		sample_format: format,
+		endian: Endianness::Native
+	};
+	let source = FlacSource {
+		reader,
+		format,
+		bits: info.bits_per_sample,
+		buffer: Vec::new(),
+		output: Vec::new(),
+		offset: 0
+	};
+
+	Ok((Box::new(source), input))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/** An eighth of a second of stereo 16-bit samples at 8000Hz, in blocks of
+	 * 400 frames, the left channel going up in a sawtooth and the right one
+	 * going down in it. */
+	const RAMP: &[u8] = include_bytes!("../fixtures/ramp.flac");
+
+	fn ramp(i: i32) -> i16 {
+		((i * 37) % 2000 - 1000) as i16
+	}
+
+	#[test]
+	fn frames_get_decoded_into_interleaved_samples() {
+		let (mut reader, format) = open(RAMP).unwrap();
+		assert_eq!(format.channels, 2);
+		assert_eq!(format.sample_rate, 8000);
+		assert_eq!(format.sample_format, cpal::SampleFormat::I16);
+
+		let mut data = Vec::new();
+		reader.read_to_end(&mut data).unwrap();
+		let samples = data.chunks(2)
+			.map(|sample| i16::from_ne_bytes([sample[0], sample[1]]))
+			.collect::<Vec<_>>();
+		let expected = (0..1000)
+			.flat_map(|i| vec![ramp(i), -ramp(i)])
+			.collect::<Vec<_>>();
+		assert_eq!(samples, expected);
+	}
+
+	#[test]
+	fn streams_cut_short_fail_once_they_get_there() {
+		let (mut reader, _) = open(&RAMP[..RAMP.len() - 100]).unwrap();
+
+		let mut data = Vec::new();
+		let what = reader.read_to_end(&mut data).unwrap_err();
+		assert_eq!(what.kind(), ErrorKind::InvalidData);
+		assert_eq!(data.len(), 800 * 2 * 2);
+	}
+
+	#[test]
+	fn other_streams_are_refused() {
+		assert!(matches!(open(&b"RIFF\0\0\0\0WAVE"[..]), Err(Error::MalformedFlac(_))));
+	}
+}
@@ -0,0 +1,231 @@
+use crate::error::Error;
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::{Duration, Instant, SystemTime};
+
+/** How long to wait before reopening anything that isn't a named pipe, which,
+ * unlike those, doesn't wait for someone to write to it. */
+const REOPEN_DELAY: Duration = Duration::from_millis(250);
+
+/** Whether the given path is a named pipe. */
+#[cfg(unix)]
+fn is_fifo(path: &Path) -> bool {
+	use std::os::unix::fs::FileTypeExt;
+	std::fs::metadata(path)
+		.map(|metadata| metadata.file_type().is_fifo())
+		.unwrap_or(false)
+}
+
+/** Whether the given path is a named pipe. */
+#[cfg(not(unix))]
+fn is_fifo(_: &Path) -> bool {
+	false
+}
+
+/** Opens the given path again in the background. Named pipes only open once
+ * someone shows up to write to them, which may well take a while. */
+fn reopen(path: PathBuf) -> Receiver<std::io::Result<File>> {
+	let (sender, receiver) = std::sync::mpsc::channel();
+	std::thread::spawn(move || {
+		if !is_fifo(&path) {
+			std::thread::sleep(REOPEN_DELAY);
+		}
+		let _ = sender.send(File::open(&path));
+	});
+
+	receiver
+}
+
+/** A source reading from a path that gets opened again every time it runs
+ * out, such as a named pipe that writers come and go from. Reads fail with
+ * [`ErrorKind::WouldBlock`] for as long as it's being reopened, so the gap can
+ * be filled in with silence, rather than holding up playback. */
+pub struct FollowSource {
+	path: PathBuf,
+	/** File being read from, if we have one open. */
+	file: Option<File>,
+	/** Where the file comes from, while it's being reopened. */
+	pending: Option<Receiver<std::io::Result<File>>>,
+	/** Number of times the path has been reopened so far. */
+	reopens: u64,
+	/** Number of times the path may be reopened, if not forever. */
+	limit: Option<u64>,
+	/** How long to wait for the path to be reopened, if not forever. */
+	timeout: Option<Duration>,
+	/** When the file last ran out. */
+	idle_since: Instant,
+	banner: bool,
+}
+impl Read for FollowSource {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		if let Some(pending) = self.pending.as_ref() {
+			match pending.try_recv() {
+				Ok(file) => {
+					self.file = Some(file?);
+					self.pending = None;
+					self.reopens += 1;
+					eprintln!("[{}] reopened {}", crate::clock::format(SystemTime::now()), self.path.display());
+				},
+				Err(TryRecvError::Empty) => {
+					let idle = self.idle_since.elapsed();
+					if self.timeout.map(|timeout| idle >= timeout).unwrap_or(false) {
+						eprintln!("warning: nothing came through {} for {:.1}s, ending the input",
+							self.path.display(),
+							idle.as_secs_f64());
+						self.pending = None;
+						return Ok(0)
+					}
+					return Err(ErrorKind::WouldBlock.into())
+				},
+				Err(TryRecvError::Disconnected) => return Ok(0)
+			}
+		}
+
+		let file = match self.file.as_mut() {
+			Some(file) => file,
+			None => return Ok(0)
+		};
+		let read = file.read(buf)?;
+		if read > 0 || buf.is_empty() { return Ok(read) }
+
+		if self.limit.map(|limit| self.reopens >= limit).unwrap_or(false) {
+			self.file = None;
+			return Ok(0)
+		}
+
+		self.file = None;
+		self.pending = Some(reopen(self.path.clone()));
+		self.idle_since = Instant::now();
+
+		Err(ErrorKind::WouldBlock.into())
+	}
+}
+impl Drop for FollowSource {
+	fn drop(&mut self) {
+		if self.banner {
+			eprintln!("reopened {} {} times", self.path.display(), self.reopens);
+		}
+	}
+}
+
+/** Opens the given path, to be reopened every time it runs out, up to the given
+ * number of times, if any. Gives up on it once reopening it takes longer than
+ * the given amount of time, if any. */
+pub fn open(path: &Path, limit: Option<u64>, timeout: Option<Duration>, banner: bool)
+	-> Result<FollowSource, Error> {
+
+	let file = File::open(path)
+		.map_err(|what| Error::Follow {
+			path: path.to_owned(),
+			what
+		})?;
+	if banner {
+		eprintln!("following {}", path.display());
+	}
+
+	Ok(FollowSource {
+		path: path.to_owned(),
+		file: Some(file),
+		pending: None,
+		reopens: 0,
+		limit,
+		timeout,
+		idle_since: Instant::now(),
+		banner
+	})
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+	use super::*;
+	use std::io::Write;
+	use std::thread::JoinHandle;
+
+	/** Makes a named pipe in the temporary directory. */
+	fn fifo(name: &str) -> PathBuf {
+		use std::os::unix::ffi::OsStrExt;
+
+		let path = std::env::temp_dir().join(format!("alplay-{}-{}.fifo", name, std::process::id()));
+		let _ = std::fs::remove_file(&path);
+		let name = std::ffi::CString::new(path.as_os_str().as_bytes()).unwrap();
+		assert_eq!(unsafe { libc::mkfifo(name.as_ptr(), 0o600) }, 0);
+
+		path
+	}
+
+	/** Opens the given pipe, writes the given payload to it and closes it
+	 * again. Opening it waits for whoever reads from it to open it, too. */
+	fn write(path: &Path, payload: &'static [u8]) -> JoinHandle<()> {
+		let path = path.to_owned();
+		std::thread::spawn(move || {
+			let mut pipe = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+			pipe.write_all(payload).unwrap();
+		})
+	}
+
+	/** Reads from the given source until it either ends or has nothing to
+	 * give for the time being, telling which one it was. */
+	fn read_some(source: &mut FollowSource) -> (Vec<u8>, bool) {
+		let mut data = Vec::new();
+		let mut buf = [0; 16];
+		loop {
+			match source.read(&mut buf) {
+				Ok(0) => return (data, true),
+				Ok(read) => data.extend(&buf[..read]),
+				Err(what) if what.kind() == ErrorKind::WouldBlock => return (data, false),
+				Err(what) => panic!("{}", what)
+			}
+		}
+	}
+
+	/** Reads the given source through to its end, waiting out the times it
+	 * has nothing to give. */
+	fn read_all(source: &mut FollowSource) -> Vec<u8> {
+		let mut data = Vec::new();
+		loop {
+			let (read, over) = read_some(source);
+			data.extend(read);
+			if over { return data }
+			std::thread::sleep(Duration::from_millis(10));
+		}
+	}
+
+	#[test]
+	fn pipes_get_reopened_up_to_the_limit() {
+		let path = fifo("follow-limit");
+		let writer = write(&path, b"abc");
+
+		/* The writer going away gets the pipe reopened, rather than ending
+		 * the input. */
+		let mut source = open(&path, Some(1), None, false).unwrap();
+		assert_eq!(read_some(&mut source), (b"abc".to_vec(), false));
+		writer.join().unwrap();
+
+		/* Reopened once, after which running out again ends it. */
+		let writer = write(&path, b"def");
+		assert_eq!(read_all(&mut source), b"def");
+		assert_eq!(source.reopens, 1);
+		writer.join().unwrap();
+
+		let _ = std::fs::remove_file(&path);
+	}
+
+	#[test]
+	fn pipes_nobody_writes_to_again_time_out() {
+		let path = fifo("follow-timeout");
+		let writer = write(&path, b"abc");
+
+		let mut source = open(&path, None, Some(Duration::from_millis(100)), false).unwrap();
+		let started = Instant::now();
+		assert_eq!(read_all(&mut source), b"abc");
+		assert!(started.elapsed() >= Duration::from_millis(100));
+		writer.join().unwrap();
+
+		/* Let go of the attempt to reopen it, which is still waiting on a
+		 * writer to show up. */
+		let _ = std::fs::OpenOptions::new().write(true).open(&path);
+		let _ = std::fs::remove_file(&path);
+	}
+}
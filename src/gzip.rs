@@ -0,0 +1,68 @@
+use flate2::read::MultiGzDecoder;
+use std::io::Read;
+
+/** A reader that decompresses gzip data as it's read. Files made up of multiple
+ * concatenated members, as produced by appending to or rotating compressed
+ * captures, are read through as a single stream. */
+pub struct Gunzip<R> {
+	decoder: MultiGzDecoder<R>,
+}
+impl<R> Gunzip<R>
+	where R: Read {
+
+	pub fn new(source: R) -> Self {
+		Self {
+			decoder: MultiGzDecoder::new(source)
+		}
+	}
+}
+impl<R> Read for Gunzip<R>
+	where R: Read {
+
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		self.decoder.read(buf)
+			.map_err(|what| std::io::Error::new(
+				what.kind(),
+				format!("gzip decompression failed: {}", what)))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Cursor;
+
+	/** Raw bytes going up from zero and wrapping around at 251, split in two
+	 * halves that got compressed into members of their own. */
+	const MEMBERS: &[u8] = include_bytes!("../fixtures/members.raw.gz");
+
+	fn raw() -> Vec<u8> {
+		(0..2048).map(|i| (i % 251) as u8).collect()
+	}
+
+	#[test]
+	fn concatenated_members_get_read_through() {
+		let mut data = Vec::new();
+		Gunzip::new(MEMBERS).read_to_end(&mut data).unwrap();
+		assert_eq!(data, raw());
+	}
+
+	#[test]
+	fn compressed_input_gets_detected_as_what_it_holds() {
+		let (mut reader, detected) = crate::detect::detect(Cursor::new(MEMBERS.to_vec())).unwrap();
+		assert_eq!(detected, None);
+
+		let mut data = Vec::new();
+		reader.read_to_end(&mut data).unwrap();
+		assert_eq!(data, raw());
+	}
+
+	#[test]
+	fn corrupted_input_fails_with_context() {
+		let mut corrupted = MEMBERS.to_vec();
+		corrupted[20] ^= 0xff;
+
+		let what = Gunzip::new(&corrupted[..]).read_to_end(&mut Vec::new()).unwrap_err();
+		assert!(what.to_string().starts_with("gzip decompression failed: "), "{}", what);
+	}
+}
@@ -0,0 +1,196 @@
+use crate::arg::{Endianness, InputFormat};
+use crate::detect::Peeker;
+use crate::error::Error;
+use std::io::Read;
+
+/** Magic number every header starts with. */
+pub const MAGIC: &[u8; 4] = b"ALPL";
+/** Version of the header layout written by this version of alplay. */
+pub const VERSION: u8 = 1;
+/** Length of a header, in bytes. */
+pub const LENGTH: usize = 13;
+
+/** Codes of the sample formats, as they're written in a header. These must
+ * never change, as producers may have them hardcoded. */
+const FORMATS: &[(u8, cpal::SampleFormat)] = &[
+	(0, cpal::SampleFormat::I16),
+	(1, cpal::SampleFormat::U16),
+	(2, cpal::SampleFormat::F32),
+];
+
+/** Code of the little endian byte order. */
+const LITTLE: u8 = 0;
+/** Code of the big endian byte order. */
+const BIG: u8 = 1;
+
+/** Writes out the header describing the given format. It's laid out as:
+ *
+ * | Offset | Size | Contents                                 |
+ * |--------|------|------------------------------------------|
+ * | 0      | 4    | `ALPL`                                   |
+ * | 4      | 1    | Version, currently 1                     |
+ * | 5      | 1    | Sample format: 0 for S16, 1 U16, 2 F32   |
+ * | 6      | 1    | Byte order: 0 for little endian, 1 big   |
+ * | 7      | 2    | Number of channels, little endian        |
+ * | 9      | 4    | Sample rate in Hz, little endian         |
+ *
+ * Native byte order gets written as whichever one this machine has. */
+pub fn encode(format: &InputFormat) -> [u8; LENGTH] {
+	let code = FORMATS.iter()
+		.find(|(_, sample_format)| *sample_format == format.sample_format)
+		.map(|(code, _)| *code)
+		.expect("sample format missing from the header codes");
+	let big = match format.endian {
+		Endianness::Little => false,
+		Endianness::Big    => true,
+		Endianness::Native => cfg!(target_endian = "big")
+	};
+
+	let mut header = [0; LENGTH];
+	header[0..4].copy_from_slice(MAGIC);
+	header[4] = VERSION;
+	header[5] = code;
+	header[6] = if big { BIG } else { LITTLE };
+	header[7..9].copy_from_slice(&format.channels.to_le_bytes());
+	header[9..13].copy_from_slice(&format.sample_rate.to_le_bytes());
+
+	header
+}
+
+/** Reads the format described by the given header. */
+pub fn decode(header: &[u8]) -> Result<InputFormat, Error> {
+	if header.len() < LENGTH {
+		return Err(Error::MalformedHeader(format!(
+			"the input ends {} bytes into the header",
+			header.len())))
+	}
+	if &header[0..4] != MAGIC {
+		return Err(Error::MalformedHeader("the magic number is missing".to_owned()))
+	}
+	if header[4] != VERSION {
+		return Err(Error::MalformedHeader(format!(
+			"version {} is not supported, only version {} is",
+			header[4],
+			VERSION)))
+	}
+
+	let sample_format = FORMATS.iter()
+		.find(|(code, _)| *code == header[5])
+		.map(|(_, sample_format)| *sample_format)
+		.ok_or_else(|| Error::MalformedHeader(format!(
+			"unknown sample format code {}",
+			header[5])))?;
+	let endian = match header[6] {
+		LITTLE => Endianness::Little,
+		BIG    => Endianness::Big,
+		code => return Err(Error::MalformedHeader(format!(
+			"unknown byte order code {}",
+			code)))
+	};
+	let channels = u16::from_le_bytes([header[7], header[8]]);
+	let sample_rate = u32::from_le_bytes([header[9], header[10], header[11], header[12]]);
+	if channels == 0 {
+		return Err(Error::MalformedHeader("the header has no channels".to_owned()))
+	}
+
+	Ok(InputFormat {
+		channels,
+		sample_rate,
+		sample_format,
+		endian
+	})
+}
+
+/** Checks whether the given source starts with a header. If it does, the
+ * header is consumed and the format it describes is returned along with the
+ * rest of the source. Otherwise, the source is returned as it is. */
+pub fn accept<R>(source: R) -> Result<(Peeker<R>, Option<InputFormat>), Error>
+	where R: Read {
+
+	let mut source = Peeker::new(source);
+	if source.peek(MAGIC.len()).map_err(Error::InputError)? != MAGIC {
+		return Ok((source, None))
+	}
+
+	let format = decode(source.peek(LENGTH).map_err(Error::InputError)?)?;
+	source.consume(LENGTH);
+
+	Ok((source, Some(format)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/** Reads the given source all the way through. */
+	fn rest<R: Read>(mut source: R) -> Vec<u8> {
+		let mut data = Vec::new();
+		source.read_to_end(&mut data).unwrap();
+		data
+	}
+
+	#[test]
+	fn header_round_trips() {
+		let formats = [
+			(cpal::SampleFormat::I16, Endianness::Little, 2, 48000),
+			(cpal::SampleFormat::U16, Endianness::Big, 1, 8000),
+			(cpal::SampleFormat::F32, Endianness::Little, 8, 768000),
+		];
+		for (sample_format, endian, channels, sample_rate) in formats {
+			let format = InputFormat {
+				channels,
+				sample_rate,
+				sample_format,
+				endian
+			};
+
+			let mut stream = encode(&format).to_vec();
+			stream.extend(b"samples");
+			let (source, accepted) = accept(&stream[..]).unwrap();
+			assert_eq!(accepted, Some(format));
+			assert_eq!(rest(source), b"samples");
+		}
+	}
+
+	#[test]
+	fn native_endian_gets_written_as_the_machine_order() {
+		let format = InputFormat {
+			channels: 2,
+			sample_rate: 44100,
+			sample_format: cpal::SampleFormat::I16,
+			endian: Endianness::Native
+		};
+		let expected = if cfg!(target_endian = "big") { Endianness::Big } else { Endianness::Little };
+		assert_eq!(decode(&encode(&format)).unwrap().endian, expected);
+	}
+
+	#[test]
+	fn headerless_input_is_left_alone() {
+		let (source, accepted) = accept(&b"ALPsamples"[..]).unwrap();
+		assert_eq!(accepted, None);
+		assert_eq!(rest(source), b"ALPsamples");
+	}
+
+	#[test]
+	fn malformed_headers_are_rejected() {
+		let format = InputFormat {
+			channels: 2,
+			sample_rate: 48000,
+			sample_format: cpal::SampleFormat::I16,
+			endian: Endianness::Little
+		};
+		let header = encode(&format);
+
+		let mut version = header;
+		version[4] = VERSION + 1;
+		let mut sample_format = header;
+		sample_format[5] = 3;
+		let mut endian = header;
+		endian[6] = 2;
+		let mut channels = header;
+		channels[7..9].copy_from_slice(&[0, 0]);
+		for header in [&header[..LENGTH - 1], &version, &sample_format, &endian, &channels] {
+			assert!(matches!(decode(header), Err(Error::MalformedHeader(_))));
+		}
+	}
+}
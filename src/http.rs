@@ -0,0 +1,195 @@
+use crate::error::Error;
+use std::io::{ErrorKind, Read};
+
+/** Largest number of redirects that get followed before giving up. */
+const MAX_REDIRECTS: u32 = 5;
+
+/** Opens a streaming HTTP(S) request to the given URL, returning the body of
+ * the response as it gets downloaded. What the server says the body is only
+ * ever gets printed, as the body itself is what format detection goes by. */
+pub fn open(url: &str, banner: bool) -> Result<Box<dyn Read + Send>, Error> {
+	let agent = ureq::AgentBuilder::new()
+		.redirects(MAX_REDIRECTS)
+		.build();
+	let response = match agent.get(url).call() {
+		Ok(response) => response,
+		Err(ureq::Error::Status(status, _)) =>
+			return Err(Error::HttpStatus {
+				url: url.to_owned(),
+				status
+			}),
+		Err(what) =>
+			return Err(Error::Http {
+				url: url.to_owned(),
+				what: what.to_string()
+			})
+	};
+
+	/* Anything else that counts as a success, such as a partial or an empty
+	 * response, still isn't the whole of what we asked for. */
+	if response.status() != 200 {
+		return Err(Error::HttpStatus {
+			url: url.to_owned(),
+			status: response.status()
+		})
+	}
+
+	if banner {
+		if response.get_url() != url {
+			eprintln!("redirected to {}", response.get_url());
+		}
+		if let Some(content_type) = response.header("Content-Type") {
+			eprintln!("the server says {} is {}", url, content_type);
+		}
+	}
+
+	Ok(Box::new(Body {
+		reader: response.into_reader()
+	}))
+}
+
+/** Body of an HTTP response. */
+struct Body<R> {
+	reader: R,
+}
+impl<R> Read for Body<R>
+	where R: Read {
+
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		match self.reader.read(buf) {
+			Err(what) => match what.kind() {
+				/* Servers streaming live audio have no clean way to end the
+				 * stream other than dropping the connection, so treat that as
+				 * the end of the stream rather than as an error. */
+				ErrorKind::ConnectionReset
+				| ErrorKind::ConnectionAborted
+				| ErrorKind::UnexpectedEof => {
+					eprintln!("warning: the connection was closed early: {}", what);
+					Ok(0)
+				},
+				_ => Err(what)
+			},
+			result => result
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Write;
+	use std::net::TcpListener;
+
+	/** Starts a server on the loopback interface answering every connection
+	 * it gets, in order, with the next of the given responses, written out as
+	 * they are. Returns the address it's listening on. */
+	fn serve(responses: Vec<Vec<u8>>) -> String {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let address = format!("http://{}", listener.local_addr().unwrap());
+
+		std::thread::spawn(move || {
+			for response in responses {
+				let (mut stream, _) = listener.accept().unwrap();
+
+				/* Requests have no body, so they end with the headers. */
+				let mut request = Vec::new();
+				let mut byte = [0];
+				while !request.ends_with(b"\r\n\r\n") {
+					if stream.read(&mut byte).unwrap() == 0 { break }
+					request.push(byte[0]);
+				}
+
+				stream.write_all(&response).unwrap();
+			}
+		});
+
+		address
+	}
+
+	/** A response with the given status line, headers and body. */
+	fn response(status: &str, headers: &[&str], body: &[u8]) -> Vec<u8> {
+		let mut response = format!("HTTP/1.1 {}\r\nConnection: close\r\n", status).into_bytes();
+		for header in headers {
+			response.extend(header.as_bytes());
+			response.extend(b"\r\n");
+		}
+		response.extend(b"\r\n");
+		response.extend(body);
+		response
+	}
+
+	/** A WAV file holding the given mono 16-bit samples at 8000Hz. */
+	fn wav(data: &[u8]) -> Vec<u8> {
+		let format = crate::arg::InputFormat {
+			channels: 1,
+			sample_rate: 8000,
+			sample_format: cpal::SampleFormat::I16,
+			endian: crate::arg::Endianness::Little
+		};
+		let mut writer = crate::wav::WavWriter::new(std::io::Cursor::new(Vec::new()), format).unwrap();
+		writer.write_all(data).unwrap();
+		writer.finish().unwrap().into_inner()
+	}
+
+	fn read_all(mut reader: Box<dyn Read + Send>) -> Vec<u8> {
+		let mut read = Vec::new();
+		reader.read_to_end(&mut read).unwrap();
+		read
+	}
+
+	#[test]
+	fn bodies_with_a_length_get_read_whole() {
+		let file = wav(&[1, 2, 3, 4, 5, 6]);
+		let length = format!("Content-Length: {}", file.len());
+		let url = serve(vec![response("200 OK", &[&length, "Content-Type: audio/wav"], &file)]);
+
+		let body = open(&format!("{}/capture.wav", url), false).unwrap();
+		let (mut reader, detected) = crate::detect::detect(body).unwrap();
+		assert_eq!(detected.unwrap().container, "WAV");
+		if cfg!(not(feature = "symphonia")) {
+			let mut samples = Vec::new();
+			reader.read_to_end(&mut samples).unwrap();
+			assert_eq!(samples, [1, 2, 3, 4, 5, 6]);
+		}
+	}
+
+	#[test]
+	fn chunked_bodies_get_put_back_together() {
+		let body = b"5\r\nhello\r\n1\r\n \r\n6\r\nchunks\r\n0\r\n\r\n";
+		let url = serve(vec![response("200 OK", &["Transfer-Encoding: chunked"], body)]);
+
+		assert_eq!(read_all(open(&url, false).unwrap()), b"hello chunks");
+	}
+
+	#[test]
+	fn redirects_get_followed() {
+		/* The redirect and what it points to come from the same server. */
+		let body = b"raw samples";
+		let url = serve(vec![
+			response("302 Found", &["Location: /moved", "Content-Length: 0"], b""),
+			response("200 OK", &[&format!("Content-Length: {}", body.len())], body)
+		]);
+
+		assert_eq!(read_all(open(&url, false).unwrap()), body);
+	}
+
+	#[test]
+	fn failed_requests_report_their_status() {
+		let url = serve(vec![
+			response("404 Not Found", &["Content-Length: 0"], b""),
+			response("204 No Content", &[], b"")
+		]);
+
+		assert!(matches!(open(&url, false), Err(Error::HttpStatus { status: 404, .. })));
+		assert!(matches!(open(&url, false), Err(Error::HttpStatus { status: 204, .. })));
+	}
+
+	#[test]
+	fn streams_cut_off_by_the_server_end_there() {
+		/* The server goes away in the middle of a chunk, as live streams do. */
+		let url = serve(vec![response("200 OK", &["Transfer-Encoding: chunked"], b"4\r\nlive\r\n10\r\ncut")]);
+
+		let read = read_all(open(&url, false).unwrap());
+		assert!(read.starts_with(b"live"), "{:?}", read);
+	}
+}
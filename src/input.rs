@@ -0,0 +1,883 @@
+use clap::ArgMatches;
+use crate::arg::{self, Arguments, OutputConfig};
+use crate::error::Error;
+use crate::{detect, filter, follow, header, net, play, playlist, registry, resample, src, tone};
+use crate::{ab_loop, buffered, channel_id, check_matrix, check_sweep, click, io_buffer, is_stdin};
+use crate::{loop_buffer, loop_times, noise, repeat, segments, sweep, trim_end};
+use crate::{ARG_AB_LOOP, ARG_ACCEPT_HEADER, ARG_ACCEPT_LOOP, ARG_ACCEPT_TIMEOUT,
+	ARG_ANTI_ALIAS_ORDER, ARG_CONNECT, ARG_CONNECT_TIMEOUT, ARG_CROSSFADE, ARG_FD,
+	ARG_FILES, ARG_FOLLOW, ARG_FOLLOW_LIMIT, ARG_FOLLOW_TIMEOUT, ARG_FORCE_CONCAT,
+	ARG_FORMAT_FROM_EXTENSION, ARG_KEEP_LFE, ARG_LISTEN, ARG_LISTEN_UNIX, ARG_LOOP,
+	ARG_MMAP, ARG_PLAYLIST, ARG_RAW, ARG_RECONNECT, ARG_RECV_BUFFER, ARG_SHUFFLE,
+	ARG_SHUFFLE_SEED, ARG_SIGNAL, ARG_SOURCE, ARG_STRICT, ARG_SWEEP, ARG_TRIM_SILENCE,
+	ARG_UDP, ARG_UDP_SEQ};
+use std::io::Read;
+use std::path::Path;
+
+/** The input, opened, along with the configuration of the output stream
+ * negotiated for it. */
+pub type Opened = (Box<dyn Read + Send>, OutputConfig);
+
+/** Opens the source of the audio data to be played and negotiates the
+ * configuration of the output stream for it. These two go together, as the
+ * input may describe its own format, which then takes part in negotiation.
+ * This is where the input gets picked out of the many places it can come
+ * from: a generated signal, the network, a socket, a file descriptor, a file
+ * being followed, a URL, the standard input or a list of files. */
+pub fn open(matches: &ArgMatches, args: &mut Arguments)
+	-> Result<Opened, Error> {
+
+	/* A lone file that is really the standard input gets treated just like
+	 * when no files are given at all, and a lone URL just like one given as
+	 * the source. */
+	let lone = matches.values_of(ARG_FILES)
+		.map(|files| files.collect::<Vec<_>>())
+		.filter(|files| files.len() == 1)
+		.map(|files| files[0]);
+	let lone_stdin = lone.map(|file| is_stdin(Path::new(file))).unwrap_or(false);
+	let lone_url = lone.filter(|file| is_url(Path::new(file)));
+
+	/* Following a file makes it a stream that never really ends. */
+	let follow = matches.is_present(ARG_FOLLOW);
+	let lone_follow = if follow {
+		match lone {
+			Some(file) if !lone_stdin && lone_url.is_none() => Some(file),
+			_ => return Err(Error::FollowNeedsOneFile)
+		}
+	} else {
+		None
+	};
+
+	if lone_stdin || lone_url.is_some() || lone_follow.is_some() || (!matches.is_present(ARG_FILES) && !matches.is_present(ARG_PLAYLIST)) {
+		/* Trimming takes two passes over the input, which streams can't do. */
+		if matches.is_present(ARG_TRIM_SILENCE) {
+			return Err(Error::TrimNeedsFiles)
+		}
+
+		if let Some(generated) = generated(matches, args)? {
+			return Ok(generated)
+		}
+
+		let source = stream(matches, args, lone_url, lone_follow)?;
+		return open_stream(matches, args, source, follow)
+	}
+
+	open_list(matches, args)
+}
+
+/** Generates the signal asked for, or receives datagrams, if asked to, along
+ * with the configuration of the output stream. Neither of them has a header
+ * for the format to be detected from. */
+fn generated(matches: &ArgMatches, args: &Arguments)
+	-> Result<Option<Opened>, Error> {
+
+	if let Some(sweep) = matches.value_of(ARG_SWEEP) {
+		let sweep = tone::parse_sweep(sweep)?;
+		let format = play::negotiate(args)?;
+		check_matrix(args, &format)?;
+
+		check_sweep(&sweep, format.sample_rate().0);
+
+		let source = tone::Sweep::new(
+			sweep,
+			args.input_channels().unwrap_or(format.channels()),
+			format.sample_rate().0,
+			args.input_sample_format().unwrap_or(format.sample_format()),
+			args.endianness().unwrap_or(play::PREFERRED_SAMPLE_ENDIAN));
+		return Ok(Some((Box::new(source), format)))
+	}
+
+	if let Some(signal) = matches.value_of(ARG_SIGNAL) {
+		let format = play::negotiate(args)?;
+		check_matrix(args, &format)?;
+
+		let channels = args.input_channels().unwrap_or(format.channels());
+		let rate = format.sample_rate().0;
+		let sample_format = args.input_sample_format().unwrap_or(format.sample_format());
+		let endian = args.endianness().unwrap_or(play::PREFERRED_SAMPLE_ENDIAN);
+		let source: Box<dyn Read + Send> = match signal {
+			"sweep" => Box::new(sweep(matches, channels, rate, sample_format, endian)?),
+			"channel-id" => Box::new(channel_id(matches, channels, rate, sample_format, endian, args.banner())?),
+			"click" => Box::new(click(matches, channels, rate, sample_format, endian)?),
+			_ => Box::new(noise(matches, channels, rate, sample_format, endian, args.banner())?)
+		};
+		return Ok(Some((source, format)))
+	}
+
+	/* Datagrams can get lost, and a header along with them, so they're
+	 * always taken to be raw samples. */
+	if let Some(address) = matches.value_of(ARG_UDP) {
+		let format = play::negotiate(args)?;
+		check_matrix(args, &format)?;
+
+		let source = net::udp(
+			address,
+			matches.is_present(ARG_UDP_SEQ),
+			args.input_channels().unwrap_or(format.channels()),
+			format.sample_rate().0,
+			args.input_sample_format().unwrap_or(format.sample_format()),
+			args.endianness().unwrap_or(play::PREFERRED_SAMPLE_ENDIAN),
+			args.banner())?;
+		return Ok(Some((Box::new(source), format)))
+	}
+
+	Ok(None)
+}
+
+/** Opens the stream the input comes from, out of a URL, the given file to be
+ * followed, a file descriptor, a socket, a network connection or, if none of
+ * them was given, the standard input. */
+fn stream(matches: &ArgMatches, args: &Arguments, url: Option<&str>, follow: Option<&str>)
+	-> Result<Box<dyn Read + Send>, Error> {
+
+	let source: Box<dyn Read + Send> = if let Some(url) = matches.value_of(ARG_SOURCE).or(url) {
+		open_url(url, args.banner())?
+	} else if let Some(path) = follow {
+		let limit = matches.value_of(ARG_FOLLOW_LIMIT)
+			.map(|limit| u64::from_str_radix(limit, 10))
+			.transpose()
+			.map_err(Error::MalformedFollowLimit)?;
+		let timeout = matches.value_of(ARG_FOLLOW_TIMEOUT)
+			.map(|timeout| u64::from_str_radix(timeout, 10))
+			.transpose()
+			.map_err(Error::MalformedFollowTimeout)?
+			.map(std::time::Duration::from_secs);
+
+		Box::new(follow::open(Path::new(path), limit, timeout, args.banner())?)
+	} else if let Some(fd) = matches.value_of(ARG_FD) {
+		open_fd(fd)?
+	} else if let Some(path) = matches.value_of(ARG_LISTEN_UNIX) {
+		listen_unix(matches, args, Path::new(path))?
+	} else if let Some(address) = matches.value_of(ARG_CONNECT) {
+		let timeout = matches.value_of(ARG_CONNECT_TIMEOUT)
+			.map(|timeout| u64::from_str_radix(timeout, 10))
+			.transpose()
+			.map_err(Error::MalformedConnectTimeout)?
+			.map(std::time::Duration::from_secs);
+
+		Box::new(net::connect(
+			address,
+			timeout,
+			matches.is_present(ARG_RECONNECT),
+			args.banner())?)
+	} else if let Some(address) = matches.value_of(ARG_LISTEN) {
+		let recv_buffer = matches.value_of(ARG_RECV_BUFFER)
+			.map(|size| usize::from_str_radix(size, 10))
+			.transpose()
+			.map_err(Error::MalformedRecvBuffer)?;
+
+		Box::new(net::listen(
+			address,
+			accept_timeout(matches)?,
+			matches.is_present(ARG_ACCEPT_LOOP),
+			recv_buffer,
+			args.banner())?)
+	} else {
+		/* On Windows, this reads the handle with ReadFile, rather than
+		 * through the C runtime, so there is no text mode to get in the way
+		 * of samples: CR LF pairs are left alone and a 0x1A byte isn't
+		 * taken for the end of the input. */
+		Box::new(std::io::stdin())
+	};
+
+	Ok(source)
+}
+
+/** Reads the given stream as the input, looking for its format in a header or
+ * a container, and negotiates the configuration of the output stream for it.
+ * Whatever was asked to be done with the input gets put on top of it. */
+fn open_stream(matches: &ArgMatches, args: &mut Arguments, source: Box<dyn Read + Send>, follow: bool)
+	-> Result<Opened, Error> {
+
+	let source = buffered(source, io_buffer(matches)?);
+
+	/* A header says everything there is to say about raw samples, so
+	 * there's no container to be detected past it. */
+	let (source, headed): (Box<dyn Read + Send>, _) = if matches.is_present(ARG_ACCEPT_HEADER) {
+		let (source, format) = header::accept(source)?;
+		if let Some(format) = format {
+			if args.banner() {
+				eprintln!("header: {}ch {}Hz {} {:?} endian",
+					format.channels,
+					format.sample_rate,
+					arg::sample_format_info(format.sample_format).name,
+					format.endian);
+			}
+			args.apply_input_format(format);
+		}
+		(Box::new(source), format.is_some())
+	} else {
+		(source, false)
+	};
+
+	let (source, detected): (Box<dyn Read + Send>, _) = if matches.is_present(ARG_RAW) || headed {
+		(source, None)
+	} else {
+		let (source, detected) = registry::open_source(registry::Input::Stream(source), args)?;
+		(Box::new(source), detected)
+	};
+
+	let format = play::negotiate(args)?;
+	check_matrix(args, &format)?;
+
+	let frame = args.input_sample_format().unwrap_or(format.sample_format()).sample_size()
+		* usize::from(args.input_channels().unwrap_or(format.channels()));
+	let source: Box<dyn Read + Send> = match trim_end(matches, format.sample_rate().0, frame)? {
+		Some(trim) => Box::new(src::TrimEnd::new(source, trim)),
+		None => source
+	};
+
+	let looping = matches.is_present(ARG_LOOP) && !matches.is_present(ARG_AB_LOOP);
+	if looping && detected.and_then(|detected| detected.loop_points).is_none() {
+		eprintln!("warning: the input has no loop embedded in it, playing it only once");
+	}
+	let source: Box<dyn Read + Send> = Box::new(embedded_loop(
+		source,
+		detected.as_ref(),
+		looping,
+		loop_times(matches)?));
+	let source: Box<dyn Read + Send> = match segments(matches, format.sample_rate().0, frame)? {
+		Some(segments) => {
+			Box::new(src::Segments::new(source, segments, args.progress())
+				.map_err(Error::InputError)?)
+		},
+		None => source
+	};
+	let source: Box<dyn Read + Send> = match ab_loop(matches, format.sample_rate().0, frame)? {
+		Some(segment) => Box::new(
+			src::AbLoop::new(source, segment, loop_times(matches)?, args.progress())
+				.map_err(Error::InputError)?),
+		None => source
+	};
+
+	/* Play silence while we're reconnecting or reopening the input, rather
+	 * than running dry. */
+	let source: Box<dyn Read + Send> = if (matches.is_present(ARG_RECONNECT) && matches.is_present(ARG_CONNECT)) || follow {
+		Box::new(src::FillGaps::new(
+			source,
+			args.input_channels().unwrap_or(format.channels()),
+			format.sample_rate().0,
+			args.input_sample_format().unwrap_or(format.sample_format()),
+			args.endianness().unwrap_or(play::PREFERRED_SAMPLE_ENDIAN)))
+	} else {
+		source
+	};
+
+	/* Streams can only be repeated from a copy of them kept in memory.
+	 * With a single item, repeating it and repeating the list are one and
+	 * the same. */
+	let limit = loop_buffer(matches)?;
+	if let Some(limit) = limit.filter(|_| repeat(matches) != src::Repeat::None) {
+		let mut source = Some(src::Replay::new(source, limit));
+		let open: src::Opener = Box::new(move || match source.take() {
+			Some(source) => Ok(Box::new(source)),
+			None => Err(std::io::Error::other(
+				"the input can only be read once"))
+		});
+		let source = src::ConcatSource::new(vec![("the input".to_owned(), open)], true)
+			.progress(false)
+			.repeat(src::Repeat::One);
+
+		return Ok((Box::new(source), format))
+	}
+
+	Ok((source, format))
+}
+
+/** Opens the list of files or the playlist given as the input, to be played
+ * one after the other, and negotiates the configuration of the output stream
+ * for the first of them. */
+fn open_list(matches: &ArgMatches, args: &mut Arguments)
+	-> Result<Opened, Error> {
+
+	let entries = if let Some(path) = matches.value_of(ARG_PLAYLIST) {
+		playlist::load(Path::new(path))?
+	} else {
+		matches.values_of(ARG_FILES)
+			.unwrap()
+			.map(|path| playlist::Entry {
+				path: path.into(),
+				duration: None,
+				format: Default::default()
+			})
+			.collect()
+	};
+
+	/* The whole list is played in the format of its first item. Problems with
+	 * the item itself are dealt with when it gets played. */
+	let raw = matches.is_present(ARG_RAW);
+	let io_buffer = io_buffer(matches)?;
+	let limit = loop_buffer(matches)?;
+	let by_extension = matches.is_present(ARG_FORMAT_FROM_EXTENSION);
+	let is_raw = move |path: &Path| raw
+		|| (by_extension && !is_stdin(path) && match extension(path) {
+			Extension::Raw(_) => true,
+			Extension::Other => false
+		});
+	let mmap = matches.is_present(ARG_MMAP);
+	if mmap && cfg!(not(feature = "mmap")) {
+		eprintln!("warning: alplay was built without memory mapping support \
+			(enable the \"mmap\" feature), reading files normally");
+	}
+	if let Some(first) = entries.first().filter(|_| !raw && by_extension) {
+		if let Extension::Raw(Some((format, suffix))) = extension(&first.path) {
+			args.assume_sample_format(format, suffix)?;
+		}
+	}
+	if let Some(first) = entries.first().filter(|first| !is_raw(&first.path) && !is_stdin(&first.path) && !is_url(&first.path)) {
+		/* Anything wrong with the file is left for when it gets played. */
+		if let Ok(file) = std::fs::File::open(&first.path) {
+			let _ = registry::open_source(registry::Input::File(file), args);
+		}
+	}
+	let format = play::negotiate(args)?;
+	check_matrix(args, &format)?;
+
+	/* Items are in the format of the input, which may have a different number
+	 * of channels than what gets played. That is the format of the list. */
+	let channels = args.input_channels().unwrap_or(format.channels());
+	let frame = args.input_sample_format().unwrap_or(format.sample_format()).sample_size()
+		* usize::from(channels);
+	let endian = args.endianness().unwrap_or(play::PREFERRED_SAMPLE_ENDIAN);
+	let list = arg::InputFormat {
+		channels,
+		sample_rate: format.sample_rate().0,
+		sample_format: args.input_sample_format().unwrap_or(format.sample_format()),
+		endian
+	};
+
+	/* Files say what format they're in through their headers. Raw items are
+	 * in the format of the list, unless the playlist or their name says
+	 * otherwise, and streams can't be read twice, so they're trusted to be.
+	 * Without --force-concat, they all have to be in the format of the list. */
+	let own = |entry: &playlist::Entry| {
+		let path = entry.path.as_path();
+		if !is_raw(path) && !is_stdin(path) && !is_url(path) {
+			let detected = std::fs::File::open(path).ok()
+				.and_then(|file| registry::open(registry::Input::File(file)).ok())
+				.and_then(|(_, detected)| detected);
+			if let Some(detected) = detected { return detected.format }
+		}
+
+		let named = match extension(path) {
+			Extension::Raw(Some((format, suffix))) if !raw && by_extension =>
+				Some((format, suffix.unwrap_or(list.endian))),
+			_ => None
+		};
+		let given = entry.format;
+		let sample_format = given.sample_format.or(named);
+		arg::InputFormat {
+			channels: given.channels.unwrap_or(list.channels),
+			sample_rate: given.sample_rate.unwrap_or(list.sample_rate),
+			sample_format: sample_format.map(|(format, _)| format).unwrap_or(list.sample_format),
+			endian: sample_format.map(|(_, endian)| endian).unwrap_or(list.endian)
+		}
+	};
+	let formats = entries.iter().map(own).collect::<Vec<_>>();
+	let force = matches.is_present(ARG_FORCE_CONCAT);
+	let order = anti_alias_order(matches)?;
+	let lfe = matches.is_present(ARG_KEEP_LFE);
+	if !force {
+		let mismatched = entries.iter()
+			.zip(&formats)
+			.filter(|(_, format)| !format.compatible(&list))
+			.map(|(entry, format)| (entry.path.display().to_string(), *format))
+			.collect::<Vec<_>>();
+		if !mismatched.is_empty() {
+			let mut items = vec![("(list)".to_owned(), list)];
+			items.extend(mismatched);
+
+			return Err(Error::MismatchedItems { items })
+		}
+	}
+
+	let segments = segments(matches, format.sample_rate().0, frame)?;
+	let ab_loop = ab_loop(matches, format.sample_rate().0, frame)?;
+	let trim_end = trim_end(matches, format.sample_rate().0, frame)?;
+	let progress = args.progress();
+	let looping = matches.is_present(ARG_LOOP) && ab_loop.is_none();
+	let times = loop_times(matches)?;
+	let trim = matches.value_of(ARG_TRIM_SILENCE)
+		.map(|threshold| threshold.parse::<f32>())
+		.transpose()
+		.map_err(Error::MalformedTrimThreshold)?
+		.map(|threshold| src::Trim {
+			threshold: 10f32.powf(threshold / 20.0),
+			frame,
+			format: args.input_sample_format().unwrap_or(format.sample_format()),
+			endian
+		});
+
+	/* Trimming takes two passes over every item, which streams can't do. */
+	if trim.is_some() && entries.iter().any(|entry| is_stdin(&entry.path) || is_url(&entry.path)) {
+		return Err(Error::TrimNeedsFiles)
+	}
+
+	let items = entries.into_iter()
+		.zip(formats)
+		.map(|(entry, from)| {
+			let name = match entry.duration {
+				Some(duration) => format!("{} ({}:{:02})",
+					entry.path.display(),
+					duration.as_secs() / 60,
+					duration.as_secs() % 60),
+				None => entry.path.display().to_string()
+			};
+			let path = entry.path;
+			let raw = is_raw(&path);
+			let banner = args.banner();
+			let segments = segments.clone();
+			let ab_loop = ab_loop.clone();
+			let open: src::Opener = Box::new(move || {
+				let read = || -> std::io::Result<(Box<dyn src::Source>, Option<detect::Detected>)> {
+					let detected = if is_stdin(&path) || is_url(&path) {
+						let stream: Box<dyn Read + Send> = if is_stdin(&path) {
+							Box::new(std::io::stdin())
+						} else {
+							open_url(&path.to_string_lossy(), banner)
+								.map_err(|what| std::io::Error::other(
+									what.to_string()))?
+						};
+						let stream = buffered(stream, io_buffer);
+						let detected = if raw {
+							Ok((Box::new(stream) as Box<dyn src::Source>, None))
+						} else {
+							registry::open(registry::Input::Stream(stream))
+						};
+
+						/* Streams can't be read twice, so they can only be
+						 * repeated from a copy of them. */
+						detected.map(|(reader, detected)| -> (Box<dyn src::Source>, _) {
+							match limit {
+								Some(limit) => (Box::new(src::Replay::new(reader, limit)), detected),
+								None => (reader, detected)
+							}
+						})
+					} else {
+						let file = std::fs::File::open(&path)?;
+
+						#[cfg(feature = "mmap")] {
+							if mmap {
+								match crate::mapped::MappedFile::new(&file) {
+									Ok(map) => {
+										if raw { return Ok((Box::new(map), None)) }
+										return registry::open(registry::Input::Mapped(map))
+											.map_err(|what| std::io::Error::new(
+												std::io::ErrorKind::InvalidData,
+												what.to_string()))
+									},
+									Err(what) =>
+										eprintln!("warning: could not map {} into memory, reading it normally: {}",
+											path.display(),
+											what)
+								}
+							}
+						}
+
+						if raw { return Ok((Box::new(file), None)) }
+						registry::open(registry::Input::File(file))
+					};
+					detected.map_err(|what| std::io::Error::new(
+						std::io::ErrorKind::InvalidData,
+						what.to_string()))
+				};
+				let open = || -> std::io::Result<Box<dyn src::Source>> {
+					let (reader, detected) = read()?;
+					/* Loops embedded in an item count its own frames, which no
+					 * longer line up once it's been converted. */
+					let (reader, detected): (Box<dyn src::Source>, _) = if !from.compatible(&list) {
+						let conform = src::Conform::new(reader, from, list)
+							.anti_alias(order)
+							.keep_lfe(lfe);
+						(Box::new(conform), None)
+					} else {
+						(reader, detected)
+					};
+					let reader: Box<dyn src::Source> = match trim_end {
+						Some(trim) => Box::new(src::TrimEnd::new(reader, trim)),
+						None => reader
+					};
+					Ok(embedded_loop(reader, detected.as_ref(), looping, times))
+				};
+
+				if let Some(segment) = ab_loop.as_ref() {
+					/* Copies of streams can't be gone back through until
+					 * they've been read all the way. */
+					if is_stdin(&path) || is_url(&path) {
+						return Err(std::io::Error::new(
+							std::io::ErrorKind::InvalidInput,
+							format!("{} can't be seeked through, so it can't be looped over", path.display())))
+					}
+
+					let reader = open()?;
+					return Ok(Box::new(src::AbLoop::new(reader, segment.clone(), times, progress)?))
+				}
+
+				if let Some(segments) = segments.as_ref() {
+					let reader = open()?;
+					return Ok(Box::new(src::Segments::new(reader, segments.clone(), progress)?))
+				}
+
+				let trim = match trim {
+					Some(trim) => trim,
+					None => return open()
+				};
+
+				/* Find where the audio is with a first pass over the file, then
+				 * only play that part of it. */
+				let (first, end) = src::audible_range(&mut open()?, &trim)?
+					.unwrap_or((0, 0));
+				let frame = trim.frame as u64;
+
+				/* Go straight to where the audio starts. Readers that can't seek,
+				 * such as those decoding compressed formats, get read through. */
+				let mut reader = open()?;
+				if !src::Source::seek_forward(&mut reader, first * frame)? {
+					std::io::copy(&mut (&mut reader).take(first * frame), &mut std::io::sink())?;
+				}
+
+				/* There's no going back to where the audio starts through the
+				 * trimmed reader, so trimmed items get opened again instead. */
+				let reader: Box<dyn Read + Send> = Box::new(reader.take((end - first) * frame));
+				Ok(Box::new(reader))
+			});
+
+			(name, open)
+		})
+		.collect();
+	let source = src::ConcatSource::new(items, matches.is_present(ARG_STRICT))
+		.progress(args.progress())
+		.repeat(repeat(matches));
+	let source = if let Some(seed) = matches.value_of(ARG_SHUFFLE_SEED) {
+		let seed = u64::from_str_radix(seed, 10)
+			.map_err(Error::MalformedShuffleSeed)?;
+		source.shuffle(seed)
+	} else if matches.is_present(ARG_SHUFFLE) {
+		let seed = std::time::SystemTime::now()
+			.duration_since(std::time::UNIX_EPOCH)
+			.map(|time| time.as_nanos() as u64)
+			.unwrap_or(0);
+		source.shuffle(seed)
+	} else {
+		source
+	};
+	let source = if let Some(length) = matches.value_of(ARG_CROSSFADE) {
+		let length = u32::from_str_radix(length, 10)
+			.map_err(Error::MalformedCrossfade)?;
+
+		let frames = u64::from(length) * u64::from(format.sample_rate().0) / 1000;
+		source.crossfade(src::Crossfade {
+			length: frames as usize * frame,
+			frame,
+			format: args.input_sample_format().unwrap_or(format.sample_format()),
+			endian
+		})
+	} else {
+		source
+	};
+
+	Ok((Box::new(source), format))
+}
+
+
+/** The order of the filter converted input files go through before going down
+ * in rate. */
+fn anti_alias_order(matches: &ArgMatches) -> Result<usize, Error> {
+	let value = match matches.value_of(ARG_ANTI_ALIAS_ORDER) {
+		Some(value) => value,
+		None => return Ok(resample::DEFAULT_ANTI_ALIAS_ORDER)
+	};
+
+	usize::from_str_radix(value, 10).ok()
+		.filter(|order| order % 2 == 0 && *order <= filter::MAX_ORDER)
+		.ok_or_else(|| Error::MalformedAntiAliasOrder { value: value.to_owned() })
+}
+
+/** Opens a file descriptor we were handed by whoever started us. */
+#[cfg(unix)]
+pub fn open_fd(fd: &str) -> Result<Box<dyn Read + Send>, Error> {
+	use std::os::unix::io::FromRawFd;
+
+	let fd = i32::from_str_radix(fd, 10)
+		.map_err(Error::MalformedFd)?;
+
+	/* Make sure the descriptor is open before taking it over, rather than
+	 * finding out on the first read. */
+	if fd < 0 || unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+		return Err(Error::BadFd {
+			fd,
+			what: std::io::Error::last_os_error()
+		})
+	}
+
+	/* The file owns the descriptor from here on, and is the only one that's
+	 * ever going to close it. */
+	Ok(Box::new(unsafe { std::fs::File::from_raw_fd(fd) }))
+}
+
+/** Opens a file descriptor we were handed by whoever started us. */
+#[cfg(not(unix))]
+pub fn open_fd(_: &str) -> Result<Box<dyn Read + Send>, Error> {
+	Err(Error::FdUnsupported)
+}
+
+/** How long to wait for someone to connect when listening, if not forever. */
+fn accept_timeout(matches: &ArgMatches) -> Result<Option<std::time::Duration>, Error> {
+	let timeout = matches.value_of(ARG_ACCEPT_TIMEOUT)
+		.map(|timeout| u64::from_str_radix(timeout, 10))
+		.transpose()
+		.map_err(Error::MalformedAcceptTimeout)?;
+
+	Ok(timeout.map(std::time::Duration::from_secs))
+}
+
+/** Takes the input from the first connection to a socket at the given path. */
+#[cfg(unix)]
+fn listen_unix(matches: &ArgMatches, args: &Arguments, path: &Path)
+	-> Result<Box<dyn Read + Send>, Error> {
+
+	let source = crate::socket::listen(
+		path,
+		accept_timeout(matches)?,
+		matches.is_present(ARG_ACCEPT_LOOP),
+		args.banner())?;
+	Ok(Box::new(source))
+}
+
+/** Takes the input from the first connection to a socket at the given path. */
+#[cfg(not(unix))]
+fn listen_unix(_: &ArgMatches, _: &Arguments, _: &Path) -> Result<Box<dyn Read + Send>, Error> {
+	Err(Error::SocketUnsupported)
+}
+
+/** Opens a stream from the given URL. */
+#[cfg(feature = "http")]
+fn open_url(url: &str, banner: bool) -> Result<Box<dyn Read + Send>, Error> {
+	crate::http::open(url, banner)
+}
+
+/** Opens a stream from the given URL. */
+#[cfg(not(feature = "http"))]
+fn open_url(url: &str, _: bool) -> Result<Box<dyn Read + Send>, Error> {
+	Err(Error::HttpUnsupported {
+		url: url.to_owned()
+	})
+}
+
+/** Whether the given path is really the URL of something to be downloaded. */
+fn is_url(path: &Path) -> bool {
+	path.to_str()
+		.map(|path| path.starts_with("http://") || path.starts_with("https://"))
+		.unwrap_or(false)
+}
+
+/** What the extension of an input file says about what's in it. */
+#[derive(Debug, PartialEq)]
+enum Extension {
+	/** Raw samples, in the given format, if the extension names one. */
+	Raw(Option<(cpal::SampleFormat, Option<arg::Endianness>)>),
+	/** Anything else, which is left to format detection. */
+	Other,
+}
+
+/** Looks at the extension of the given file. Containers are not told apart
+ * here, as detection already does that from their contents. */
+fn extension(path: &Path) -> Extension {
+	let extension = match path.extension().and_then(|extension| extension.to_str()) {
+		Some(extension) => extension.to_ascii_lowercase(),
+		None => return Extension::Other
+	};
+
+	match extension.as_str() {
+		"raw" | "pcm" => Extension::Raw(None),
+		name => match arg::parse_sample_format(name) {
+			Ok(format) => Extension::Raw(Some(format)),
+			Err(_) => Extension::Other
+		}
+	}
+}
+
+/** Has the given input keep playing the loop embedded in it once it gets to
+ * it, if looping was asked for and it has one. */
+fn embedded_loop<R>(
+	source: R,
+	detected: Option<&detect::Detected>,
+	looping: bool,
+	times: Option<u64>) -> Box<dyn src::Source>
+	where R: src::Source + 'static {
+
+	let detected = detected.filter(|_| looping);
+	match detected.and_then(|detected| detected.loop_points) {
+		Some(points) => {
+			let format = detected.unwrap().format;
+			let frame = format.sample_format.sample_size() * usize::from(format.channels);
+			Box::new(src::LoopRegion::new(source, points, frame, times))
+		},
+		None => Box::new(source)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/** Writes a short WAV file in the given format where tests can get to it. */
+	fn wav_file(name: &str, format: arg::InputFormat) -> std::path::PathBuf {
+		use std::io::Write;
+
+		let path = std::env::temp_dir().join(format!("alplay-{}-{}.wav", name, std::process::id()));
+		let mut writer = crate::wav::WavWriter::new(std::fs::File::create(&path).unwrap(), format).unwrap();
+		writer.write_all(&[0; 400]).unwrap();
+		writer.finish().unwrap();
+
+		path
+	}
+
+	#[test]
+	fn items_get_checked_against_the_format_of_the_list() {
+		let stereo = arg::InputFormat {
+			channels: 2,
+			sample_rate: 48000,
+			sample_format: cpal::SampleFormat::I16,
+			endian: arg::Endianness::Little
+		};
+		let first = wav_file("concat-first", stereo);
+		let second = wav_file("concat-second", arg::InputFormat { sample_rate: 44100, ..stereo });
+		let opened = |files: &[&Path], extra: &[&str]| {
+			let matches = clap::App::new("alplay")
+				.args(&crate::device_args())
+				.args(&crate::input_args())
+				.args(&crate::playback_args())
+				.get_matches_from(["alplay", "--output", "null"].iter()
+					.copied()
+					.chain(extra.iter().copied())
+					.chain(files.iter().map(|file| file.to_str().unwrap())));
+			let mut args = Arguments::new(&matches).unwrap();
+			open(&matches, &mut args).map(|_| ())
+		};
+
+		/* The header of the second file gives away its rate. */
+		match opened(&[&first, &second], &[]) {
+			Err(Error::MismatchedItems { items }) => {
+				assert_eq!(items.len(), 2);
+				assert_eq!(items[0].1.sample_rate, 48000);
+				assert_eq!(items[1].1.sample_rate, 44100);
+			},
+			other => panic!("expected mismatched items, got {:?}", other.err())
+		}
+		assert!(opened(&[&first, &second], &["--force-concat"]).is_ok());
+		assert!(opened(&[&first, &first], &[]).is_ok());
+
+		/* Raw items are checked by their name, without being opened. */
+		match opened(&[&first, Path::new("samples.u16le")], &["--format-from-extension"]) {
+			Err(Error::MismatchedItems { items }) =>
+				assert_eq!(items[1].1.sample_format, cpal::SampleFormat::U16),
+			other => panic!("expected mismatched items, got {:?}", other.err())
+		}
+
+		let _ = std::fs::remove_file(first);
+		let _ = std::fs::remove_file(second);
+	}
+
+	#[test]
+	fn silence_only_gets_trimmed_off_files() {
+		let file = wav_file("trim-file", arg::InputFormat {
+			channels: 2,
+			sample_rate: 48000,
+			sample_format: cpal::SampleFormat::I16,
+			endian: arg::Endianness::Little
+		});
+		let opened = |files: &[&str]| {
+			let matches = clap::App::new("alplay")
+				.args(&crate::device_args())
+				.args(&crate::input_args())
+				.args(&crate::playback_args())
+				.get_matches_from(["alplay", "--output", "null", "--trim-silence", "-60"].iter()
+					.chain(files));
+			let mut args = Arguments::new(&matches).unwrap();
+			open(&matches, &mut args).map(|_| ())
+		};
+
+		let path = file.to_str().unwrap();
+		assert!(opened(&[path, path]).is_ok());
+		for stream in ["-", "http://localhost/trim.wav"] {
+			match opened(&[path, stream]) {
+				Err(Error::TrimNeedsFiles) => {},
+				other => panic!("expected {} to be refused, got {:?}", stream, other.err())
+			}
+		}
+
+		let _ = std::fs::remove_file(file);
+	}
+
+	#[test]
+	fn extensions_name_sample_formats() {
+		use arg::Endianness::{Big, Little};
+		use cpal::SampleFormat::{F32, I16, U16};
+
+		let table = [
+			("input.s16le", Extension::Raw(Some((I16, Some(Little))))),
+			("input.s16be", Extension::Raw(Some((I16, Some(Big))))),
+			("input.s16", Extension::Raw(Some((I16, None)))),
+			("input.u16le", Extension::Raw(Some((U16, Some(Little))))),
+			("input.u16", Extension::Raw(Some((U16, None)))),
+			("input.f32be", Extension::Raw(Some((F32, Some(Big))))),
+			("input.F32LE", Extension::Raw(Some((F32, Some(Little))))),
+			("input.f32", Extension::Raw(Some((F32, None)))),
+			("input.raw", Extension::Raw(None)),
+			("input.PCM", Extension::Raw(None)),
+			("input.wav", Extension::Other),
+			("input.aiff", Extension::Other),
+			("input.s24le", Extension::Other),
+			("input.le", Extension::Other),
+			("s16le", Extension::Other),
+			("input", Extension::Other),
+		];
+		for (path, expected) in table {
+			assert_eq!(extension(Path::new(path)), expected, "{}", path);
+		}
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn descriptors_get_read_like_stdin() {
+		use std::io::Write;
+		use std::os::unix::io::FromRawFd;
+
+		let mut fds = [0; 2];
+		assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+		let mut writer = unsafe { std::fs::File::from_raw_fd(fds[1]) };
+
+		let samples = (0..100000u32).map(|i| (i * 3) as u8).collect::<Vec<_>>();
+		let written = samples.clone();
+		let feeder = std::thread::spawn(move || writer.write_all(&written));
+
+		let mut source = open_fd(&fds[0].to_string()).unwrap();
+		let mut read = Vec::new();
+		source.read_to_end(&mut read).unwrap();
+		feeder.join().unwrap().unwrap();
+		assert_eq!(read, samples);
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn descriptors_must_be_open() {
+		for fd in ["-1", "2147483647"] {
+			match open_fd(fd) {
+				Err(Error::BadFd { .. }) => {},
+				other => panic!("expected {} to be refused, got {:?}", fd, other.map(|_| ()))
+			}
+		}
+		assert!(matches!(open_fd("three"), Err(Error::MalformedFd(_))));
+	}
+
+	#[test]
+	fn descriptors_are_not_taken_along_with_files() {
+		let matches = clap::App::new("alplay")
+			.args(&crate::device_args())
+			.args(&crate::input_args())
+			.args(&crate::playback_args())
+			.get_matches_from_safe(["alplay", "--fd", "3", "input.raw"]);
+		assert_eq!(matches.unwrap_err().kind, clap::ErrorKind::ArgumentConflict);
+	}
+}
@@ -1,12 +1,37 @@
-use clap::{App, Arg, SubCommand};
-use crate::arg::Arguments;
+/* Doc comments are indented with tabs, just like the code is, constants spell
+ * their lifetimes out, and numbers get parsed in an explicit radix. */
+#![allow(
+	clippy::tabs_in_doc_comments,
+	clippy::redundant_static_lifetimes,
+	clippy::from_str_radix_10)]
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use crate::arg::{Arguments, OutputConfig};
+use crate::error::Error;
+use std::io::Read;
+use std::path::Path;
 
 /** Playback functionality. */
 mod play;
 
-/** Diagnostics functionality. */
+/** Diagnostics functionality. Listings go to the standard output, one item
+ * per line, so that they can be captured or piped along on their own, while
+ * their headers, along with any notes, warnings and errors, go to the standard
+ * error, like every other message. */
 mod diag;
 
+/** Sanity checks on the audio setup. */
+mod doctor;
+
+/** Measurement of inputs, without playing them. */
+mod analyze;
+
+/** Throughput measurements of the stages of the pipeline. */
+mod bench;
+
+/** Recording from input devices. */
+mod record;
+
 /** Runtime argument processor. */
 mod arg;
 
@@ -16,73 +41,449 @@ mod error;
 /** Audio source types. */
 mod src;
 
+/** Sample conversion routines. */
+mod convert;
+
+/** Playlist parsing. */
+mod playlist;
+
+/** Sample rate conversion. */
+mod resample;
+
+/** Low-pass filtering. */
+mod filter;
+
+/** Container format detection. */
+mod detect;
+
+/** Container formats we know of and how to open them. */
+mod registry;
+
+/** Headers describing raw samples. */
+mod header;
+
+/** WAV file parsing. */
+mod wav;
+
+/** AIFF file parsing. */
+mod aiff;
+
+/** AU file parsing. */
+mod au;
+
+/** Wall-clock time handling. */
+mod clock;
+
+/** Channel mixing. */
+mod mix;
+
+/** FLAC decoding. */
+#[cfg(feature = "flac")]
+mod flac;
+
+/** Ogg Vorbis decoding. */
+#[cfg(feature = "vorbis")]
+mod vorbis;
+
+/** MP3 decoding. */
+#[cfg(feature = "mp3")]
+mod mp3;
+
+/** General purpose decoding, through symphonia. */
+#[cfg(feature = "symphonia")]
+mod universal;
+
+/** Decompression of gzip input. */
+#[cfg(feature = "gzip")]
+mod gzip;
+
+/** Decompression of zstd input. */
+#[cfg(feature = "zstd")]
+mod zst;
+
+/** Memory mapped input files. */
+#[cfg(feature = "mmap")]
+mod mapped;
+
+/** Selection of where the input comes from. */
+mod input;
+
+/** Network stream sources. */
+mod net;
+
+/** Inputs that get reopened as they run out. */
+mod follow;
+
+/** Test signals. */
+mod tone;
+
+/** Track metadata read from a side channel. */
+mod meta;
+
+/** Descriptions of raw inputs kept in files of their own. */
+mod sidecar;
+
+/** Unix domain socket sources. */
+#[cfg(unix)]
+mod socket;
+
+/** HTTP(S) stream sources. */
+#[cfg(feature = "http")]
+mod http;
+
+/** Exit status for when playback stops because the input went idle, so that it
+ * can be told apart from other failures. */
+const EXIT_IDLE: i32 = 3;
+
+/** Exit status for when playback gets aborted for having run out of samples
+ * too many times, so that it can be told apart from other failures. */
+const EXIT_UNDERRUNS: i32 = 4;
+
+/** Exit status for when the sanity checks only turn up warnings, so that it can
+ * be told apart from them passing and from them failing. */
+const EXIT_WARNINGS: i32 = 5;
+
 /** Argument ID for host specification. */
 const ARG_HOST: &'static str = "HOST";
 /** Argument ID for device specification */
 const ARG_DEVICE: &'static str = "DEVICE";
+/** Argument ID for the specification of devices to mirror the output to. */
+const ARG_ALSO_DEVICE: &'static str = "ALSO_DEVICE";
 /** Argument ID for channel count specification */
 const ARG_CHANNELS: &'static str = "CHANNELS";
 /** Argument ID for channel count specification */
 const ARG_EXTERNAL_SYNC: &'static str = "EXTERNAL_SYNC";
+/** Argument ID for playing as many channels as the device defaults to. */
+const ARG_CHANNELS_FROM_DEVICE: &'static str = "CHANNELS_FROM_DEVICE";
+/** Argument ID for folding the LFE channel into a surround downmix. */
+const ARG_KEEP_LFE: &'static str = "KEEP_LFE";
+/** Argument ID for leaving the LFE channel out of a surround downmix. */
+const ARG_DROP_LFE: &'static str = "DROP_LFE";
+/** Argument ID for skipping negotiation of the stream configuration. */
+const ARG_FORCE_CONFIG: &'static str = "FORCE_CONFIG";
 /** Argument ID for sample rate specification */
 const ARG_SAMPLE_RATE: &'static str = "SAMPLE_RATE";
+/** Argument ID for the sample rate the input is taken to be at, whatever it
+ * says. */
+const ARG_ASSUME_RATE: &'static str = "ASSUME_RATE";
 /** Argument ID for sample format specification */
 const ARG_SAMPLE_FORMAT: &'static str = "SAMPLE_FORMAT";
+/** Argument ID for the endianness of the input samples. */
+const ARG_ENDIAN: &'static str = "ENDIAN";
+/** Argument ID for the wall-clock time at which playback starts. */
+const ARG_START_AT: &'static str = "START_AT";
+/** Argument ID for the channel mixing matrix. */
+const ARG_MATRIX: &'static str = "MATRIX";
+/** Argument ID for leaving out the banner. */
+const ARG_NO_BANNER: &'static str = "NO_BANNER";
+/** Argument ID for leaving out progress messages. */
+const ARG_NO_PROGRESS: &'static str = "NO_PROGRESS";
+/** Argument ID for leaving out the end of input message. */
+const ARG_NO_EOF_MESSAGE: &'static str = "NO_EOF_MESSAGE";
+/** Argument ID for the callback timestamp log. */
+const ARG_TIMESTAMPS: &'static str = "TIMESTAMPS";
+/** Argument ID for the log of clipped samples. */
+const ARG_CLIP_REPORT: &'static str = "CLIP_REPORT";
+/** Argument ID for the dither applied to samples quantized for the output. */
+const ARG_DITHER: &'static str = "DITHER";
+/** Argument ID for where the output gets played to. */
+const ARG_OUTPUT: &'static str = "OUTPUT";
+/** Argument ID for the number of bytes every raw sample takes up. */
+const ARG_SAMPLE_SIZE: &'static str = "SAMPLE_SIZE";
+/** Argument ID for the number of bits in every raw sample that carry data. */
+const ARG_VALID_BITS: &'static str = "VALID_BITS";
+/** Argument ID for capturing the output to a file as it's played. */
+const ARG_TEE: &'static str = "TEE";
+/** Argument ID for the log every run gets recorded in. */
+const ARG_AUDIT_LOG: &'static str = "AUDIT_LOG";
+/** Argument ID for rendering the output to a file, rather than playing it. */
+const ARG_RENDER_TO: &'static str = "RENDER_TO";
+/** Subcommand ID for playback. */
+const CMD_PLAY: &'static str = "play";
 /** Subcommand ID for device listing. */
-const ARG_LIST_DEVICES: &'static str = "DEVICES";
+const CMD_DEVICES: &'static str = "devices";
 /** Subcommand ID for host listing. */
-const ARG_LIST_HOSTS: &'static str = "HOSTS";
+const CMD_HOSTS: &'static str = "hosts";
+/** Subcommand ID for describing how the input would be played. */
+const CMD_PROBE: &'static str = "probe";
+/** Subcommand ID for writing out a header describing raw samples. */
+const CMD_HEADER: &'static str = "header";
+/** Subcommand ID for playing a generated signal. */
+const CMD_GEN: &'static str = "gen";
+/** Subcommand ID for running sanity checks on the audio setup. */
+const CMD_DOCTOR: &'static str = "doctor";
+/** Subcommand ID for measuring the input without playing it. */
+const CMD_ANALYZE: &'static str = "analyze";
+/** Subcommand ID for measuring how fast the stages of the pipeline go. */
+const CMD_BENCH: &'static str = "bench";
+/** Subcommand ID for recording from an input device. */
+const CMD_RECORD: &'static str = "record";
+/** Argument ID for the WAV file a recording gets written to. */
+const ARG_RECORD_TO: &'static str = "RECORD_TO";
+/** Argument ID for reporting the results of the sanity checks, or of the
+ * measurements, as JSON. */
+const ARG_JSON: &'static str = "JSON";
+/** Argument ID for a stage of the pipeline to be benchmarked. */
+const ARG_STAGE: &'static str = "STAGE";
+/** Argument ID for how long every stage gets benchmarked for, in seconds. */
+const ARG_BENCH_DURATION: &'static str = "BENCH_DURATION";
+/** Argument ID for the format the benchmarked conversion converts into. */
+const ARG_OUTPUT_FORMAT: &'static str = "OUTPUT_FORMAT";
+/** Argument ID for the kind of signal to be generated. */
+const ARG_SIGNAL: &'static str = "SIGNAL";
+/** Argument ID for the color of generated noise. */
+const ARG_COLOR: &'static str = "COLOR";
+/** Argument ID for the level of generated signals. */
+const ARG_LEVEL: &'static str = "LEVEL";
+/** Argument ID for the seed generated noise comes from. */
+const ARG_SEED: &'static str = "SEED";
+/** Argument ID for how long generated signals last. */
+const ARG_DURATION: &'static str = "DURATION";
+/** Argument ID for generating noise of its own for every channel. */
+const ARG_DECORRELATE: &'static str = "DECORRELATE";
+/** Argument ID for the frequency generated sweeps start at. */
+const ARG_FROM: &'static str = "FROM";
+/** Argument ID for the frequency generated sweeps end at. */
+const ARG_TO: &'static str = "TO";
+/** Argument ID for sweeping through frequencies linearly. */
+const ARG_LINEAR: &'static str = "LINEAR";
+/** Argument ID for the silence played around generated sweeps. */
+const ARG_PAD: &'static str = "PAD";
+/** Argument ID for how long the bursts played on every channel last. */
+const ARG_BURST_MS: &'static str = "BURST_MS";
+/** Argument ID for the silence played after every burst. */
+const ARG_GAP_MS: &'static str = "GAP_MS";
+/** Argument ID for the tempo generated clicks are played at. */
+const ARG_BPM: &'static str = "BPM";
+/** Argument ID for how often generated clicks are accented. */
+const ARG_ACCENT: &'static str = "ACCENT";
+/** Argument ID for the list of input files. */
+const ARG_FILES: &'static str = "FILES";
+/** Argument ID for the playlist file. */
+const ARG_PLAYLIST: &'static str = "PLAYLIST";
+/** Argument ID for shuffled playback of input files. */
+const ARG_SHUFFLE: &'static str = "SHUFFLE";
+/** Argument ID for the seed of the shuffled playback order. */
+const ARG_SHUFFLE_SEED: &'static str = "SHUFFLE_SEED";
+/** Argument ID for the repeat mode of input files. */
+const ARG_REPEAT: &'static str = "REPEAT";
+/** Argument ID for the largest copy of a stream kept in memory to repeat it. */
+const ARG_LOOP_BUFFER: &'static str = "LOOP_BUFFER";
+/** Argument ID for the size of the buffer streams are read through. */
+const ARG_IO_BUFFER: &'static str = "IO_BUFFER";
+/** Argument ID for the crossfade between input files. */
+const ARG_CROSSFADE: &'static str = "CROSSFADE";
+/** Argument ID for the interval of frame alignment checks. */
+const ARG_RESYNC: &'static str = "RESYNC";
+/** Argument ID for the size of the chunks external sync reads the input in. */
+const ARG_SYNC_CHUNK: &'static str = "SYNC_CHUNK";
+/** Argument ID for the source URL. */
+const ARG_SOURCE: &'static str = "SOURCE";
+/** Argument ID for the file descriptor to read the input from. */
+const ARG_FD: &'static str = "FD";
+/** Argument ID for the Unix domain socket to take the input from. */
+const ARG_LISTEN_UNIX: &'static str = "LISTEN_UNIX";
+/** Argument ID for the TCP address to take the input from. */
+const ARG_LISTEN: &'static str = "LISTEN";
+/** Argument ID for the size of the receive buffer of TCP connections. */
+const ARG_RECV_BUFFER: &'static str = "RECV_BUFFER";
+/** Argument ID for the TCP server to take the input from. */
+const ARG_CONNECT: &'static str = "CONNECT";
+/** Argument ID for how long to wait for a connection to the server. */
+const ARG_CONNECT_TIMEOUT: &'static str = "CONNECT_TIMEOUT";
+/** Argument ID for reconnecting to the server once the connection is lost. */
+const ARG_RECONNECT: &'static str = "RECONNECT";
+/** Argument ID for how long to wait for a connection. */
+const ARG_ACCEPT_TIMEOUT: &'static str = "ACCEPT_TIMEOUT";
+/** Argument ID for accepting further connections once one is over. */
+const ARG_ACCEPT_LOOP: &'static str = "ACCEPT_LOOP";
+/** Argument ID for reopening the input file every time it runs out. */
+const ARG_FOLLOW: &'static str = "FOLLOW";
+/** Argument ID for how many times the input file may be reopened. */
+const ARG_FOLLOW_LIMIT: &'static str = "FOLLOW_LIMIT";
+/** Argument ID for how long to wait for the input file to be reopened. */
+const ARG_FOLLOW_TIMEOUT: &'static str = "FOLLOW_TIMEOUT";
+/** Argument ID for the UDP address to take the input from. */
+const ARG_UDP: &'static str = "UDP";
+/** Argument ID for the datagrams carrying sequence numbers. */
+const ARG_UDP_SEQ: &'static str = "UDP_SEQ";
+/** Argument ID for playing a sine sweep instead of any input. */
+const ARG_SWEEP: &'static str = "SWEEP";
+/** Argument ID for the WAV file generated signals get rendered to. */
+const ARG_RENDER: &'static str = "RENDER";
+/** Argument ID for forwarding the input to the standard output. */
+const ARG_PIPE_THROUGH: &'static str = "PIPE_THROUGH";
+/** Argument ID for waiting for more input once it runs out. */
+const ARG_HOLD_OPEN: &'static str = "HOLD_OPEN";
+/** Argument ID for how long to wait for more input. */
+const ARG_IDLE_TIMEOUT: &'static str = "IDLE_TIMEOUT";
+/** Argument ID for delaying the output. */
+const ARG_DELAY: &'static str = "DELAY";
+/** Argument ID for the inherited file descriptor track metadata comes from. */
+const ARG_META_FD: &'static str = "META_FD";
+/** Argument ID for the path track metadata comes from. */
+const ARG_META_FILE: &'static str = "META_FILE";
+/** Argument ID for fading out on pauses and interruptions, and back in on
+ * resuming. */
+const ARG_RAMP_ON_PAUSE: &'static str = "RAMP_ON_PAUSE";
+/** Argument ID for the number of underruns after which playback is aborted. */
+const ARG_MAX_UNDERRUNS: &'static str = "MAX_UNDERRUNS";
+/** Argument ID for how long to spin for while waiting for playback to end. */
+const ARG_SPIN_US: &'static str = "SPIN_US";
+/** Argument ID for the threshold used to trim silence off input files. */
+const ARG_TRIM_SILENCE: &'static str = "TRIM_SILENCE";
+/** Argument ID for the amount of the input left out at its end. */
+const ARG_TRIM_END: &'static str = "TRIM_END";
+/** Argument ID for the segments of the input to be played. */
+const ARG_SEGMENTS: &'static str = "SEGMENTS";
+/** Argument ID for playing the loops embedded in the input. */
+const ARG_LOOP: &'static str = "LOOP";
+/** Argument ID for the segment of the input to be played over and over. */
+const ARG_AB_LOOP: &'static str = "AB_LOOP";
+/** Argument ID for printing the arguments that reproduce the configuration. */
+const ARG_PRINT_INVOCATION: &'static str = "PRINT_INVOCATION";
+/** Argument ID for the file describing the format of the input. */
+const ARG_SIDECAR: &'static str = "SIDECAR";
+/** Argument ID for taking the format from a header at the start of the input. */
+const ARG_ACCEPT_HEADER: &'static str = "ACCEPT_HEADER";
+/** Argument ID for skipping container format detection. */
+const ARG_RAW: &'static str = "RAW";
+/** Argument ID for guessing the format of input files from their extension. */
+const ARG_FORMAT_FROM_EXTENSION: &'static str = "FORMAT_FROM_EXTENSION";
+/** Argument ID for mapping input files into memory. */
+const ARG_MMAP: &'static str = "MMAP";
+/** Argument ID for reading the whole input before playing it. */
+const ARG_PRELOAD: &'static str = "PRELOAD";
+/** Argument ID for the largest input that may be preloaded, in MiB. */
+const ARG_PRELOAD_LIMIT: &'static str = "PRELOAD_LIMIT";
+/** Largest input that gets preloaded when no limit is given, in MiB. */
+const PRELOAD_LIMIT_MIB: u64 = 512;
+/** Size of the buffer streams are read through, unless told otherwise. */
+const DEFAULT_IO_BUFFER: usize = 256 * 1024;
+/** Argument ID for strict handling of input files. */
+const ARG_STRICT: &'static str = "STRICT";
+/** Argument ID for converting input files that aren't all in the same format. */
+const ARG_FORCE_CONCAT: &'static str = "FORCE_CONCAT";
+/** Argument ID for the order of the filter converted input files go through
+ * before going down in rate. */
+const ARG_ANTI_ALIAS_ORDER: &'static str = "ANTI_ALIAS_ORDER";
 
 fn main() {
 	let matches = App::new(env!("CARGO_PKG_NAME"))
 		.version(env!("CARGO_PKG_VERSION"))
 		.author(env!("CARGO_PKG_AUTHORS"))
 		.about("")
-		.args(&[
-			Arg::with_name(ARG_HOST)
-				.short("s")
-				.long("host")
-				.takes_value(true)
-				.help("specify the name of the audio host to be used"),
-			Arg::with_name(ARG_DEVICE)
-				.short("d")
-				.long("device")
-				.takes_value(true)
-				.help("specify the name of the audio device to be used"),
-			Arg::with_name(ARG_LIST_HOSTS)
-				.short("l")
-				.long("list-hosts")
-				.takes_value(false)
-				.help("list all available audio hosts"),
-			Arg::with_name(ARG_LIST_DEVICES)
-				.short("L")
-				.long("list-devices")
-				.takes_value(false)
-				.help("list all audio output devices in a given host"),
-			Arg::with_name(ARG_CHANNELS)
-				.short("c")
-				.long("channels")
-				.takes_value(true)
-				.help("specify the number of channels for audio playback"),
-			Arg::with_name(ARG_SAMPLE_RATE)
-				.short("r")
-				.long("rate")
-				.takes_value(true)
-				.help("specify the sample rate for audio playback"),
-			Arg::with_name(ARG_SAMPLE_FORMAT)
-				.short("f")
-				.long("format")
-				.takes_value(true)
-				.help("specify the sample format for audio playback"),
-			Arg::with_name(ARG_EXTERNAL_SYNC)
-				.short("e")
-				.long("external-sync")
-				.takes_value(false)
-				.help("sync playback to external source")
+		.args(&device_args())
+		/* Playing is what we do when no subcommand is given, so that the
+		 * command line keeps working the way it did before there were any. */
+		.args(&input_args())
+		.args(&playback_args())
+		.subcommands(vec![
+			SubCommand::with_name(CMD_PLAY)
+				.about("play the input, which is also done when no subcommand is given")
+				.args(&input_args())
+				.args(&playback_args()),
+			SubCommand::with_name(CMD_DEVICES)
+				.about("list all audio output devices in a given host"),
+			SubCommand::with_name(CMD_HOSTS)
+				.about("list all available audio hosts"),
+			SubCommand::with_name(CMD_PROBE)
+				.about("print how the input would be played, without playing it")
+				.args(&input_args()),
+			SubCommand::with_name(CMD_HEADER)
+				.about("write out a header describing raw samples in the given format, for use with --accept-header")
+				.args(&header_args()),
+			SubCommand::with_name(CMD_GEN)
+				.about("play a generated signal, rather than any input")
+				.args(&gen_args())
+				.args(&playback_args()),
+			SubCommand::with_name(CMD_DOCTOR)
+				.about("check that hosts, devices and streams work, to find out what's wrong with the audio setup")
+				.arg(Arg::with_name(ARG_JSON)
+					.long("json")
+					.takes_value(false)
+					.help("report the results as a JSON array")),
+			SubCommand::with_name(CMD_ANALYZE)
+				.about("measure the peak and RMS levels, DC offset, clipping and length of the input, without playing it. no audio device is opened")
+				.args(&analyze_args()),
+			SubCommand::with_name(CMD_BENCH)
+				.about("measure how fast every stage of the pipeline goes over generated samples. no audio device is opened")
+				.args(&bench_args()),
+			SubCommand::with_name(CMD_RECORD)
+				.about("record from an input device to a WAV file, until interrupted. --device picks the device by its index among the input devices of the host")
+				.args(&record_args())
 		])
 		.get_matches();
 
-	let args = match Arguments::new(&matches) {
+	/* Global arguments get handed down to the subcommand, so its matches
+	 * have everything the subcommand needs. */
+	let (command, matches) = match matches.subcommand() {
+		(command, Some(sub)) => (command, sub.clone()),
+		(_, None) => (CMD_PLAY, matches)
+	};
+
+	/* Headers describe the input, not the output, so no device is needed. */
+	if command == CMD_HEADER {
+		if let Err(what) = write_header(&matches) {
+			eprintln!("error: {}", what);
+			std::process::exit(1);
+		}
+		return
+	}
+
+	/* Rendering a generated signal only ever writes a file out, so no device
+	 * is needed either. */
+	if matches.is_present(ARG_RENDER) {
+		if let Err(what) = render(&matches, command) {
+			eprintln!("error: {}", what);
+			std::process::exit(1);
+		}
+		return
+	}
+
+	/* Measuring only ever reads the input. */
+	if command == CMD_ANALYZE {
+		if let Err(what) = analyze(&matches) {
+			eprintln!("error: {}", what);
+			std::process::exit(1);
+		}
+		return
+	}
+
+	/* Benchmarks only ever go over generated samples. */
+	if command == CMD_BENCH {
+		if let Err(what) = bench(&matches) {
+			eprintln!("error: {}", what);
+			std::process::exit(1);
+		}
+		return
+	}
+
+	/* Recording picks an input device of its own. */
+	if command == CMD_RECORD {
+		if let Err(what) = ctrlc::set_handler(play::interrupt) {
+			eprintln!("warning: could not install the interrupt handler: {}", what);
+		}
+		if let Err(what) = record::record(&matches) {
+			eprintln!("error: {}", what);
+			std::process::exit(1);
+		}
+		return
+	}
+
+	/* The checks are all the more useful when the device can't even be picked,
+	 * so that gets reported as one of them, rather than as an error. */
+	if command == CMD_DOCTOR {
+		let args = Arguments::new(&matches);
+		match doctor::run(&doctor::checks(&args), matches.is_present(ARG_JSON)) {
+			doctor::Outcome::Pass => {},
+			doctor::Outcome::Warn => std::process::exit(EXIT_WARNINGS),
+			doctor::Outcome::Fail => std::process::exit(1)
+		}
+		return
+	}
+
+	let mut args = match Arguments::new(&matches) {
 		Ok(args) => args,
 		Err(what) => {
 			eprintln!("error: {}", what);
@@ -90,17 +491,1805 @@ fn main() {
 		}
 	};
 
-	if matches.is_present(ARG_LIST_HOSTS) {
+	/* Playback gets to wind down on its own when interrupted, so that nothing
+	 * is left behind. Anywhere else, this exits right away, which still beats
+	 * being killed, as cleanup registered to run on exit gets to run. */
+	if let Err(what) = ctrlc::set_handler(play::interrupt) {
+		eprintln!("warning: could not install the interrupt handler: {}", what);
+	}
+	#[cfg(unix)]
+	play::handle_job_control();
+
+	if command == CMD_HOSTS {
 		diag::list_hosts();
-	} else if matches.is_present(ARG_LIST_DEVICES) {
-		diag::list_devices(&args);
+	} else if command == CMD_DEVICES {
+		if let Err(what) = diag::list_devices(&args) {
+			eprintln!("error: {}", what);
+			std::process::exit(1);
+		}
+	} else if command == CMD_PROBE {
+		match input::open(&matches, &mut args) {
+			Ok((_, format)) => {
+				if matches.is_present(ARG_PRINT_INVOCATION) {
+					println!("{}", diag::invocation(&args, &format));
+				}
+				diag::probe(&args, &format)
+			},
+			Err(what) => {
+				eprintln!("error: {}", what);
+				std::process::exit(1);
+			}
+		}
 	} else {
-		let stdin = std::io::stdin();
+		let (source, format) = match input::open(&matches, &mut args) {
+			Ok(input) => input,
+			Err(what) => {
+				eprintln!("error: {}", what);
+				std::process::exit(1);
+			}
+		};
+		if matches.is_present(ARG_PRINT_INVOCATION) {
+			println!("{}", diag::invocation(&args, &format));
+		}
+		if let Some(path) = matches.value_of(ARG_AUDIT_LOG) {
+			if let Err(what) = audit(Path::new(path), &matches, &args, &format) {
+				eprintln!("warning: {}", what);
+			}
+		}
+		let source = if matches.is_present(ARG_PRELOAD) {
+			match preload(&matches, &args, source) {
+				Ok(data) => Box::new(std::io::Cursor::new(data)),
+				Err(what) => {
+					eprintln!("error: {}", what);
+					std::process::exit(1);
+				}
+			}
+		} else {
+			source
+		};
+		let source: Box<dyn Read + Send> = if matches.is_present(ARG_PIPE_THROUGH) {
+			Box::new(src::Tee::new(source, std::io::stdout(), 64))
+		} else {
+			source
+		};
+		let source: Box<dyn Read + Send> = match args.packing() {
+			Some(packing) => Box::new(src::Unpack::new(source, packing)),
+			None => source
+		};
+
+		if let Some(fd) = matches.value_of(ARG_META_FD) {
+			match input::open_fd(fd) {
+				Ok(channel) => meta::watch(channel, args.progress()),
+				Err(what) => {
+					eprintln!("error: {}", what);
+					std::process::exit(1);
+				}
+			}
+		} else if let Some(path) = matches.value_of(ARG_META_FILE) {
+			meta::watch_file(path.into(), args.progress());
+		}
+
+		let idle_timeout = matches.value_of(ARG_IDLE_TIMEOUT)
+			.map(|timeout| u64::from_str_radix(timeout, 10))
+			.transpose()
+			.map_err(Error::MalformedIdleTimeout);
+		let idle_timeout = match idle_timeout {
+			Ok(timeout) => timeout.map(std::time::Duration::from_secs),
+			Err(what) => {
+				eprintln!("error: {}", what);
+				std::process::exit(1);
+			}
+		};
+
+		/* Keep an eye on the input itself, before anything that could make up
+		 * data of its own while it's quiet. */
+		let activity = src::Activity::new();
+		let source: Box<dyn Read + Send> = if idle_timeout.is_some() {
+			Box::new(src::Watch::new(source, activity.clone()))
+		} else {
+			source
+		};
+		let idle = idle_timeout.map(|timeout| (activity, timeout));
+
+		/* Everything up to here deals with the input as it comes in, so it's
+		 * only remapped right before being played. */
+		let remap = |source| remap(&matches, &args, &format, source);
+		let inputs = args.input_channels().unwrap_or(format.channels());
+		let hold_open = matches.is_present(ARG_HOLD_OPEN);
+
+		let delay = matches.value_of(ARG_DELAY)
+			.map(|delay| u64::from_str_radix(delay, 10))
+			.transpose()
+			.map_err(Error::MalformedDelay);
+		let delay = match delay {
+			Ok(delay) => delay,
+			Err(what) => {
+				eprintln!("error: {}", what);
+				std::process::exit(1);
+			}
+		};
+		let delay = |source: Box<dyn Read + Send>| -> Box<dyn Read + Send> {
+			match delay {
+				Some(delay) => Box::new(src::Delay::new(
+					source,
+					(delay * u64::from(format.sample_rate().0) / 1000) as usize,
+					format.channels(),
+					args.input_sample_format().unwrap_or(format.sample_format()),
+					args.endianness().unwrap_or(play::PREFERRED_SAMPLE_ENDIAN))),
+				None => source
+			}
+		};
+
 		if matches.is_present(ARG_EXTERNAL_SYNC) {
-			let source = src::Skipper::new_with_capacity(stdin, 16 * 1024 * 1024);
-			play::play(&args, source);
+			let resync = matches.value_of(ARG_RESYNC)
+				.map(|interval| u64::from_str_radix(interval, 10))
+				.transpose()
+				.map_err(Error::MalformedResync);
+			let resync = match resync {
+				Ok(resync) => resync,
+				Err(what) => {
+					eprintln!("error: {}", what);
+					std::process::exit(1);
+				}
+			};
+			let chunk = matches.value_of(ARG_SYNC_CHUNK)
+				.map(|chunk| usize::from_str_radix(chunk, 10))
+				.transpose()
+				.map_err(Error::MalformedSyncChunk);
+			let chunk = match chunk {
+				Ok(chunk) => chunk,
+				Err(what) => {
+					eprintln!("error: {}", what);
+					std::process::exit(1);
+				}
+			};
+
+			/* The reader thread is free to wait on the input as long as it
+			 * likes, as the data it already has keeps getting played. */
+			let source: Box<dyn Read + Send> = if hold_open {
+				Box::new(src::HoldOpen::new(source, true))
+			} else {
+				source
+			};
+
+			let source = match chunk {
+				Some(chunk) => src::Skipper::new_with_chunk(source, 16 * 1024 * 1024, chunk),
+				None => src::Skipper::new_with_capacity(source, 16 * 1024 * 1024)
+			};
+			let source = match resync {
+				Some(interval) => {
+					let frame = args.input_sample_format().unwrap_or(format.sample_format()).sample_size()
+						* usize::from(inputs);
+					source.resync(frame, std::time::Duration::from_millis(interval))
+				},
+				None => source
+			};
+			finish(play::play(&args, &format, delay(remap(Box::new(source))), idle));
 		} else {
-			play::play(&args, stdin);
+			/* Play silence while waiting on more input, rather than holding up
+			 * the output. */
+			let source: Box<dyn Read + Send> = if hold_open {
+				Box::new(src::FillGaps::new(
+					src::HoldOpen::new(source, false),
+					inputs,
+					format.sample_rate().0,
+					args.input_sample_format().unwrap_or(format.sample_format()),
+					args.endianness().unwrap_or(play::PREFERRED_SAMPLE_ENDIAN)))
+			} else {
+				source
+			};
+			let source = delay(remap(source));
+			match args.render_to() {
+				Some(path) => finish(play::render(&args, &format, source, path)),
+				None => finish(play::play(&args, &format, source, idle))
+			}
+		}
+	}
+}
+
+/** Appends a record of this run to the audit log at the given path. Records
+ * are JSON objects, one per line, so that the log can be appended to by runs
+ * that don't know about one another. */
+fn audit(
+	path: &Path,
+	matches: &ArgMatches,
+	args: &Arguments,
+	format: &OutputConfig) -> Result<(), Error> {
+
+	use std::io::Write;
+
+	let record = audit_record(matches, args, format)?;
+	let error = |what| Error::AuditLog {
+		path: path.to_owned(),
+		what
+	};
+
+	/* A single write keeps records from interleaving with those of other runs
+	 * appending at the same time. */
+	let mut log = std::fs::OpenOptions::new()
+		.create(true)
+		.append(true)
+		.open(path)
+		.map_err(error)?;
+	log.write_all(format!("{}\n", record).as_bytes()).map_err(error)
+}
+
+/** The record of this run that goes in the audit log, as a JSON object. The
+ * timestamp is in seconds since the Unix epoch, and the device is null when
+ * rendering or playing to the null output, neither of which use one. */
+#[cfg(feature = "json")]
+fn audit_record(
+	matches: &ArgMatches,
+	args: &Arguments,
+	format: &OutputConfig) -> Result<String, Error> {
+
+	use cpal::traits::DeviceTrait;
+
+	let timestamp = std::time::SystemTime::now()
+		.duration_since(std::time::UNIX_EPOCH)
+		.map(|since| since.as_secs_f64())
+		.unwrap_or(0.0);
+	let device = if args.render_to().is_some() || args.null_output() {
+		serde_json::Value::Null
+	} else {
+		serde_json::json!({
+			"name": args.device().name().ok(),
+			"pick": args.device_pick().map(|(_, pick)| pick)
+		})
+	};
+
+	let record = serde_json::json!({
+		"timestamp": timestamp,
+		"host": {
+			"name": args.host().id().name(),
+			"pick": args.host_pick().map(|(_, pick)| pick)
+		},
+		"device": device,
+		"config": {
+			"channels": format.channels(),
+			"sample_rate": format.sample_rate().0,
+			"sample_format": arg::sample_format_info(format.sample_format()).name
+		},
+		"render_to": args.render_to().map(|path| path.display().to_string()),
+		"source": audit_source(matches)
+	});
+
+	Ok(record.to_string())
+}
+
+/** The record of this run that goes in the audit log, which can't be written
+ * without JSON support. */
+#[cfg(not(feature = "json"))]
+fn audit_record(_: &ArgMatches, _: &Arguments, _: &OutputConfig)
+	-> Result<String, Error> {
+
+	Err(Error::AuditLogJsonUnsupported)
+}
+
+/** What the input of this run is, as given on the command line. */
+#[cfg_attr(not(feature = "json"), allow(dead_code))]
+fn audit_source(matches: &ArgMatches) -> String {
+	if let Some(signal) = matches.value_of(ARG_SIGNAL) {
+		format!("{} signal", signal)
+	} else if matches.is_present(ARG_SWEEP) {
+		"sweep".to_owned()
+	} else if let Some(source) = matches.value_of(ARG_SOURCE) {
+		source.to_owned()
+	} else if let Some(fd) = matches.value_of(ARG_FD) {
+		format!("file descriptor {}", fd)
+	} else if let Some(playlist) = matches.value_of(ARG_PLAYLIST) {
+		format!("playlist {}", playlist)
+	} else if let Some(files) = matches.values_of(ARG_FILES) {
+		files.collect::<Vec<_>>().join(", ")
+	} else {
+		"standard input".to_owned()
+	}
+}
+
+/** Exits according to how playback went. By now, everything used for it has
+ * been dropped already. */
+fn finish(result: Result<(), Error>) {
+	let status = exit_status(&result);
+	if let Err(what) = result {
+		eprintln!("error: {}", what);
+	}
+	if let Some(status) = status {
+		std::process::exit(status);
+	}
+}
+
+/** Status the process exits with once playback is over with the given result,
+ * unless it went through just fine. */
+fn exit_status(result: &Result<(), Error>) -> Option<i32> {
+	match result {
+		Err(Error::IdleTimeout { .. }) => Some(EXIT_IDLE),
+		Err(Error::TooManyUnderruns { .. }) => Some(EXIT_UNDERRUNS),
+		Err(_) => Some(1),
+		Ok(_) if play::interrupted() => Some(130),
+		Ok(_) => None
+	}
+}
+
+/** Arguments that pick the host and device, shared by every subcommand. */
+fn device_args() -> Vec<Arg<'static, 'static>> {
+	vec![
+		Arg::with_name(ARG_HOST)
+			.short("s")
+			.long("host")
+			.takes_value(true)
+			.help("specify the name of the audio host to be used. defaults to the ALPLAY_HOST environment variable, if set")
+			.global(true),
+		Arg::with_name(ARG_DEVICE)
+			.short("d")
+			.long("device")
+			.takes_value(true)
+			.help("specify the name of the audio device to be used. defaults to the ALPLAY_DEVICE environment variable, if set")
+			.global(true)
+	]
+}
+
+/** Arguments that describe the input and where it comes from. */
+fn input_args() -> Vec<Arg<'static, 'static>> {
+	vec![
+		Arg::with_name(ARG_CHANNELS)
+			.short("c")
+			.long("channels")
+			.takes_value(true)
+			.help("specify the number of channels for audio playback"),
+		Arg::with_name(ARG_SAMPLE_RATE)
+			.short("r")
+			.long("rate")
+			.takes_value(true)
+			.help("specify the sample rate for audio playback"),
+		Arg::with_name(ARG_ASSUME_RATE)
+			.long("assume-rate")
+			.takes_value(true)
+			.value_name("HZ")
+			.conflicts_with(ARG_SAMPLE_RATE)
+			.help("play the input as if it had been recorded at the given sample rate, whatever its header says, which changes its speed and pitch along with it. there is no resampling involved"),
+		Arg::with_name(ARG_SAMPLE_FORMAT)
+			.short("f")
+			.long("format")
+			.takes_value(true)
+			.help("specify the sample format for audio playback"),
+		Arg::with_name(ARG_ENDIAN)
+			.long("endian")
+			.takes_value(true)
+			.value_name("little|big|native")
+			.help("specify the byte order of the input samples, for any sample format"),
+		Arg::with_name(ARG_SAMPLE_SIZE)
+			.long("sample-size")
+			.takes_value(true)
+			.value_name("BYTES")
+			.requires(ARG_RAW)
+			.conflicts_with(ARG_SAMPLE_FORMAT)
+			.help("take raw input to be signed integer samples this many bytes wide, from 1 to 4, unpacking them before playback. for layouts none of the formats have, such as 20 bit samples in 3 bytes"),
+		Arg::with_name(ARG_VALID_BITS)
+			.long("valid-bits")
+			.takes_value(true)
+			.value_name("BITS")
+			.requires(ARG_SAMPLE_SIZE)
+			.help("number of bits in every sample given by --sample-size that carry data, which are the lowest ones. the rest are ignored. defaults to all of them"),
+		Arg::with_name(ARG_FORCE_CONFIG)
+			.long("force-config")
+			.takes_value(false)
+			.help("open the stream with exactly the given rate, channels and format, or the defaults for whichever are missing, without checking them against what the device says it supports. for drivers that misreport their capabilities. any failure is reported as it comes from the audio backend"),
+		Arg::with_name(ARG_ACCEPT_HEADER)
+			.long("accept-header")
+			.takes_value(false)
+			.help("take the format of streams, such as the standard input, from the header written by the header subcommand, if they start with one. the format given on the command line takes precedence, with a warning"),
+		Arg::with_name(ARG_SIDECAR)
+			.long("header")
+			.takes_value(true)
+			.value_name("PATH")
+			.help("take the channels, rate, format and byte order of the input from the given file, either key=value lines or, when built with the json feature, a JSON object, with channels, rate, format and endian keys. flags given on the command line take precedence over it, one by one"),
+		Arg::with_name(ARG_PRINT_INVOCATION)
+			.long("print-invocation")
+			.takes_value(false)
+			.conflicts_with(ARG_PIPE_THROUGH)
+			.help("print the arguments that pick the same host, device and stream configuration again on this machine to the standard output"),
+		Arg::with_name(ARG_RAW)
+			.long("raw")
+			.takes_value(false)
+			.help("play the input as raw samples, even if it looks like a container format"),
+		Arg::with_name(ARG_FORMAT_FROM_EXTENSION)
+			.long("format-from-extension")
+			.takes_value(false)
+			.help("take input files named .raw or .pcm to hold raw samples, and ones named after a sample format, as in .s16le, to hold raw samples in that format"),
+		Arg::with_name(ARG_MMAP)
+			.long("mmap")
+			.takes_value(false)
+			.help("map input files into memory rather than reading them"),
+		Arg::with_name(ARG_PLAYLIST)
+			.long("playlist")
+			.takes_value(true)
+			.conflicts_with(ARG_FILES)
+			.help("play the files listed in the given M3U playlist"),
+		Arg::with_name(ARG_SOURCE)
+			.long("source")
+			.takes_value(true)
+			.conflicts_with_all(&[ARG_FILES, ARG_PLAYLIST])
+			.help("play the stream at the given HTTP(S) URL"),
+		Arg::with_name(ARG_FD)
+			.long("fd")
+			.takes_value(true)
+			.value_name("N")
+			.conflicts_with_all(&[ARG_FILES, ARG_PLAYLIST, ARG_SOURCE])
+			.help("read the input from the given inherited file descriptor, instead of standard input"),
+		Arg::with_name(ARG_LISTEN_UNIX)
+			.long("listen-unix")
+			.takes_value(true)
+			.value_name("PATH")
+			.conflicts_with_all(&[ARG_FILES, ARG_PLAYLIST, ARG_SOURCE, ARG_FD])
+			.help("create a Unix domain socket at the given path and read the input from the first connection to it"),
+		Arg::with_name(ARG_LISTEN)
+			.long("listen")
+			.takes_value(true)
+			.value_name("ADDRESS:PORT")
+			.conflicts_with_all(&[ARG_FILES, ARG_PLAYLIST, ARG_SOURCE, ARG_FD, ARG_LISTEN_UNIX])
+			.help("listen for TCP connections on the given address and read the input from the first one"),
+		Arg::with_name(ARG_CONNECT)
+			.long("connect")
+			.takes_value(true)
+			.value_name("HOST:PORT")
+			.conflicts_with_all(&[ARG_FILES, ARG_PLAYLIST, ARG_SOURCE, ARG_FD, ARG_LISTEN_UNIX, ARG_LISTEN])
+			.help("connect to the given TCP server and read the input from it"),
+		Arg::with_name(ARG_CONNECT_TIMEOUT)
+			.long("connect-timeout")
+			.takes_value(true)
+			.value_name("SECONDS")
+			.requires(ARG_CONNECT)
+			.help("give up on connecting to the server after the given number of seconds"),
+		Arg::with_name(ARG_RECONNECT)
+			.long("reconnect")
+			.takes_value(false)
+			.help("keep reconnecting to the server when the connection is lost, playing silence in the meantime. also waits for the output device to come back should it go away, such as when it gets unplugged, and then carries on from where it left off"),
+		Arg::with_name(ARG_RECV_BUFFER)
+			.long("recv-buffer")
+			.takes_value(true)
+			.value_name("BYTES")
+			.requires(ARG_LISTEN)
+			.help("ask the system to buffer up to the given number of bytes of incoming data on TCP connections"),
+		Arg::with_name(ARG_ACCEPT_TIMEOUT)
+			.long("accept-timeout")
+			.takes_value(true)
+			.value_name("SECONDS")
+			.help("when listening, give up if nobody connects within the given number of seconds"),
+		Arg::with_name(ARG_ACCEPT_LOOP)
+			.long("accept-loop")
+			.takes_value(false)
+			.help("when listening, wait for the next connection once one is over, instead of ending the input"),
+		Arg::with_name(ARG_FOLLOW)
+			.long("follow")
+			.takes_value(false)
+			.requires(ARG_FILES)
+			.help("open the input file again every time it runs out, such as a named pipe whose writers come and go, playing silence in the meantime"),
+		Arg::with_name(ARG_FOLLOW_LIMIT)
+			.long("follow-limit")
+			.takes_value(true)
+			.value_name("COUNT")
+			.requires(ARG_FOLLOW)
+			.help("end the input once it has been reopened the given number of times"),
+		Arg::with_name(ARG_FOLLOW_TIMEOUT)
+			.long("follow-timeout")
+			.takes_value(true)
+			.value_name("SECONDS")
+			.requires(ARG_FOLLOW)
+			.help("end the input if reopening it takes longer than the given number of seconds"),
+		Arg::with_name(ARG_UDP)
+			.long("udp")
+			.takes_value(true)
+			.value_name("ADDRESS:PORT")
+			.conflicts_with_all(&[ARG_FILES, ARG_PLAYLIST, ARG_SOURCE, ARG_FD, ARG_LISTEN_UNIX, ARG_LISTEN, ARG_CONNECT])
+			.help("play the payloads of the datagrams sent to the given UDP address, as raw samples"),
+		Arg::with_name(ARG_UDP_SEQ)
+			.long("udp-seq")
+			.takes_value(false)
+			.requires(ARG_UDP)
+			.help("the datagrams start with a 16 byte header holding a big endian sequence number, used to play silence in place of lost ones"),
+		Arg::with_name(ARG_SWEEP)
+			.long("sweep")
+			.takes_value(true)
+			.value_name("F0:F1:SECONDS")
+			.conflicts_with_all(&[ARG_FILES, ARG_PLAYLIST, ARG_SOURCE, ARG_FD, ARG_LISTEN_UNIX, ARG_LISTEN, ARG_CONNECT, ARG_UDP])
+			.help("play an exponential sine sweep from F0Hz to F1Hz over the given number of seconds, instead of any input, for measuring frequency response"),
+		Arg::with_name(ARG_RENDER)
+			.long("render")
+			.takes_value(true)
+			.value_name("FILE")
+			.requires(ARG_SWEEP)
+			.help("write the sweep to the given WAV file, rather than playing it. no audio device is opened"),
+		Arg::with_name(ARG_FILES)
+			.index(1)
+			.multiple(true)
+			.help("files, or HTTP(S) URLs, to be played back to back, instead of standard input")
+	]
+}
+
+/** Arguments describing the samples a header is written for. */
+fn header_args() -> Vec<Arg<'static, 'static>> {
+	vec![
+		Arg::with_name(ARG_CHANNELS)
+			.short("c")
+			.long("channels")
+			.takes_value(true)
+			.required(true)
+			.help("number of interleaved channels"),
+		Arg::with_name(ARG_SAMPLE_RATE)
+			.short("r")
+			.long("rate")
+			.takes_value(true)
+			.required(true)
+			.help("number of frames per second"),
+		Arg::with_name(ARG_SAMPLE_FORMAT)
+			.short("f")
+			.long("format")
+			.takes_value(true)
+			.required(true)
+			.help("format of every sample"),
+		Arg::with_name(ARG_ENDIAN)
+			.long("endian")
+			.takes_value(true)
+			.value_name("little|big|native")
+			.help("byte order of every sample, for any sample format. defaults to native")
+	]
+}
+
+/** Arguments taken by the analyzer. */
+fn analyze_args() -> Vec<Arg<'static, 'static>> {
+	vec![
+		Arg::with_name(ARG_FILES)
+			.index(1)
+			.help("file to be measured, instead of standard input"),
+		Arg::with_name(ARG_CHANNELS)
+			.short("c")
+			.long("channels")
+			.takes_value(true)
+			.help("number of interleaved channels in raw input"),
+		Arg::with_name(ARG_SAMPLE_RATE)
+			.short("r")
+			.long("rate")
+			.takes_value(true)
+			.help("number of frames per second in raw input"),
+		Arg::with_name(ARG_SAMPLE_FORMAT)
+			.short("f")
+			.long("format")
+			.takes_value(true)
+			.help("format of every sample in raw input"),
+		Arg::with_name(ARG_ENDIAN)
+			.long("endian")
+			.takes_value(true)
+			.value_name("little|big|native")
+			.help("byte order of every sample in raw input"),
+		Arg::with_name(ARG_RAW)
+			.long("raw")
+			.takes_value(false)
+			.help("measure the input as raw samples, even if it looks like a container format"),
+		Arg::with_name(ARG_JSON)
+			.long("json")
+			.takes_value(false)
+			.help("report the measurements as a JSON object")
+	]
+}
+
+/** Arguments taken by the benchmarks. */
+fn bench_args() -> Vec<Arg<'static, 'static>> {
+	vec![
+		Arg::with_name(ARG_STAGE)
+			.long("stage")
+			.takes_value(true)
+			.multiple(true)
+			.number_of_values(1)
+			.possible_values(&["skipper", "conversion", "remap"])
+			.help("stage to be measured. may be given more than once. defaults to every stage"),
+		Arg::with_name(ARG_BENCH_DURATION)
+			.long("duration")
+			.takes_value(true)
+			.value_name("SECONDS")
+			.default_value("3")
+			.help("how long every stage gets measured for"),
+		Arg::with_name(ARG_CHANNELS)
+			.short("c")
+			.long("channels")
+			.takes_value(true)
+			.help("number of interleaved channels in the generated samples"),
+		Arg::with_name(ARG_SAMPLE_FORMAT)
+			.short("f")
+			.long("format")
+			.takes_value(true)
+			.help("format of the generated samples"),
+		Arg::with_name(ARG_ENDIAN)
+			.long("endian")
+			.takes_value(true)
+			.value_name("little|big|native")
+			.help("byte order of the generated samples"),
+		Arg::with_name(ARG_OUTPUT_FORMAT)
+			.long("output-format")
+			.takes_value(true)
+			.help("format the conversion stage converts samples into. defaults to the format of the generated samples"),
+		Arg::with_name(ARG_JSON)
+			.long("json")
+			.takes_value(false)
+			.help("report the measurements as a JSON array")
+	]
+}
+
+/** Arguments taken by the recorder. */
+fn record_args() -> Vec<Arg<'static, 'static>> {
+	vec![
+		Arg::with_name(ARG_RECORD_TO)
+			.index(1)
+			.required(true)
+			.value_name("FILE")
+			.help("WAV file the recording gets written to"),
+		Arg::with_name(ARG_CHANNELS)
+			.short("c")
+			.long("channels")
+			.takes_value(true)
+			.help("number of channels to record. defaults to whatever the device would rather have"),
+		Arg::with_name(ARG_SAMPLE_RATE)
+			.short("r")
+			.long("rate")
+			.takes_value(true)
+			.help("number of frames per second to record. defaults to whatever the device would rather have"),
+		Arg::with_name(ARG_SAMPLE_FORMAT)
+			.short("f")
+			.long("format")
+			.takes_value(true)
+			.help("format of the recorded samples. defaults to whatever the device would rather have. unsigned samples get stored as signed ones"),
+		Arg::with_name(ARG_DURATION)
+			.long("duration")
+			.takes_value(true)
+			.value_name("SECONDS")
+			.help("how long to record for. records until interrupted by default"),
+		Arg::with_name(ARG_NO_BANNER)
+			.long("no-banner")
+			.takes_value(false)
+			.help("don't print what is being recorded and how")
+	]
+}
+
+/** Arguments taken by the signal generator. */
+fn gen_args() -> Vec<Arg<'static, 'static>> {
+	vec![
+		Arg::with_name(ARG_CHANNELS)
+			.short("c")
+			.long("channels")
+			.takes_value(true)
+			.help("number of channels to generate. defaults to whatever the device would rather have"),
+		Arg::with_name(ARG_SAMPLE_RATE)
+			.short("r")
+			.long("rate")
+			.takes_value(true)
+			.help("number of frames per second to generate. defaults to whatever the device would rather have"),
+		Arg::with_name(ARG_SAMPLE_FORMAT)
+			.short("f")
+			.long("format")
+			.takes_value(true)
+			.help("format of the generated samples. defaults to whatever the device would rather have"),
+		Arg::with_name(ARG_RENDER)
+			.long("render")
+			.takes_value(true)
+			.value_name("FILE")
+			.help("write the signal to the given WAV file, rather than playing it. no audio device is opened, and the signal must have an end to it"),
+		Arg::with_name(ARG_SIGNAL)
+			.index(1)
+			.required(true)
+			.possible_values(&["noise", "sweep", "channel-id", "click"])
+			.help("kind of signal to be generated"),
+		Arg::with_name(ARG_COLOR)
+			.long("color")
+			.takes_value(true)
+			.possible_values(&["white", "pink"])
+			.default_value("white")
+			.help("color of the noise: white has the same power at every frequency, pink the same power in every octave"),
+		Arg::with_name(ARG_LEVEL)
+			.long("level")
+			.takes_value(true)
+			.allow_hyphen_values(true)
+			.value_name("dBFS")
+			.help("level of the signal, at most 0. for noise, this is its RMS level, -20 by default, with peaks past full scale getting clipped. for sweeps, channel-id bursts and clicks, it's their peak level, -6 by default"),
+		Arg::with_name(ARG_SEED)
+			.long("seed")
+			.takes_value(true)
+			.help("seed the noise comes from, so that the same noise can be played again. defaults to one based on the time"),
+		Arg::with_name(ARG_DURATION)
+			.long("duration")
+			.takes_value(true)
+			.value_name("SECONDS")
+			.help("how long the signal lasts. noise and clicks play until interrupted by default, and sweeps last 10 seconds"),
+		Arg::with_name(ARG_DECORRELATE)
+			.long("decorrelate")
+			.takes_value(false)
+			.help("generate noise of its own for every channel, rather than playing the same noise on all of them"),
+		Arg::with_name(ARG_FROM)
+			.long("from")
+			.takes_value(true)
+			.value_name("HZ")
+			.default_value("20")
+			.help("frequency the sweep starts at"),
+		Arg::with_name(ARG_TO)
+			.long("to")
+			.takes_value(true)
+			.value_name("HZ")
+			.default_value("20000")
+			.help("frequency the sweep ends at"),
+		Arg::with_name(ARG_LINEAR)
+			.long("linear")
+			.takes_value(false)
+			.help("sweep through the frequencies linearly, rather than spending the same time on every octave"),
+		Arg::with_name(ARG_PAD)
+			.long("pad")
+			.takes_value(true)
+			.value_name("SECONDS")
+			.help("play the given number of seconds of silence before and after the sweep"),
+		Arg::with_name(ARG_BURST_MS)
+			.long("burst-ms")
+			.takes_value(true)
+			.value_name("MILLISECONDS")
+			.default_value("500")
+			.help("how long the tone played on every channel lasts, for channel-id"),
+		Arg::with_name(ARG_GAP_MS)
+			.long("gap-ms")
+			.takes_value(true)
+			.value_name("MILLISECONDS")
+			.default_value("500")
+			.help("how long the silence after every burst lasts, for channel-id"),
+		Arg::with_name(ARG_BPM)
+			.long("bpm")
+			.takes_value(true)
+			.default_value("120")
+			.help("tempo the clicks are played at, in beats per minute"),
+		Arg::with_name(ARG_ACCENT)
+			.long("accent")
+			.takes_value(true)
+			.value_name("BEATS")
+			.help("accent the first of every given number of beats with a higher click")
+	]
+}
+
+/** Parses the number given to the signal generator for the given argument, if
+ * any, making sure it's one the given check accepts. */
+fn signal_number<F>(matches: &ArgMatches, arg: &str, what: &str, accept: F)
+	-> Result<Option<f64>, Error>
+	where F: Fn(f64) -> bool {
+
+	matches.value_of(arg)
+		.map(|value| value.trim().parse::<f64>()
+			.ok()
+			.filter(|number| number.is_finite() && accept(*number))
+			.ok_or_else(|| Error::MalformedSignal {
+				value: value.to_owned(),
+				what: what.to_owned()
+			}))
+		.transpose()
+}
+
+/** Warns about sweeps going past what can be played at the given rate. */
+fn check_sweep(sweep: &tone::SweepSpec, rate: u32) {
+	let nyquist = f64::from(rate) / 2.0;
+	if sweep.start.max(sweep.end) >= nyquist {
+		eprintln!("warning: the sweep goes past {}Hz, above which it can't be played at {}Hz, and will alias",
+			nyquist,
+			rate);
+	}
+}
+
+/** Creates the sweep asked for by the arguments of the signal generator, in
+ * the given format. */
+fn sweep(
+	matches: &ArgMatches,
+	channels: u16,
+	rate: u32,
+	format: cpal::SampleFormat,
+	endian: arg::Endianness) -> Result<tone::Sweep, Error> {
+
+	let frequency = |arg, default| signal_number(matches, arg, "frequencies must be positive numbers of Hz", |hz| hz > 0.0)
+		.map(|hz| hz.unwrap_or(default));
+	let spec = tone::SweepSpec {
+		start: frequency(ARG_FROM, 20.0)?,
+		end: frequency(ARG_TO, 20000.0)?,
+		duration: signal_number(matches, ARG_DURATION, "the duration must be a positive number of seconds", |seconds| seconds > 0.0)?
+			.unwrap_or(10.0),
+		linear: matches.is_present(ARG_LINEAR)
+	};
+	let level = signal_number(matches, ARG_LEVEL, "the level must be a number of dBFS no higher than 0", |level| level <= 0.0)?
+		.unwrap_or(tone::DEFAULT_SWEEP_LEVEL);
+	let pad = signal_number(matches, ARG_PAD, "the padding must be a number of seconds", |seconds| seconds >= 0.0)?
+		.unwrap_or(0.0);
+	check_sweep(&spec, rate);
+
+	Ok(tone::Sweep::new(spec, channels, rate, format, endian)
+		.level(level)
+		.padding(pad))
+}
+
+/** Creates the noise asked for by the arguments of the signal generator, in the
+ * given format. */
+fn noise(
+	matches: &ArgMatches,
+	channels: u16,
+	rate: u32,
+	format: cpal::SampleFormat,
+	endian: arg::Endianness,
+	banner: bool) -> Result<tone::Noise, Error> {
+
+	let malformed = |value: &str, what: &str| Error::MalformedSignal {
+		value: value.to_owned(),
+		what: what.to_owned()
+	};
+
+	let color = matches.value_of(ARG_COLOR).unwrap_or("white");
+	let color = tone::parse_color(color)
+		.ok_or_else(|| malformed(color, "expected white or pink"))?;
+	let level = signal_number(matches, ARG_LEVEL, "the level must be a number of dBFS no higher than 0", |level| level <= 0.0)?
+		.unwrap_or(tone::DEFAULT_NOISE_LEVEL);
+	let seed = match matches.value_of(ARG_SEED) {
+		Some(value) => u64::from_str_radix(value, 10)
+			.map_err(|_| malformed(value, "the seed must be a whole number"))?,
+		None => std::time::SystemTime::now()
+			.duration_since(std::time::UNIX_EPOCH)
+			.map(|time| time.as_nanos() as u64)
+			.unwrap_or(0)
+	};
+	let frames = signal_number(matches, ARG_DURATION, "the duration must be a positive number of seconds", |seconds| seconds > 0.0)?
+		.map(|seconds| (seconds * f64::from(rate)).round() as u64);
+
+	/* Printing the seed lets noise that turned out to be interesting be played
+	 * again. */
+	if banner {
+		eprintln!("generating {} noise at {}dBFS RMS with seed {}",
+			matches.value_of(ARG_COLOR).unwrap_or("white"),
+			level,
+			seed);
+	}
+
+	Ok(tone::Noise::new(
+		color,
+		level,
+		seed,
+		matches.is_present(ARG_DECORRELATE),
+		frames,
+		channels,
+		format,
+		endian))
+}
+
+/** Creates the channel identification bursts asked for by the arguments of the
+ * signal generator, in the given format. */
+fn channel_id(
+	matches: &ArgMatches,
+	channels: u16,
+	rate: u32,
+	format: cpal::SampleFormat,
+	endian: arg::Endianness,
+	announce: bool) -> Result<tone::ChannelId, Error> {
+
+	let frames = |ms: Option<f64>| (ms.unwrap_or(500.0) / 1000.0 * f64::from(rate)).round() as u64;
+	let burst = frames(signal_number(matches, ARG_BURST_MS, "bursts must last a positive number of milliseconds", |ms| ms > 0.0)?);
+	let gap = frames(signal_number(matches, ARG_GAP_MS, "gaps must last a number of milliseconds", |ms| ms >= 0.0)?);
+	let level = signal_number(matches, ARG_LEVEL, "the level must be a number of dBFS no higher than 0", |level| level <= 0.0)?
+		.unwrap_or(tone::DEFAULT_SWEEP_LEVEL);
+
+	/* Going around the channels once is enough to tell them apart, so it only
+	 * keeps going when asked to. */
+	let rounds = if matches.is_present(ARG_LOOP) {
+		loop_times(matches)?
+	} else {
+		Some(1)
+	};
+
+	Ok(tone::ChannelId::new(
+		channels,
+		rate,
+		level,
+		burst,
+		gap,
+		rounds,
+		announce,
+		format,
+		endian))
+}
+
+/** Creates the clicks asked for by the arguments of the signal generator, in the
+ * given format. */
+fn click(
+	matches: &ArgMatches,
+	channels: u16,
+	rate: u32,
+	format: cpal::SampleFormat,
+	endian: arg::Endianness) -> Result<tone::Click, Error> {
+
+	let bpm = signal_number(matches, ARG_BPM, "the tempo must be a positive number of beats per minute", |bpm| bpm >= 0.001)?
+		.unwrap_or(120.0);
+	let accent = matches.value_of(ARG_ACCENT)
+		.map(|value| u64::from_str_radix(value, 10).ok()
+			.filter(|beats| *beats > 0)
+			.ok_or_else(|| Error::MalformedSignal {
+				value: value.to_owned(),
+				what: "accents must come every positive whole number of beats".to_owned()
+			}))
+		.transpose()?;
+	let level = signal_number(matches, ARG_LEVEL, "the level must be a number of dBFS no higher than 0", |level| level <= 0.0)?
+		.unwrap_or(tone::DEFAULT_SWEEP_LEVEL);
+	let frames = signal_number(matches, ARG_DURATION, "the duration must be a positive number of seconds", |seconds| seconds > 0.0)?
+		.map(|seconds| (seconds * f64::from(rate)).round() as u64);
+
+	Ok(tone::Click::new(bpm, accent, level, frames, channels, rate, format, endian))
+}
+
+/** Measures the input given by the arguments and prints the measurements out.
+ * Containers get detected, and their format used, just like when playing them.
+ * Raw input is taken to be in the format given by the arguments, or the
+ * preferred one for playback otherwise. This never goes near an audio
+ * device. */
+fn analyze(matches: &ArgMatches) -> Result<(), Error> {
+	let path = matches.value_of(ARG_FILES)
+		.map(Path::new)
+		.filter(|path| !is_stdin(path));
+	let input = match path {
+		Some(path) => registry::Input::File(std::fs::File::open(path).map_err(Error::InputError)?),
+		None => registry::Input::Stream(Box::new(std::io::stdin()))
+	};
+
+	let (source, detected) = if matches.is_present(ARG_RAW) {
+		let source: Box<dyn src::Source> = match input {
+			registry::Input::File(file) => Box::new(file),
+			registry::Input::Stream(stream) => Box::new(stream),
+			#[cfg(feature = "mmap")]
+			registry::Input::Mapped(map) => Box::new(map)
+		};
+		(source, None)
+	} else {
+		registry::open(input)?
+	};
+
+	let format = match detected {
+		Some(detected) => detected.format,
+		None => arg::sample_description(matches, play::PREFERRED_FORMAT)?
+	};
+
+	let analysis = analyze::measure(source, format).map_err(Error::read_failed)?;
+	analyze::print(&analysis, detected.as_ref(), matches.is_present(ARG_JSON));
+
+	Ok(())
+}
+
+/** Measures how fast the stages asked for by the arguments go, over samples
+ * generated in the format given by them, and prints the results. */
+fn bench(matches: &ArgMatches) -> Result<(), Error> {
+	/* The generated samples never get played, so there's no rate to be
+	 * given for them, and the preferred one always goes. */
+	let format = arg::sample_description(matches, play::PREFERRED_FORMAT)?;
+	let output = match matches.value_of(ARG_OUTPUT_FORMAT) {
+		Some(output) => arg::parse_sample_format(output)?.0,
+		None => format.sample_format
+	};
+
+	/* Has a default value, so it's always there. */
+	let duration = u64::from_str_radix(matches.value_of(ARG_BENCH_DURATION).unwrap(), 10)
+		.map_err(Error::MalformedBenchDuration)?;
+	if duration == 0 {
+		return Err(Error::InvalidBenchDuration)
+	}
+	let duration = std::time::Duration::from_secs(duration);
+
+	/* Clap only lets the names of stages through. */
+	let stages = match matches.values_of(ARG_STAGE) {
+		Some(stages) => stages
+			.map(|stage| bench::Stage::from_name(stage).unwrap())
+			.collect::<Vec<_>>(),
+		None => bench::Stage::ALL.to_vec()
+	};
+
+	let measurements = stages.into_iter()
+		.map(|stage| bench::measure(stage, format, output, duration))
+		.collect::<Result<Vec<_>, _>>()
+		.map_err(Error::read_failed)?;
+	bench::print(&measurements, matches.is_present(ARG_JSON));
+
+	Ok(())
+}
+
+/** Renders the generated signal asked for by the arguments to a WAV file, in
+ * the format given by them, or the preferred one for playback otherwise. This
+ * never goes near an audio device. */
+fn render(matches: &ArgMatches, command: &str) -> Result<(), Error> {
+	/* Rendering is only ever asked for alongside a generated signal, which clap
+	 * made sure of. */
+	let path = Path::new(matches.value_of(ARG_RENDER).unwrap());
+
+	/* WAV files are little endian through and through, whatever byte order
+	 * was asked for. */
+	let format = arg::InputFormat {
+		endian: arg::Endianness::Little,
+		..arg::sample_description(matches, play::PREFERRED_FORMAT)?
+	};
+	let arg::InputFormat { channels, sample_rate, sample_format, endian } = format;
+	let banner = !matches.is_present(ARG_NO_BANNER);
+	let mut source: Box<dyn src::Source> = if command == CMD_GEN {
+		match matches.value_of(ARG_SIGNAL) {
+			Some("sweep") => Box::new(sweep(matches, channels, sample_rate, sample_format, endian)?),
+			Some("channel-id") => Box::new(channel_id(matches, channels, sample_rate, sample_format, endian, false)?),
+			Some("click") => Box::new(click(matches, channels, sample_rate, sample_format, endian)?),
+			_ => Box::new(noise(matches, channels, sample_rate, sample_format, endian, banner)?)
+		}
+	} else {
+		let sweep = tone::parse_sweep(matches.value_of(ARG_SWEEP).unwrap())?;
+		check_sweep(&sweep, sample_rate);
+		Box::new(tone::Sweep::new(sweep, channels, sample_rate, sample_format, endian))
+	};
+	let length = source.len_hint().ok_or(Error::RenderEndless)?;
+
+	let error = |what| Error::Render {
+		path: path.to_owned(),
+		what
+	};
+	let file = std::fs::File::create(path).map_err(error)?;
+	let mut writer = wav::WavWriter::new(std::io::BufWriter::new(file), format).map_err(error)?;
+	std::io::copy(&mut source, &mut writer).map_err(error)?;
+	writer.finish().map_err(error)?;
+
+	if banner {
+		eprintln!("rendered {} to {} ({} bytes of samples, {} channels of {} at {}Hz)",
+			source.description(),
+			path.display(),
+			length,
+			channels,
+			arg::sample_format_info(sample_format).name,
+			sample_rate);
+	}
+
+	Ok(())
+}
+
+/** Writes out the header describing the samples given by the arguments to the
+ * standard output. */
+fn write_header(matches: &ArgMatches) -> Result<(), Error> {
+	/* Everything but the byte order is required, so clap made sure it's
+	 * there, and only the byte order ever comes from the defaults. */
+	let format = arg::sample_description(matches, arg::InputFormat {
+		endian: arg::Endianness::Native,
+		..play::PREFERRED_FORMAT
+	})?;
+	let header = header::encode(&format);
+
+	use std::io::Write;
+	let mut stdout = std::io::stdout();
+	stdout.write_all(&header)
+		.and_then(|_| stdout.flush())
+		.map_err(Error::HeaderOutput)
+}
+
+/** Arguments that only matter when actually playing the input. */
+fn playback_args() -> Vec<Arg<'static, 'static>> {
+	vec![
+		Arg::with_name(ARG_ALSO_DEVICE)
+			.long("also-device")
+			.takes_value(true)
+			.multiple(true)
+			.number_of_values(1)
+			.help("also play the output on the given audio device, converted into what it can play if need be, can be repeated"),
+		Arg::with_name(ARG_CHANNELS_FROM_DEVICE)
+			.long("channels-from-device")
+			.takes_value(false)
+			.help("play as many channels as the device defaults to, remapping the input to match. --channels then gives the number of channels in the input"),
+		Arg::with_name(ARG_KEEP_LFE)
+			.long("keep-lfe")
+			.takes_value(false)
+			.conflicts_with(ARG_DROP_LFE)
+			.help("fold the LFE channel into both sides when 5.1 or 7.1 input gets downmixed to stereo"),
+		Arg::with_name(ARG_DROP_LFE)
+			.long("drop-lfe")
+			.takes_value(false)
+			.help("leave the LFE channel out when 5.1 or 7.1 input gets downmixed to stereo, which is the default"),
+		Arg::with_name(ARG_TIMESTAMPS)
+			.long("timestamps")
+			.takes_value(true)
+			.value_name("FILE")
+			.help("log the callback time, projected playback time and frame count of every callback to the given file, as CSV"),
+		Arg::with_name(ARG_CLIP_REPORT)
+			.long("clip-report")
+			.takes_value(true)
+			.value_name("FILE")
+			.help("log the frame, channel and value of every sample played at or past full scale to the given file, as CSV. the log stops after the first 100000 of them"),
+		Arg::with_name(ARG_DITHER)
+			.long("dither")
+			.takes_value(true)
+			.possible_values(&["none", "rectangular", "triangular"])
+			.help("dither applied to samples that get mixed or converted into integer ones on their way to the output, none by default"),
+		Arg::with_name(ARG_OUTPUT)
+			.long("output")
+			.takes_value(true)
+			.possible_values(&["device", "null"])
+			.conflicts_with(ARG_RENDER_TO)
+			.help("where to play the output to, the device unless given otherwise. null skips the audio device altogether, taking the output in at the pace a device would and throwing it away, for exercising everything else where there's no device"),
+		Arg::with_name(ARG_TEE)
+			.long("tee")
+			.takes_value(true)
+			.value_name("FILE")
+			.conflicts_with(ARG_RENDER_TO)
+			.help("capture the raw samples handed to the device, after every conversion, to the given file. buffers the file can't be written fast enough for get dropped, rather than holding up playback"),
+		Arg::with_name(ARG_AUDIT_LOG)
+			.long("audit-log")
+			.takes_value(true)
+			.value_name("FILE")
+			.help("append a line of JSON recording when this run started, the host, device and configuration it resolved to and what it played to the given file. needs the json feature"),
+		Arg::with_name(ARG_RENDER_TO)
+			.long("render-to")
+			.takes_value(true)
+			.value_name("FILE")
+			.conflicts_with_all(&[
+				ARG_CHANNELS_FROM_DEVICE,
+				ARG_ALSO_DEVICE,
+				ARG_EXTERNAL_SYNC,
+				ARG_HOLD_OPEN,
+				ARG_IDLE_TIMEOUT,
+				ARG_RENDER
+			])
+			.help("rather than playing the output, convert it just as it would be played, as fast as possible, and write the raw samples the device would have been given to the given file. the output format comes from --channels, --rate and --format alone"),
+		Arg::with_name(ARG_NO_BANNER)
+			.long("no-banner")
+			.takes_value(false)
+			.help("don't print what is being played and how"),
+		Arg::with_name(ARG_NO_PROGRESS)
+			.long("no-progress")
+			.takes_value(false)
+			.help("don't announce input files as they start playing"),
+		Arg::with_name(ARG_NO_EOF_MESSAGE)
+			.long("no-eof-message")
+			.takes_value(false)
+			.help("don't print a message once the input is over"),
+		Arg::with_name(ARG_MATRIX)
+			.long("matrix")
+			.takes_value(true)
+			.allow_hyphen_values(true)
+			.value_name("MATRIX")
+			.help("mix the channels through a matrix, given as one weighted sum of input channels per output channel, as in \"0.7*0+0.7*1;0.7*0+0.7*1\""),
+		Arg::with_name(ARG_START_AT)
+			.long("start-at")
+			.takes_value(true)
+			.value_name("TIMESTAMP")
+			.help("wait until the given wall-clock time, either RFC 3339 or seconds since the epoch, before starting playback"),
+		Arg::with_name(ARG_EXTERNAL_SYNC)
+			.short("e")
+			.long("external-sync")
+			.takes_value(false)
+			.help("sync playback to external source"),
+		Arg::with_name(ARG_SHUFFLE)
+			.long("shuffle")
+			.takes_value(false)
+			.help("play the input files in a random order"),
+		Arg::with_name(ARG_SHUFFLE_SEED)
+			.long("shuffle-seed")
+			.takes_value(true)
+			.help("seed for the random order of the input files, implies --shuffle"),
+		Arg::with_name(ARG_REPEAT)
+			.long("repeat")
+			.takes_value(true)
+			.possible_values(&["none", "all", "one"])
+			.help("what to repeat once an input file or the whole list is over"),
+		Arg::with_name(ARG_LOOP_BUFFER)
+			.long("loop-buffer")
+			.takes_value(true)
+			.value_name("BYTES")
+			.requires(ARG_REPEAT)
+			.help("repeat inputs that can't be read twice, such as pipes, from a copy of them kept in memory, of up to the given size. K, M and G suffixes are accepted"),
+		Arg::with_name(ARG_IO_BUFFER)
+			.long("io-buffer")
+			.takes_value(true)
+			.value_name("BYTES")
+			.help("read inputs that aren't files, such as pipes and connections, in chunks of up to the given size, or \"off\" to read them as they come. K, M and G suffixes are accepted. defaults to 256K"),
+		Arg::with_name(ARG_CROSSFADE)
+			.long("crossfade")
+			.takes_value(true)
+			.help("overlap consecutive input files by the given number of milliseconds"),
+		Arg::with_name(ARG_RESYNC)
+			.long("resync")
+			.takes_value(true)
+			.value_name("MS")
+			.requires(ARG_EXTERNAL_SYNC)
+			.help("check every given number of milliseconds that the input is still aligned to whole frames, realigning it if it isn't. corrections may cause a short glitch"),
+		Arg::with_name(ARG_SYNC_CHUNK)
+			.long("sync-chunk")
+			.takes_value(true)
+			.value_name("BYTES")
+			.requires(ARG_EXTERNAL_SYNC)
+			.help("read the input in chunks of the given size when syncing to an external source"),
+		Arg::with_name(ARG_TRIM_SILENCE)
+			.long("trim-silence")
+			.takes_value(true)
+			.allow_hyphen_values(true)
+			.value_name("dBFS")
+			.help("skip leading and trailing samples at or below the given level in input files"),
+		Arg::with_name(ARG_TRIM_END)
+			.long("trim-end")
+			.takes_value(true)
+			.value_name("BYTES|TIME")
+			.help("leave out the given amount at the end of every input, either a number of bytes, optionally followed by K, M or G, or a time, given as in --segments, that ends in s or has a colon in it, such as 1.5s or 00:02. inputs of unknown length get played behind by that amount"),
+		Arg::with_name(ARG_SEGMENTS)
+			.long("segments")
+			.takes_value(true)
+			.value_name("START-END,...")
+			.conflicts_with(ARG_TRIM_SILENCE)
+			.help("only play the given segments of every input, back to back, such as 00:10-00:25,01:40-02:00. times are given as [[HH:]MM:]SS[.fraction], and segments must be in order and must not overlap"),
+		Arg::with_name(ARG_LOOP)
+			.long("loop")
+			.takes_value(true)
+			.min_values(0)
+			.max_values(1)
+			.value_name("TIMES")
+			.conflicts_with(ARG_SEGMENTS)
+			.help("once the loop embedded in an input, such as the one in the smpl chunk of a WAV file, is reached, keep playing it until interrupted, or play it the given number of times and move on. with --ab-loop, only sets how many times the segment is played, and with gen channel-id, how many times it goes around the channels"),
+		Arg::with_name(ARG_AB_LOOP)
+			.long("ab-loop")
+			.takes_value(true)
+			.value_name("START-END")
+			.conflicts_with_all(&[ARG_SEGMENTS, ARG_TRIM_SILENCE])
+			.help("play the given segment of every input over and over until interrupted, such as 00:10-00:15, with times given as in --segments. the input must be able to seek"),
+		Arg::with_name(ARG_PRELOAD)
+			.long("preload")
+			.takes_value(false)
+			.conflicts_with(ARG_EXTERNAL_SYNC)
+			.help("read the whole input into memory before starting playback"),
+		Arg::with_name(ARG_PRELOAD_LIMIT)
+			.long("preload-limit")
+			.takes_value(true)
+			.value_name("MiB")
+			.requires(ARG_PRELOAD)
+			.help("refuse to preload inputs larger than this, 512 MiB by default"),
+		Arg::with_name(ARG_STRICT)
+			.long("strict")
+			.takes_value(false)
+			.help("abort playback if any of the input files can't be opened, or if a WAV file turns out to be shorter than its header says"),
+		Arg::with_name(ARG_FORCE_CONCAT)
+			.long("force-concat")
+			.takes_value(false)
+			.help("play input files that aren't all in the same format, converting them into the format of the list"),
+		Arg::with_name(ARG_ANTI_ALIAS_ORDER)
+			.long("anti-alias-order")
+			.takes_value(true)
+			.value_name("ORDER")
+			.requires(ARG_FORCE_CONCAT)
+			.help("order of the low-pass filter files get run through before being converted down to a lower sample rate, an even number up to 16, or 0 to leave it out. 8 by default"),
+		Arg::with_name(ARG_PIPE_THROUGH)
+			.long("pipe-through")
+			.takes_value(false)
+			.help("forward the input to the standard output while playing it"),
+		Arg::with_name(ARG_HOLD_OPEN)
+			.long("hold-open")
+			.takes_value(false)
+			.help("keep waiting for more input once it runs out, playing silence in the meantime, instead of ending playback"),
+		Arg::with_name(ARG_IDLE_TIMEOUT)
+			.long("idle-timeout")
+			.takes_value(true)
+			.value_name("SECONDS")
+			.help("stop playback once no input has come in for the given number of seconds, exiting with status 3"),
+		Arg::with_name(ARG_META_FD)
+			.long("meta-fd")
+			.takes_value(true)
+			.value_name("N")
+			.help("read lines of track metadata, such as \"title=Some Song\", from the given inherited file descriptor, announcing the title whenever it changes"),
+		Arg::with_name(ARG_META_FILE)
+			.long("meta-file")
+			.takes_value(true)
+			.value_name("PATH")
+			.conflicts_with(ARG_META_FD)
+			.help("read lines of track metadata, such as \"title=Some Song\", from the given file or named pipe, announcing the title whenever it changes"),
+		Arg::with_name(ARG_DELAY)
+			.long("delay")
+			.takes_value(true)
+			.value_name("MS")
+			.help("delay the output by the given number of milliseconds, by playing that much silence first. inputs that keep coming in real time get held back as long"),
+		Arg::with_name(ARG_RAMP_ON_PAUSE)
+			.long("ramp-on-pause")
+			.takes_value(false)
+			.help("fade the output out when paused or interrupted, and back in when resumed, instead of cutting it off. playback gets paused with Ctrl-Z, and resumed once the job is continued"),
+		Arg::with_name(ARG_MAX_UNDERRUNS)
+			.long("max-underruns")
+			.takes_value(true)
+			.value_name("COUNT")
+			.help("abort playback, exiting with status 4, once the output has run out of samples more than the given number of times. this is an estimate, as running out is taken to be whenever filling a buffer in takes longer than playing it, which a device with more buffered up can ride out. unlimited by default"),
+		Arg::with_name(ARG_SPIN_US)
+			.long("spin-us")
+			.takes_value(true)
+			.value_name("MICROSECONDS")
+			.help("spin for the given number of microseconds before going to sleep while waiting for playback to end, noticing its end a little sooner at the cost of keeping a core busy that long every 10ms. at most 10000. defaults to 0, which never spins")
+	]
+}
+
+/** Reads the whole of the given source into memory, so that playback doesn't
+ * have to wait on it anymore. Fails rather than going past the limit set by the
+ * user, as the input may well be endless. */
+fn preload<R>(matches: &ArgMatches, args: &Arguments, mut source: R) -> Result<Vec<u8>, Error>
+	where R: Read {
+
+	let limit = matches.value_of(ARG_PRELOAD_LIMIT)
+		.map(|limit| u64::from_str_radix(limit, 10))
+		.transpose()
+		.map_err(Error::MalformedPreloadLimit)?
+		.unwrap_or(PRELOAD_LIMIT_MIB);
+	let limit = limit.saturating_mul(1024 * 1024);
+
+	let mut data = Vec::new();
+	let mut chunk = vec![0; 1024 * 1024];
+	let mut shown = 0;
+	loop {
+		let read = match source.read(&mut chunk) {
+			Ok(0) => break,
+			Ok(read) => read,
+			Err(what) if what.kind() == std::io::ErrorKind::Interrupted => continue,
+			Err(what) => return Err(Error::InputError(what))
+		};
+		if data.len() as u64 + read as u64 > limit {
+			return Err(Error::PreloadTooLarge { limit })
+		}
+		data.extend_from_slice(&chunk[..read]);
+
+		/* Let the user know we're still at it on large inputs. */
+		let mib = data.len() / (1024 * 1024);
+		if args.progress() && mib >= shown + 16 {
+			eprint!("\rpreloading: {} MiB", mib);
+			shown = mib;
+		}
+	}
+	if args.progress() && shown > 0 {
+		eprintln!("\rpreloaded {} MiB", data.len() / (1024 * 1024));
+	}
+
+	Ok(data)
+}
+
+/** Whether the given path refers to the standard input, be it through the
+ * usual `-` convention or through one of the device files for it, which can't
+ * be relied on to behave like regular files. */
+fn is_stdin(path: &Path) -> bool {
+	path == Path::new("-")
+		|| path == Path::new("/dev/stdin")
+		|| path == Path::new("/dev/fd/0")
+}
+
+/** Segments of the input to be played, if only some of it is to be, as
+ * offsets in bytes from its start, for frames of the given size played at the
+ * given rate. */
+fn segments(matches: &ArgMatches, rate: u32, frame: usize)
+	-> Result<Option<Vec<src::Segment>>, Error> {
+
+	let value = match matches.value_of(ARG_SEGMENTS) {
+		Some(value) => value,
+		None => return Ok(None)
+	};
+	let malformed = |what: String| Error::MalformedSegments {
+		value: value.to_owned(),
+		what
+	};
+
+	let mut segments: Vec<src::Segment> = Vec::new();
+	for segment in value.split(',') {
+		let segment = segment.trim();
+		let (start, end) = time_range(segment, rate, frame).map_err(malformed)?;
+		if let Some(last) = segments.last() {
+			if start < last.end {
+				return Err(malformed(format!("\"{}\" starts before \"{}\" ends", segment, last.name)))
+			}
+		}
+
+		segments.push(src::Segment {
+			start,
+			end,
+			name: segment.to_owned()
+		});
+	}
+
+	Ok(Some(segments))
+}
+
+/** Number of bytes to be left out at the end of every input, if any. Times
+ * are told apart from numbers of bytes by their colons or their `s` suffix,
+ * and land on the start of a frame. */
+fn trim_end(matches: &ArgMatches, rate: u32, frame: usize) -> Result<Option<u64>, Error> {
+	let value = match matches.value_of(ARG_TRIM_END) {
+		Some(value) => value.trim(),
+		None => return Ok(None)
+	};
+	let malformed = || Error::MalformedTrimEnd { value: value.to_owned() };
+
+	let bytes = if value.contains(':') || value.ends_with('s') {
+		let time = clock::parse_offset(value.trim_end_matches('s')).ok_or_else(malformed)?;
+		(time.as_secs_f64() * f64::from(rate)).round() as u64 * frame as u64
+	} else {
+		parse_size(value).ok_or_else(malformed)? as u64
+	};
+
+	Ok(Some(bytes).filter(|bytes| *bytes > 0))
+}
+
+/** Parses a range of times, given as `START-END`, into the offsets of the
+ * first byte in it and of the first byte past it, for the given sample rate
+ * and frame size. Both land on the start of a frame. */
+fn time_range(range: &str, rate: u32, frame: usize) -> Result<(u64, u64), String> {
+	let offset = |time: std::time::Duration|
+		(time.as_secs_f64() * f64::from(rate)).round() as u64 * frame as u64;
+
+	let (start, end) = match range.find('-') {
+		Some(index) => (&range[..index], &range[index + 1..]),
+		None => return Err(format!("\"{}\" is not a range of times", range))
+	};
+	let start = clock::parse_offset(start)
+		.ok_or_else(|| format!("\"{}\" is not a time", start))?;
+	let end = clock::parse_offset(end)
+		.ok_or_else(|| format!("\"{}\" is not a time", end))?;
+
+	if end <= start {
+		return Err(format!("\"{}\" ends before it starts", range))
+	}
+	Ok((offset(start), offset(end)))
+}
+
+/** The segment to be played over and over, if any, in the same terms as the
+ * segments given with [`ARG_SEGMENTS`]. */
+fn ab_loop(matches: &ArgMatches, rate: u32, frame: usize)
+	-> Result<Option<src::Segment>, Error> {
+
+	let value = match matches.value_of(ARG_AB_LOOP) {
+		Some(value) => value.trim(),
+		None => return Ok(None)
+	};
+	let (start, end) = time_range(value, rate, frame)
+		.map_err(|what| Error::MalformedAbLoop {
+			value: value.to_owned(),
+			what
+		})?;
+
+	Ok(Some(src::Segment {
+		start,
+		end,
+		name: value.to_owned()
+	}))
+}
+
+/** Number of times loops get played, or none if they're to be played until
+ * playback is interrupted. */
+fn loop_times(matches: &ArgMatches) -> Result<Option<u64>, Error> {
+	let value = match matches.value_of(ARG_LOOP) {
+		Some(value) => value,
+		None => return Ok(None)
+	};
+
+	u64::from_str_radix(value, 10).ok()
+		.filter(|times| *times > 0)
+		.map(Some)
+		.ok_or_else(|| Error::MalformedLoopCount { value: value.to_owned() })
+}
+
+/** What to repeat once the input is over. */
+fn repeat(matches: &ArgMatches) -> src::Repeat {
+	match matches.value_of(ARG_REPEAT) {
+		Some("all") => src::Repeat::All,
+		Some("one") => src::Repeat::One,
+		_ => src::Repeat::None
+	}
+}
+
+/** Largest copy of a stream we may keep in memory to repeat it, if any. */
+fn loop_buffer(matches: &ArgMatches) -> Result<Option<usize>, Error> {
+	let value = match matches.value_of(ARG_LOOP_BUFFER) {
+		Some(value) => value,
+		None => return Ok(None)
+	};
+
+	parse_size(value)
+		.map(Some)
+		.ok_or_else(|| Error::MalformedLoopBuffer { value: value.to_owned() })
+}
+
+/** Size of the buffer streams are read through, if they're to be buffered. */
+fn io_buffer(matches: &ArgMatches) -> Result<Option<usize>, Error> {
+	let value = match matches.value_of(ARG_IO_BUFFER) {
+		Some(value) => value,
+		None => return Ok(Some(DEFAULT_IO_BUFFER))
+	};
+
+	if value.eq_ignore_ascii_case("off") {
+		return Ok(None)
+	}
+	parse_size(value)
+		.filter(|size| *size > 0)
+		.map(Some)
+		.ok_or_else(|| Error::MalformedIoBuffer { value: value.to_owned() })
+}
+
+/** Parses a number of bytes, optionally followed by a K, M or G suffix. */
+fn parse_size(value: &str) -> Option<usize> {
+	let (number, unit) = match value.to_ascii_uppercase().chars().last() {
+		Some('K') => (&value[..value.len() - 1], 1024),
+		Some('M') => (&value[..value.len() - 1], 1024 * 1024),
+		Some('G') => (&value[..value.len() - 1], 1024 * 1024 * 1024),
+		_ => (value, 1)
+	};
+	let number = usize::from_str_radix(number, 10).ok()?;
+
+	number.checked_mul(unit)
+}
+
+/** Reads the given stream through a buffer of the given size, if any, so that
+ * it takes fewer, larger reads to get through it. Pipes in particular tend to
+ * hand data out in small pieces. Format detection peeks at whatever is put
+ * on top of the buffer, so it doesn't get in the way of it. */
+fn buffered(source: Box<dyn Read + Send>, size: Option<usize>) -> Box<dyn Read + Send> {
+	match size {
+		Some(size) => Box::new(std::io::BufReader::with_capacity(size, source)),
+		None => source
+	}
+}
+
+/** Remaps the input onto the channels of the output, if they differ. That's
+ * either because the device picks the number of channels, or because it can't
+ * play surround input as it is, which then gets downmixed to stereo, rather
+ * than having all but its front channels cut off. A mixing matrix given by the
+ * user takes the input onto the output instead, whether they differ or not. */
+fn remap(
+	matches: &ArgMatches,
+	args: &Arguments,
+	format: &OutputConfig,
+	source: Box<dyn Read + Send>) -> Box<dyn Read + Send> {
+
+	let inputs = args.input_channels().unwrap_or(format.channels());
+	if inputs == format.channels() && args.matrix().is_none() {
+		return source
+	}
+	let remap = src::Remap::new(
+		source,
+		inputs,
+		format.channels(),
+		args.input_sample_format().unwrap_or(format.sample_format()),
+		args.endianness().unwrap_or(play::PREFERRED_SAMPLE_ENDIAN))
+		.dither(args.dither());
+
+	if let Some(matrix) = args.matrix() {
+		return Box::new(remap.downmix(matrix.clone()))
+	}
+
+	let lfe = matches.is_present(ARG_KEEP_LFE);
+	match mix::Matrix::surround(inputs, format.channels(), lfe) {
+		Some(matrix) => {
+			if args.banner() {
+				eprintln!("downmixing {}.1 surround to stereo, {} the LFE",
+					inputs - 1,
+					if lfe { "keeping" } else { "dropping" });
+			}
+			Box::new(remap.downmix(matrix))
+		},
+		None => Box::new(remap)
+	}
+}
+
+/** Makes sure the mixing matrix, if any, fits both the input and the
+ * negotiated output. */
+fn check_matrix(args: &Arguments, format: &OutputConfig) -> Result<(), Error> {
+	match args.matrix() {
+		Some(matrix) => matrix.validate(
+				args.input_channels().unwrap_or(format.channels()),
+				format.channels())
+			.map_err(Error::MismatchedMatrix),
+		None => Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn time_ranges_land_on_whole_frames() {
+		/* A third of a second at 10Hz is three and a third frames, which
+		 * rounds to three. */
+		assert_eq!(time_range("0.1-0.3333", 10, 4), Ok((4, 12)));
+		assert_eq!(time_range("00:10-00:15", 48000, 4), Ok((10 * 48000 * 4, 15 * 48000 * 4)));
+		assert_eq!(time_range("1:00-1:00.5", 8000, 2), Ok((60 * 8000 * 2, 60 * 8000 * 2 + 8000)));
+	}
+
+	#[test]
+	fn malformed_time_ranges_are_refused() {
+		assert!(time_range("00:10", 48000, 4).is_err());
+		assert!(time_range("00:15-00:10", 48000, 4).is_err());
+		assert!(time_range("00:10-00:10", 48000, 4).is_err());
+		assert!(time_range("a-b", 48000, 4).is_err());
+	}
+
+	#[test]
+	fn segments_must_be_in_order_without_overlapping() {
+		let parsed = |value: &str| {
+			let matches = clap::App::new("alplay")
+				.args(&device_args())
+				.args(&input_args())
+				.args(&playback_args())
+				.get_matches_from(["alplay", "--segments", value]);
+			segments(&matches, 10, 2)
+		};
+
+		let given = parsed("1-2, 2-3.5").unwrap().unwrap();
+		assert_eq!(given.iter().map(|segment| (segment.start, segment.end)).collect::<Vec<_>>(),
+			[(20, 40), (40, 70)]);
+		assert_eq!(given[1].name, "2-3.5");
+
+		for value in ["1-3,2-4", "2-3,0-1", "1-2,", "1-2,x"] {
+			match parsed(value) {
+				Err(Error::MalformedSegments { .. }) => {},
+				other => panic!("expected {} to be refused, got {:?}", value, other)
+			}
+		}
+	}
+
+	#[test]
+	fn surround_played_in_stereo_gets_downmixed() {
+		let matches = clap::App::new("alplay")
+			.args(&device_args())
+			.args(&input_args())
+			.args(&playback_args())
+			.get_matches_from(["alplay", "--output", "null", "-c", "6", "-r", "48000", "-f", "f32le"]);
+		let args = Arguments::new(&matches).unwrap();
+		let format = OutputConfig::new(2, cpal::SampleRate(48000), cpal::SampleFormat::F32);
+
+		/* Front left on its own, then the LFE on its own, which gets left
+		 * out by default. */
+		let frames = [1.0f32, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+		let input = frames.iter().flat_map(|sample| sample.to_le_bytes()).collect::<Vec<_>>();
+
+		let mut output = Vec::new();
+		remap(&matches, &args, &format, Box::new(std::io::Cursor::new(input)))
+			.read_to_end(&mut output)
+			.unwrap();
+		let output = output.chunks_exact(4)
+			.map(|sample| f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]))
+			.collect::<Vec<_>>();
+
+		let left = 1.0 / (1.0 + 2.0 * std::f32::consts::FRAC_1_SQRT_2);
+		assert_eq!(output.len(), 4);
+		assert!((output[0] - left).abs() < 1e-6);
+		assert_eq!(&output[1..], &[0.0, 0.0, 0.0]);
+	}
+
+	#[test]
+	fn the_matrix_takes_the_input_onto_the_output() {
+		let args = |matrix: &str| {
+			let matches = clap::App::new("alplay")
+				.args(&device_args())
+				.args(&input_args())
+				.args(&playback_args())
+				.get_matches_from(["alplay", "--output", "null", "-c", "6", "-r", "48000", "-f", "f32le", "--matrix", matrix]);
+			let args = Arguments::new(&matches).unwrap();
+			(matches, args)
+		};
+		let format = OutputConfig::new(2, cpal::SampleRate(48000), cpal::SampleFormat::F32);
+
+		/* Rows go by the channels of the output, columns by those of the
+		 * input. */
+		assert!(check_matrix(&args("2;0.5*4+0.5*5").1, &format).is_ok());
+		assert!(check_matrix(&args("2;6").1, &format).is_err());
+		assert!(check_matrix(&args("2;2;2").1, &format).is_err());
+
+		/* The matrix is used in place of the downmix surround would get. */
+		let (matches, args) = args("2;0.5*4+0.5*5");
+		let frames = [0.0f32, 0.0, 0.5, 1.0, 0.25, 0.75];
+		let input = frames.iter().flat_map(|sample| sample.to_le_bytes()).collect::<Vec<_>>();
+
+		let mut output = Vec::new();
+		remap(&matches, &args, &format, Box::new(std::io::Cursor::new(input)))
+			.read_to_end(&mut output)
+			.unwrap();
+		let output = output.chunks_exact(4)
+			.map(|sample| f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]))
+			.collect::<Vec<_>>();
+		assert_eq!(output, [0.5, 0.5]);
+	}
+
+	#[cfg(feature = "json")]
+	#[test]
+	fn audit_records_leave_out_a_device_never_opened() {
+		let matches = clap::App::new("alplay")
+			.args(&device_args())
+			.args(&input_args())
+			.args(&playback_args())
+			.get_matches_from(["alplay", "--output", "null", "-c", "2", "-r", "8000", "-f", "s16le", "input.raw"]);
+		let args = Arguments::new(&matches).unwrap();
+		let format = play::negotiate(&args).unwrap();
+
+		let record: serde_json::Value = serde_json::from_str(&audit_record(&matches, &args, &format).unwrap()).unwrap();
+		assert!(record["device"].is_null());
+		assert_eq!(record["config"]["channels"], 2);
+		assert_eq!(record["config"]["sample_rate"], 8000);
+		assert_eq!(record["source"], "input.raw");
+	}
+
+	#[test]
+	fn sizes_take_binary_suffixes() {
+		assert_eq!(parse_size("0"), Some(0));
+		assert_eq!(parse_size("512"), Some(512));
+		assert_eq!(parse_size("4K"), Some(4 * 1024));
+		assert_eq!(parse_size("4k"), Some(4 * 1024));
+		assert_eq!(parse_size("3M"), Some(3 * 1024 * 1024));
+		assert_eq!(parse_size("2g"), Some(2 * 1024 * 1024 * 1024));
+
+		for value in ["", "K", "-1", "1.5K", "4KB", "x"] {
+			assert_eq!(parse_size(value), None, "{:?} was taken for a size", value);
+		}
+		assert_eq!(parse_size(&format!("{}", usize::MAX)), Some(usize::MAX));
+		assert_eq!(parse_size(&format!("{}K", usize::MAX / 1024 + 1)), None);
+		assert_eq!(parse_size(&format!("{}0", usize::MAX)), None);
+	}
+
+	#[test]
+	fn io_buffers_must_hold_something() {
+		let io_buffer = |value: Option<&str>| {
+			let mut args = vec!["alplay".to_owned()];
+			args.extend(value.map(|value| format!("--io-buffer={}", value)));
+			let matches = clap::App::new("alplay")
+				.args(&device_args())
+				.args(&input_args())
+				.args(&playback_args())
+				.get_matches_from(args);
+			io_buffer(&matches)
+		};
+
+		assert_eq!(io_buffer(None).unwrap(), Some(DEFAULT_IO_BUFFER));
+		assert_eq!(io_buffer(Some("64K")).unwrap(), Some(64 * 1024));
+		assert_eq!(io_buffer(Some("off")).unwrap(), None);
+
+		let overflowing = format!("{}G", usize::MAX);
+		for value in ["0", "0K", "-4K", "lots", overflowing.as_str()] {
+			match io_buffer(Some(value)) {
+				Err(Error::MalformedIoBuffer { .. }) => {},
+				other => panic!("expected {} to be refused, got {:?}", value, other)
+			}
+		}
+	}
+
+	/** Hands the data out a few bytes at a time, the way pipes tend to. */
+	struct Trickle(std::io::Cursor<Vec<u8>>);
+	impl Read for Trickle {
+		fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+			let len = usize::min(buf.len(), 3);
+			self.0.read(&mut buf[..len])
+		}
+	}
+
+	#[test]
+	fn buffering_leaves_the_bytes_read_alone() {
+		let format = arg::InputFormat {
+			channels: 2,
+			sample_rate: 44100,
+			sample_format: cpal::SampleFormat::I16,
+			endian: arg::Endianness::Little
+		};
+		let samples = (0..10000u32).map(|i| (i * 13) as u8).collect::<Vec<_>>();
+		let mut writer = wav::WavWriter::new(std::io::Cursor::new(Vec::new()), format).unwrap();
+		std::io::Write::write_all(&mut writer, &samples).unwrap();
+		let file = writer.finish().unwrap().into_inner();
+
+		let read = |data: &[u8], size: Option<usize>, detect: bool| {
+			let stream = buffered(Box::new(Trickle(std::io::Cursor::new(data.to_vec()))), size);
+			let mut read = Vec::new();
+			if detect {
+				let (mut reader, detected) = registry::open(registry::Input::Stream(stream)).unwrap();
+				assert_eq!(detected.unwrap().container, "WAV");
+				reader.read_to_end(&mut read).unwrap();
+			} else {
+				let mut stream = stream;
+				stream.read_to_end(&mut read).unwrap();
+			}
+			read
+		};
+
+		/* Buffers smaller than what detection peeks at, than a read, and
+		 * larger than the whole input, along with no buffer at all. */
+		for size in [Some(5), Some(1000), Some(DEFAULT_IO_BUFFER)] {
+			assert_eq!(read(&samples, size, false), samples);
+			assert_eq!(read(&file, size, false), file);
+			assert_eq!(read(&file, size, true), read(&file, None, true));
+		}
+		if cfg!(not(feature = "symphonia")) {
+			assert_eq!(read(&file, Some(1000), true), samples);
 		}
 	}
 }
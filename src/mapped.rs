@@ -0,0 +1,151 @@
+use crate::src::Source;
+use memmap2::Mmap;
+use std::io::{Read, Seek, SeekFrom};
+
+/** A file that has been mapped into memory, read straight out of the mapping
+ * through a cursor of its own. */
+pub struct MappedFile {
+	map: Mmap,
+	/** Offset into the file the next read starts at. */
+	position: u64,
+}
+impl MappedFile {
+	/** Maps the given file into memory.
+	 *
+	 * The file should not be changed by anyone else while it's mapped, as
+	 * there's no telling what we'd read from it then. This is the same promise
+	 * every player that maps its input makes, and a file that gets truncated
+	 * under us will at worst crash playback. */
+	pub fn new(file: &std::fs::File) -> std::io::Result<Self> {
+		let map = unsafe { Mmap::map(file)? };
+		Ok(Self {
+			map,
+			position: 0
+		})
+	}
+}
+impl Read for MappedFile {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		let start = usize::min(self.position as usize, self.map.len());
+		let len = usize::min(buf.len(), self.map.len() - start);
+		buf[..len].copy_from_slice(&self.map[start..start + len]);
+		self.position += len as u64;
+
+		Ok(len)
+	}
+}
+impl Seek for MappedFile {
+	fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+		let (base, offset) = match pos {
+			SeekFrom::Start(offset) => (offset, 0),
+			SeekFrom::End(offset) => (self.map.len() as u64, offset),
+			SeekFrom::Current(offset) => (self.position, offset)
+		};
+		let target = if offset >= 0 {
+			base.checked_add(offset as u64)
+		} else {
+			base.checked_sub(offset.unsigned_abs())
+		};
+
+		/* Seeking past the end is fine, reads from there just come up empty. */
+		self.position = target.ok_or(std::io::Error::new(
+			std::io::ErrorKind::InvalidInput,
+			"seek to a negative position"))?;
+		Ok(self.position)
+	}
+}
+impl Source for MappedFile {
+	fn rewind(&mut self) -> std::io::Result<bool> {
+		self.position = 0;
+		Ok(true)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::registry::{self, Input};
+
+	fn fixture(name: &str) -> std::path::PathBuf {
+		let format = crate::arg::InputFormat {
+			channels: 2,
+			sample_rate: 44100,
+			sample_format: cpal::SampleFormat::I16,
+			endian: crate::arg::Endianness::Little
+		};
+		let samples = (0..4096u32).map(|i| (i * 7) as u8).collect::<Vec<_>>();
+
+		let path = std::env::temp_dir().join(format!("alplay-{}-{}.wav", name, std::process::id()));
+		let mut writer = crate::wav::WavWriter::new(std::fs::File::create(&path).unwrap(), format).unwrap();
+		std::io::Write::write_all(&mut writer, &samples).unwrap();
+		writer.finish().unwrap();
+
+		path
+	}
+
+	fn decoded(input: Input) -> (Vec<u8>, String) {
+		let (mut reader, detected) = registry::open(input).unwrap();
+		let mut read = Vec::new();
+		reader.read_to_end(&mut read).unwrap();
+
+		(read, detected.unwrap().to_string())
+	}
+
+	#[test]
+	fn mapped_files_decode_the_same_as_read_ones() {
+		let path = fixture("mapped-decode");
+		let file = std::fs::File::open(&path).unwrap();
+		let map = MappedFile::new(&std::fs::File::open(&path).unwrap()).unwrap();
+
+		let (read, detected) = decoded(Input::File(file));
+		let (mapped, mapped_detected) = decoded(Input::Mapped(map));
+		std::fs::remove_file(&path).unwrap();
+
+		assert!(!read.is_empty());
+		assert_eq!(mapped_detected, detected);
+		assert_eq!(mapped, read);
+	}
+
+	#[test]
+	fn mapped_files_read_the_same_as_read_ones() {
+		let path = fixture("mapped-raw");
+		let mut read = Vec::new();
+		std::fs::File::open(&path).unwrap().read_to_end(&mut read).unwrap();
+		let mut map = MappedFile::new(&std::fs::File::open(&path).unwrap()).unwrap();
+		std::fs::remove_file(&path).unwrap();
+
+		/* Read it in odd sized pieces, so that they never line up with the
+		 * size of the file. */
+		let mut mapped = Vec::new();
+		let mut buffer = [0; 37];
+		loop {
+			let len = map.read(&mut buffer).unwrap();
+			if len == 0 { break }
+			mapped.extend_from_slice(&buffer[..len]);
+		}
+		assert_eq!(mapped, read);
+
+		assert!(Source::rewind(&mut map).unwrap());
+		let mut again = Vec::new();
+		map.read_to_end(&mut again).unwrap();
+		assert_eq!(again, read);
+	}
+
+	#[test]
+	fn seeking_is_done_on_the_cursor() {
+		let path = fixture("mapped-seek");
+		let mut map = MappedFile::new(&std::fs::File::open(&path).unwrap()).unwrap();
+		let len = std::fs::metadata(&path).unwrap().len();
+		std::fs::remove_file(&path).unwrap();
+
+		assert_eq!(map.seek(SeekFrom::Start(10)).unwrap(), 10);
+		assert_eq!(map.seek(SeekFrom::Current(-4)).unwrap(), 6);
+		assert_eq!(map.seek(SeekFrom::End(-2)).unwrap(), len - 2);
+		assert_eq!(map.read(&mut [0; 8]).unwrap(), 2);
+		assert!(map.seek(SeekFrom::Current(-(len as i64) - 1)).is_err());
+
+		/* Past the end, there's just nothing left to read. */
+		assert_eq!(map.seek(SeekFrom::End(16)).unwrap(), len + 16);
+		assert_eq!(map.read(&mut [0; 8]).unwrap(), 0);
+	}
+}
@@ -0,0 +1,155 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+
+/** Longest line taken from a metadata channel. Anything past it is dropped,
+ * so that a writer that never ends its lines can't run us out of memory. */
+const MAX_LINE: usize = 4096;
+
+/** Reads track metadata from the given channel on a thread of its own, for as
+ * long as the channel stays open. Every line is a `key=value` pair, of which
+ * only `title` is understood, and announced, if so asked, whenever it changes.
+ * Anything else is ignored, as is the channel closing. */
+pub fn watch<R>(source: R, announce: bool)
+	where R: Read + Send + 'static {
+
+	std::thread::spawn(move || read(source, announce.then(std::io::stderr)));
+}
+
+/** Same as [`watch`], but the channel is opened from the given path on the
+ * thread reading from it, as named pipes only open once someone shows up to
+ * write to them. */
+pub fn watch_file(path: PathBuf, announce: bool) {
+	std::thread::spawn(move || match std::fs::File::open(&path) {
+		Ok(file) => read(file, announce.then(std::io::stderr)),
+		Err(what) =>
+			eprintln!("warning: could not open the metadata at {}: {}",
+				path.display(),
+				what)
+	});
+}
+
+/** Reads lines of metadata out of the given channel until it closes, writing
+ * changes of title out to the given output, if any. */
+fn read<R, W>(source: R, mut announce: Option<W>)
+	where R: Read,
+		W: Write {
+
+	let mut source = BufReader::new(source);
+	let mut line = Vec::new();
+	let mut title = None;
+	loop {
+		line.clear();
+
+		/* Lines get put back together from however many reads it takes, and
+		 * one left unfinished when the channel closes still counts. */
+		let read = source.by_ref()
+			.take(MAX_LINE as u64)
+			.read_until(b'\n', &mut line);
+		match read {
+			Ok(0) => break,
+			Ok(_) => {},
+			Err(what) if what.kind() == std::io::ErrorKind::Interrupted => continue,
+			Err(what) => {
+				eprintln!("warning: stopped reading metadata: {}", what);
+				break
+			}
+		}
+
+		/* Overly long lines get thrown away, along with whatever is left of
+		 * them. */
+		if line.len() == MAX_LINE && line.last() != Some(&b'\n') {
+			loop {
+				let buf = match source.fill_buf() {
+					Ok(buf) => buf,
+					Err(_) => return
+				};
+				if buf.is_empty() { return }
+
+				match buf.iter().position(|byte| *byte == b'\n') {
+					Some(index) => {
+						source.consume(index + 1);
+						break
+					},
+					None => {
+						let len = buf.len();
+						source.consume(len);
+					}
+				}
+			}
+			continue
+		}
+
+		let line = String::from_utf8_lossy(&line);
+		let line = line.trim_end_matches(['\n', '\r']);
+		let (key, value) = match line.find('=') {
+			Some(index) => (line[..index].trim(), line[index + 1..].trim()),
+			None => continue
+		};
+
+		if key == "title" && title.as_deref() != Some(value) {
+			if let Some(output) = &mut announce {
+				let _ = writeln!(output, "now playing: {}", value);
+			}
+			title = Some(value.to_owned());
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/** Feeds the given pieces through a pipe, one write at a time, and gives
+	 * back what got announced from the other end of it. */
+	#[cfg(unix)]
+	fn announced(pieces: Vec<Vec<u8>>) -> String {
+		use std::os::unix::io::FromRawFd;
+
+		let mut fds = [0; 2];
+		assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+		let channel = unsafe { std::fs::File::from_raw_fd(fds[0]) };
+		let mut writer = unsafe { std::fs::File::from_raw_fd(fds[1]) };
+
+		let feeder = std::thread::spawn(move || {
+			for piece in pieces {
+				writer.write_all(&piece).unwrap();
+				writer.flush().unwrap();
+				std::thread::sleep(std::time::Duration::from_millis(5));
+			}
+		});
+
+		let mut output = Vec::new();
+		read(channel, Some(&mut output));
+		feeder.join().unwrap();
+
+		String::from_utf8(output).unwrap()
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn titles_get_announced_as_they_change() {
+		let pieces = [
+			"title=First Song\n",
+			"artist=Someone\nnot a pair\n",
+			"ti", "tle = Second", " Song\r\n",
+			"title=Second Song\n",
+			"title=Last Song"
+		];
+		let output = announced(pieces.iter().map(|piece| piece.as_bytes().to_vec()).collect());
+
+		assert_eq!(output, "now playing: First Song\n\
+			now playing: Second Song\n\
+			now playing: Last Song\n");
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn overly_long_lines_get_thrown_away() {
+		let mut long = b"title=".to_vec();
+		long.resize(MAX_LINE * 3, b'a');
+		long.extend_from_slice(b"\ntitle=Short\n");
+
+		assert_eq!(announced(vec![b"title=Before\n".to_vec(), long]),
+			"now playing: Before\nnow playing: Short\n");
+	}
+}
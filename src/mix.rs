@@ -0,0 +1,293 @@
+use crate::arg::Endianness;
+use crate::convert::{self, Quantizer};
+use std::f32::consts::FRAC_1_SQRT_2;
+
+/** A mixing matrix. Every row describes an output channel, and holds the gain
+ * every input channel gets mixed into it with. */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matrix {
+	rows: Vec<Vec<f32>>,
+}
+impl Matrix {
+	/** Parses a matrix in the form of `<row>;<row>;...`, where every row is a
+	 * sum of input channels, as in `0.5*0+0.5*1`. Channels that are not
+	 * weighted by anything get mixed in at full gain, and channels that are
+	 * not mentioned are left out. */
+	pub fn parse(spec: &str) -> Result<Self, String> {
+		let mut rows = Vec::new();
+		for (i, row) in spec.split(';').enumerate() {
+			let row = row.trim();
+			if row.is_empty() {
+				return Err(format!("output channel {} is empty", i))
+			}
+
+			let mut gains = Vec::new();
+			for term in row.split('+') {
+				let term = term.trim();
+				let (gain, channel) = match term.rfind('*') {
+					Some(star) => {
+						let gain = term[..star].trim();
+						let gain = gain.parse::<f32>()
+							.map_err(|what| format!("invalid gain \"{}\": {}", gain, what))?;
+						if !gain.is_finite() {
+							return Err(format!("invalid gain \"{}\"", gain))
+						}
+
+						(gain, term[star + 1..].trim())
+					},
+					None => (1.0, term)
+				};
+				let channel = channel.parse::<usize>()
+					.map_err(|what| format!("invalid input channel \"{}\": {}", channel, what))?;
+
+				if gains.len() <= channel {
+					gains.resize(channel + 1, 0.0);
+				}
+				gains[channel] += gain;
+			}
+
+			rows.push(gains);
+		}
+
+		Ok(Self { rows })
+	}
+
+	/** The downmix of the standard surround layout with the given number of
+	 * channels into the given number of channels, if there is one. Only 5.1 and
+	 * 7.1 get downmixed, into stereo, with their channels in SMPTE order, as
+	 * in WAV files: front left and right, center, LFE, then the surrounds,
+	 * left before right, with the back ones ahead of the side ones in 7.1.
+	 *
+	 * Following ITU-R BS.775, the center and the surrounds get folded into
+	 * their side at -3dB, while the LFE is left out unless it's to be kept, in
+	 * which case it's folded into both sides at -3dB as well. Every output is
+	 * then scaled down by the sum of its gains, so that nothing that was within
+	 * full scale before the downmix can clip after it. */
+	pub fn surround(inputs: u16, outputs: u16, lfe: bool) -> Option<Self> {
+		if outputs != 2 { return None }
+
+		let lfe = if lfe { FRAC_1_SQRT_2 } else { 0.0 };
+		let side = FRAC_1_SQRT_2;
+		let mut rows = match inputs {
+			6 => vec![
+				vec![1.0, 0.0, FRAC_1_SQRT_2, lfe, side, 0.0],
+				vec![0.0, 1.0, FRAC_1_SQRT_2, lfe, 0.0, side],
+			],
+			8 => vec![
+				vec![1.0, 0.0, FRAC_1_SQRT_2, lfe, side, 0.0, side, 0.0],
+				vec![0.0, 1.0, FRAC_1_SQRT_2, lfe, 0.0, side, 0.0, side],
+			],
+			_ => return None
+		};
+		for row in &mut rows {
+			let sum = row.iter().sum::<f32>();
+			row.iter_mut().for_each(|gain| *gain /= sum);
+		}
+
+		Some(Self { rows })
+	}
+
+	/** The downmix of any number of channels into a single one, with all of
+	 * them at the same gain, so that nothing within full scale can clip. */
+	pub fn average(inputs: u16) -> Self {
+		let gain = 1.0 / f32::from(inputs.max(1));
+		Self { rows: vec![vec![gain; usize::from(inputs)]] }
+	}
+
+	/** Checks whether this matrix takes in the given number of input channels
+	 * and puts out the given number of output channels. Every row is an output
+	 * channel, and every column an input channel. */
+	pub fn validate(&self, inputs: u16, outputs: u16) -> Result<(), String> {
+		let outputs = usize::from(outputs);
+		if self.rows.len() != outputs {
+			return Err(format!("it has {} output channels, but {} are being played",
+				self.rows.len(),
+				outputs))
+		}
+
+		let inputs = usize::from(inputs);
+		let columns = self.rows.iter().map(|row| row.len()).max().unwrap_or(0);
+		if columns > inputs {
+			return Err(format!("it refers to input channel {}, but the input only has {}",
+				columns - 1,
+				inputs))
+		}
+
+		Ok(())
+	}
+
+	/** Mixes a single frame of samples in the given format and byte order,
+	 * appending the result to the given buffer. The frame that comes out may
+	 * have a different number of channels than the one that goes in. */
+	pub fn mix(
+		&self,
+		quantizer: &mut Quantizer,
+		format: cpal::SampleFormat,
+		endian: Endianness,
+		frame: &[u8],
+		output: &mut Vec<u8>) {
+
+		let size = format.sample_size();
+		let mut sample = vec![0; size];
+		for row in &self.rows {
+			let value = row.iter()
+				.zip(frame.chunks_exact(size))
+				.map(|(gain, input)| gain * convert::decode(format, endian, input))
+				.sum();
+			convert::encode(quantizer, format, endian, value, &mut sample);
+			output.extend(&sample);
+		}
+	}
+
+	/** Mixes a single frame of samples that have already been decoded,
+	 * appending the result to the given buffer. */
+	pub fn mix_decoded(&self, frame: &[f32], output: &mut Vec<f32>) {
+		for row in &self.rows {
+			output.push(row.iter()
+				.zip(frame)
+				.map(|(gain, input)| gain * input)
+				.sum());
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::convert::Dither;
+
+	fn floats(samples: &[f32]) -> Vec<u8> {
+		samples.iter().flat_map(|sample| sample.to_ne_bytes()).collect()
+	}
+
+	#[test]
+	fn malformed_matrices_are_rejected() {
+		for spec in ["", "0;;1", "0.5*", "x*0", "0.5*x", "inf*0", "NaN*1", "-1"] {
+			assert!(Matrix::parse(spec).is_err(), "{:?} was accepted", spec);
+		}
+	}
+
+	#[test]
+	fn terms_add_up() {
+		let matrix = Matrix::parse(" 0.5*0 + 0.25 * 1 + 0.25*0 ; 1 ").unwrap();
+		assert_eq!(matrix.rows, vec![vec![0.75, 0.25], vec![0.0, 1.0]]);
+	}
+
+	#[test]
+	fn matrices_must_fit_the_channels() {
+		let matrix = Matrix::parse("0;1;2").unwrap();
+		assert!(matrix.validate(3, 3).is_ok());
+		assert!(matrix.validate(3, 2).is_err());
+		assert!(Matrix::parse("0;2").unwrap().validate(2, 2).is_err());
+
+		/* Rows go by the output, and columns by the input. */
+		let matrix = Matrix::parse("0.5*0+0.5*1+0.5*2").unwrap();
+		assert!(matrix.validate(3, 1).is_ok());
+		assert!(matrix.validate(2, 1).is_err());
+		assert!(matrix.validate(3, 3).is_err());
+		assert!(Matrix::parse("0;0").unwrap().validate(1, 2).is_ok());
+	}
+
+	#[test]
+	fn swapping_and_averaging_mix() {
+		let matrix = Matrix::parse("1;0.5*0+0.5*1").unwrap();
+		let mut quantizer = Quantizer::new(Dither::None);
+		let mut output = Vec::new();
+		for frame in floats(&[0.25, -0.75, 1.0, 0.0]).chunks_exact(8) {
+			matrix.mix(&mut quantizer, cpal::SampleFormat::F32, Endianness::Native, frame, &mut output);
+		}
+
+		assert_eq!(output, floats(&[-0.75, -0.25, 0.0, 0.5]));
+	}
+
+	#[test]
+	fn surround_downmixes_keep_within_full_scale() {
+		let matrix = Matrix::surround(6, 2, false).unwrap();
+		let mut quantizer = Quantizer::new(Dither::None);
+		let mut output = Vec::new();
+		matrix.mix(
+			&mut quantizer,
+			cpal::SampleFormat::F32,
+			Endianness::Native,
+			&floats(&[1.0; 6]),
+			&mut output);
+
+		/* Everything at full scale comes out at full scale, LFE left out. */
+		for sample in output.chunks_exact(4) {
+			let sample = f32::from_ne_bytes([sample[0], sample[1], sample[2], sample[3]]);
+			assert!((sample - 1.0).abs() < 1e-6);
+		}
+		assert!(Matrix::surround(6, 1, false).is_none());
+		assert!(Matrix::surround(4, 2, false).is_none());
+	}
+
+	/** Mixes every channel of the given surround layout on its own, giving
+	 * back the left and right output for each one. */
+	fn one_by_one(matrix: &Matrix, inputs: usize) -> Vec<(f32, f32)> {
+		(0..inputs)
+			.map(|channel| {
+				let mut frame = vec![0.0; inputs];
+				frame[channel] = 1.0;
+				let mut output = Vec::new();
+				matrix.mix_decoded(&frame, &mut output);
+				(output[0], output[1])
+			})
+			.collect()
+	}
+
+	fn close(a: f32, b: f32) -> bool {
+		(a - b).abs() < 1e-6
+	}
+
+	#[test]
+	fn kept_lfe_gets_folded_in_at_3db_down_and_renormalized() {
+		/* Front, center, LFE and surround make for a sum of one and three
+		 * times -3dB on either side. */
+		let front = 1.0 / (1.0 + 3.0 * FRAC_1_SQRT_2);
+		let folded = FRAC_1_SQRT_2 * front;
+
+		let gains = one_by_one(&Matrix::surround(6, 2, true).unwrap(), 6);
+		assert!(close(gains[0].0, front) && close(gains[0].1, 0.0));
+		assert!(close(gains[1].0, 0.0) && close(gains[1].1, front));
+		for &(left, right) in &[gains[2], gains[3]] {
+			assert!(close(left, folded) && close(right, folded));
+		}
+		assert!(close(gains[4].0, folded) && close(gains[4].1, 0.0));
+		assert!(close(gains[5].0, 0.0) && close(gains[5].1, folded));
+
+		/* Everything at full scale still comes out at full scale. */
+		let mut output = Vec::new();
+		Matrix::surround(6, 2, true).unwrap().mix_decoded(&[1.0; 6], &mut output);
+		assert!(output.iter().all(|sample| close(*sample, 1.0)));
+
+		/* Left out, the LFE doesn't take any of the gain of the rest. */
+		let gains = one_by_one(&Matrix::surround(6, 2, false).unwrap(), 6);
+		assert_eq!(gains[3], (0.0, 0.0));
+		assert!(close(gains[0].0, 1.0 / (1.0 + 2.0 * FRAC_1_SQRT_2)));
+	}
+
+	#[test]
+	fn seven_one_folds_sides_and_backs_into_their_own_side() {
+		/* L R C LFE SL SR BL BR, with both the sides and the backs at -3dB. */
+		let front = 1.0 / (1.0 + 3.0 * FRAC_1_SQRT_2);
+		let folded = FRAC_1_SQRT_2 * front;
+
+		let gains = one_by_one(&Matrix::surround(8, 2, false).unwrap(), 8);
+		assert!(close(gains[0].0, front) && close(gains[0].1, 0.0));
+		assert!(close(gains[1].0, 0.0) && close(gains[1].1, front));
+		assert!(close(gains[2].0, folded) && close(gains[2].1, folded));
+		assert_eq!(gains[3], (0.0, 0.0));
+		for &left in &[4, 6] {
+			assert!(close(gains[left].0, folded) && close(gains[left].1, 0.0));
+		}
+		for &right in &[5, 7] {
+			assert!(close(gains[right].0, 0.0) && close(gains[right].1, folded));
+		}
+
+		/* The LFE, when kept, goes into both sides like the center does. */
+		let gains = one_by_one(&Matrix::surround(8, 2, true).unwrap(), 8);
+		let front = 1.0 / (1.0 + 4.0 * FRAC_1_SQRT_2);
+		assert!(close(gains[0].0, front));
+		assert!(close(gains[3].0, FRAC_1_SQRT_2 * front) && close(gains[3].1, FRAC_1_SQRT_2 * front));
+	}
+}
@@ -0,0 +1,189 @@
+use crate::arg::{Endianness, InputFormat};
+use crate::error::Error;
+use minimp3::{Decoder, Frame};
+use std::io::{ErrorKind, Read};
+
+/** Checks whether the given bytes start with the header of an MPEG audio
+ * frame. Besides the sync word, the fields that can't take some values are
+ * checked too, so that raw samples are less likely to be mistaken for one. */
+pub fn is_frame_header(bytes: &[u8]) -> bool {
+	if bytes.len() < 4 { return false }
+
+	let sync = bytes[0] == 0xff && bytes[1] & 0xe0 == 0xe0;
+	let version = (bytes[1] >> 3) & 0x03;
+	let layer = (bytes[1] >> 1) & 0x03;
+	let bitrate = bytes[2] >> 4;
+	let sample_rate = (bytes[2] >> 2) & 0x03;
+
+	sync && version != 0x01 && layer != 0x00 && bitrate != 0x0f && sample_rate != 0x03
+}
+
+/** A reader that decodes the frames of an MP3 stream as they're needed, handing
+ * out their samples interleaved and in native byte order. Frames are decoded
+ * one at a time, so variable bit rate streams work just as well. */
+pub struct Mp3Source<R> {
+	decoder: Decoder<R>,
+	/** Number of channels the stream started out with. */
+	channels: usize,
+	/** Sample rate the stream started out with. */
+	sample_rate: i32,
+	/** Decoded samples that have yet to be handed out. */
+	output: Vec<u8>,
+	/** How much of the decoded samples has been handed out already. */
+	offset: usize,
+}
+impl<R> Mp3Source<R>
+	where R: Read {
+
+	/** Fills the output buffer with the samples of the given frame. */
+	fn fill(&mut self, frame: Frame) {
+		self.output.clear();
+		self.offset = 0;
+		for sample in frame.data {
+			self.output.extend(&sample.to_ne_bytes());
+		}
+	}
+
+	/** Decodes the next frame of the stream into the output buffer. Returns
+	 * whether there was any frame left to decode. */
+	fn decode(&mut self) -> std::io::Result<bool> {
+		let frame = match self.decoder.next_frame() {
+			Ok(frame) => frame,
+			Err(minimp3::Error::Eof) | Err(minimp3::Error::InsufficientData) =>
+				return Ok(false),
+			Err(minimp3::Error::SkippedData) => {
+				/* Garbage between frames, which the decoder skipped over. */
+				self.output.clear();
+				self.offset = 0;
+				return Ok(true)
+			},
+			Err(minimp3::Error::Io(what)) => return Err(what),
+		};
+
+		/* We've no way to switch the format of the output mid-stream. */
+		if frame.channels != self.channels || frame.sample_rate != self.sample_rate {
+			return Err(std::io::Error::new(
+				ErrorKind::InvalidData,
+				format!("the stream changes from {} channels at {}Hz to {} channels at {}Hz",
+					self.channels,
+					self.sample_rate,
+					frame.channels,
+					frame.sample_rate)))
+		}
+
+		self.fill(frame);
+		Ok(true)
+	}
+}
+impl<R> Read for Mp3Source<R>
+	where R: Read {
+
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		if buf.is_empty() { return Ok(0) }
+		while self.offset == self.output.len() {
+			if !self.decode()? { return Ok(0) }
+		}
+
+		let len = usize::min(buf.len(), self.output.len() - self.offset);
+		buf[..len].copy_from_slice(&self.output[self.offset..self.offset + len]);
+		self.offset += len;
+
+		Ok(len)
+	}
+}
+
+/** Opens the MP3 stream in the given source, starting at its first frame.
+ * The first frame gets decoded right away, as it's the only place the format
+ * of the stream can be learned from. Returns a reader over the decoded samples,
+ * along with their format. */
+pub fn open<R>(source: R) -> Result<(Box<dyn Read + Send>, InputFormat), Error>
+	where R: Read + Send + 'static {
+
+	let mut decoder = Decoder::new(source);
+	let frame = loop {
+		match decoder.next_frame() {
+			Ok(frame) => break frame,
+			Err(minimp3::Error::SkippedData) => continue,
+			Err(minimp3::Error::Io(what)) => return Err(Error::InputError(what)),
+			Err(what) => return Err(Error::MalformedMp3(what.to_string()))
+		}
+	};
+
+	if frame.channels == 0 || frame.channels > usize::from(u16::MAX) || frame.sample_rate <= 0 {
+		return Err(Error::MalformedMp3(
+			format!("the first frame has {} channels at {}Hz", frame.channels, frame.sample_rate)))
+	}
+
+	let input = InputFormat {
+		channels: frame.channels as u16,
+		sample_rate: frame.sample_rate as u32,
+		sample_format: cpal::SampleFormat::I16,
+		endian: Endianness::Native
+	};
+	let mut source = Mp3Source {
+		decoder,
+		channels: frame.channels,
+		sample_rate: frame.sample_rate,
+		output: Vec::new(),
+		offset: 0
+	};
+	source.fill(frame);
+
+	Ok((Box::new(source), input))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/** Thirty frames of mono silence at 32kHz, all of them at 32kbps. */
+	const CBR: &[u8] = include_bytes!("../fixtures/cbr.mp3");
+	/** Thirty frames of mono silence at 32kHz, every third one at 64kbps
+	 * rather than 32kbps. */
+	const VBR: &[u8] = include_bytes!("../fixtures/vbr.mp3");
+
+	/** Number of samples in every channel of an MPEG-1 Layer III frame. */
+	const FRAME: usize = 1152;
+
+	fn decoded(stream: &'static [u8]) -> (InputFormat, Vec<u8>) {
+		let (mut reader, format) = open(stream).unwrap();
+		let mut data = Vec::new();
+		reader.read_to_end(&mut data).unwrap();
+
+		(format, data)
+	}
+
+	#[test]
+	fn every_frame_gets_decoded() {
+		for stream in [CBR, VBR] {
+			let (format, data) = decoded(stream);
+			assert_eq!(format.channels, 1);
+			assert_eq!(format.sample_rate, 32000);
+			assert_eq!(format.sample_format, cpal::SampleFormat::I16);
+
+			assert_eq!(data.len(), 30 * FRAME * 2);
+			assert!(data.iter().all(|byte| *byte == 0));
+		}
+	}
+
+	#[test]
+	fn frame_headers_are_told_apart_from_raw_samples() {
+		assert!(is_frame_header(CBR));
+		assert!(is_frame_header(&VBR[288..]));
+
+		/* The sync word alone isn't enough, the fields after it have to be
+		 * valid too. */
+		assert!(!is_frame_header(&[0xff, 0xeb, 0x18, 0xc0]));
+		assert!(!is_frame_header(&[0xff, 0xfb, 0xf8, 0xc0]));
+		assert!(!is_frame_header(&[0xff, 0xfb, 0x1c, 0xc0]));
+		assert!(!is_frame_header(&[0xff, 0xfb, 0x18]));
+	}
+
+	#[test]
+	fn streams_get_recognized_on_stdin() {
+		let (_, detected) = crate::detect::detect(std::io::Cursor::new(VBR.to_vec())).unwrap();
+		let detected = detected.unwrap();
+		assert_eq!(detected.container, "MP3");
+		assert_eq!(detected.format.sample_rate, 32000);
+	}
+}
@@ -0,0 +1,767 @@
+use crate::arg::Endianness;
+use crate::error::Error;
+use std::io::{ErrorKind, Read};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, SyncSender, TryRecvError, TrySendError};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/** How long to wait before trying to reconnect for the first time. */
+const RECONNECT_DELAY: Duration = Duration::from_millis(250);
+/** Longest we ever wait between attempts to reconnect. */
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(8);
+
+/** Something connections can be accepted from. */
+pub trait Listener {
+	type Connection: Read + Send;
+
+	/** Waits for the next connection, returning it along with a description of
+	 * whoever is on the other end. Connections are always blocking. */
+	fn accept(&self) -> std::io::Result<(Self::Connection, String)>;
+
+	/** Sets whether waiting for connections blocks. */
+	fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()>;
+}
+impl Listener for TcpListener {
+	type Connection = TcpStream;
+
+	fn accept(&self) -> std::io::Result<(TcpStream, String)> {
+		let (connection, peer) = TcpListener::accept(self)?;
+		connection.set_nonblocking(false)?;
+
+		Ok((connection, peer.to_string()))
+	}
+
+	fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+		TcpListener::set_nonblocking(self, nonblocking)
+	}
+}
+
+/** Waits for a connection on the given listener, for up to the given amount
+ * of time, if any. */
+fn accept<L>(listener: &L, timeout: Option<Duration>)
+	-> std::io::Result<Option<(L::Connection, String)>>
+	where L: Listener {
+
+	let timeout = match timeout {
+		Some(timeout) => timeout,
+		None => return listener.accept().map(Some)
+	};
+
+	listener.set_nonblocking(true)?;
+	let deadline = Instant::now() + timeout;
+	let accepted = loop {
+		match listener.accept() {
+			Ok(accepted) => break accepted,
+			Err(what) if what.kind() == ErrorKind::WouldBlock => {
+				if Instant::now() >= deadline { return Ok(None) }
+				std::thread::sleep(Duration::from_millis(10));
+			},
+			Err(what) if what.kind() == ErrorKind::Interrupted => continue,
+			Err(what) => return Err(what)
+		}
+	};
+	listener.set_nonblocking(false)?;
+
+	Ok(Some(accepted))
+}
+
+/** Whether the given error just means the peer went away without saying so. */
+fn is_disconnect(what: &std::io::Error) -> bool {
+	matches!(what.kind(),
+		ErrorKind::ConnectionReset
+			| ErrorKind::ConnectionAborted
+			| ErrorKind::BrokenPipe)
+}
+
+/** A source reading from the connections accepted from a listener. */
+pub struct ListenerSource<L>
+	where L: Listener {
+
+	listener: L,
+	/** Connection currently being read from. */
+	connection: L::Connection,
+	/** Whether to wait for another connection once the current one is over,
+	 * rather than ending the input. */
+	accept_loop: bool,
+	/** Whether to announce every connection. */
+	banner: bool,
+}
+impl<L> ListenerSource<L>
+	where L: Listener {
+
+	/** Waits for the first connection on the given listener, for up to the
+	 * given amount of time, if any. Returns `None` if nobody connects in
+	 * time. */
+	pub fn new(
+		listener: L,
+		timeout: Option<Duration>,
+		accept_loop: bool,
+		banner: bool) -> std::io::Result<Option<Self>> {
+
+		let (connection, peer) = match accept(&listener, timeout)? {
+			Some(accepted) => accepted,
+			None => return Ok(None)
+		};
+		if banner {
+			eprintln!("connection from {}", peer);
+		}
+
+		Ok(Some(Self {
+			listener,
+			connection,
+			accept_loop,
+			banner
+		}))
+	}
+}
+impl<L> Read for ListenerSource<L>
+	where L: Listener {
+
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		loop {
+			/* A peer that goes away abruptly is no different from one that
+			 * says goodbye, as far as playback is concerned. */
+			let read = match self.connection.read(buf) {
+				Ok(read) => read,
+				Err(what) if is_disconnect(&what) => {
+					eprintln!("warning: the connection was cut off: {}", what);
+					0
+				},
+				Err(what) => return Err(what)
+			};
+			if read > 0 || buf.is_empty() || !self.accept_loop { return Ok(read) }
+
+			/* Whoever connects next picks up where the last peer left off, so
+			 * it had better send data in the same format. */
+			let (connection, peer) = self.listener.accept()?;
+			if self.banner {
+				eprintln!("connection from {}", peer);
+			}
+			self.connection = connection;
+		}
+	}
+}
+
+/** Sets the size of the buffer the system keeps incoming data in for the
+ * connections accepted from the given listener. */
+#[cfg(unix)]
+fn set_recv_buffer(listener: &TcpListener, size: usize) -> std::io::Result<()> {
+	use std::convert::TryFrom;
+	use std::os::unix::io::AsRawFd;
+
+	let size = libc::c_int::try_from(size).unwrap_or(libc::c_int::MAX);
+	let result = unsafe {
+		libc::setsockopt(
+			listener.as_raw_fd(),
+			libc::SOL_SOCKET,
+			libc::SO_RCVBUF,
+			&size as *const libc::c_int as *const libc::c_void,
+			std::mem::size_of::<libc::c_int>() as libc::socklen_t)
+	};
+
+	if result == 0 {
+		Ok(())
+	} else {
+		Err(std::io::Error::last_os_error())
+	}
+}
+
+/** Sets the size of the buffer the system keeps incoming data in for the
+ * connections accepted from the given listener. */
+#[cfg(not(unix))]
+fn set_recv_buffer(_: &TcpListener, _: usize) -> std::io::Result<()> {
+	Err(std::io::Error::new(
+		ErrorKind::Other,
+		"not supported on this platform"))
+}
+
+/** Listens for TCP connections on the given address, and waits for someone to
+ * connect, for up to the given amount of time, if any. */
+pub fn listen(
+	address: &str,
+	timeout: Option<Duration>,
+	accept_loop: bool,
+	recv_buffer: Option<usize>,
+	banner: bool) -> Result<ListenerSource<TcpListener>, Error> {
+
+	let error = |what| Error::Listen {
+		address: address.to_owned(),
+		what
+	};
+	let listener = TcpListener::bind(address).map_err(error)?;
+
+	if let Some(size) = recv_buffer {
+		if let Err(what) = set_recv_buffer(&listener, size) {
+			eprintln!("warning: could not set the receive buffer size: {}", what);
+		}
+	}
+
+	if banner {
+		match listener.local_addr() {
+			Ok(local) => eprintln!("listening on {}", local),
+			Err(_) => eprintln!("listening on {}", address)
+		}
+	}
+
+	ListenerSource::new(listener, timeout, accept_loop, banner)
+		.map_err(error)?
+		.ok_or(Error::AcceptTimeout {
+			address: address.to_owned()
+		})
+}
+
+/** Connects to the given address, giving up on every one it resolves to after
+ * the given amount of time, if any. */
+fn dial(address: &str, timeout: Option<Duration>) -> std::io::Result<TcpStream> {
+	let mut last = None;
+	for resolved in address.to_socket_addrs()? {
+		let connection = match timeout {
+			Some(timeout) => TcpStream::connect_timeout(&resolved, timeout),
+			None => TcpStream::connect(resolved)
+		};
+		match connection {
+			Ok(connection) => return Ok(connection),
+			Err(what) => last = Some(what)
+		}
+	}
+
+	Err(last.unwrap_or(std::io::Error::new(
+		ErrorKind::NotFound,
+		"the address did not resolve to anything")))
+}
+
+/** How long to wait before the attempt to reconnect that comes after one that
+ * was waited for for the given amount of time. */
+fn backoff(delay: Duration) -> Duration {
+	Duration::min(delay * 2, MAX_RECONNECT_DELAY)
+}
+
+/** Keeps trying to connect to the given address in the background, waiting
+ * longer and longer between attempts, until it works. */
+fn redial(address: String, timeout: Option<Duration>) -> Receiver<TcpStream> {
+	let (sender, receiver) = std::sync::mpsc::channel();
+	std::thread::spawn(move || {
+		let mut delay = RECONNECT_DELAY;
+		loop {
+			std::thread::sleep(delay);
+			match dial(&address, timeout) {
+				Ok(connection) => {
+					let _ = sender.send(connection);
+					return
+				},
+				Err(what) => eprintln!("warning: could not reconnect to {}: {}", address, what)
+			}
+			delay = backoff(delay);
+		}
+	});
+
+	receiver
+}
+
+/** A source reading from a connection we made to a server. If reconnecting is
+ * enabled, losing the connection doesn't end the input: reads fail with
+ * [`ErrorKind::WouldBlock`] for as long as we're trying to get it back, so the
+ * gap can be filled in with silence, rather than holding up playback. */
+pub struct ConnectSource {
+	address: String,
+	/** How long to wait for every attempt to connect, if not forever. */
+	timeout: Option<Duration>,
+	/** Whether to try to get the connection back once it's lost. */
+	reconnect: bool,
+	/** Connection being read from, if we have one. */
+	connection: Option<TcpStream>,
+	/** Where a new connection comes from, while we're trying to get one. */
+	pending: Option<Receiver<TcpStream>>,
+}
+impl Read for ConnectSource {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		if let Some(pending) = self.pending.as_ref() {
+			match pending.try_recv() {
+				Ok(connection) => {
+					eprintln!("reconnected to {}", self.address);
+					self.connection = Some(connection);
+					self.pending = None;
+				},
+				Err(TryRecvError::Empty) => return Err(ErrorKind::WouldBlock.into()),
+				Err(TryRecvError::Disconnected) => return Ok(0)
+			}
+		}
+
+		let connection = match self.connection.as_mut() {
+			Some(connection) => connection,
+			None => return Ok(0)
+		};
+		let read = match connection.read(buf) {
+			Ok(read) => read,
+			Err(what) if is_disconnect(&what) => {
+				eprintln!("warning: the connection to {} was cut off: {}", self.address, what);
+				0
+			},
+			Err(what) => return Err(what)
+		};
+		if read > 0 || buf.is_empty() || !self.reconnect { return Ok(read) }
+
+		eprintln!("warning: lost the connection to {}, reconnecting", self.address);
+		self.connection = None;
+		self.pending = Some(redial(self.address.clone(), self.timeout));
+
+		Err(ErrorKind::WouldBlock.into())
+	}
+}
+
+/** Connects to the server at the given address, giving up after the given
+ * amount of time, if any. */
+pub fn connect(address: &str, timeout: Option<Duration>, reconnect: bool, banner: bool)
+	-> Result<ConnectSource, Error> {
+
+	let connection = dial(address, timeout)
+		.map_err(|what| Error::Network {
+			address: address.to_owned(),
+			what
+		})?;
+	if banner {
+		match connection.peer_addr() {
+			Ok(peer) => eprintln!("connected to {}", peer),
+			Err(_) => eprintln!("connected to {}", address)
+		}
+	}
+
+	Ok(ConnectSource {
+		address: address.to_owned(),
+		timeout,
+		reconnect,
+		connection: Some(connection),
+		pending: None
+	})
+}
+
+/** Size of the header that comes before the payload of every datagram, when
+ * they're sequenced. */
+const UDP_HEADER: usize = 16;
+/** Largest payload a datagram can carry. */
+const UDP_MAX_DATAGRAM: usize = 65536;
+/** Number of datagrams that get held onto while playback catches up. Any more
+ * than this, and the newest ones get dropped. */
+const UDP_QUEUE: usize = 1024;
+/** How far behind a sequence number can be and still be taken as a datagram
+ * that arrived late, rather than as the sender having started over. */
+const UDP_REORDER_WINDOW: u64 = 64;
+/** Longest gap that gets filled in with silence, in seconds. */
+const UDP_MAX_GAP: u32 = 1;
+
+/** Counters of what came in through a [`UdpSource`]. */
+#[derive(Default)]
+struct UdpStats {
+	/** Number of datagrams received. */
+	packets: u64,
+	/** Number of payload bytes received. */
+	bytes: u64,
+	/** Number of datagrams dropped because playback couldn't keep up. */
+	overflows: u64,
+	/** Number of gaps in the sequence numbers. */
+	gaps: u64,
+	/** Number of datagrams missing from those gaps. */
+	lost: u64,
+	/** Number of datagrams that arrived too late to be played. */
+	late: u64,
+}
+
+/** A source reading the payloads of the datagrams sent to a UDP socket, back
+ * to back. The socket gets drained by a thread of its own, so that datagrams
+ * don't pile up in the system while playback is busy.
+ *
+ * When sequenced, every datagram starts with a header whose first eight bytes
+ * hold its sequence number, as a big endian integer, the rest of the header
+ * being reserved. Missing datagrams are then made up for with as much silence
+ * as they would have taken up, going by the size of the one that came after
+ * them, and datagrams arriving out of order are dropped. Otherwise, datagrams
+ * are played as they come, lost ones and all. */
+pub struct UdpSource {
+	address: String,
+	datagrams: Receiver<Vec<u8>>,
+	/** Whether the datagrams carry a sequence number. */
+	sequenced: bool,
+	/** Sequence number expected of the next datagram, if any came in yet. */
+	expected: Option<u64>,
+	/** A single silent frame. */
+	silence: Vec<u8>,
+	/** Number of frames played every second. */
+	rate: u32,
+	/** Silence still owed for the datagrams found missing. */
+	missing: usize,
+	/** Payload of the datagram being handed out. */
+	payload: Vec<u8>,
+	/** How much of the payload has been handed out already. */
+	offset: usize,
+	stats: Arc<Mutex<UdpStats>>,
+	stop: Arc<AtomicBool>,
+	thread: Option<JoinHandle<()>>,
+	banner: bool,
+}
+impl UdpSource {
+	/** Takes in the next datagram, working out how much silence has to be
+	 * played before it. Returns whether there was any datagram left. */
+	fn next(&mut self) -> std::io::Result<bool> {
+		loop {
+			let mut datagram = match self.datagrams.recv() {
+				Ok(datagram) => datagram,
+				Err(_) => return Ok(false)
+			};
+			if !self.sequenced {
+				self.payload = datagram;
+				self.offset = 0;
+				return Ok(true)
+			}
+
+			if datagram.len() < UDP_HEADER {
+				eprintln!("warning: dropping a datagram too short to have a header");
+				continue
+			}
+			let mut sequence = [0; 8];
+			sequence.copy_from_slice(&datagram[..8]);
+			let sequence = u64::from_be_bytes(sequence);
+			datagram.drain(..UDP_HEADER);
+
+			let mut stats = self.stats.lock().unwrap();
+			match self.expected {
+				Some(expected) if sequence < expected
+					&& expected - sequence <= UDP_REORDER_WINDOW => {
+
+					/* Its spot has been played already. */
+					stats.late += 1;
+					continue
+				},
+				Some(expected) if sequence > expected => {
+					let lost = sequence - expected;
+					stats.gaps += 1;
+					stats.lost += lost;
+
+					/* Only whole frames of silence keep the data aligned. */
+					let frame = self.silence.len() as u64;
+					let bytes = lost.saturating_mul(datagram.len() as u64) / frame * frame;
+					let limit = u64::from(self.rate * UDP_MAX_GAP) * frame;
+					self.missing = u64::min(bytes, limit) as usize;
+				},
+				_ => {}
+			}
+			self.expected = Some(sequence.wrapping_add(1));
+
+			self.payload = datagram;
+			self.offset = 0;
+			return Ok(true)
+		}
+	}
+}
+impl Read for UdpSource {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		if buf.is_empty() { return Ok(0) }
+		while self.missing == 0 && self.offset == self.payload.len() {
+			if !self.next()? { return Ok(0) }
+		}
+
+		if self.missing > 0 {
+			/* Missing data always comes in whole frames, right in between two
+			 * datagrams, so the silence starts at the start of a frame. */
+			let len = usize::min(buf.len(), self.missing);
+			let played = self.silence.len() - self.missing % self.silence.len();
+			for (index, byte) in buf[..len].iter_mut().enumerate() {
+				*byte = self.silence[(played + index) % self.silence.len()];
+			}
+			self.missing -= len;
+
+			return Ok(len)
+		}
+
+		let len = usize::min(buf.len(), self.payload.len() - self.offset);
+		buf[..len].copy_from_slice(&self.payload[self.offset..self.offset + len]);
+		self.offset += len;
+
+		Ok(len)
+	}
+}
+impl Drop for UdpSource {
+	fn drop(&mut self) {
+		self.stop.store(true, Ordering::Relaxed);
+		if let Some(thread) = self.thread.take() {
+			let _ = thread.join();
+		}
+
+		if self.banner {
+			let stats = self.stats.lock().unwrap();
+			eprintln!("received {} datagrams ({} bytes) on {}", stats.packets, stats.bytes, self.address);
+			if self.sequenced {
+				eprintln!("{} gaps, {} datagrams lost, {} arrived late", stats.gaps, stats.lost, stats.late);
+			}
+			if stats.overflows > 0 {
+				eprintln!("{} datagrams dropped while playback was behind", stats.overflows);
+			}
+		}
+	}
+}
+
+/** Receives datagrams on the given socket until told to stop, queueing them up
+ * for the source to read from. */
+fn drain(
+	socket: UdpSocket,
+	queue: SyncSender<Vec<u8>>,
+	stats: Arc<Mutex<UdpStats>>,
+	stop: Arc<AtomicBool>) {
+
+	let mut buffer = vec![0; UDP_MAX_DATAGRAM];
+	while !stop.load(Ordering::Relaxed) {
+		let received = match socket.recv(&mut buffer) {
+			Ok(received) => received,
+			Err(what) if what.kind() == ErrorKind::WouldBlock
+				|| what.kind() == ErrorKind::TimedOut
+				|| what.kind() == ErrorKind::Interrupted => continue,
+			Err(what) => {
+				eprintln!("warning: could not receive from the socket, ending the input: {}", what);
+				return
+			}
+		};
+
+		let mut stats = stats.lock().unwrap();
+		stats.packets += 1;
+		stats.bytes += received as u64;
+		match queue.try_send(buffer[..received].to_vec()) {
+			Ok(_) => {},
+			Err(TrySendError::Full(_)) => stats.overflows += 1,
+			Err(TrySendError::Disconnected(_)) => return
+		}
+	}
+}
+
+/** Binds a UDP socket to the given address, and reads the input from the
+ * datagrams sent to it. Missing datagrams are made up for with silence in the
+ * given format, if they're sequenced. */
+pub fn udp(
+	address: &str,
+	sequenced: bool,
+	channels: u16,
+	rate: u32,
+	format: cpal::SampleFormat,
+	endian: Endianness,
+	banner: bool) -> Result<UdpSource, Error> {
+
+	let error = |what| Error::Listen {
+		address: address.to_owned(),
+		what
+	};
+	let socket = UdpSocket::bind(address).map_err(error)?;
+
+	/* Waking up every now and then is how the thread notices it's done. */
+	socket.set_read_timeout(Some(Duration::from_millis(100))).map_err(error)?;
+
+	if banner {
+		match socket.local_addr() {
+			Ok(local) => eprintln!("receiving datagrams on {}", local),
+			Err(_) => eprintln!("receiving datagrams on {}", address)
+		}
+	}
+
+	let mut silence = vec![0; format.sample_size() * usize::from(channels)];
+	crate::convert::silence(format, endian, &mut silence);
+
+	let (sender, receiver) = std::sync::mpsc::sync_channel(UDP_QUEUE);
+	let stats = Arc::new(Mutex::new(UdpStats::default()));
+	let stop = Arc::new(AtomicBool::new(false));
+	let thread = {
+		let stats = stats.clone();
+		let stop = stop.clone();
+		std::thread::spawn(move || drain(socket, sender, stats, stop))
+	};
+
+	Ok(UdpSource {
+		address: address.to_owned(),
+		datagrams: receiver,
+		sequenced,
+		expected: None,
+		silence,
+		rate,
+		missing: 0,
+		payload: Vec::new(),
+		offset: 0,
+		stats,
+		stop,
+		thread: Some(thread),
+		banner
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Write;
+	use std::net::SocketAddr;
+
+	/** Connects to the given address once for every one of the given
+	 * payloads, one after the other, sending it and hanging up. */
+	fn send_all(address: SocketAddr, payloads: &'static [&'static [u8]]) -> JoinHandle<()> {
+		std::thread::spawn(move || {
+			for payload in payloads {
+				let mut connection = TcpStream::connect(address).unwrap();
+				connection.write_all(payload).unwrap();
+			}
+		})
+	}
+
+	/** Reads exactly the given number of bytes from the given source. */
+	fn read_exact<R: Read>(source: &mut R, len: usize) -> Vec<u8> {
+		let mut data = vec![0; len];
+		source.read_exact(&mut data).unwrap();
+		data
+	}
+
+	#[test]
+	fn connections_get_read_one_after_the_other() {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let sender = send_all(listener.local_addr().unwrap(), &[b"abc", b"def"]);
+
+		/* Whoever connects next picks up where the last one left off. */
+		let mut source = ListenerSource::new(listener, Some(Duration::from_secs(5)), true, false)
+			.unwrap()
+			.unwrap();
+		assert_eq!(read_exact(&mut source, 6), b"abcdef");
+		sender.join().unwrap();
+	}
+
+	#[test]
+	fn without_the_accept_loop_the_first_connection_is_all() {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let sender = send_all(listener.local_addr().unwrap(), &[b"abc"]);
+
+		let mut source = ListenerSource::new(listener, None, false, false)
+			.unwrap()
+			.unwrap();
+		let mut data = Vec::new();
+		source.read_to_end(&mut data).unwrap();
+		assert_eq!(data, b"abc");
+		sender.join().unwrap();
+	}
+
+	#[test]
+	fn lost_connections_get_dialed_again() {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let address = listener.local_addr().unwrap().to_string();
+		let server = std::thread::spawn(move || {
+			for payload in [&b"abc"[..], &b"def"[..]] {
+				let (mut connection, _) = listener.accept().unwrap();
+				connection.write_all(payload).unwrap();
+			}
+		});
+		let mut source = connect(&address, Some(Duration::from_secs(5)), true, false).unwrap();
+
+		/* The first connection reads just like any other stream. */
+		assert_eq!(read_exact(&mut source, 3), b"abc");
+
+		/* Losing it doesn't end the input, it just has nothing to give for as
+		 * long as it takes to get it back, which is at least the first delay. */
+		let lost = Instant::now();
+		let mut buf = [0; 3];
+		let read = loop {
+			match source.read(&mut buf) {
+				Err(what) if what.kind() == ErrorKind::WouldBlock => {
+					assert!(lost.elapsed() < Duration::from_secs(5));
+					std::thread::sleep(Duration::from_millis(10));
+				},
+				other => break other.unwrap()
+			}
+		};
+		assert!(lost.elapsed() >= RECONNECT_DELAY);
+		assert!(read > 0);
+		assert_eq!(&buf[..read], &b"def"[..read]);
+		server.join().unwrap();
+	}
+
+	#[test]
+	fn reconnecting_backs_off_up_to_a_limit() {
+		let mut delay = RECONNECT_DELAY;
+		let mut delays = Vec::new();
+		for _ in 0..8 {
+			delays.push(delay.as_millis());
+			delay = backoff(delay);
+		}
+		assert_eq!(delays, [250, 500, 1000, 2000, 4000, 8000, 8000, 8000]);
+	}
+
+	#[test]
+	fn nobody_connecting_times_out() {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		assert!(ListenerSource::new(listener, Some(Duration::from_millis(50)), true, false)
+			.unwrap()
+			.is_none());
+
+		match listen("127.0.0.1:0", Some(Duration::from_millis(50)), false, None, false) {
+			Err(Error::AcceptTimeout { address }) => assert_eq!(address, "127.0.0.1:0"),
+			other => panic!("expected the wait to time out, got {:?}", other.err())
+		}
+	}
+
+	/** A sequenced source of mono 16 bit samples at the given rate, along
+	 * with where its datagrams come from. */
+	fn sequenced(rate: u32) -> (SyncSender<Vec<u8>>, UdpSource) {
+		let (sender, receiver) = std::sync::mpsc::sync_channel(UDP_QUEUE);
+		let source = UdpSource {
+			address: "test".to_owned(),
+			datagrams: receiver,
+			sequenced: true,
+			expected: None,
+			silence: vec![0; 2],
+			rate,
+			missing: 0,
+			payload: Vec::new(),
+			offset: 0,
+			stats: Default::default(),
+			stop: Default::default(),
+			thread: None,
+			banner: false
+		};
+
+		(sender, source)
+	}
+
+	/** A datagram with the given sequence number, carrying the given payload. */
+	fn datagram(sequence: u64, payload: &[u8]) -> Vec<u8> {
+		let mut datagram = sequence.to_be_bytes().to_vec();
+		datagram.resize(UDP_HEADER, 0);
+		datagram.extend(payload);
+		datagram
+	}
+
+	#[test]
+	fn missing_datagrams_get_made_up_for_with_silence() {
+		let (sender, mut source) = sequenced(48000);
+		sender.send(datagram(0, &[1; 4])).unwrap();
+		sender.send(datagram(3, &[2; 4])).unwrap();
+		/* Too late, its spot has been played. */
+		sender.send(datagram(1, &[3; 4])).unwrap();
+		sender.send(datagram(4, &[4; 4])).unwrap();
+		std::mem::drop(sender);
+
+		/* Two datagrams missing, as large as the one after them. */
+		let mut data = Vec::new();
+		source.read_to_end(&mut data).unwrap();
+		assert_eq!(data, [&[1; 4][..], &[0; 8], &[2; 4], &[4; 4]].concat());
+
+		let stats = source.stats.lock().unwrap();
+		assert_eq!((stats.gaps, stats.lost, stats.late), (1, 2, 1));
+	}
+
+	#[test]
+	fn gaps_get_filled_in_for_a_second_at_most() {
+		/* Ten frames a second, and a gap large enough for a whole lot more. */
+		let (sender, mut source) = sequenced(10);
+		sender.send(datagram(0, &[1; 2])).unwrap();
+		sender.send(datagram(1000, &[2; 2])).unwrap();
+		std::mem::drop(sender);
+
+		let mut data = Vec::new();
+		source.read_to_end(&mut data).unwrap();
+		let silence = 10 * UDP_MAX_GAP as usize * 2;
+		assert_eq!(data, [&[1; 2][..], &vec![0; silence], &[2; 2]].concat());
+		assert_eq!(source.stats.lock().unwrap().lost, 999);
+	}
+}
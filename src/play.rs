@@ -1,10 +1,17 @@
-use crate::arg::{Endianness, Arguments};
+use crate::arg::{Endianness, Arguments, InputFormat, OutputConfig};
 use crate::error::Error;
+use crate::convert::{self, Dither, Quantizer};
+use crate::src::{Activity, Source};
 use cpal::StreamConfig;
 use cpal::traits::{DeviceTrait, StreamTrait};
-use std::io::Read;
-use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, SyncSender, TrySendError};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
 
 /** When no sample rate is specified, the playback will try to select the value
  * that gets the closest to this number and that is still supported. */
@@ -22,88 +29,1821 @@ pub const PREFERRED_SAMPLE_FORMAT: cpal::SampleFormat = cpal::SampleFormat::I16;
  * value that gets the closest to this number and that is still supported. */
 pub const PREFERRED_SAMPLE_ENDIAN: Endianness = Endianness::Little;
 
-/** Plays audio from a given source. */
-pub fn play<R>(args: &Arguments, mut source: R)
-	where R: Read + Send + 'static {
+/** All of the preferred values above, as a description of samples in the
+ * format playback prefers. */
+pub const PREFERRED_FORMAT: InputFormat = InputFormat {
+	channels: PREFERRED_CHANNELS,
+	sample_rate: PREFERRED_SAMPLE_RATE,
+	sample_format: PREFERRED_SAMPLE_FORMAT,
+	endian: PREFERRED_SAMPLE_ENDIAN
+};
 
-	eprint!("playing <file> ");
-	if let Some((index, name)) = args.device_pick() {
-		eprint!("to device {} ({}) ", index, name);
-	} else {
-		eprint!("to the default device ");
+/** Length of the fade out applied when playback gets paused or stopped early,
+ * and of the fade in applied when it gets resumed, in milliseconds. */
+pub const RAMP_MS: u32 = 10;
+
+/** Longest the playback thread sleeps for at a time while waiting for playback
+ * to end, as it still has to check on interruptions and on idle inputs. */
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/** How often the devices get checked on for the output device to be back,
+ * after it went away. */
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/** Most samples the clip report logs, past which clipping is everywhere and
+ * logging any more of it would only fill the disk up. */
+const CLIP_REPORT_LIMIT: u64 = 100_000;
+
+/** Number of buffers the capture of the output can fall behind by before
+ * buffers start getting dropped from it. */
+const TEE_BUFFERS: usize = 64;
+
+/** Length of the buffers the capture of the output gets made up front with,
+ * in milliseconds. Callbacks handing out more than this at a time have the
+ * buffers grow into it, once. */
+const TEE_BUFFER_MS: usize = 100;
+
+/** Number of lines a log written from the callback can fall behind by before
+ * lines start getting dropped from it. */
+const LOG_LINES: usize = 4096;
+
+/** Number of frames in every buffer the null output asks for. */
+pub const NULL_BUFFER_FRAMES: usize = 512;
+
+/** Number of frames rendered at a time. */
+const RENDER_FRAMES: usize = 4096;
+
+/** Number of times we've been interrupted so far. */
+static INTERRUPTS: AtomicUsize = AtomicUsize::new(0);
+
+/** Whether playback is under way, in which case being interrupted stops it and
+ * lets everything get torn down properly, rather than exiting right away. */
+static PLAYING: AtomicBool = AtomicBool::new(false);
+
+/** Whether playback has been asked to pause, until it gets asked to resume. */
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+/** Whether the process stops itself once playback is paused, which is what
+ * the terminal asked of it in the first place. */
+static STOP_ONCE_PAUSED: AtomicBool = AtomicBool::new(false);
+
+/** Handles an interruption. Outside of playback, or when interrupted a second
+ * time, this exits on the spot. */
+pub fn interrupt() {
+	if !PLAYING.load(Ordering::Relaxed) || INTERRUPTS.fetch_add(1, Ordering::Relaxed) > 0 {
+		std::process::exit(130);
 	}
-	if let Some((index, name)) = args.host_pick() {
-		eprintln!("within host {} ({})", index, name);
-	} else {
-		eprintln!("within the default host");
+}
+
+/** Whether we've been interrupted. */
+pub fn interrupted() -> bool {
+	INTERRUPTS.load(Ordering::Relaxed) > 0
+}
+
+/** Runs the given function with interruptions getting handled the way they
+ * are during playback, for anything else that has to wind down on its own,
+ * such as a recording that still needs its file finished. */
+pub fn interruptible<T, F>(f: F) -> T
+	where F: FnOnce() -> T {
+
+	PLAYING.store(true, Ordering::Relaxed);
+	let result = f();
+	PLAYING.store(false, Ordering::Relaxed);
+
+	result
+}
+
+/** Pauses playback when the terminal asks us to stop, such as on Ctrl-Z, and
+ * resumes it once we're continued, rather than leaving the device to play
+ * whatever it was given last over and over in the meantime. Outside of
+ * playback, we stop right away, just like we would have otherwise. */
+#[cfg(unix)]
+pub fn handle_job_control() {
+	extern "C" fn stop(_: libc::c_int) {
+		if PLAYING.load(Ordering::Relaxed) {
+			STOP_ONCE_PAUSED.store(true, Ordering::Relaxed);
+			PAUSED.store(true, Ordering::Relaxed);
+		} else {
+			unsafe { libc::raise(libc::SIGSTOP); }
+		}
+	}
+	extern "C" fn resume(_: libc::c_int) {
+		PAUSED.store(false, Ordering::Relaxed);
+	}
+
+	let stop = stop as extern "C" fn(libc::c_int);
+	let resume = resume as extern "C" fn(libc::c_int);
+	unsafe {
+		libc::signal(libc::SIGTSTP, stop as libc::sighandler_t);
+		libc::signal(libc::SIGCONT, resume as libc::sighandler_t);
+	}
+}
+
+/** State shared between the playback thread and the output stream callback,
+ * through which the playback gets controlled. */
+struct State {
+	/** Whether the playback is over. */
+	end: AtomicBool,
+	/** Whether the playback has been asked to stop. The callback will fade the
+	 * output out and then end the playback. */
+	stop: AtomicBool,
+	/** Whether the playback has been asked to pause. The callback will fade the
+	 * output out and then let the playback thread know it's gone silent. */
+	pause: AtomicBool,
+	/** Whether the output has been faded out for a pause, so that the streams
+	 * can be paused without clicking. */
+	silent: AtomicBool,
+	/** The error the playback failed with, if any. */
+	failure: Mutex<Option<Error>>,
+	/** Whether the output device went away, and the stream along with it. */
+	lost: AtomicBool,
+	/** Notified once the playback is over, or once the device went away. */
+	ended: Condvar,
+	/** Lock the playback thread waits for the playback to end with. */
+	waiting: Mutex<()>,
+}
+impl State {
+	/** Ends the playback, waking the playback thread up. */
+	fn finish(&self) {
+		self.end.store(true, Ordering::Relaxed);
+		self.wake();
+	}
+
+	/** Lets the playback thread know the output device went away. */
+	fn lose(&self) {
+		self.lost.store(true, Ordering::Relaxed);
+		self.wake();
+	}
+
+	/** Wakes the playback thread up, if it's waiting. */
+	fn wake(&self) {
+		let _waiting = self.waiting.lock().unwrap();
+		self.ended.notify_all();
+	}
+
+	/** Whether the playback thread has anything to wake up for. */
+	fn woken(&self) -> bool {
+		self.end.load(Ordering::Relaxed)
+			|| self.lost.load(Ordering::Relaxed)
+			|| self.silent.load(Ordering::Relaxed)
+	}
+
+	/** Waits for the playback to end, or for the output device to go away, for
+	 * up to [`POLL_INTERVAL`]. With a spin
+	 * given, the flag gets checked over and over for that long first, before
+	 * going to sleep. This shaves the time it takes the thread to be woken up
+	 * off of the end of playback, at the cost of keeping a core busy for as
+	 * long as the spin lasts, every time around. */
+	fn wait(&self, spin: Option<Duration>) {
+		if let Some(spin) = spin {
+			let start = Instant::now();
+			while start.elapsed() < spin {
+				if self.woken() { return }
+				std::hint::spin_loop();
+			}
+		}
+
+		let waiting = self.waiting.lock().unwrap();
+		if self.woken() { return }
+		let _ = self.ended.wait_timeout(waiting, POLL_INTERVAL);
+	}
+
+	/** Ends the playback with the given error. Only the first error is kept,
+	 * as whatever comes after it is most likely a consequence of it. */
+	fn fail(&self, what: Error) {
+		let mut failure = self.failure.lock().unwrap();
+		if failure.is_none() {
+			*failure = Some(what);
+		}
+		std::mem::drop(failure);
+		self.finish();
+	}
+}
+
+/** Turns samples read from the input into samples in the format of the
+ * output, in native byte order. This is everything that happens to the samples
+ * on their way from the input to the device, whether it's played or rendered,
+ * once they've been mixed onto the channels of the output. */
+pub struct Conversion {
+	/** Format of the input samples. */
+	input: cpal::SampleFormat,
+	/** Byte order of the input samples. */
+	endian: Endianness,
+	/** Size of a single frame of input samples, in bytes. */
+	input_frame: usize,
+	/** Inputs in a format the output can't take get read into this buffer of
+	 * their own, and converted from there. */
+	staging: Vec<u8>,
+	quantizer: Quantizer,
+	/** Whether an input that turns out to be truncated should fail, rather
+	 * than just end early with a warning. */
+	strict: bool,
+}
+impl Conversion {
+	/** The conversion playback with the given arguments puts the input
+	 * through on its way to an output of the given configuration. */
+	pub fn new(args: &Arguments, format: &OutputConfig) -> Self {
+		let mut conversion = Self::with(
+			args.input_sample_format().unwrap_or(format.sample_format()),
+			args.endianness().unwrap_or(PREFERRED_SAMPLE_ENDIAN),
+			format.channels());
+		conversion.quantizer = Quantizer::new(args.dither());
+		conversion.strict = args.strict();
+
+		conversion
 	}
 
-	let format = args.config(
+	/** A conversion of input samples in the given format and byte order, with
+	 * the given number of channels. */
+	pub fn with(input: cpal::SampleFormat, endian: Endianness, channels: u16) -> Self {
+		Self {
+			input,
+			endian,
+			input_frame: input.sample_size() * usize::from(channels),
+			staging: Vec::new(),
+			quantizer: Quantizer::new(Dither::None),
+			strict: false
+		}
+	}
+
+	/** Fills the given buffer of samples in the given format in with what's
+	 * read from the given source. Returns how many complete frames were read.
+	 * When there weren't enough to fill all of it, the rest of it is silence,
+	 * starting from the last complete frame, as some drivers don't like short
+	 * buffers. */
+	pub fn fill<R>(
+		&mut self,
+		source: &mut R,
+		format: cpal::SampleFormat,
+		output: &mut [u8]) -> std::io::Result<usize>
+		where R: Read {
+
+		let converting = self.input != format;
+		let bytes = if converting {
+			let samples = output.len() / format.sample_size();
+			self.staging.resize(samples * self.input.sample_size(), 0);
+			&mut self.staging[..]
+		} else {
+			&mut output[..]
+		};
+
+		let mut filled = 0;
+		while filled < bytes.len() {
+			match source.read(&mut bytes[filled..]) {
+				Ok(0) => break,
+				Ok(read) => filled += read,
+				Err(what) if what.kind() == std::io::ErrorKind::Interrupted => continue,
+				Err(what) if Error::is_truncation(&what) && !self.strict => {
+					eprintln!("warning: {}", what);
+					break
+				},
+				Err(what) => return Err(what)
+			}
+		}
+
+		let start = filled - filled % self.input_frame;
+		convert::silence(self.input, self.endian, &mut bytes[start..]);
+
+		/* The device wants its samples in native byte order. Everything past
+		 * this point works with that. */
+		convert::to_native(self.input, self.endian, bytes);
+		if converting {
+			convert::convert(
+				&mut self.quantizer,
+				self.input,
+				&self.staging,
+				format,
+				output);
+		}
+
+		Ok(filled / self.input_frame)
+	}
+}
+
+/** Negotiates the configuration of the output stream. */
+pub fn negotiate(args: &Arguments) -> Result<OutputConfig, Error> {
+	args.config(
 		PREFERRED_SAMPLE_RATE,
 		PREFERRED_CHANNELS,
-		PREFERRED_SAMPLE_FORMAT);
-	let format = match format {
-		Ok(format) => format,
-		Err(what) => {
-			eprintln!("error: {}", what);
-			std::process::exit(1)
-		}
-	};
+		PREFERRED_SAMPLE_FORMAT)
+}
+
+/** What's wrong with the rate the given source is about to be played at, if
+ * anything. Sources that know their own format had better agree with what
+ * we're about to play them as, or they'll come out garbled. */
+fn rate_mismatch<R>(args: &Arguments, format: &OutputConfig, source: &R) -> Option<String>
+	where R: Source {
+
+	let hint = source.format_hint()?;
+	if hint.sample_rate == format.sample_rate().0 || args.assume_rate().is_some() {
+		return None
+	}
+
+	Some(format!("{} is at {}Hz, but it's going to be played at {}Hz",
+		source.description(),
+		hint.sample_rate,
+		format.sample_rate().0))
+}
+
+/** Lines telling the user what is about to be played, to where and how. */
+fn banner<R>(args: &Arguments, format: &OutputConfig, source: &R) -> String
+	where R: Source {
+
+	use std::fmt::Write;
 
 	let endian = args.endianness().unwrap_or(PREFERRED_SAMPLE_ENDIAN);
-	eprint!("playing as: {:?}{}, ",
+	let mut banner = String::new();
+
+	let _ = write!(banner, "playing {} ", source.description());
+	if let Some(length) = source.len_hint() {
+		let _ = write!(banner, "({} bytes) ", length);
+	}
+	if args.null_output() {
+		banner.push_str("to the null output ");
+	} else if let Some((index, name)) = args.device_pick() {
+		let _ = write!(banner, "to device {} ({}) ", index, name);
+	} else {
+		banner.push_str("to the default device ");
+	}
+	if let Some((index, name)) = args.host_pick() {
+		let _ = writeln!(banner, "within host {} ({})", index, name);
+	} else {
+		banner.push_str("within the default host\n");
+	}
+
+	let _ = writeln!(banner, "playing as: {:?}{}, {} channels, {}Hz",
 		format.sample_format(),
 		match endian {
 			Endianness::Little => "LE",
 			Endianness::Big    => "BE",
 			Endianness::Native => "",
-		});
-	eprint!("{} channels, ", format.channels());
-	eprintln!("{}Hz", format.sample_rate().0);
+		},
+		format.channels(),
+		format.sample_rate().0);
+
+	banner
+}
+
+/** Plays audio from a given source, using the given stream configuration.
+ *
+ * The streams are always dropped by the time this returns, be it because the
+ * input ran out, because playback failed or because we were interrupted. So is
+ * the source, unless a read from it is stuck, in which case it gets dropped as
+ * soon as the read comes back. */
+pub fn play<R>(
+	args: &Arguments,
+	format: &OutputConfig,
+	source: R,
+	idle: Option<(Activity, Duration)>) -> Result<(), Error>
+	where R: Source + 'static {
+
+	if let Some(warning) = rate_mismatch(args, format, &source) {
+		eprintln!("warning: {}", warning);
+	}
+	if args.banner() {
+		eprint!("{}", banner(args, format, &source));
+	}
 
 	/* Create the output stream. */
-	let end0 = Arc::new(AtomicBool::new(false));
-	let end1 = end0.clone();
+	let state0 = Arc::new(State {
+		end: AtomicBool::new(false),
+		stop: AtomicBool::new(false),
+		pause: AtomicBool::new(false),
+		silent: AtomicBool::new(false),
+		failure: Mutex::new(None),
+		lost: AtomicBool::new(false),
+		ended: Condvar::new(),
+		waiting: Mutex::new(())
+	});
+	let state1 = state0.clone();
 
-	let device = args.device();
-	let output = device.build_output_stream_raw(
-		&format.config(),
-		format.sample_format(),
-		move |data, info| {
-			let result = source.read_exact(data.bytes_mut());
+	let size = format.sample_format().sample_size();
+	let frame = size * usize::from(format.channels());
+
+	let mut conversion = Conversion::new(args, format);
+	let ramp_length = (format.sample_rate().0 * RAMP_MS / 1000).max(1) as usize;
+	let mut ramp = ramp_length;
+	let eof_message = args.eof_message();
+
+	/* Set up the streams the output gets mirrored to. They get fed with what
+	 * the main stream plays. */
+	let mut mirrors = Vec::new();
+	let mut feeds = Vec::new();
+	for (device, (index, _)) in args.mirrors() {
+		let index = *index;
+		let config = args.mirror_config_for(device, format).map_err(|what| Error::Mirror {
+			index,
+			what: what.to_string()
+		})?;
+
+		let (stream, feed) = mirror(device, &config, format, state0.clone())
+			.map_err(|what| Error::MirrorStream { index, what })?;
+		if args.banner() {
+			eprintln!("mirroring to device {} as {} channels of {} at {}Hz",
+				index,
+				config.channels(),
+				crate::arg::sample_format_info(config.sample_format()).name.to_ascii_uppercase(),
+				config.sample_rate().0);
+		}
+
+		mirrors.push(stream);
+		feeds.push(feed);
+	}
+
+	/* Mirrors that fall behind this much get their oldest data dropped. */
+	let mirror_limit = frame * format.sample_rate().0 as usize / 2;
+
+	/* Log of the timing of every callback, if one was asked for. Just like the
+	 * clip report, it gets written from a thread of its own. */
+	let timestamp_log = args.timestamps().map(|path| {
+		let line = |log: &mut dyn Write, timing: Timing| writeln!(log, "{:.6},{:.6},{:.6},{},{:.6}",
+			timing.callback,
+			timing.playback,
+			timing.playback - timing.callback,
+			timing.frames,
+			timing.duration);
+		log(path, "callback,playback,latency,frames,duration", line).map_err(|what| Error::TimestampLog {
+			path: path.to_owned(),
+			what
+		})
+	}).transpose()?;
+	let timings_dropped = Arc::new(AtomicU64::new(0));
+	let (mut timestamps, timestamp_log) = match timestamp_log {
+		Some((feed, writer)) => (Some((feed, timings_dropped.clone())), Some(writer)),
+		None => (None, None)
+	};
+	let mut origin = None;
+	let mut null_origin = None;
+	let mut drained = false;
+
+	/* Log of every clipped sample, if one was asked for, along with how many
+	 * frames have been played before the current buffer. The file gets written
+	 * from a thread of its own, so that the callback never has to wait on it. */
+	let clip_report = args.clip_report().map(|path| {
+		let line = |log: &mut dyn Write, clip: Clip|
+			writeln!(log, "{},{},{}", clip.frame, clip.channel, clip.value);
+		log(path, "frame,channel,value", line).map_err(|what| Error::ClipReport {
+			path: path.to_owned(),
+			what
+		})
+	}).transpose()?;
+	let clips_dropped = Arc::new(AtomicU64::new(0));
+	let clips_limited = Arc::new(AtomicBool::new(false));
+	let (mut clips, clip_report) = match clip_report {
+		Some((feed, writer)) => (Some((feed, clips_dropped.clone(), clips_limited.clone())), Some(writer)),
+		None => (None, None)
+	};
+	let mut clipped = 0u64;
+	let mut played = 0u64;
+
+	/* Capture of the output, if one was asked for. The file gets written from
+	 * a thread of its own, so that the callback never has to wait on it, out
+	 * of a pool of buffers it hands back once they're written, so that the
+	 * callback never has to allocate any. */
+	let tee_capacity = frame * format.sample_rate().0 as usize * TEE_BUFFER_MS / 1000;
+	let tee = args.tee().map(|path| tee(path, tee_capacity)).transpose()?;
+	let tee_dropped = Arc::new(AtomicU64::new(0));
+	let (mut tee_feed, tee) = match tee {
+		Some(((feed, pool), writer)) => (Some((feed, pool, tee_dropped.clone())), Some(writer)),
+		None => (None, None)
+	};
+
+	/* Keep track of what the device is, so it can be told apart from the
+	 * others should it go away and come back. */
+	let reconnect = args.reconnect();
+	let identity = if reconnect {
+		Some(args.device().name().ok())
+	} else {
+		None
+	};
+
+	let config = format.config();
+	let output_format = format.sample_format();
+	let format = *format;
+	let max_underruns = args.max_underruns();
+	let mut underruns = 0u64;
+
+	/* Reads happen on a thread of their own, so that the callback can be let
+	 * go of in the middle of one that never comes back, such as from an input
+	 * that's gone quiet, rather than holding the streams up. */
+	let (mut source, release) = crate::src::Detach::new(source);
+	let callback = move |bytes: &mut [u8], sample_format: cpal::SampleFormat, timestamp: Option<cpal::OutputStreamTimestamp>| {
+		let entered = Instant::now();
+		let frames = bytes.len() / (sample_format.sample_size() * usize::from(format.channels()));
+		let budget = Duration::from_secs_f64(frames as f64 / f64::from(format.sample_rate().0));
+
+		/* The buffer filled in when the input ran out has been handed to
+		 * the device by now, so it's safe to call it done. */
+		if drained {
+			convert::silence(sample_format, Endianness::Native, bytes);
+			state1.finish();
+			return
+		}
+
+		let filled = match conversion.fill(&mut source, sample_format, bytes) {
+			Ok(filled) => filled,
+			Err(what) => {
+				/* Play silence until the stream gets torn down. */
+				convert::silence(sample_format, Endianness::Native, bytes);
+				drained = true;
+				state1.fail(Error::read_failed(what));
+				return
+			}
+		};
+
+		/* Reads block until the input has something to give, so a fill is
+		 * never short of samples until the input is over, and whether the
+		 * device ran dry can't be told from how much got filled in. It's
+		 * guessed at instead: taking longer to fill the buffer in than it
+		 * takes to play it likely left the device without samples for a
+		 * while. This is only a heuristic. A device with more than one
+		 * buffer's worth queued up can ride out a slow fill, and a fill that
+		 * only just makes it in time can still be too late for it. */
+		if entered.elapsed() > budget {
+			underruns += 1;
+			if let Some(limit) = max_underruns.filter(|limit| underruns > *limit) {
+				drained = true;
+				state1.fail(Error::TooManyUnderruns { limit });
+			}
+		}
+		if filled < frames {
+			if eof_message && !source.released() {
+				eprintln!("e o f");
+			}
+			drained = true;
+		}
+
+		let stop = state1.stop.load(Ordering::Relaxed);
+		if stop || state1.pause.load(Ordering::Relaxed) {
+			/* Fade out rather than stopping abruptly, which clicks. */
+			convert::fade_out(
+				&mut conversion.quantizer,
+				sample_format,
+				frame,
+				&mut ramp,
+				ramp_length,
+				bytes);
+
+			if ramp == 0 && stop {
+				state1.finish();
+			} else if ramp == 0 {
+				state1.silent.store(true, Ordering::Relaxed);
+				state1.wake();
+			}
+		} else if ramp < ramp_length {
+			/* Coming back from a pause, fade back in. */
+			convert::fade_in(
+				&mut conversion.quantizer,
+				sample_format,
+				frame,
+				&mut ramp,
+				ramp_length,
+				bytes);
+		}
+
+		if let Some((feed, dropped, limited)) = &clips {
+			let result = report_clips(
+				feed,
+				dropped,
+				sample_format,
+				usize::from(format.channels()),
+				played,
+				&mut clipped,
+				bytes);
 			match result {
-				/*Ok(result) =>
-					eprintln!("{:?}: fed {} bytes with {} bytes",
-						info.timestamp().playback,
-						data.bytes().len(),
-						result),*/
-				Err(what) => if what.kind() == std::io::ErrorKind::UnexpectedEof {
-					eprintln!("e o f");
-					end1.store(true, Ordering::Relaxed);
-				} else {
-					eprintln!("error: data read failed: {}", what);
-					std::process::exit(1);
+				Ok(true) => {},
+				Ok(false) => {
+					limited.store(true, Ordering::Relaxed);
+					clips = None;
 				},
-				_ => {}
+				/* The writer gave up, and will say why once playback is over. */
+				Err(_) => clips = None
 			}
+			played += frames as u64;
+		}
 
-			let samples = data.bytes().len() / data.sample_format().sample_size();
-			let per_sec = format.sample_rate().0 * format.channels() as u32;
-			let projected = samples as f64 / per_sec as f64;
-		},
-		|what| {
-			eprintln!("error: output stream failed: {}", what);
-			std::process::exit(1);
+		if let Some((feed, pool, dropped)) = &tee_feed {
+			/* Every buffer in the pool being in the writer's hands means it's
+			 * fallen behind. */
+			let sent = match pool.try_recv() {
+				Ok(mut buffer) => {
+					buffer.clear();
+					buffer.extend_from_slice(bytes);
+					feed.try_send(buffer)
+				},
+				Err(_) => Err(TrySendError::Full(Vec::new()))
+			};
+			match sent {
+				Ok(()) => {},
+				Err(TrySendError::Full(_)) => {
+					dropped.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+				},
+				/* The writer gave up, and will say why once playback is over. */
+				Err(TrySendError::Disconnected(_)) => tee_feed = None
+			}
+		}
+
+		for feed in &feeds {
+			let mut feed = feed.lock().unwrap();
+			feed.extend(bytes.iter());
+			if feed.len() > mirror_limit {
+				let excess = feed.len() - mirror_limit;
+				feed.drain(..excess);
+			}
+		}
+
+		let samples = bytes.len() / sample_format.sample_size();
+		let per_sec = format.sample_rate().0 * format.channels() as u32;
+		let projected = samples as f64 / per_sec as f64;
+
+		if let Some((feed, dropped)) = &timestamps {
+			/* The stream only gives us instants relative to one another, so
+			 * everything is logged relative to the first callback. Without a
+			 * stream, buffers are played the moment they're filled in. */
+			let (callback, playback) = match timestamp {
+				Some(timestamp) => {
+					let origin = *origin.get_or_insert(timestamp.callback);
+					let since = |instant: &cpal::StreamInstant| instant
+						.duration_since(&origin)
+						.map(|duration| duration.as_secs_f64())
+						.unwrap_or(0.0);
+
+					(since(&timestamp.callback), since(&timestamp.playback))
+				},
+				None => {
+					let since = entered.duration_since(*null_origin.get_or_insert(entered)).as_secs_f64();
+					(since, since)
+				}
+			};
+			let timing = Timing {
+				callback,
+				playback,
+				frames: samples / usize::from(format.channels()),
+				duration: projected
+			};
+			match feed.try_send(timing) {
+				Ok(()) => {},
+				Err(TrySendError::Full(_)) => { dropped.fetch_add(1, Ordering::Relaxed); },
+				/* The writer gave up, and will say why once playback is over. */
+				Err(TrySendError::Disconnected(_)) => timestamps = None
+			}
+		}
+	};
+
+	/* The callback is kept apart from the stream, so that it can be handed to
+	 * a new stream, with the input right where it was left, should the device
+	 * go away and come back. Only one stream ever has it at a time, so locking
+	 * it doesn't hold the callback up. */
+	let callback = Arc::new(Mutex::new(callback));
+	let mut output = Some(if args.null_output() {
+		Output::Null(NullOutput::new(&config, output_format, &callback))
+	} else {
+		Output::Device(build(args.device(), &config, output_format, &callback, &state0, reconnect)?)
+	});
+
+	/* Hold off until the requested time, if any. Instances on different
+	 * machines are only ever as synchronized as their clocks are. */
+	if let Some(start) = args.start_at() {
+		if SystemTime::now() > start {
+			eprintln!("warning: the start time has already passed, starting right away");
+		}
+		crate::clock::wait_until(start);
+	}
+
+	/* Start all of the streams as closely together as we can. */
+	PLAYING.store(true, Ordering::Relaxed);
+	let started = output.as_ref().unwrap().play()
+		.and_then(|_| mirrors.iter().try_for_each(|mirror| mirror.play()))
+		.map_err(Error::PlayStream);
+	if let Err(what) = started {
+		state0.fail(what);
+	}
+
+	while !state0.end.load(Ordering::Relaxed) {
+		if let Some((activity, timeout)) = &idle {
+			if let Some(idle) = activity.idle().filter(|idle| idle >= timeout) {
+				state0.fail(Error::IdleTimeout { idle });
+				break
+			}
+		}
+		if interrupted() {
+			/* Fading out takes the callback a little while. Without it, there's
+			 * nothing left to wait for. */
+			if !args.ramp_on_pause() { break }
+			state0.stop.store(true, Ordering::Relaxed);
+		} else if PAUSED.load(Ordering::Relaxed) && output.is_some() {
+			/* The streams only get paused once the callback is done fading
+			 * out, if it's fading out at all. */
+			if args.ramp_on_pause() && !state0.silent.load(Ordering::Relaxed) {
+				state0.pause.store(true, Ordering::Relaxed);
+				state0.wait(args.spin());
+				continue
+			}
+
+			let output = output.as_ref().unwrap();
+			let _ = output.pause();
+			for mirror in &mirrors { let _ = mirror.pause(); }
+			if STOP_ONCE_PAUSED.swap(false, Ordering::Relaxed) {
+				#[cfg(unix)]
+				unsafe { libc::raise(libc::SIGSTOP); }
+			}
+			while PAUSED.load(Ordering::Relaxed) && !interrupted() {
+				std::thread::sleep(POLL_INTERVAL);
+			}
+
+			/* Time spent paused is no time spent waiting on the input. */
+			if let Some((activity, _)) = &idle {
+				activity.reset();
+			}
+			state0.pause.store(false, Ordering::Relaxed);
+			state0.silent.store(false, Ordering::Relaxed);
+			let resumed = output.play()
+				.and_then(|_| mirrors.iter().try_for_each(|mirror| mirror.play()))
+				.map_err(Error::PlayStream);
+			if let Err(what) = resumed {
+				state0.fail(what);
+				break
+			}
+			continue
+		}
+		if state0.lost.swap(false, Ordering::Relaxed) {
+			eprintln!("warning: the output device went away, waiting for it to come back");
+
+			/* Dropping the stream gets it to let go of the callback. */
+			if let Some(stream) = output.take() {
+				let _ = stream.pause();
+				std::mem::drop(stream);
+			}
+
+			let name = identity.as_ref().unwrap();
+			match reopen(args, name.as_deref(), &config, output_format, &callback, &state0) {
+				Ok(Some(stream)) => output = Some(Output::Device(stream)),
+				Ok(None) => break,
+				Err(what) => {
+					state0.fail(what);
+					break
+				}
+			}
+			continue
+		}
+
+		state0.wait(args.spin());
+	}
+
+	if let Some(output) = &output { let _ = output.pause(); }
+	for mirror in &mirrors { let _ = mirror.pause(); }
+
+	/* Dropping the streams waits on their callbacks, so whatever read the
+	 * callback is in the middle of gets let go of first. */
+	release.release();
+	std::mem::drop(output);
+	std::mem::drop(callback);
+	std::mem::drop(mirrors);
+	PLAYING.store(false, Ordering::Relaxed);
+
+	/* With the callback gone, the writer runs out of buffers to write once
+	 * it's through with the ones it has, and finishes up. */
+	if let Some(writer) = tee {
+		let path = args.tee().unwrap();
+		let dropped = tee_dropped.load(Ordering::Relaxed);
+		match writer.join().unwrap() {
+			Ok(captured) if dropped > 0 =>
+				eprintln!("warning: captured {} bytes to {}, but dropped {} bytes the capture couldn't keep up with",
+					captured,
+					path.display(),
+					dropped),
+			Ok(captured) => if args.banner() {
+				eprintln!("captured {} bytes to {}", captured, path.display());
+			},
+			Err(what) => eprintln!("warning: {}", Error::Tee {
+				path: path.to_owned(),
+				what
+			})
+		}
+	}
+
+	if let Some(writer) = timestamp_log {
+		match writer.join().unwrap() {
+			Ok(_) => {
+				let dropped = timings_dropped.load(Ordering::Relaxed);
+				if dropped > 0 {
+					eprintln!("warning: dropped {} callbacks the timestamp log couldn't keep up with",
+						dropped);
+				}
+			},
+			Err(what) => eprintln!("warning: {}", Error::TimestampLog {
+				path: args.timestamps().unwrap().to_owned(),
+				what
+			})
+		}
+	}
+	if let Some(writer) = clip_report {
+		match writer.join().unwrap() {
+			Ok(_) => {
+				if clips_limited.load(Ordering::Relaxed) {
+					eprintln!("warning: clipping all over, the clip report stops at the first {} samples",
+						CLIP_REPORT_LIMIT);
+				}
+				let dropped = clips_dropped.load(Ordering::Relaxed);
+				if dropped > 0 {
+					eprintln!("warning: dropped {} clipped samples the clip report couldn't keep up with",
+						dropped);
+				}
+			},
+			Err(what) => eprintln!("warning: {}", Error::ClipReport {
+				path: args.clip_report().unwrap().to_owned(),
+				what
+			})
+		}
+	}
+
+	let failure = state0.failure.lock().unwrap().take();
+	match failure {
+		Some(what) => Err(what),
+		None => Ok(())
+	}
+}
+
+/** Runs the given source through the same conversion it would go through on
+ * its way to the device, as fast as it can, writing the samples the device
+ * would have been given, in native byte order, to the given file. Rather than
+ * padding the end out to a whole buffer, as playback does, only complete
+ * frames get written. */
+pub fn render<R>(
+	args: &Arguments,
+	format: &OutputConfig,
+	mut source: R,
+	path: &Path) -> Result<(), Error>
+	where R: Source {
+
+	let error = |what| Error::Render {
+		path: path.to_owned(),
+		what
+	};
+
+	if args.banner() {
+		eprintln!("rendering {} to {} as {} channels of {} at {}Hz",
+			source.description(),
+			path.display(),
+			format.channels(),
+			crate::arg::sample_format_info(format.sample_format()).name,
+			format.sample_rate().0);
+	}
+
+	let mut output = BufWriter::new(std::fs::File::create(path).map_err(error)?);
+	let mut conversion = Conversion::new(args, format);
+	let sample_format = format.sample_format();
+	let frame = sample_format.sample_size() * usize::from(format.channels());
+	let mut buffer = vec![0; RENDER_FRAMES * frame];
+
+	let mut written = 0u64;
+	let mut shown = 0;
+	loop {
+		if interrupted() { break }
+
+		let filled = conversion.fill(&mut source, sample_format, &mut buffer)
+			.map_err(Error::read_failed)?;
+
+		let length = filled * frame;
+		output.write_all(&buffer[..length]).map_err(error)?;
+		written += length as u64;
+
+		let mib = written / (1024 * 1024);
+		if args.progress() && mib >= shown + 16 {
+			eprint!("\rrendered: {} MiB", mib);
+			shown = mib;
+		}
+
+		if filled < RENDER_FRAMES { break }
+	}
+	output.flush().map_err(error)?;
+
+	if args.progress() {
+		eprintln!("\rrendered {} bytes", written);
+	}
+
+	Ok(())
+}
+
+/** Where the output goes. */
+enum Output {
+	/** A stream on the output device. */
+	Device(cpal::Stream),
+	/** Nowhere at all, at the pace a device would have taken it in. */
+	Null(NullOutput),
+}
+impl Output {
+	fn play(&self) -> Result<(), cpal::PlayStreamError> {
+		match self {
+			Self::Device(stream) => stream.play(),
+			Self::Null(null) => {
+				null.running.store(true, Ordering::Relaxed);
+				Ok(())
+			}
+		}
+	}
+
+	fn pause(&self) -> Result<(), cpal::PauseStreamError> {
+		match self {
+			Self::Device(stream) => stream.pause(),
+			Self::Null(null) => {
+				null.running.store(false, Ordering::Relaxed);
+				Ok(())
+			}
+		}
+	}
+}
+
+/** An output that runs the callback from a thread of its own, asking it for
+ * buffers at exactly the pace a device playing them would, and throwing them
+ * away. Everything else about playback works just like it does with a device,
+ * which is what makes it useful where there's none to be had. */
+struct NullOutput {
+	/** Whether the callback is being run. */
+	running: Arc<AtomicBool>,
+	/** Whether the thread should be done with. */
+	closed: Arc<AtomicBool>,
+	thread: Option<JoinHandle<()>>,
+}
+impl NullOutput {
+	/** Starts a thread running the given callback with buffers of the given
+	 * configuration, once the output is played. */
+	fn new<F>(
+		config: &StreamConfig,
+		sample_format: cpal::SampleFormat,
+		callback: &Arc<Mutex<F>>) -> Self
+		where F: FnMut(&mut [u8], cpal::SampleFormat, Option<cpal::OutputStreamTimestamp>) + Send + 'static {
+
+		let running0 = Arc::new(AtomicBool::new(false));
+		let running1 = running0.clone();
+		let closed0 = Arc::new(AtomicBool::new(false));
+		let closed1 = closed0.clone();
+
+		let callback = callback.clone();
+		let frame = sample_format.sample_size() * usize::from(config.channels);
+		let period = Duration::from_secs_f64(NULL_BUFFER_FRAMES as f64 / f64::from(config.sample_rate.0));
+		let thread = std::thread::spawn(move || {
+			let mut buffer = vec![0; NULL_BUFFER_FRAMES * frame];
+
+			/* Every buffer is due a period after the one before it, rather than
+			 * a period after it was done with, so that the time the callback
+			 * takes doesn't add up into drift. */
+			let mut due = None;
+			while !closed1.load(Ordering::Relaxed) {
+				if !running1.load(Ordering::Relaxed) {
+					due = None;
+					std::thread::sleep(POLL_INTERVAL);
+					continue
+				}
+
+				let deadline = *due.get_or_insert_with(Instant::now);
+				let now = Instant::now();
+				if deadline > now {
+					std::thread::sleep(deadline - now);
+				}
+
+				(*callback.lock().unwrap())(&mut buffer, sample_format, None);
+				due = Some(deadline + period);
+			}
 		});
-	let output = match output {
-		Ok(output) => output,
-		Err(what) => {
-			eprintln!("error: could not initialize output stream: {}", what);
-			std::process::exit(1);
+
+		Self {
+			running: running0,
+			closed: closed0,
+			thread: Some(thread)
+		}
+	}
+}
+impl Drop for NullOutput {
+	/** Waits for the thread to be done with the callback, just like dropping
+	 * a stream does. */
+	fn drop(&mut self) {
+		self.closed.store(true, Ordering::Relaxed);
+		if let Some(thread) = self.thread.take() {
+			let _ = thread.join();
+		}
+	}
+}
+
+/** Builds the output stream on the given device, running the given callback.
+ * With reconnection enabled, the device going away gets reported through the
+ * given state, rather than failing the playback. */
+fn build<F>(
+	device: &cpal::Device,
+	config: &StreamConfig,
+	sample_format: cpal::SampleFormat,
+	callback: &Arc<Mutex<F>>,
+	state: &Arc<State>,
+	reconnect: bool) -> Result<cpal::Stream, Error>
+	where F: FnMut(&mut [u8], cpal::SampleFormat, Option<cpal::OutputStreamTimestamp>) + Send + 'static {
+
+	let callback = callback.clone();
+	let state = state.clone();
+	device.build_output_stream_raw(
+		config,
+		sample_format,
+		move |data, info| (*callback.lock().unwrap())(
+			data.bytes_mut(),
+			sample_format,
+			Some(info.timestamp())),
+		move |what| match what {
+			cpal::StreamError::DeviceNotAvailable if reconnect => state.lose(),
+			what => state.fail(Error::StreamFailed(what))
+		})
+		.map_err(Error::OutputStream)
+}
+
+/** Waits for the output device that went away to come back, checking on the
+ * devices every [`RECONNECT_INTERVAL`], and then builds a new stream on it,
+ * running the given callback. The device is found by its name, which is all
+ * there is to tell devices apart by. Returns nothing if we were interrupted in
+ * the meantime. */
+fn reopen<F>(
+	args: &Arguments,
+	name: Option<&str>,
+	config: &StreamConfig,
+	sample_format: cpal::SampleFormat,
+	callback: &Arc<Mutex<F>>,
+	state: &Arc<State>) -> Result<Option<cpal::Stream>, Error>
+	where F: FnMut(&mut [u8], cpal::SampleFormat, Option<cpal::OutputStreamTimestamp>) + Send + 'static {
+
+	use cpal::traits::HostTrait;
+
+	loop {
+		if interrupted() { return Ok(None) }
+		std::thread::sleep(RECONNECT_INTERVAL);
+
+		let mut devices = match args.host().output_devices() {
+			Ok(devices) => devices,
+			Err(_) => continue
+		};
+		let device = devices.find(|device| name.is_some() && device.name().ok().as_deref() == name);
+
+		if let Some(device) = device {
+			let stream = build(&device, config, sample_format, callback, state, true)?;
+			stream.play().map_err(Error::PlayStream)?;
+			if args.banner() {
+				eprintln!("the output device is back, resuming playback");
+			}
+
+			return Ok(Some(stream))
+		}
+	}
+}
+
+/** Thread writing the buffers of a tee out, returning how many bytes it
+ * wrote. */
+type TeeWriter = JoinHandle<std::io::Result<u64>>;
+
+/** Channel buffers get sent to the writer of a tee through, along with the
+ * pool they get taken out of. */
+type TeeFeed = (SyncSender<Vec<u8>>, Receiver<Vec<u8>>);
+
+/** Creates the given file and starts a thread writing whatever buffers get
+ * sent through the returned channel to it, in order. Buffers are taken out of
+ * the returned pool, made up front with the given capacity, and the thread
+ * puts them back in it once they're written. The thread finishes once the
+ * channel is dropped, returning how many bytes it wrote. */
+fn tee(path: &Path, capacity: usize) -> Result<(TeeFeed, TeeWriter), Error> {
+
+	let file = std::fs::File::create(path).map_err(|what| Error::Tee {
+		path: path.to_owned(),
+		what
+	})?;
+
+	let (recycle, pool) = std::sync::mpsc::sync_channel::<Vec<u8>>(TEE_BUFFERS);
+	for _ in 0..TEE_BUFFERS {
+		let _ = recycle.send(Vec::with_capacity(capacity));
+	}
+
+	let (feed, buffers) = std::sync::mpsc::sync_channel::<Vec<u8>>(TEE_BUFFERS);
+	let writer = std::thread::spawn(move || {
+		let mut file = BufWriter::new(file);
+		let mut written = 0u64;
+		for buffer in buffers {
+			file.write_all(&buffer)?;
+			written += buffer.len() as u64;
+
+			/* Only ever as many buffers as the pool holds are around, so
+			 * there's always room for this one. */
+			let _ = recycle.try_send(buffer);
+		}
+		file.flush()?;
+
+		Ok(written)
+	});
+
+	Ok(((feed, pool), writer))
+}
+
+/** Thread writing the lines of a log out, returning how many it wrote. */
+type LogWriter = JoinHandle<std::io::Result<u64>>;
+
+/** Creates the given file, starting it with the given header, and starts a
+ * thread writing a line for every entry sent through the returned channel to
+ * it, in order. The thread finishes once the channel is dropped, returning how
+ * many lines it wrote. */
+fn log<T, F>(path: &Path, header: &str, line: F) -> std::io::Result<(SyncSender<T>, LogWriter)>
+	where T: Send + 'static,
+		F: Fn(&mut dyn Write, T) -> std::io::Result<()> + Send + 'static {
+
+	let mut file = BufWriter::new(std::fs::File::create(path)?);
+	writeln!(file, "{}", header)?;
+
+	let (feed, entries) = std::sync::mpsc::sync_channel::<T>(LOG_LINES);
+	let writer = std::thread::spawn(move || {
+		let mut written = 0u64;
+		for entry in entries {
+			line(&mut file, entry)?;
+			written += 1;
+		}
+		file.flush()?;
+
+		Ok(written)
+	});
+
+	Ok((feed, writer))
+}
+
+/** Timing of a callback, as it goes into the timestamp log. Times are in
+ * seconds since the first callback. */
+struct Timing {
+	/** When the callback got called. */
+	callback: f64,
+	/** When the buffer it filled in is going to be played. */
+	playback: f64,
+	/** Number of frames in the buffer. */
+	frames: usize,
+	/** How long the buffer takes to play. */
+	duration: f64,
+}
+
+/** A sample at or past full scale, as it goes into the clip report. */
+struct Clip {
+	/** Number of frames played before the one the sample is in. */
+	frame: u64,
+	/** Channel the sample is in. */
+	channel: usize,
+	/** Value of the sample. */
+	value: f32,
+}
+
+/** Sends every sample at or past full scale in the given buffer of native
+ * samples, the first frame of which comes after the given number of frames,
+ * off to the clip report, keeping count of how many have been sent. Samples
+ * the report can't keep up with get counted as dropped instead. Returns
+ * whether there's room left in the report for more of them, or an error if the
+ * report is no longer being written. */
+fn report_clips(
+	feed: &SyncSender<Clip>,
+	dropped: &AtomicU64,
+	format: cpal::SampleFormat,
+	channels: usize,
+	played: u64,
+	clipped: &mut u64,
+	bytes: &[u8]) -> Result<bool, TrySendError<Clip>> {
+
+	let size = format.sample_size();
+	for (index, sample) in bytes.chunks_exact(size).enumerate() {
+		let value = convert::decode(format, Endianness::Native, sample);
+		if !convert::clipped(format, value) { continue }
+
+		if *clipped == CLIP_REPORT_LIMIT {
+			return Ok(false)
+		}
+		let clip = Clip {
+			frame: played + (index / channels) as u64,
+			channel: index % channels,
+			value
+		};
+		match feed.try_send(clip) {
+			Ok(()) => {},
+			Err(TrySendError::Full(_)) => { dropped.fetch_add(1, Ordering::Relaxed); },
+			Err(what) => return Err(what)
+		}
+		*clipped += 1;
+	}
+
+	Ok(true)
+}
+
+/** Buffer the data played on a mirrored device gets pushed into. */
+type Feed = Arc<Mutex<VecDeque<u8>>>;
+
+/** Builds a stream on the given device that plays whatever data gets pushed
+ * into the returned buffer, in the given configuration, in native byte order.
+ * The data gets converted into the configuration of the stream, downmixing or
+ * resampling it if need be. Silence is played whenever there's no data. */
+fn mirror(
+	device: &cpal::Device,
+	config: &OutputConfig,
+	input: &OutputConfig,
+	state: Arc<State>)
+	-> Result<(cpal::Stream, Feed), cpal::BuildStreamError> {
+
+	let feed = Arc::new(Mutex::new(VecDeque::new()));
+	let output = config.sample_format();
+	let mut conform = mirror_conform(feed.clone(), input, config);
+	let stream = device.build_output_stream_raw(
+		&config.config(),
+		output,
+		move |data, _| fill_mirror(&mut conform, output, data.bytes_mut()),
+		move |what| state.fail(Error::MirrorFailed(what)))?;
+
+	Ok((stream, feed))
+}
+
+/** Reads whatever has been pushed into a feed so far. Rather than waiting for
+ * more, it tells the reader to come back later once the feed runs dry. */
+struct FeedReader(Feed);
+impl Read for FeedReader {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		let mut feed = self.0.lock().unwrap();
+		if feed.is_empty() {
+			return Err(std::io::ErrorKind::WouldBlock.into())
+		}
+
+		let len = usize::min(buf.len(), feed.len());
+		for (target, source) in buf.iter_mut().zip(feed.drain(..len)) {
+			*target = source;
 		}
+
+		Ok(len)
+	}
+}
+
+/** Converts the data pushed into the given feed, in the given configuration,
+ * into the configuration a mirror plays. */
+fn mirror_conform(feed: Feed, input: &OutputConfig, output: &OutputConfig)
+	-> crate::src::Conform<FeedReader> {
+
+	let format = |config: &OutputConfig| InputFormat {
+		channels: config.channels(),
+		sample_rate: config.sample_rate().0,
+		sample_format: config.sample_format(),
+		endian: Endianness::Native
 	};
+	crate::src::Conform::new(FeedReader(feed), format(input), format(output))
+}
 
-	output.play();
-	while !end0.load(Ordering::Relaxed) { }
-	output.pause();
+/** Fills the buffer of a mirror with as much converted data as there is, and
+ * with silence past that. */
+fn fill_mirror<R>(conform: &mut crate::src::Conform<R>, format: cpal::SampleFormat, bytes: &mut [u8])
+	where R: Read {
+
+	let mut filled = 0;
+	while filled < bytes.len() {
+		match conform.read(&mut bytes[filled..]) {
+			Ok(0) | Err(_) => break,
+			Ok(read) => filled += read
+		}
+	}
+	convert::silence(format, Endianness::Native, &mut bytes[filled..]);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Cursor;
+
+	fn f32s(samples: &[f32]) -> Vec<u8> {
+		samples.iter().flat_map(|sample| sample.to_le_bytes()).collect()
+	}
+
+	/** Renders the given input with the given arguments, the way --render-to
+	 * does, remapped onto the output first, and gives back what was written. */
+	fn rendered(args: &[&str], input: Vec<u8>) -> Vec<u8> {
+		let path = std::env::temp_dir().join(format!(
+			"alplay-render-{}-{:?}.raw",
+			std::process::id(),
+			std::thread::current().id()));
+		let matches = clap::App::new("alplay")
+			.args(&crate::device_args())
+			.args(&crate::input_args())
+			.args(&crate::playback_args())
+			.get_matches_from(["alplay", "--no-banner", "--no-progress", "--render-to", path.to_str().unwrap()]
+				.iter()
+				.chain(args));
+		let args = Arguments::new(&matches).unwrap();
+		let format = negotiate(&args).unwrap();
+
+		let source = crate::remap(&matches, &args, &format, Box::new(Cursor::new(input)));
+		render(&args, &format, source, &path).unwrap();
+		let output = std::fs::read(&path).unwrap();
+		std::fs::remove_file(&path).unwrap();
+
+		output
+	}
+
+	/** Playback goes through state of the whole process, such as whether it's
+	 * been interrupted, so tests playing anything take turns at it. */
+	static PLAYBACK: Mutex<()> = Mutex::new(());
+
+	/** Plays the given source to the null output with the given arguments,
+	 * watching it for going idle and interrupting it after the given time, if
+	 * at all, and gives back how playback went, along with everything that was
+	 * handed to the output. */
+	fn played<R>(
+		args: &[&str],
+		source: R,
+		idle: Option<(Activity, Duration)>,
+		interrupt: Option<Duration>) -> (Result<(), Error>, Vec<u8>)
+		where R: Source + 'static {
+
+		played_with(args, source, idle, move || if let Some(after) = interrupt {
+			std::thread::sleep(after);
+			INTERRUPTS.fetch_add(1, Ordering::Relaxed);
+		})
+	}
+
+	/** Same as [`played`], but with the given function controlling playback
+	 * from a thread of its own while it's under way. */
+	fn played_with<R, F>(
+		args: &[&str],
+		source: R,
+		idle: Option<(Activity, Duration)>,
+		control: F) -> (Result<(), Error>, Vec<u8>)
+		where R: Source + 'static,
+			F: FnOnce() + Send + 'static {
+
+		let _turn = PLAYBACK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+		let path = std::env::temp_dir().join(format!(
+			"alplay-tee-{}-{:?}.raw",
+			std::process::id(),
+			std::thread::current().id()));
+		let matches = clap::App::new("alplay")
+			.args(&crate::device_args())
+			.args(&crate::input_args())
+			.args(&crate::playback_args())
+			.get_matches_from(["alplay", "--no-banner", "--no-progress", "--output", "null", "--tee", path.to_str().unwrap()]
+				.iter()
+				.chain(args));
+		let args = Arguments::new(&matches).unwrap();
+		let format = negotiate(&args).unwrap();
+
+		let control = std::thread::spawn(control);
+		let result = play(&args, &format, source, idle);
+		control.join().unwrap();
+		INTERRUPTS.store(0, Ordering::Relaxed);
+		PAUSED.store(false, Ordering::Relaxed);
+
+		let output = std::fs::read(&path).unwrap_or_default();
+		let _ = std::fs::remove_file(&path);
+
+		(result, output)
+	}
+
+	fn native_f32(bytes: &[u8]) -> Vec<f32> {
+		bytes.chunks_exact(4)
+			.map(|sample| f32::from_ne_bytes([sample[0], sample[1], sample[2], sample[3]]))
+			.collect()
+	}
+
+	fn native_i16(samples: &[i16]) -> Vec<u8> {
+		samples.iter().flat_map(|sample| sample.to_ne_bytes()).collect()
+	}
+
+	/** Native samples pushed into a feed, converted for a mirror with the given
+	 * configuration, read out over a buffer of the given number of frames. */
+	fn mirrored(frames: &[[f32; 2]], output: &OutputConfig, len: usize) -> Vec<f32> {
+		let input = OutputConfig::new(2, cpal::SampleRate(48000), cpal::SampleFormat::F32);
+		let feed = Feed::default();
+		let mut conform = mirror_conform(feed.clone(), &input, output);
+		feed.lock().unwrap().extend(frames.iter().flatten().flat_map(|sample| sample.to_ne_bytes()));
+
+		let mut bytes = vec![0xaa; len * 4 * usize::from(output.channels())];
+		fill_mirror(&mut conform, output.sample_format(), &mut bytes);
+		bytes.chunks_exact(4)
+			.map(|sample| f32::from_ne_bytes([sample[0], sample[1], sample[2], sample[3]]))
+			.collect()
+	}
+
+	#[test]
+	fn mirrors_get_the_output_converted_into_what_they_play() {
+		/* Into mono, both sides count the same. The last frame is held back
+		 * until the one after it comes in, so it plays as silence for now. */
+		let mono = OutputConfig::new(1, cpal::SampleRate(48000), cpal::SampleFormat::F32);
+		let output = mirrored(&[[0.5, 0.25]; 100], &mono, 100);
+		assert!(output[..99].iter().all(|sample| *sample == 0.375));
+		assert_eq!(output[99], 0.0);
+
+		/* At half the rate, half as many frames come out. */
+		let half = OutputConfig::new(2, cpal::SampleRate(24000), cpal::SampleFormat::F32);
+		let output = mirrored(&[[0.5, 0.5]; 200], &half, 200);
+		assert_eq!(output.iter().filter(|sample| **sample != 0.0).count(), 2 * 100);
+
+		/* Nothing pushed at all plays as silence. */
+		assert!(mirrored(&[], &mono, 10).iter().all(|sample| *sample == 0.0));
+	}
+
+	#[test]
+	fn renders_come_out_in_native_byte_order() {
+		let input = [0x12, 0x34, 0x80, 0x00, 0x7f, 0xff, 0x00, 0x01];
+		let output = rendered(&["-c", "2", "-r", "8000", "-f", "s16be"], input.to_vec());
+		assert_eq!(output, native_i16(&[0x1234, -32768, 32767, 1]));
+
+		/* Unsigned samples stay unsigned. */
+		let output = rendered(&["-c", "1", "-r", "8000", "-f", "u16le"], vec![0x00, 0x80, 0xff, 0xff]);
+		assert_eq!(output, [0x8000u16, 0xffff].iter().flat_map(|sample| sample.to_ne_bytes()).collect::<Vec<_>>());
+	}
+
+	#[test]
+	fn renders_go_through_the_matrix() {
+		let input = [1000i16, 3000, -2000, 2000, 32767, 32767]
+			.iter()
+			.flat_map(|sample| sample.to_le_bytes())
+			.collect::<Vec<_>>();
+		let output = rendered(
+			&["-c", "2", "-r", "8000", "-f", "s16le", "--matrix", "0.5*0+0.5*1;1*1"],
+			input);
+		assert_eq!(output, native_i16(&[2000, 3000, 0, 2000, 32767, 32767]));
+	}
+
+	#[test]
+	fn renders_get_dithered_when_asked_to() {
+		/* Half a step rounds to even, which is nothing, unless there is noise
+		 * to push it either way. */
+		let input = native_i16(&[1; 1000]);
+		let args = ["-c", "1", "-r", "8000", "-f", "s16", "--matrix", "0.5*0"];
+		assert!(rendered(&args, input.clone()).iter().all(|byte| *byte == 0));
+
+		let output = rendered(&[&args[..], &["--dither", "triangular"]].concat(), input);
+		let output = output.chunks_exact(2)
+			.map(|sample| i16::from_ne_bytes([sample[0], sample[1]]))
+			.collect::<Vec<_>>();
+		assert!(output.iter().all(|sample| *sample == 0 || *sample == 1));
+		assert!(output.contains(&0) && output.contains(&1));
+	}
+
+	#[test]
+	fn renders_leave_partial_frames_out() {
+		let input = f32s(&[0.25, -0.5, 1.0]);
+		let output = rendered(&["-c", "2", "-r", "8000", "-f", "f32le"], input);
+		assert_eq!(output, [0.25f32, -0.5].iter().flat_map(|sample| sample.to_ne_bytes()).collect::<Vec<_>>());
+	}
+
+	#[test]
+	fn interrupting_fades_out_when_asked_to() {
+		let args = ["-c", "1", "-r", "8000", "-f", "f32le"];
+		let input = || -> Box<dyn Read + Send> { Box::new(Cursor::new(f32s(&[0.5; 16000]))) };
+		let interrupt = Some(Duration::from_millis(200));
+
+		/* Without a ramp, the output gets cut off right where it is, leaving
+		 * at most a buffer that was let go of halfway through to silence. */
+		let (result, output) = played(&args, input(), None, interrupt);
+		assert!(result.is_ok());
+		let output = native_f32(&output);
+		let end = output.iter().position(|sample| *sample != 0.5).unwrap_or(output.len());
+		assert!(end > 0);
+		assert!(output[end..].iter().all(|sample| *sample == 0.0));
+
+		/* With one, it goes down a step every frame, starting with the
+		 * first buffer after the interruption, and is silent from there on. */
+		let (result, output) = played(&[&args[..], &["--ramp-on-pause"]].concat(), input(), None, interrupt);
+		assert!(result.is_ok());
+		let output = native_f32(&output);
+		let ramp = (8000 * RAMP_MS / 1000) as usize;
+		let start = output.iter().position(|sample| *sample != 0.5).unwrap() - 1;
+		assert_eq!(start % NULL_BUFFER_FRAMES, 0);
+		for (step, sample) in output[start..start + ramp].iter().enumerate() {
+			let expected = 0.5 * ((ramp - step) as f32 / ramp as f32);
+			assert!((sample - expected).abs() < 1e-6, "{} at step {}", sample, step);
+		}
+		assert!(output[start + ramp..].iter().all(|sample| *sample == 0.0));
+	}
+
+	#[test]
+	fn pausing_fades_out_and_resuming_fades_back_in() {
+		let args = ["-c", "1", "-r", "8000", "-f", "f32le", "--ramp-on-pause"];
+		let input: Box<dyn Read + Send> = Box::new(Cursor::new(f32s(&[0.5; 4000])));
+		let paused = Arc::new(AtomicBool::new(false));
+		let pauser = paused.clone();
+
+		let (result, output) = played_with(&args, input, None, move || {
+			std::thread::sleep(Duration::from_millis(100));
+			PAUSED.store(true, Ordering::Relaxed);
+			std::thread::sleep(Duration::from_millis(200));
+			pauser.store(true, Ordering::Relaxed);
+			PAUSED.store(false, Ordering::Relaxed);
+		});
+		assert!(result.is_ok());
+		assert!(paused.load(Ordering::Relaxed));
+		let output = native_f32(&output);
+		let ramp = (8000 * RAMP_MS / 1000) as usize;
+
+		/* The output goes down a step every frame, starting with the first
+		 * buffer after the pause, and is silent for the rest of that buffer. */
+		let down = output.iter().position(|sample| *sample != 0.5).unwrap() - 1;
+		assert_eq!(down % NULL_BUFFER_FRAMES, 0);
+		for (step, sample) in output[down..down + ramp].iter().enumerate() {
+			let expected = 0.5 * ((ramp - step) as f32 / ramp as f32);
+			assert!((sample - expected).abs() < 1e-6, "{} at step {} down", sample, step);
+		}
+
+		/* Once resumed, it goes back up a step every frame, from the start of
+		 * the first buffer played, and plays the rest of the input as it is. */
+		let silent = down + ramp;
+		let up = silent + output[silent..].iter().position(|sample| *sample != 0.0).unwrap();
+		assert_eq!(up % NULL_BUFFER_FRAMES, 0);
+		assert!(output[silent..up].iter().all(|sample| *sample == 0.0));
+		for (step, sample) in output[up..up + ramp].iter().enumerate() {
+			let expected = 0.5 * ((step + 1) as f32 / ramp as f32);
+			assert!((sample - expected).abs() < 1e-6, "{} at step {} up", sample, step);
+		}
+		let end = output[up + ramp..].iter()
+			.position(|sample| *sample != 0.5)
+			.map(|end| up + ramp + end)
+			.unwrap_or(output.len());
+		assert!(end > up + ramp);
+		assert!(output[end..].iter().all(|sample| *sample == 0.0));
+	}
+
+	#[test]
+	fn clipped_samples_get_reported() {
+		let path = std::env::temp_dir().join(format!("alplay-clips-{}.csv", std::process::id()));
+		let mut input = vec![0.25; 4000];
+		input[100] = 1.5;
+		input[3001] = -1.0;
+		let args = ["-c", "2", "-r", "8000", "-f", "f32le", "--clip-report", path.to_str().unwrap()];
+
+		let (result, _) = played(&args, Box::new(Cursor::new(f32s(&input))) as Box<dyn Read + Send>, None, None);
+		assert!(result.is_ok());
+		let report = std::fs::read_to_string(&path).unwrap();
+		std::fs::remove_file(&path).unwrap();
+		assert_eq!(report, "frame,channel,value\n50,0,1.5\n1500,1,-1\n");
+	}
+
+	#[test]
+	fn callbacks_get_their_timing_logged() {
+		let path = std::env::temp_dir().join(format!("alplay-timestamps-{}.csv", std::process::id()));
+		let input: Box<dyn Read + Send> = Box::new(Cursor::new(f32s(&[0.25; 4096])));
+		let args = ["-c", "1", "-r", "8000", "-f", "f32le", "--timestamps", path.to_str().unwrap()];
+
+		let (result, _) = played(&args, input, None, None);
+		assert!(result.is_ok());
+		let log = std::fs::read_to_string(&path).unwrap();
+		std::fs::remove_file(&path).unwrap();
+
+		let mut lines = log.lines();
+		assert_eq!(lines.next(), Some("callback,playback,latency,frames,duration"));
+		let lines = lines.map(|line| line.split(',').collect::<Vec<_>>()).collect::<Vec<_>>();
+		assert!(lines.len() >= 4096 / NULL_BUFFER_FRAMES);
+		for fields in &lines {
+			assert_eq!(fields[3], NULL_BUFFER_FRAMES.to_string());
+			assert_eq!(fields[4], format!("{:.6}", NULL_BUFFER_FRAMES as f64 / 8000.0));
+		}
+		assert_eq!(lines[0][0], "0.000000");
+	}
+
+	#[test]
+	fn tee_buffers_get_handed_back_to_be_reused() {
+		let path = std::env::temp_dir().join(format!("alplay-pool-{}.raw", std::process::id()));
+		let ((feed, pool), writer) = tee(&path, 16).unwrap();
+
+		let mut buffer = pool.recv().unwrap();
+		assert!(buffer.capacity() >= 16);
+		let address = buffer.as_ptr();
+		buffer.extend_from_slice(b"abcd");
+		feed.send(buffer).unwrap();
+
+		/* The rest of the pool comes out first, and the buffer that got
+		 * written out last of all. */
+		for _ in 1..TEE_BUFFERS {
+			assert!(pool.recv().unwrap().is_empty());
+		}
+		let recycled = pool.recv().unwrap();
+		assert_eq!(recycled.as_ptr(), address);
+
+		std::mem::drop(feed);
+		assert_eq!(writer.join().unwrap().unwrap(), 4);
+		assert_eq!(std::fs::read(&path).unwrap(), b"abcd");
+		std::fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn clips_the_report_cant_keep_up_with_get_dropped() {
+		let (feed, clips) = std::sync::mpsc::sync_channel(1);
+		let dropped = AtomicU64::new(0);
+		let mut clipped = 0;
+		let bytes = f32s(&[1.0, 0.0, -1.0, 2.0]);
+
+		let result = report_clips(&feed, &dropped, cpal::SampleFormat::F32, 2, 10, &mut clipped, &bytes);
+		assert!(matches!(result, Ok(true)));
+		assert_eq!(clipped, 3);
+		assert_eq!(dropped.load(Ordering::Relaxed), 2);
+
+		let clip = clips.try_recv().unwrap();
+		assert_eq!((clip.frame, clip.channel, clip.value), (10, 0, 1.0));
+
+		/* Once the report is gone, there's no point in going on. */
+		std::mem::drop(clips);
+		let result = report_clips(&feed, &dropped, cpal::SampleFormat::F32, 2, 12, &mut clipped, &bytes);
+		assert!(matches!(result, Err(TrySendError::Disconnected(_))));
+	}
+
+	/** An endless source of silence that takes longer to read a buffer's
+	 * worth of from than it takes to play it. */
+	struct Slow;
+	impl Read for Slow {
+		fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+			std::thread::sleep(Duration::from_millis(100));
+			let len = buf.len().min(NULL_BUFFER_FRAMES * 4);
+			buf[..len].fill(0);
+			Ok(len)
+		}
+	}
+
+	#[test]
+	fn too_many_underruns_abort_playback() {
+		/* A buffer of mono samples at 8kHz plays in 64ms. */
+		let args = ["-c", "1", "-r", "8000", "-f", "f32le", "--max-underruns", "2"];
+		let input: Box<dyn Read + Send> = Box::new(Slow);
+		let (result, _) = played(&args, input, None, None);
+
+		assert!(matches!(result, Err(Error::TooManyUnderruns { limit: 2 })), "{:?}", result.err());
+		assert_eq!(crate::exit_status(&result), Some(crate::EXIT_UNDERRUNS));
+		assert_eq!(crate::EXIT_UNDERRUNS, 4);
+	}
+
+	/** A source that gives a buffer's worth of silence and then goes quiet
+	 * for a second before ending. */
+	struct Stalling(bool);
+	impl Read for Stalling {
+		fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+			if std::mem::replace(&mut self.0, true) {
+				std::thread::sleep(Duration::from_secs(1));
+				return Ok(0)
+			}
+			let len = buf.len().min(NULL_BUFFER_FRAMES * 4);
+			buf[..len].fill(0);
+			Ok(len)
+		}
+	}
+
+	#[test]
+	fn inputs_that_go_quiet_time_out() {
+		let args = ["-c", "1", "-r", "8000", "-f", "f32le"];
+		let timeout = Duration::from_millis(200);
+		let activity = Activity::new();
+		let input: Box<dyn Read + Send> = Box::new(crate::src::Watch::new(Stalling(false), activity.clone()));
+		let (result, _) = played(&args, input, Some((activity, timeout)), None);
+
+		match &result {
+			Err(Error::IdleTimeout { idle }) => assert!(*idle >= timeout),
+			other => panic!("expected the input to time out, got {:?}", other)
+		}
+		assert_eq!(crate::exit_status(&result), Some(crate::EXIT_IDLE));
+	}
+
+	#[test]
+	fn delays_dont_count_as_going_quiet() {
+		/* Half a second of silence goes ahead of the input, which isn't
+		 * asked for anything until it's over. */
+		let args = ["-c", "1", "-r", "8000", "-f", "f32le"];
+		let activity = Activity::new();
+		let input = crate::src::Watch::new(Cursor::new(f32s(&[0.5; 800])), activity.clone());
+		let input: Box<dyn Read + Send> = Box::new(crate::src::Delay::new(
+			input,
+			4000,
+			1,
+			cpal::SampleFormat::F32,
+			Endianness::Little));
+		let (result, output) = played(&args, input, Some((activity, Duration::from_millis(200))), None);
+
+		assert!(result.is_ok(), "{:?}", result.err());
+		let output = native_f32(&output);
+		assert!(output[..4000].iter().all(|sample| *sample == 0.0));
+		assert!(output[4000..4800].iter().all(|sample| *sample == 0.5));
+	}
+
+	/** A source of two frames of mono samples that then turns out to be
+	 * truncated. */
+	struct Truncated(Cursor<Vec<u8>>);
+	impl Read for Truncated {
+		fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+			match self.0.read(buf)? {
+				0 => Err(std::io::Error::new(
+					std::io::ErrorKind::UnexpectedEof,
+					Error::TruncatedData { expected: 8, got: 4 })),
+				read => Ok(read)
+			}
+		}
+	}
+
+	#[test]
+	fn truncated_input_ends_unless_strict() {
+		let source = || Truncated(Cursor::new(native_i16(&[100, 200])));
+		let mut output = [0xff; 8];
+
+		let mut conversion = Conversion::with(cpal::SampleFormat::I16, Endianness::Native, 1);
+		let frames = conversion.fill(&mut source(), cpal::SampleFormat::I16, &mut output).unwrap();
+		assert_eq!(frames, 2);
+		assert_eq!(output, &native_i16(&[100, 200, 0, 0])[..]);
+
+		conversion.strict = true;
+		let what = conversion.fill(&mut source(), cpal::SampleFormat::I16, &mut output).err().unwrap();
+		assert!(matches!(Error::read_failed(what), Error::TruncatedData { expected: 8, got: 4 }));
+	}
+
+	#[test]
+	fn integer_input_gets_converted_for_float_outputs() {
+		/* Two frames of big endian stereo, and half of a third one. */
+		let input = [0x80, 0x00, 0x40, 0x00, 0x00, 0x00, 0xc0, 0x00, 0x12, 0x34];
+		let mut conversion = Conversion::with(cpal::SampleFormat::I16, Endianness::Big, 2);
+
+		let mut output = vec![0xff; 4 * 2 * 4];
+		let frames = conversion
+			.fill(&mut Cursor::new(input), cpal::SampleFormat::F32, &mut output)
+			.unwrap();
+		assert_eq!(frames, 2);
+
+		let samples = output.chunks_exact(4)
+			.map(|sample| f32::from_ne_bytes([sample[0], sample[1], sample[2], sample[3]]))
+			.collect::<Vec<_>>();
+		assert_eq!(samples, [-1.0, 0.5, 0.0, -0.5, 0.0, 0.0, 0.0, 0.0]);
+	}
+
+	#[test]
+	fn matching_input_is_only_brought_into_native_order() {
+		let input = 1234i16.to_be_bytes();
+		let mut conversion = Conversion::with(cpal::SampleFormat::I16, Endianness::Big, 1);
+
+		let mut output = [0; 2];
+		conversion
+			.fill(&mut Cursor::new(input), cpal::SampleFormat::I16, &mut output)
+			.unwrap();
+		assert_eq!(output, 1234i16.to_ne_bytes());
+	}
+
+	/** A source that says what it is, without having anything to read. */
+	struct Hinted {
+		format: Option<InputFormat>,
+		length: Option<u64>
+	}
+	impl Read for Hinted {
+		fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+			Ok(0)
+		}
+	}
+	impl Source for Hinted {
+		fn format_hint(&self) -> Option<InputFormat> {
+			self.format
+		}
+
+		fn len_hint(&self) -> Option<u64> {
+			self.length
+		}
+
+		fn description(&self) -> String {
+			"the hinted input".to_owned()
+		}
+	}
+
+	fn null_args(args: &[&str]) -> Arguments {
+		let matches = clap::App::new("alplay")
+			.args(&crate::device_args())
+			.args(&crate::input_args())
+			.args(&crate::playback_args())
+			.get_matches_from(["alplay", "--output", "null"].iter().chain(args));
+		Arguments::new(&matches).unwrap()
+	}
+
+	#[test]
+	fn the_banner_tells_what_the_source_knows() {
+		let args = null_args(&["-c", "2", "-r", "44100", "-f", "s16le"]);
+		let format = negotiate(&args).unwrap();
+
+		let unknown = Hinted { format: None, length: None };
+		assert_eq!(banner(&args, &format, &unknown),
+			"playing the hinted input to the null output within the default host\n\
+			playing as: I16LE, 2 channels, 44100Hz\n");
+
+		let known = Hinted { format: None, length: Some(1764) };
+		assert!(banner(&args, &format, &known)
+			.starts_with("playing the hinted input (1764 bytes) to the null output "));
+	}
+
+	#[test]
+	fn sources_at_another_rate_get_warned_about() {
+		let hinted = |sample_rate| Hinted {
+			format: Some(InputFormat {
+				channels: 2,
+				sample_rate,
+				sample_format: cpal::SampleFormat::I16,
+				endian: Endianness::Little
+			}),
+			length: None
+		};
+
+		let args = null_args(&["-r", "48000"]);
+		let format = negotiate(&args).unwrap();
+		assert_eq!(rate_mismatch(&args, &format, &hinted(48000)), None);
+		assert_eq!(rate_mismatch(&args, &format, &Hinted { format: None, length: None }), None);
+		assert_eq!(rate_mismatch(&args, &format, &hinted(44100)).unwrap(),
+			"the hinted input is at 44100Hz, but it's going to be played at 48000Hz");
+
+		/* Unless the rate was overridden on purpose. */
+		let args = null_args(&["--assume-rate", "48000"]);
+		assert_eq!(rate_mismatch(&args, &format, &hinted(44100)), None);
+	}
 }
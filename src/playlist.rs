@@ -0,0 +1,196 @@
+use crate::arg::Endianness;
+use crate::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/** A single item in a playlist. */
+#[derive(Debug, Clone)]
+pub struct Entry {
+	/** Path to the file to be played. */
+	pub path: PathBuf,
+	/** Duration of the item, as advertised by an `#EXTINF` directive. This is
+	 * only ever used for display purposes. */
+	pub duration: Option<Duration>,
+	/** Format of the item, as given by an `#EXTFORMAT` directive. */
+	pub format: Format,
+}
+
+/** Format of the samples in an item, as far as the playlist gives it. Whatever
+ * is left out is assumed to be in the format the whole list is played in. */
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct Format {
+	/** Number of interleaved channels. */
+	pub channels: Option<u16>,
+	/** Number of frames per second. */
+	pub sample_rate: Option<u32>,
+	/** Format and byte order of every sample. */
+	pub sample_format: Option<(cpal::SampleFormat, Endianness)>,
+}
+
+/** Loads the M3U playlist at the given path. */
+pub fn load(path: &Path) -> Result<Vec<Entry>, Error> {
+	let data = std::fs::read(path)
+		.map_err(|what| Error::PlaylistUnreadable {
+			path: path.to_owned(),
+			what
+		})?;
+	let text = String::from_utf8(data)
+		.map_err(|_| Error::MalformedPlaylist {
+			path: path.to_owned(),
+			line: None,
+			what: "the playlist is not valid UTF-8".to_owned()
+		})?;
+
+	/* Relative paths in the playlist are relative to the playlist itself. */
+	let base = path.parent().unwrap_or(Path::new(""));
+	parse(&text, base)
+		.map_err(|(line, what)| Error::MalformedPlaylist {
+			path: path.to_owned(),
+			line: Some(line),
+			what
+		})
+}
+
+/** Parses the contents of an M3U playlist, resolving relative paths against the
+ * given base directory. Errors come with the number of the line they were
+ * found in. */
+pub fn parse(text: &str, base: &Path) -> Result<Vec<Entry>, (usize, String)> {
+	/* Some editors like to start UTF-8 files with a byte order mark. */
+	let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+
+	let mut entries = Vec::new();
+	let mut duration = None;
+	let mut format = Format::default();
+	for (i, line) in text.lines().enumerate() {
+		let line = line.trim();
+		if line.is_empty() { continue }
+
+		if let Some(info) = line.strip_prefix("#EXTINF:") {
+			/* Extended M3U information, in the form of `#EXTINF:<secs>,<title>`.
+			 * Negative durations are used to indicate an unknown length. */
+			let value = info.split(',').next().unwrap_or("").trim();
+			let invalid = |what: &dyn std::fmt::Display| (
+				i + 1,
+				format!("invalid #EXTINF duration \"{}\": {}", value, what));
+			let secs = value.parse::<f64>().map_err(|what| invalid(&what))?;
+
+			duration = if secs < 0.0 {
+				None
+			} else {
+				Some(Duration::try_from_secs_f64(secs).map_err(|what| invalid(&what))?)
+			};
+			continue
+		}
+		if let Some(info) = line.strip_prefix("#EXTFORMAT:") {
+			/* Format of a raw item, in the form of a comma separated list of
+			 * `channels=<n>`, `rate=<hz>` and `format=<name>` pairs. */
+			format = parse_format(info).map_err(|what| (i + 1, what))?;
+			continue
+		}
+		if line.starts_with('#') { continue }
+
+		entries.push(Entry {
+			path: base.join(line),
+			duration: duration.take(),
+			format: std::mem::take(&mut format)
+		});
+	}
+
+	Ok(entries)
+}
+
+/** Parses the contents of an `#EXTFORMAT` directive. */
+fn parse_format(info: &str) -> Result<Format, String> {
+	let mut format = Format::default();
+	for pair in info.split(',') {
+		let pair = pair.trim();
+		if pair.is_empty() { continue }
+
+		let (key, value) = pair.split_once('=')
+			.ok_or_else(|| format!("invalid #EXTFORMAT entry \"{}\", expected a key=value pair", pair))?;
+		let (key, value) = (key.trim(), value.trim());
+		match key {
+			"channels" => format.channels = Some(u16::from_str_radix(value, 10)
+				.ok()
+				.filter(|channels| *channels > 0)
+				.ok_or_else(|| format!("invalid #EXTFORMAT channel count \"{}\"", value))?),
+			"rate" => format.sample_rate = Some(u32::from_str_radix(value, 10)
+				.ok()
+				.filter(|rate| *rate > 0)
+				.ok_or_else(|| format!("invalid #EXTFORMAT sample rate \"{}\"", value))?),
+			"format" => format.sample_format = crate::arg::parse_sample_format(value)
+				.map(|(format, suffix)| Some((format, suffix.unwrap_or(Endianness::Native))))
+				.map_err(|what| format!("invalid #EXTFORMAT sample format: {}", what))?,
+			key => return Err(format!("unknown #EXTFORMAT key \"{}\"", key))
+		}
+	}
+
+	Ok(format)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn crlf_and_bom_are_ignored() {
+		let text = "\u{feff}#EXTM3U\r\n#EXTINF:125,Some Title\r\nfirst.wav\r\n\r\nsub/second.raw\r\n";
+		let entries = parse(text, Path::new("/music")).unwrap();
+
+		assert_eq!(entries.len(), 2);
+		assert_eq!(entries[0].path, Path::new("/music/first.wav"));
+		assert_eq!(entries[0].duration, Some(Duration::from_secs(125)));
+		assert_eq!(entries[1].path, Path::new("/music/sub/second.raw"));
+		assert_eq!(entries[1].duration, None);
+	}
+
+	#[test]
+	fn absolute_paths_are_kept() {
+		let entries = parse("/elsewhere/item.wav\n", Path::new("/music")).unwrap();
+		assert_eq!(entries[0].path, Path::new("/elsewhere/item.wav"));
+	}
+
+	#[test]
+	fn negative_durations_are_unknown() {
+		let entries = parse("#EXTINF:-1,Stream\nitem.wav\n", Path::new("")).unwrap();
+		assert_eq!(entries[0].duration, None);
+	}
+
+	#[test]
+	fn malformed_durations_report_their_line() {
+		let error = parse("#EXTM3U\n\n#EXTINF:soon,Title\nitem.wav\n", Path::new("")).unwrap_err();
+		assert_eq!(error.0, 3);
+	}
+
+	#[test]
+	fn durations_too_long_to_hold_report_their_line() {
+		for secs in ["1e300", "inf", "NaN"] {
+			let text = format!("#EXTM3U\n#EXTINF:{},Title\nitem.wav\n", secs);
+			let error = parse(&text, Path::new("")).unwrap_err();
+			assert_eq!(error.0, 2, "{}", secs);
+			assert!(error.1.starts_with("invalid #EXTINF duration"), "{}", error.1);
+		}
+	}
+
+	#[test]
+	fn formats_apply_to_the_next_item() {
+		let text = "#EXTFORMAT:rate=44100, channels=1,format=s16be\nfirst.raw\nsecond.raw\n";
+		let entries = parse(text, Path::new("")).unwrap();
+
+		assert_eq!(entries[0].format, Format {
+			channels: Some(1),
+			sample_rate: Some(44100),
+			sample_format: Some((cpal::SampleFormat::I16, Endianness::Big))
+		});
+		assert_eq!(entries[1].format, Format::default());
+	}
+
+	#[test]
+	fn malformed_formats_report_their_line() {
+		for format in ["rate=fast", "channels=0", "format=s24", "depth=16", "44100"] {
+			let text = format!("item.raw\n#EXTFORMAT:{}\nitem.raw\n", format);
+			let error = parse(&text, Path::new("")).unwrap_err();
+			assert_eq!(error.0, 2, "{}", format);
+		}
+	}
+}
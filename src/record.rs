@@ -0,0 +1,399 @@
+use clap::ArgMatches;
+use crate::arg::{self, Endianness, InputFormat, SupportedRange};
+use crate::error::Error;
+use crate::wav::WavWriter;
+use cpal::traits::{DeviceTrait, StreamTrait};
+use std::io::{BufWriter, Seek, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, SyncSender, TrySendError};
+use std::time::Duration;
+
+/** Number of buffers the recorded samples get handed to the writer in. The
+ * callback drops whatever it records while all of them are in the writer's
+ * hands. */
+const BUFFERS: usize = 64;
+
+/** How much of the recording every buffer holds to begin with, in
+ * milliseconds. Callbacks bigger than this make the buffers grow. */
+const BUFFER_MS: usize = 100;
+
+/** How often the recording gets checked on for being over. */
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/** Records from the input device picked by the arguments into the WAV file
+ * given to the record subcommand, until the given duration is up or until
+ * interrupted, whichever comes first. */
+pub fn record(matches: &ArgMatches) -> Result<(), Error> {
+	/* The file is a required argument, which clap made sure of. */
+	let path = Path::new(matches.value_of(crate::ARG_RECORD_TO).unwrap());
+	let banner = !matches.is_present(crate::ARG_NO_BANNER);
+	let seconds = duration(matches)?;
+
+	let (host, host_pick) = arg::pick_host(matches)?;
+	let (device, _) = arg::pick_input_device(matches, &host, host_pick)?;
+
+	/* Whatever isn't asked for is recorded the way the device would rather
+	 * have it. */
+	let default = device.default_input_config()?;
+	let format = arg::sample_description(matches, InputFormat {
+		channels: default.channels(),
+		sample_rate: default.sample_rate().0,
+		sample_format: default.sample_format(),
+		endian: Endianness::Native
+	})?;
+	let supported = device.supported_input_configs()?
+		.map(|range| SupportedRange::from(&range))
+		.collect::<Vec<_>>();
+	let is_default = format.channels == default.channels()
+		&& format.sample_rate == default.sample_rate().0
+		&& format.sample_format == default.sample_format();
+	if !is_default && !supports(&supported, &format) {
+		return Err(Error::NoSuitableRecordConfig { format })
+	}
+
+	let error = |what| Error::Record {
+		path: path.to_owned(),
+		what
+	};
+	let file = std::fs::File::create(path).map_err(error)?;
+	let writer = WavWriter::new(BufWriter::new(file), stored(format)).map_err(error)?;
+
+	let frame = format.sample_format.sample_size() * usize::from(format.channels);
+	let limit = seconds.map(|seconds| {
+		(seconds * f64::from(format.sample_rate)).round() as u64 * frame as u64
+	});
+
+	/* The buffers are all made up front, and handed back once written, so
+	 * that the callback never has to allocate any. */
+	let capacity = frame * format.sample_rate as usize * BUFFER_MS / 1000;
+	let (recycle, pool) = std::sync::mpsc::sync_channel::<Vec<u8>>(BUFFERS);
+	for _ in 0..BUFFERS {
+		let _ = recycle.send(Vec::with_capacity(capacity));
+	}
+	let (feed, buffers) = std::sync::mpsc::sync_channel::<Vec<u8>>(BUFFERS);
+	let sample_format = format.sample_format;
+	let writer = std::thread::spawn(move || write(buffers, recycle, sample_format, writer, limit)
+		.map(|(_, written)| written));
+
+	let dropped = Arc::new(AtomicU64::new(0));
+	let failure = Arc::new(Mutex::new(None));
+	let stream = {
+		let dropped = dropped.clone();
+		let failure = failure.clone();
+		let mut feed = Some(feed);
+		device.build_input_stream_raw(
+			&cpal::StreamConfig {
+				channels: format.channels,
+				sample_rate: cpal::SampleRate(format.sample_rate),
+				buffer_size: cpal::BufferSize::Default
+			},
+			format.sample_format,
+			move |data, _| {
+				let bytes = data.bytes();
+				if let Some(sender) = &feed {
+					let sent = match pool.try_recv() {
+						Ok(mut buffer) => {
+							buffer.clear();
+							buffer.extend_from_slice(bytes);
+							sender.try_send(buffer)
+						},
+						Err(_) => Err(TrySendError::Full(Vec::new()))
+					};
+					match sent {
+						Ok(()) => {},
+						Err(TrySendError::Full(_)) => {
+							dropped.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+						},
+						/* The writer is done, either because the recording
+						 * is as long as it was asked to be or because it
+						 * gave up, which it says once the recording is over. */
+						Err(TrySendError::Disconnected(_)) => feed = None
+					}
+				}
+			},
+			move |what| {
+				failure.lock().unwrap().get_or_insert(Error::InputStreamFailed(what));
+			})
+			.map_err(Error::InputStream)?
+	};
+
+	if banner {
+		eprintln!("recording {} channels of {} at {}Hz to {}",
+			format.channels,
+			arg::sample_format_info(format.sample_format).name,
+			format.sample_rate,
+			path.display());
+	}
+
+	let recorded: Result<(), Error> = crate::play::interruptible(|| {
+		stream.play().map_err(Error::RecordStream)?;
+		while !crate::play::interrupted()
+			&& !writer.is_finished()
+			&& failure.lock().unwrap().is_none() {
+
+			std::thread::sleep(POLL_INTERVAL);
+		}
+		let _ = stream.pause();
+
+		Ok(())
+	});
+
+	/* Dropping the stream drops the callback along with its end of the
+	 * channel, which lets the writer finish the file up. */
+	std::mem::drop(stream);
+	let written = writer.join().unwrap().map_err(error)?;
+	recorded?;
+	if let Some(what) = failure.lock().unwrap().take() {
+		return Err(what)
+	}
+
+	let dropped = dropped.load(Ordering::Relaxed);
+	if dropped > 0 {
+		eprintln!("warning: recorded {} bytes to {}, but dropped {} bytes writing couldn't keep up with",
+			written,
+			path.display(),
+			dropped);
+	} else if banner {
+		eprintln!("recorded {} bytes to {}", written, path.display());
+	}
+
+	Ok(())
+}
+
+/** Parses how long to record for, if given, in seconds. */
+fn duration(matches: &ArgMatches) -> Result<Option<f64>, Error> {
+	matches.value_of(crate::ARG_DURATION)
+		.map(|value| value.trim().parse::<f64>()
+			.ok()
+			.filter(|seconds| seconds.is_finite() && *seconds > 0.0)
+			.ok_or_else(|| Error::MalformedRecordDuration {
+				value: value.to_owned()
+			}))
+		.transpose()
+}
+
+/** Whether any of the given configurations the device supports records in the
+ * given format. */
+fn supports(supported: &[SupportedRange], format: &InputFormat) -> bool {
+	supported.iter().any(|range| range.channels == format.channels
+		&& range.sample_format == format.sample_format
+		&& (range.min_sample_rate..=range.max_sample_rate).contains(&format.sample_rate))
+}
+
+/** Format the samples recorded in the given format get stored in. WAV files
+ * are little endian through and through, and have no unsigned 16 bit samples,
+ * which get stored as signed ones instead. */
+fn stored(format: InputFormat) -> InputFormat {
+	let sample_format = match format.sample_format {
+		cpal::SampleFormat::U16 => cpal::SampleFormat::I16,
+		sample_format => sample_format
+	};
+
+	InputFormat {
+		sample_format,
+		endian: Endianness::Little,
+		..format
+	}
+}
+
+/** Writes the samples in the buffers sent through the given channel, in the
+ * given format and in native byte order, out to the given WAV file, in the
+ * format they get [`stored`] in. Buffers get handed back through the given
+ * sender once written. Writing stops once the given number of bytes, if any,
+ * has been written, or once the channel is dropped, at which point the file
+ * gets finished. Gives the sink back, along with how many bytes of samples
+ * were written. */
+fn write<W>(
+	buffers: Receiver<Vec<u8>>,
+	recycle: SyncSender<Vec<u8>>,
+	sample_format: cpal::SampleFormat,
+	mut writer: WavWriter<W>,
+	limit: Option<u64>) -> std::io::Result<(W, u64)>
+	where W: Write + Seek {
+
+	use std::convert::TryFrom;
+
+	let mut written = 0u64;
+	let mut stored = Vec::new();
+	for buffer in buffers {
+		let left = limit.map(|limit| limit - written).unwrap_or(u64::MAX);
+		let take = usize::try_from(left).unwrap_or(usize::MAX).min(buffer.len());
+
+		stored.clear();
+		store(sample_format, &buffer[..take], &mut stored);
+		writer.write_all(&stored)?;
+		written += take as u64;
+
+		/* Only ever as many buffers as the pool holds are around, so there's
+		 * always room for this one. */
+		let _ = recycle.try_send(buffer);
+		if Some(written) == limit {
+			break
+		}
+	}
+	let sink = writer.finish()?;
+
+	Ok((sink, written))
+}
+
+/** Converts the given samples, in the given format and in native byte order,
+ * into the format they get [`stored`] in, appending them to the given
+ * buffer. */
+fn store(sample_format: cpal::SampleFormat, samples: &[u8], stored: &mut Vec<u8>) {
+	match sample_format {
+		cpal::SampleFormat::I16 => for sample in samples.chunks_exact(2) {
+			stored.extend_from_slice(&i16::from_ne_bytes([sample[0], sample[1]]).to_le_bytes());
+		},
+		cpal::SampleFormat::U16 => for sample in samples.chunks_exact(2) {
+			let sample = u16::from_ne_bytes([sample[0], sample[1]]) ^ 0x8000;
+			stored.extend_from_slice(&sample.to_le_bytes());
+		},
+		cpal::SampleFormat::F32 => for sample in samples.chunks_exact(4) {
+			let sample = f32::from_ne_bytes([sample[0], sample[1], sample[2], sample[3]]);
+			stored.extend_from_slice(&sample.to_le_bytes());
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Cursor;
+
+	fn matches(args: &[&str]) -> ArgMatches<'static> {
+		clap::App::new("alplay")
+			.args(&crate::device_args())
+			.args(&crate::record_args())
+			.get_matches_from(["alplay", "out.wav"].iter().chain(args))
+	}
+
+	/** Records the given buffers of samples in the given format, returning
+	 * the samples in the WAV file they get written to, along with their
+	 * format, how many bytes were said to be written, and how many buffers
+	 * got handed back. */
+	fn recorded(sample_format: cpal::SampleFormat, buffers: &[Vec<u8>], limit: Option<u64>)
+		-> (Vec<u8>, InputFormat, u64, usize) {
+
+		use std::io::{Read, SeekFrom};
+
+		let (feed, received) = std::sync::mpsc::sync_channel(BUFFERS);
+		let (recycle, pool) = std::sync::mpsc::sync_channel(BUFFERS);
+		for buffer in buffers {
+			feed.send(buffer.clone()).unwrap();
+		}
+		std::mem::drop(feed);
+
+		let format = InputFormat {
+			channels: 1,
+			sample_rate: 8000,
+			sample_format,
+			endian: Endianness::Native
+		};
+		let writer = WavWriter::new(Cursor::new(Vec::new()), stored(format)).unwrap();
+		let (file, written) = write(received, recycle, sample_format, writer, limit).unwrap();
+
+		/* Read it back the way detection does, past its magic number. */
+		let mut file = Cursor::new(file.into_inner());
+		file.set_position(crate::registry::MAGIC as u64);
+		let skip = |source: &mut Cursor<Vec<u8>>, count: u64| {
+			source.seek(SeekFrom::Current(count as i64)).map(|_| ())
+		};
+		let (mut reader, format, _) = crate::wav::open(file, skip, None).unwrap();
+		let mut samples = Vec::new();
+		reader.read_to_end(&mut samples).unwrap();
+
+		(samples, format, written, pool.try_iter().count())
+	}
+
+	fn native(samples: &[i16]) -> Vec<u8> {
+		samples.iter().flat_map(|sample| sample.to_ne_bytes().to_vec()).collect()
+	}
+
+	fn little(samples: &[i16]) -> Vec<u8> {
+		samples.iter().flat_map(|sample| sample.to_le_bytes().to_vec()).collect()
+	}
+
+	#[test]
+	fn buffers_get_written_out_and_handed_back() {
+		let buffers = [native(&[1, -2, 3]), native(&[-4, 5])];
+		let (samples, format, written, recycled) = recorded(cpal::SampleFormat::I16, &buffers, None);
+
+		assert_eq!(samples, little(&[1, -2, 3, -4, 5]));
+		assert_eq!(format.sample_format, cpal::SampleFormat::I16);
+		assert_eq!(format.sample_rate, 8000);
+		assert_eq!(written, 10);
+		assert_eq!(recycled, 2);
+	}
+
+	#[test]
+	fn recording_stops_once_long_enough() {
+		let buffers = [native(&[1, 2, 3]), native(&[4, 5, 6]), native(&[7, 8, 9])];
+		let (samples, _, written, _) = recorded(cpal::SampleFormat::I16, &buffers, Some(8));
+
+		assert_eq!(samples, little(&[1, 2, 3, 4]));
+		assert_eq!(written, 8);
+	}
+
+	#[test]
+	fn unsigned_recordings_get_stored_as_signed() {
+		let buffers = [[0x8000u16, 0, 0xffff].iter()
+			.flat_map(|sample| sample.to_ne_bytes().to_vec())
+			.collect::<Vec<_>>()];
+		let (samples, format, _, _) = recorded(cpal::SampleFormat::U16, &buffers, None);
+
+		assert_eq!(samples, little(&[0, i16::MIN, i16::MAX]));
+		assert_eq!(format.sample_format, cpal::SampleFormat::I16);
+	}
+
+	#[test]
+	fn durations_get_parsed() {
+		assert_eq!(duration(&matches(&[])).unwrap(), None);
+		assert_eq!(duration(&matches(&["--duration", "2.5"])).unwrap(), Some(2.5));
+		for value in &["0", "-1", "inf", "NaN", "soon"] {
+			let arg = format!("--duration={}", value);
+			assert!(matches!(
+				duration(&matches(&[&arg])),
+				Err(Error::MalformedRecordDuration { .. })));
+		}
+	}
+
+	#[test]
+	fn formats_get_checked_against_what_the_device_supports() {
+		let supported = [
+			SupportedRange {
+				channels: 2,
+				min_sample_rate: 44100,
+				max_sample_rate: 48000,
+				sample_format: cpal::SampleFormat::I16
+			}
+		];
+		let format = InputFormat {
+			channels: 2,
+			sample_rate: 48000,
+			sample_format: cpal::SampleFormat::I16,
+			endian: Endianness::Native
+		};
+
+		assert!(supports(&supported, &format));
+		assert!(!supports(&supported, &InputFormat { channels: 1, ..format }));
+		assert!(!supports(&supported, &InputFormat { sample_rate: 96000, ..format }));
+		assert!(!supports(&supported, &InputFormat { sample_format: cpal::SampleFormat::F32, ..format }));
+	}
+
+	#[test]
+	fn samples_get_stored_little_endian_and_signed() {
+		let mut stored = Vec::new();
+		store(cpal::SampleFormat::I16, &(-2i16).to_ne_bytes(), &mut stored);
+		store(cpal::SampleFormat::U16, &0x8001u16.to_ne_bytes(), &mut stored);
+		store(cpal::SampleFormat::U16, &0u16.to_ne_bytes(), &mut stored);
+		store(cpal::SampleFormat::F32, &0.5f32.to_ne_bytes(), &mut stored);
+
+		let mut expected = (-2i16).to_le_bytes().to_vec();
+		expected.extend(&1i16.to_le_bytes());
+		expected.extend(&i16::MIN.to_le_bytes());
+		expected.extend(&0.5f32.to_le_bytes());
+		assert_eq!(stored, expected);
+	}
+}
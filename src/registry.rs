@@ -0,0 +1,319 @@
+use crate::arg::{Arguments, InputFormat};
+use crate::detect::{Detected, Peeker, Seeker, Skip};
+use crate::error::Error;
+use crate::src::{LoopPoints, Source};
+use std::io::Read;
+
+/** Length of the start of an input containers get recognized by. */
+pub const MAGIC: usize = 12;
+
+/** Length of the start of an Ogg Vorbis stream needed to tell it apart from
+ * other kinds of Ogg streams: the header of the first page, with its single
+ * segment, followed by the packet type and the codec name. */
+const OGG_VORBIS_MAGIC: usize = 28 + 7;
+
+/** Function telling whether the given start of an input belongs to a
+ * container. It's shorter than [`MAGIC`] when the input is. */
+pub type Probe = fn(&[u8]) -> bool;
+
+/** Function reading the header of a container from the given source, which
+ * has yet to have anything read from it, returning a reader over its samples
+ * along with their format and the loop embedded in the container, if any. */
+pub type Constructor<R> = fn(Peeker<R>, Skip<R>, Option<Seeker<R>>) -> Opened;
+
+/** What a container turns out to hold, once it's been opened. */
+pub type Opened = Result<(Box<dyn Source>, InputFormat, Option<LoopPoints>), Error>;
+
+/** A container format, along with how to recognize it and how to read it. */
+pub struct Container<R> {
+	/** Name of the container format. */
+	pub name: &'static str,
+	pub probe: Probe,
+	pub open: Constructor<R>,
+}
+
+/** Every container format we know of, in the order they get tried in. Adding
+ * support for a new one is a matter of adding it here. */
+pub fn containers<R>() -> Vec<Container<R>>
+	where R: Read + Send + 'static {
+
+	#[cfg_attr(not(feature = "mp3"), allow(unused_mut))]
+	let mut containers = vec![
+		Container { name: "WAV", probe: is_wav, open: open_wav },
+		Container { name: "AIFF", probe: is_aiff, open: open_aiff },
+		Container { name: "AIFF-C", probe: is_aifc, open: open_aifc },
+		Container { name: "AU", probe: is_au, open: open_au },
+		Container { name: "FLAC", probe: is_flac, open: open_flac },
+		Container { name: "Ogg Vorbis", probe: is_ogg, open: open_vorbis },
+	];
+
+	/* Raw samples can look like the start of an MP3 stream, which is a risk
+	 * only worth taking when we can actually decode MP3. */
+	#[cfg(feature = "mp3")] {
+		containers.push(Container { name: "MP3", probe: crate::mp3::is_frame_header, open: open_mp3 });
+	}
+
+	containers
+}
+
+/** An input to be opened, which gets read differently depending on whether it
+ * can seek. */
+pub enum Input {
+	/** An input that can only be read through once. */
+	Stream(Box<dyn Read + Send>),
+	/** A file, which containers can find their way back through. */
+	File(std::fs::File),
+	/** A file that has been mapped into memory. */
+	#[cfg(feature = "mmap")]
+	Mapped(crate::mapped::MappedFile),
+}
+
+/** Opens the given input, checking whether it holds one of the containers we
+ * know of. If it does, its header is parsed and a reader over its samples is
+ * returned, along with their format. Otherwise, the input is returned as it
+ * is. */
+pub fn open(input: Input) -> Result<(Box<dyn Source>, Option<Detected>), Error> {
+	match input {
+		Input::Stream(stream) => {
+			let (reader, detected) = crate::detect::detect(stream)?;
+			Ok((Box::new(reader), detected))
+		},
+		Input::File(file) => crate::detect::detect_seekable(file),
+		#[cfg(feature = "mmap")]
+		Input::Mapped(map) => crate::detect::detect_seekable(map),
+	}
+}
+
+/** Same as [`open`], but the format of whatever container gets found is taken
+ * to be the format of the input from then on. */
+pub fn open_source(input: Input, args: &mut Arguments)
+	-> Result<(Box<dyn Source>, Option<Detected>), Error> {
+
+	let (reader, detected) = open(input)?;
+	if let Some(detected) = detected {
+		if args.banner() {
+			eprintln!("detected {}", detected);
+		}
+		args.apply_input_format(detected.format);
+	}
+
+	Ok((reader, detected))
+}
+
+fn is_wav(magic: &[u8]) -> bool {
+	magic.len() == MAGIC && &magic[0..4] == b"RIFF" && &magic[8..12] == b"WAVE"
+}
+
+fn is_aiff(magic: &[u8]) -> bool {
+	magic.len() == MAGIC && &magic[0..4] == b"FORM" && &magic[8..12] == b"AIFF"
+}
+
+fn is_aifc(magic: &[u8]) -> bool {
+	magic.len() == MAGIC && &magic[0..4] == b"FORM" && &magic[8..12] == b"AIFC"
+}
+
+fn is_au(magic: &[u8]) -> bool {
+	magic.len() == MAGIC && &magic[0..4] == b".snd"
+}
+
+fn is_flac(magic: &[u8]) -> bool {
+	magic.len() >= 4 && &magic[0..4] == b"fLaC"
+}
+
+/** Any Ogg stream gets taken for Ogg Vorbis, so that the others can be told
+ * apart from raw samples and refused. */
+fn is_ogg(magic: &[u8]) -> bool {
+	magic.len() >= 4 && &magic[0..4] == b"OggS"
+}
+
+/** Gives the source back right past the magic number of its container, which
+ * is all that has been peeked at. */
+fn past_magic<R>(mut peeker: Peeker<R>) -> R
+	where R: Read {
+
+	peeker.consume(MAGIC);
+	let (source, rest) = peeker.into_parts();
+	debug_assert!(rest.is_empty());
+
+	source
+}
+
+/** Peeks at the magic number of the container at the start of the source. */
+fn magic<R>(peeker: &mut Peeker<R>) -> Result<[u8; MAGIC], Error>
+	where R: Read {
+
+	let mut magic = [0; MAGIC];
+	let peeked = peeker.peek(MAGIC).map_err(Error::InputError)?;
+	magic[..peeked.len()].copy_from_slice(peeked);
+
+	Ok(magic)
+}
+
+fn open_wav<R>(peeker: Peeker<R>, skip: Skip<R>, seek: Option<Seeker<R>>)
+	-> Opened
+	where R: Read + Send + 'static {
+
+	crate::wav::open(past_magic(peeker), skip, seek)
+}
+
+fn open_aiff<R>(peeker: Peeker<R>, skip: Skip<R>, seek: Option<Seeker<R>>)
+	-> Opened
+	where R: Read + Send + 'static {
+
+	let (reader, format) = crate::aiff::open(past_magic(peeker), skip, seek, false)?;
+	Ok((reader, format, None))
+}
+
+fn open_aifc<R>(peeker: Peeker<R>, skip: Skip<R>, seek: Option<Seeker<R>>)
+	-> Opened
+	where R: Read + Send + 'static {
+
+	let (reader, format) = crate::aiff::open(past_magic(peeker), skip, seek, true)?;
+	Ok((reader, format, None))
+}
+
+fn open_au<R>(mut peeker: Peeker<R>, skip: Skip<R>, seek: Option<Seeker<R>>)
+	-> Opened
+	where R: Read + Send + 'static {
+
+	let mut fields = [0; 8];
+	fields.copy_from_slice(&magic(&mut peeker)?[4..12]);
+
+	let (reader, format) = crate::au::open(past_magic(peeker), skip, seek, fields)?;
+	Ok((reader, format, None))
+}
+
+/** The decoder wants to see the magic number for itself. */
+#[cfg(feature = "flac")]
+fn open_flac<R>(peeker: Peeker<R>, _: Skip<R>, _: Option<Seeker<R>>)
+	-> Opened
+	where R: Read + Send + 'static {
+
+	let (reader, format) = crate::flac::open(peeker)?;
+	Ok((Box::new(reader), format, None))
+}
+
+/** Without FLAC support, FLAC streams get refused, rather than played as
+ * noise. */
+#[cfg(not(feature = "flac"))]
+fn open_flac<R>(_: Peeker<R>, _: Skip<R>, _: Option<Seeker<R>>)
+	-> Opened {
+
+	Err(Error::ContainerNotBuilt { container: "FLAC", feature: "flac" })
+}
+
+/** The first page of a Vorbis stream holds nothing but its identification
+ * header, which comes right after the page header. */
+fn open_vorbis<R>(mut peeker: Peeker<R>, _: Skip<R>, _: Option<Seeker<R>>)
+	-> Opened
+	where R: Read + Send + 'static {
+
+	let vorbis = peeker.peek(OGG_VORBIS_MAGIC)
+		.map_err(Error::InputError)?
+		.ends_with(b"\x01vorbis");
+	if !vorbis {
+		return Err(Error::UnsupportedContainer("non-Vorbis Ogg"))
+	}
+
+	#[cfg(feature = "vorbis")] {
+		let (reader, format) = crate::vorbis::open(peeker)?;
+		Ok((Box::new(reader), format, None))
+	}
+	#[cfg(not(feature = "vorbis"))] {
+		Err(Error::ContainerNotBuilt { container: "Ogg Vorbis", feature: "vorbis" })
+	}
+}
+
+/** The decoder finds its own way to the first frame. */
+#[cfg(feature = "mp3")]
+fn open_mp3<R>(peeker: Peeker<R>, _: Skip<R>, _: Option<Seeker<R>>)
+	-> Opened
+	where R: Read + Send + 'static {
+
+	let (reader, format) = crate::mp3::open(peeker)?;
+	Ok((Box::new(reader), format, None))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Cursor;
+
+	fn opened(data: Vec<u8>) -> Result<(Vec<u8>, Option<Detected>), Error> {
+		let (mut reader, detected) = open(Input::Stream(Box::new(Cursor::new(data))))?;
+		let mut read = Vec::new();
+		reader.read_to_end(&mut read).unwrap();
+
+		Ok((read, detected))
+	}
+
+	#[test]
+	fn wav_magic_goes_to_the_wav_reader() {
+		let format = InputFormat {
+			channels: 2,
+			sample_rate: 44100,
+			sample_format: cpal::SampleFormat::I16,
+			endian: crate::arg::Endianness::Little
+		};
+		let mut writer = crate::wav::WavWriter::new(Cursor::new(Vec::new()), format).unwrap();
+		std::io::Write::write_all(&mut writer, &[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+		let file = writer.finish().unwrap().into_inner();
+
+		let (read, detected) = opened(file).unwrap();
+		let detected = detected.unwrap();
+		assert_eq!(detected.container, "WAV");
+
+		/* The general purpose decoder, when there is one, hands out samples
+		 * of its own making. */
+		if cfg!(not(feature = "symphonia")) {
+			assert_eq!(detected.format, format);
+			assert_eq!(read, [1, 2, 3, 4, 5, 6, 7, 8]);
+		}
+	}
+
+	#[test]
+	fn unknown_headers_are_taken_for_raw_samples() {
+		let data = (0..64).collect::<Vec<u8>>();
+
+		let (read, detected) = opened(data.clone()).unwrap();
+		assert!(detected.is_none());
+		assert_eq!(read, data);
+	}
+
+	#[cfg(not(any(feature = "flac", feature = "symphonia")))]
+	#[test]
+	fn containers_built_without_are_refused() {
+		let mut data = b"fLaC".to_vec();
+		data.extend(&[0; 60]);
+
+		let error = opened(data).err().unwrap();
+		assert!(matches!(error, Error::ContainerNotBuilt { container: "FLAC", feature: "flac" }));
+		assert!(error.to_string().contains("built without FLAC support"));
+	}
+
+	#[test]
+	fn detected_formats_get_played_as() {
+		let format = InputFormat {
+			channels: 3,
+			sample_rate: 22050,
+			sample_format: cpal::SampleFormat::I16,
+			endian: crate::arg::Endianness::Little
+		};
+		let mut writer = crate::wav::WavWriter::new(Cursor::new(Vec::new()), format).unwrap();
+		std::io::Write::write_all(&mut writer, &[0; 60]).unwrap();
+		let file = writer.finish().unwrap().into_inner();
+
+		let matches = clap::App::new("alplay")
+			.args(&crate::device_args())
+			.args(&crate::input_args())
+			.args(&crate::playback_args())
+			.get_matches_from(["alplay", "--output", "null", "--no-banner"]);
+		let mut args = Arguments::new(&matches).unwrap();
+		let before = crate::play::negotiate(&args).unwrap();
+		assert_ne!((before.channels(), before.sample_rate().0), (3, 22050));
+
+		open_source(Input::Stream(Box::new(Cursor::new(file))), &mut args).unwrap();
+		let after = crate::play::negotiate(&args).unwrap();
+		assert_eq!((after.channels(), after.sample_rate().0), (3, 22050));
+	}
+}
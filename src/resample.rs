@@ -0,0 +1,189 @@
+use crate::filter::LowPass;
+
+/** Order of the filter frames get run through before going down in rate,
+ * unless told otherwise. */
+pub const DEFAULT_ANTI_ALIAS_ORDER: usize = 8;
+
+/** Where the filter frames get run through before going down in rate cuts off,
+ * as a fraction of the Nyquist frequency of the new rate. */
+const ANTI_ALIAS_CUTOFF: f64 = 0.9;
+
+/** Converts decoded frames from one sample rate into another, by interpolating
+ * linearly between the frames coming in. Going down in rate, whatever is past
+ * the new Nyquist frequency gets filtered out beforehand, so that it doesn't
+ * alias into what can be heard. */
+pub struct Resampler {
+	/** Rate of the frames coming in. */
+	from: u32,
+	/** Rate of the frames going out. */
+	to: u32,
+	/** Number of interleaved channels in every frame. */
+	channels: usize,
+	/** Filter the frames get run through before going down in rate. */
+	anti_alias: Option<LowPass>,
+	/** Frames that have yet to be interpolated between. */
+	frames: Vec<f32>,
+	/** Position of the next frame to go out, in frames coming in, as a
+	 * multiple of one over the rate going out. */
+	position: u64,
+}
+impl Resampler {
+	/** Creates a new resampler for frames with the given number of channels. */
+	pub fn new(from: u32, to: u32, channels: u16) -> Self {
+		Self {
+			from,
+			to,
+			channels: usize::from(channels),
+			anti_alias: anti_alias_filter(DEFAULT_ANTI_ALIAS_ORDER, from, to, channels),
+			frames: Vec::new(),
+			position: 0
+		}
+	}
+
+	/** Filters frames with a low-pass filter of the given order before going
+	 * down in rate, rather than with the default one. An order of zero leaves
+	 * the filter out. */
+	pub fn anti_alias(mut self, order: usize) -> Self {
+		self.anti_alias = anti_alias_filter(order, self.from, self.to, self.channels as u16);
+		self
+	}
+
+	/** Hands a single frame coming in over to the resampler. */
+	pub fn push(&mut self, frame: &[f32]) {
+		let start = self.frames.len();
+		self.frames.extend(&frame[..self.channels]);
+		if let Some(filter) = &mut self.anti_alias {
+			filter.run(&mut self.frames[start..]);
+		}
+	}
+
+	/** Forgets every frame pushed so far, to start over from a clean state. */
+	pub fn reset(&mut self) {
+		self.frames.clear();
+		self.position = 0;
+		if let Some(filter) = &mut self.anti_alias {
+			filter.reset();
+		}
+	}
+
+	/** Puts out as many frames as can be interpolated from the ones pushed so
+	 * far. Until the input is over, the last of them is kept around, as the
+	 * frames going out after it are yet to be pushed. */
+	pub fn pull(&mut self, over: bool, output: &mut Vec<f32>) {
+		let channels = self.channels;
+		let count = self.frames.len() / channels;
+		let rate = u64::from(self.to);
+
+		loop {
+			let index = (self.position / rate) as usize;
+			let next = if index + 1 < count {
+				index + 1
+			} else if over && index < count {
+				index
+			} else {
+				break
+			};
+			let fraction = (self.position % rate) as f32 / rate as f32;
+
+			for channel in 0..channels {
+				let a = self.frames[index * channels + channel];
+				let b = self.frames[next * channels + channel];
+				output.push(a + (b - a) * fraction);
+			}
+			self.position += u64::from(self.from);
+		}
+
+		/* Let go of the frames that have been gone past. */
+		let done = usize::min((self.position / rate) as usize, count);
+		self.frames.drain(..done * channels);
+		self.position -= done as u64 * rate;
+	}
+}
+
+/** The filter of the given order that frames have to go through to go from one
+ * rate into the other, if any. It cuts off a little short of the Nyquist
+ * frequency of the new rate, for the filter to have rolled off by the time it
+ * gets there. */
+fn anti_alias_filter(order: usize, from: u32, to: u32, channels: u16) -> Option<LowPass> {
+	if order == 0 || to >= from { return None }
+
+	let nyquist = f64::from(to) / 2.0;
+	Some(LowPass::new(order, nyquist * ANTI_ALIAS_CUTOFF, from, channels))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn resample(from: u32, to: u32, frames: &[f32]) -> Vec<f32> {
+		resample_filtered(from, to, 0, frames)
+	}
+
+	fn resample_filtered(from: u32, to: u32, order: usize, frames: &[f32]) -> Vec<f32> {
+		let mut resampler = Resampler::new(from, to, 1).anti_alias(order);
+		let mut output = Vec::new();
+		for frame in frames {
+			resampler.push(&[*frame]);
+			resampler.pull(false, &mut output);
+		}
+		resampler.pull(true, &mut output);
+
+		output
+	}
+
+	#[test]
+	fn same_rate_passes_frames_through() {
+		let frames = [0.0, 0.5, -0.5, 0.25];
+		assert_eq!(resample(44100, 44100, &frames), frames);
+	}
+
+	#[test]
+	fn frames_in_between_get_interpolated() {
+		/* Every frame in between two others is halfway between them, and the
+		 * last one gets held for as long as the frames before it. */
+		assert_eq!(
+			resample(24000, 48000, &[0.0, 0.5, -0.5, 0.25]),
+			[0.0, 0.25, 0.5, 0.0, -0.5, -0.125, 0.25, 0.25]);
+		assert_eq!(resample(48000, 24000, &[0.0, 0.5, -0.5, 0.25]), [0.0, -0.5]);
+	}
+
+	#[test]
+	fn lengths_follow_the_ratio_of_the_rates() {
+		assert_eq!(resample(48000, 44100, &[0.0; 48000]).len(), 44100);
+		assert_eq!(resample(44100, 48000, &[0.0; 44100]).len(), 48000);
+	}
+
+	/** Level of a tone at the given frequency once it's gone from 48kHz down
+	 * to 16kHz, as a fraction of the level it went in at, leaving out the
+	 * start, where the filter is yet to settle. */
+	fn tone_level(frequency: f32, order: usize) -> f32 {
+		let tone = (0..48000)
+			.map(|i| 0.5 * (2.0 * std::f32::consts::PI * frequency * i as f32 / 48000.0).sin())
+			.collect::<Vec<_>>();
+		let output = resample_filtered(48000, 16000, order, &tone);
+
+		let samples = &output[1600..];
+		let rms = (samples.iter().map(|sample| sample * sample).sum::<f32>() / samples.len() as f32).sqrt();
+		rms / (0.5 * std::f32::consts::FRAC_1_SQRT_2)
+	}
+
+	#[test]
+	fn what_would_alias_gets_filtered_out_going_down_in_rate() {
+		/* 12kHz is past the 8kHz a 16kHz rate can hold, and would otherwise
+		 * come out as a 4kHz tone at full level. */
+		assert!(tone_level(12000.0, 0) > 0.9);
+		assert!(tone_level(12000.0, DEFAULT_ANTI_ALIAS_ORDER) < 0.05);
+		assert!(tone_level(12000.0, 2) > tone_level(12000.0, 4));
+
+		/* What the new rate can hold goes through as it was. */
+		let level = tone_level(1000.0, DEFAULT_ANTI_ALIAS_ORDER);
+		assert!((level - 1.0).abs() < 0.05, "{}", level);
+	}
+
+	#[test]
+	fn going_up_in_rate_is_left_unfiltered() {
+		assert!(Resampler::new(16000, 48000, 1).anti_alias.is_none());
+		assert!(Resampler::new(48000, 48000, 1).anti_alias.is_none());
+		assert!(Resampler::new(48000, 16000, 1).anti_alias(0).anti_alias.is_none());
+	}
+}
@@ -0,0 +1,212 @@
+use crate::arg::{Endianness, parse_endianness, parse_sample_format, reconcile_endianness};
+use crate::error::Error;
+use std::path::Path;
+
+/** Parameters of a raw input, as described by a sidecar file kept next to it.
+ * Whatever the file leaves out is left for the command line and the defaults
+ * to decide. */
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Sidecar {
+	/** Number of interleaved channels. */
+	pub channels: Option<u16>,
+	/** Number of frames per second. */
+	pub sample_rate: Option<u32>,
+	/** Format of every sample. */
+	pub sample_format: Option<cpal::SampleFormat>,
+	/** Byte order of every sample, be it given on its own or as a suffix of
+	 * the sample format. */
+	pub endian: Option<Endianness>,
+}
+
+/** Loads the sidecar file at the given path. Files ending in `.json`, or whose
+ * contents start with a brace, are read as a JSON object. Anything else is
+ * read as `key=value` lines, where blank lines and lines starting with `#` are
+ * skipped. Either way, the keys are `channels`, `rate`, `format` and `endian`,
+ * with any others being ignored, so that descriptors carrying more than this
+ * can be used as they are. */
+pub fn load(path: &Path) -> Result<Sidecar, Error> {
+	let text = std::fs::read_to_string(path)
+		.map_err(|what| Error::SidecarRead {
+			path: path.to_owned(),
+			what
+		})?;
+	let malformed = |what: String| Error::MalformedSidecar {
+		path: path.to_owned(),
+		what
+	};
+
+	let json = path.extension().map(|extension| extension == "json").unwrap_or(false)
+		|| text.trim_start().starts_with('{');
+	let fields = if json {
+		json_fields(path, &text)?
+	} else {
+		let mut fields = Vec::new();
+		for (index, line) in text.lines().enumerate() {
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') { continue }
+
+			match line.find('=') {
+				Some(split) => fields.push((
+					line[..split].trim().to_owned(),
+					line[split + 1..].trim().to_owned())),
+				None => return Err(malformed(format!(
+					"line {} is not a key=value pair",
+					index + 1)))
+			}
+		}
+		fields
+	};
+
+	let mut sidecar = Sidecar::default();
+	let mut suffix = None;
+	for (key, value) in fields {
+		let number = |what: &str| malformed(format!(
+			"{} should be a whole number, not \"{}\"",
+			what,
+			value));
+
+		match key.as_str() {
+			"channels" => {
+				let channels = u16::from_str_radix(&value, 10).map_err(|_| number("channels"))?;
+				sidecar.channels = Some(channels);
+			},
+			"rate" | "sample_rate" => {
+				let rate = u32::from_str_radix(&value, 10).map_err(|_| number("rate"))?;
+				sidecar.sample_rate = Some(rate);
+			},
+			"format" | "sample_format" => {
+				let (format, endian) = parse_sample_format(&value)
+					.map_err(|what| malformed(what.to_string()))?;
+				sidecar.sample_format = Some(format);
+				suffix = endian;
+			},
+			"endian" | "endianness" => {
+				let endian = parse_endianness(&value)
+					.map_err(|what| malformed(what.to_string()))?;
+				sidecar.endian = Some(endian);
+			},
+			_ => {}
+		}
+	}
+	sidecar.endian = reconcile_endianness(suffix, sidecar.endian)
+		.map_err(|what| malformed(what.to_string()))?;
+
+	Ok(sidecar)
+}
+
+/** Reads the fields of the JSON object in the given text, with their values
+ * turned into the strings they would have been in a `key=value` file. */
+#[cfg(feature = "json")]
+fn json_fields(path: &Path, text: &str) -> Result<Vec<(String, String)>, Error> {
+	let malformed = |what: String| Error::MalformedSidecar {
+		path: path.to_owned(),
+		what
+	};
+
+	let value = serde_json::from_str::<serde_json::Value>(text)
+		.map_err(|what| malformed(what.to_string()))?;
+	let object = value.as_object()
+		.ok_or_else(|| malformed("expected a JSON object".to_owned()))?;
+
+	Ok(object.iter()
+		.map(|(key, value)| match value {
+			serde_json::Value::String(value) => (key.clone(), value.clone()),
+			value => (key.clone(), value.to_string())
+		})
+		.collect())
+}
+
+/** Reads the fields of the JSON object in the given text, with their values
+ * turned into the strings they would have been in a `key=value` file. */
+#[cfg(not(feature = "json"))]
+fn json_fields(path: &Path, _: &str) -> Result<Vec<(String, String)>, Error> {
+	Err(Error::SidecarJsonUnsupported {
+		path: path.to_owned()
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::path::PathBuf;
+
+	/** Writes the given sidecar out to a file of its own, with the given
+	 * extension, and loads it back. */
+	fn written(name: &str, extension: &str, text: &str) -> Result<Sidecar, Error> {
+		let path = std::env::temp_dir().join(format!(
+			"alplay-{}-{}.{}",
+			name,
+			std::process::id(),
+			extension));
+		std::fs::write(&path, text).unwrap();
+		let sidecar = load(&path);
+		std::fs::remove_file(&path).unwrap();
+
+		sidecar
+	}
+
+	fn malformed(result: Result<Sidecar, Error>) -> bool {
+		matches!(result, Err(Error::MalformedSidecar { .. }))
+	}
+
+	#[test]
+	fn key_value_lines_are_read() {
+		let text = "# lab capture\n\nchannels = 4\nsample_rate=48000\nformat=f32\nendian=big\nprobe=ch3\n";
+		let sidecar = written("lines", "meta", text).unwrap();
+		assert_eq!(sidecar.channels, Some(4));
+		assert_eq!(sidecar.sample_rate, Some(48000));
+		assert_eq!(sidecar.sample_format, Some(cpal::SampleFormat::F32));
+		assert_eq!(sidecar.endian, Some(Endianness::Big));
+	}
+
+	#[test]
+	fn missing_keys_are_left_unset() {
+		let sidecar = written("partial", "meta", "rate=8000\n").unwrap();
+		assert_eq!(sidecar.channels, None);
+		assert_eq!(sidecar.sample_rate, Some(8000));
+		assert_eq!(sidecar.sample_format, None);
+		assert_eq!(sidecar.endian, None);
+	}
+
+	#[test]
+	fn format_suffixes_give_the_byte_order() {
+		let sidecar = written("suffix", "meta", "format=s16le\n").unwrap();
+		assert_eq!(sidecar.sample_format, Some(cpal::SampleFormat::I16));
+		assert_eq!(sidecar.endian, Some(Endianness::Little));
+
+		assert!(malformed(written("clash", "meta", "format=s16le\nendian=big\n")));
+	}
+
+	#[test]
+	fn malformed_sidecars_are_refused() {
+		assert!(malformed(written("pair", "meta", "channels 2\n")));
+		assert!(malformed(written("channels", "meta", "channels=two\n")));
+		assert!(malformed(written("rate", "meta", "rate=-1\n")));
+		assert!(malformed(written("format", "meta", "format=s24\n")));
+
+		let missing = PathBuf::from("/nonexistent/alplay.meta");
+		assert!(matches!(load(&missing), Err(Error::SidecarRead { .. })));
+	}
+
+	#[cfg(feature = "json")]
+	#[test]
+	fn json_objects_are_read() {
+		let text = r#"{"channels": 2, "rate": 44100, "format": "u16", "endian": "little", "gain": 1.5}"#;
+		let sidecar = written("object", "json", text).unwrap();
+		assert_eq!(sidecar.channels, Some(2));
+		assert_eq!(sidecar.sample_rate, Some(44100));
+		assert_eq!(sidecar.sample_format, Some(cpal::SampleFormat::U16));
+		assert_eq!(sidecar.endian, Some(Endianness::Little));
+
+		assert!(malformed(written("array", "json", "[1, 2]")));
+		assert!(malformed(written("broken", "json", "{\"channels\": ")));
+	}
+
+	#[cfg(not(feature = "json"))]
+	#[test]
+	fn json_needs_its_feature() {
+		assert!(matches!(
+			written("object", "json", "{\"channels\": 2}"),
+			Err(Error::SidecarJsonUnsupported { .. })));
+	}
+}
@@ -0,0 +1,140 @@
+use crate::error::Error;
+use crate::net::{Listener, ListenerSource};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/** Path of the socket we're listening on, if any, which gets removed when the
+ * process exits. */
+static SOCKET: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/** Removes the socket we've been listening on. Runs when the process exits,
+ * be it by returning from main or through [`std::process::exit`]. */
+extern "C" fn remove_socket() {
+	if let Ok(mut socket) = SOCKET.lock() {
+		if let Some(path) = socket.take() {
+			let _ = std::fs::remove_file(path);
+		}
+	}
+}
+
+impl Listener for UnixListener {
+	type Connection = UnixStream;
+
+	fn accept(&self) -> std::io::Result<(UnixStream, String)> {
+		let (connection, peer) = UnixListener::accept(self)?;
+		connection.set_nonblocking(false)?;
+
+		/* Clients rarely bother binding their end to a path. */
+		let peer = match peer.as_pathname() {
+			Some(path) => path.display().to_string(),
+			None => "an unnamed socket".to_owned()
+		};
+		Ok((connection, peer))
+	}
+
+	fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+		UnixListener::set_nonblocking(self, nonblocking)
+	}
+}
+
+/** Creates a socket at the given path and waits for someone to connect to it,
+ * for up to the given amount of time, if any. The socket is removed once the
+ * process exits. A file that's already at the path is never replaced, as there
+ * is no telling whether it's a stale socket or something else entirely. */
+pub fn listen(path: &Path, timeout: Option<Duration>, accept_loop: bool, banner: bool)
+	-> Result<ListenerSource<UnixListener>, Error> {
+
+	if std::fs::symlink_metadata(path).is_ok() {
+		return Err(Error::SocketExists {
+			path: path.to_owned()
+		})
+	}
+
+	let error = |what| Error::Socket {
+		path: path.to_owned(),
+		what
+	};
+	let listener = UnixListener::bind(path).map_err(error)?;
+
+	*SOCKET.lock().unwrap() = Some(path.to_owned());
+	if unsafe { libc::atexit(remove_socket) } != 0 {
+		eprintln!("warning: {} will not be removed on exit", path.display());
+	}
+
+	if banner {
+		eprintln!("listening on {}", path.display());
+	}
+	ListenerSource::new(listener, timeout, accept_loop, banner)
+		.map_err(error)?
+		.ok_or(Error::AcceptTimeout {
+			address: path.display().to_string()
+		})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::{Read, Write};
+
+	/** A path in the temporary directory nothing is at yet. */
+	fn path(name: &str) -> PathBuf {
+		let path = std::env::temp_dir().join(format!("alplay-{}-{}.sock", name, std::process::id()));
+		let _ = std::fs::remove_file(&path);
+		path
+	}
+
+	#[test]
+	fn connections_get_read_one_after_the_other() {
+		let path = path("socket-loop");
+		let sender = {
+			let path = path.clone();
+			std::thread::spawn(move || {
+				for payload in [&b"abc"[..], &b"def"[..]] {
+					/* The socket only shows up once it's being listened on. */
+					let mut connection = loop {
+						match UnixStream::connect(&path) {
+							Ok(connection) => break connection,
+							Err(_) => std::thread::sleep(Duration::from_millis(10))
+						}
+					};
+					connection.write_all(payload).unwrap();
+				}
+			})
+		};
+
+		let mut source = listen(&path, Some(Duration::from_secs(5)), true, false).unwrap();
+		let mut data = [0; 6];
+		source.read_exact(&mut data).unwrap();
+		assert_eq!(&data, b"abcdef");
+		sender.join().unwrap();
+
+		let _ = std::fs::remove_file(&path);
+	}
+
+	#[test]
+	fn files_in_the_way_are_left_alone() {
+		let path = path("socket-exists");
+		std::fs::write(&path, b"not a socket").unwrap();
+
+		match listen(&path, Some(Duration::from_millis(50)), false, false) {
+			Err(Error::SocketExists { path: existing }) => assert_eq!(existing, path),
+			other => panic!("expected the path to be taken, got {:?}", other.err())
+		}
+		assert_eq!(std::fs::read(&path).unwrap(), b"not a socket");
+
+		let _ = std::fs::remove_file(&path);
+	}
+
+	#[test]
+	fn nobody_connecting_times_out() {
+		let path = path("socket-timeout");
+		match listen(&path, Some(Duration::from_millis(50)), false, false) {
+			Err(Error::AcceptTimeout { .. }) => {},
+			other => panic!("expected the wait to time out, got {:?}", other.err())
+		}
+
+		let _ = std::fs::remove_file(&path);
+	}
+}
@@ -1,14 +1,40 @@
-use std::io::{Read, Error};
+use std::io::{Read, Seek, SeekFrom, Write, Error};
 use std::thread::JoinHandle;
 use std::sync::{Mutex, Arc, Condvar};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::RecvError;
-use std::borrow::Borrow;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{SyncSender, TrySendError};
 use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use crate::arg::{Endianness, InputFormat};
+use crate::convert::{Dither, Quantizer};
+use crate::resample::Resampler;
+
+/** Parameters for keeping the data handed out by a [`Skipper`] aligned to
+ * whole frames. */
+struct Align {
+	/** Size of a single frame, in bytes. */
+	frame: usize,
+	/** How often the alignment gets checked. */
+	interval: Duration,
+	/** When the alignment was last checked. */
+	last: Instant,
+}
+
+/** Default size of the chunks a [`Skipper`] reads its source in. */
+const SKIPPER_CHUNK: usize = 16 * 1024;
+
+/** How long dropping a [`Skipper`] waits for its thread to wrap up. */
+const SKIPPER_GRACE: Duration = Duration::from_millis(500);
 
 pub struct Skipper<R>{
 	thread: Option<JoinHandle<()>>,
 	slider: Arc<Mutex<VecDeque<u8>>>,
+	/** Total number of bytes taken in from the source. Only ever changed with
+	 * the lock on the slider held, so that the two agree. */
+	received: Arc<AtomicU64>,
+	/** Total number of bytes handed out so far. */
+	handed: u64,
+	align: Option<Align>,
 	stop:   Arc<AtomicBool>,
 	done:   Arc<AtomicBool>,
 	cond:   Arc<(Mutex<bool>, Condvar)>,
@@ -18,9 +44,21 @@ impl<R> Skipper<R>
 	where R: Read + Send + 'static {
 
 	pub fn new_with_capacity(source: R, capacity: usize) -> Self {
+		Self::new_with_chunk(source, capacity, SKIPPER_CHUNK)
+	}
+
+	/** Same as [`Skipper::new_with_capacity`], but reading from the source in
+	 * chunks of the given size. Larger chunks mean the buffer gets locked less
+	 * often, at the cost of data arriving in bigger bursts. */
+	pub fn new_with_chunk(source: R, capacity: usize, chunk: usize) -> Self {
+		let chunk = chunk.max(1);
+
 		let slider0 = Arc::new(Mutex::new(VecDeque::with_capacity(capacity)));
 		let slider1 = slider0.clone();
 
+		let received0 = Arc::new(AtomicU64::new(0));
+		let received1 = received0.clone();
+
 		let stop0 = Arc::new(AtomicBool::new(false));
 		let stop1 = stop0.clone();
 
@@ -33,15 +71,20 @@ impl<R> Skipper<R>
 		let thread = std::thread::spawn(
 			move || Self::handle(
 				slider1,
+				received1,
 				source,
 				stop1,
 				done1,
 				capacity,
+				chunk,
 				cond1)
 		);
 
 		Self {
 			slider: slider0,
+			received: received0,
+			handed: 0,
+			align: None,
 			stop: stop0,
 			done: done0,
 			cond: cond0,
@@ -50,113 +93,2573 @@ impl<R> Skipper<R>
 		}
 	}
 
+	/** Periodically checks whether the data being handed out still lines up
+	 * with the frames of the input, and realigns it if it doesn't, by leaving
+	 * out the bytes of the frame it's caught in the middle of. Reads from the
+	 * source need not come in whole frames, and neither does the data dropped
+	 * when playback falls behind, so this keeps channels from getting swapped
+	 * around for good. Every correction may cause a short audible glitch. */
+	pub fn resync(mut self, frame: usize, interval: Duration) -> Self {
+		self.align = Some(Align {
+			frame,
+			interval,
+			last: Instant::now()
+		});
+		self
+	}
+
+	/* These are all plain settings, passed along by the thread that gets
+	 * spawned to run this. */
+	#[allow(clippy::too_many_arguments)]
 	fn handle(
 		slider: Arc<Mutex<VecDeque<u8>>>,
+		received: Arc<AtomicU64>,
 		mut source: R,
 		stop: Arc<AtomicBool>,
 		done: Arc<AtomicBool>,
 		cap:  usize,
+		chunk: usize,
 		cond: Arc<(Mutex<bool>, Condvar)>) {
 
+		let mut buffer = vec![0; chunk];
 		while !stop.load(Ordering::Relaxed) {
-			let mut buffer = [0; 1024];
-			let read = source.read(&mut buffer[..]).unwrap();
+			let read = match source.read(&mut buffer[..]) {
+				Ok(read) => read,
+				Err(what) if what.kind() == std::io::ErrorKind::Interrupted => continue,
+				Err(what) => {
+					eprintln!("warning: could not read the input, ending it here: {}", what);
+					0
+				}
+			};
 			if read == 0 {
 				/* End of file. */
-				done.store(true, Ordering::Relaxed);
 				break;
 			}
 
+			/* Only the most recent `cap` bytes are kept. A single read may be
+			 * larger than the whole buffer on its own, in which case both all
+			 * of the buffered data and the start of the read get dropped. */
+			let fresh = &buffer[read - usize::min(read, cap)..read];
+
 			let mut edit = slider.lock().unwrap();
-			if edit.len() + read > cap {
+			if edit.len() + fresh.len() > cap {
 				let len = edit.len();
-				edit.drain(.. len + read - cap);
+				edit.drain(.. len + fresh.len() - cap);
 			}
-			edit.extend(&buffer[..read]);
+			edit.extend(fresh);
+			received.fetch_add(read as u64, Ordering::Relaxed);
 
 			std::mem::drop(edit);
 
-			/* Now we have to wait for the  */
+			/* Let the reader know there's data, should it be waiting for it. */
 			let mut data = cond.0.lock().unwrap();
 			*data = true;
 
 			cond.1.notify_all();
 		}
 
+		/* Wake up whoever might be waiting for data that isn't coming. */
 		done.store(true, Ordering::Relaxed);
+		*cond.0.lock().unwrap() = true;
+		cond.1.notify_all();
 	}
 }
 impl<R> Drop for Skipper<R> {
 	fn drop(&mut self) {
 		self.stop.store(true, Ordering::Relaxed);
-		self.thread
-			.take()
-			.unwrap()
-			.join()
-			.unwrap();
+		let thread = match self.thread.take() {
+			Some(thread) => thread,
+			None => return
+		};
+
+		/* The thread only ever notices it's been asked to stop in between
+		 * reads, and nothing can cut a read short, as blocking reads from pipes,
+		 * terminals and sockets can't be cancelled from another thread. One
+		 * that's stuck waiting on a source that's gone quiet is left behind,
+		 * rather than holding up the way out, and drops the source once its
+		 * read comes back. */
+		let deadline = Instant::now() + SKIPPER_GRACE;
+		while !self.done.load(Ordering::Relaxed) && Instant::now() < deadline {
+			std::thread::sleep(Duration::from_millis(1));
+		}
+		if self.done.load(Ordering::Relaxed) {
+			let _ = thread.join();
+		}
+	}
+}
+/** Data gets dropped whenever playback falls behind, so there's no telling
+ * how much of it is left, nor any going back. */
+impl<R> Source for Skipper<R>
+	where R: Read + Send {
+
+	fn description(&self) -> String {
+		"the input, synced externally".to_owned()
 	}
 }
 impl<R> Read for Skipper<R>
 	where R: Read {
 
 	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-		if self.done.load(Ordering::Relaxed) {
-			/* End of file. */
-			return Ok(0);
-		}
-
-		let lock = self.slider.lock().unwrap();
-		let mut lock = if lock.len() == 0 {
-			/* We have no data to draw from, we're gonna have to wait for more.
-			 *
-			 * In order to do that we drop the lock on the buffer we are holding
-			 * so that the reader thread can do work, then we wait for either a
-			 * notification that some data is ready or the knowledge that the
-			 * thread has stopped. */
+		if buf.is_empty() { return Ok(0) }
+
+		/* Wait for either some data or the end of the input. Data only ever
+		 * gets added with the lock on the slider held, before the condition
+		 * gets locked to notify us, so checking for it with the condition
+		 * locked can't miss a notification. */
+		let mut cond = self.cond.0.lock().unwrap();
+		let mut lock = loop {
+			let lock = self.slider.lock().unwrap();
+			if !lock.is_empty() { break lock }
+			if self.done.load(Ordering::Relaxed) {
+				/* End of file. */
+				return Ok(0)
+			}
 			std::mem::drop(lock);
 
-			/* Wait for some data to present itself. */
-			let mut cond = self.cond.0.lock().unwrap();
 			*cond = false;
-
 			while !*cond {
 				cond = self.cond.1.wait(cond).unwrap();
 			}
-			std::mem::drop(cond);
+		};
+		std::mem::drop(cond);
 
-			/* Now that we aren't waiting on further progress from the reader
-			 * thread we can reacquire the lock we let go of earlier. */
-			self.slider.lock().unwrap()
-		} else {
-			/* Don't change the lock. */
-			lock
+		/* Check whether we're still in alignment with the input, which is
+		 * lost whenever the bytes dropped to make room for newer data don't
+		 * add up to whole frames. */
+		if let Some(align) = &mut self.align {
+			if align.last.elapsed() >= align.interval {
+				let start = self.received.load(Ordering::Relaxed) - lock.len() as u64;
+				let frame = align.frame as u64;
+				let skew = ((start % frame + frame - self.handed % frame) % frame) as usize;
+				if skew != 0 {
+					eprintln!("warning: the input went out of frame alignment, realigning by {} bytes", skew);
+					let skew = usize::min(skew, lock.len());
+					lock.drain(..skew);
+				}
+
+				align.last = Instant::now();
+			}
+		}
+
+		/* Hand the oldest data we have out, and let go of it. */
+		let len = usize::min(buf.len(), lock.len());
+		for (target, source) in buf.iter_mut().zip(lock.drain(..len)) {
+			*target = source;
+		}
+		self.handed += len as u64;
+
+		Ok(len)
+	}
+}
+
+
+/** A reader over samples, which may be able to tell what they are and to go
+ * back to their start without having to be opened again. Everything beyond
+ * reading is optional, and readers that know nothing about themselves are
+ * sources all the same. */
+pub trait Source: Read + Send {
+	/** Goes back to the start of the samples, if possible. Returns whether
+	 * it did. Readers that can't do it leave themselves untouched. */
+	fn rewind(&mut self) -> std::io::Result<bool> {
+		Ok(false)
+	}
+
+	/** Moves forward by the given number of bytes without reading them, if
+	 * possible. Returns whether it did. Readers that can't do it leave
+	 * themselves untouched, and have to be read through instead. */
+	fn seek_forward(&mut self, _bytes: u64) -> std::io::Result<bool> {
+		Ok(false)
+	}
+
+	/** Format of the samples, if the reader knows it. */
+	fn format_hint(&self) -> Option<InputFormat> {
+		None
+	}
+
+	/** Number of bytes left to be read, if the reader knows it. */
+	fn len_hint(&self) -> Option<u64> {
+		None
+	}
+
+	/** What the samples are being read from, for the user's benefit. */
+	fn description(&self) -> String {
+		"the input".to_owned()
+	}
+}
+impl Source for Box<dyn Read + Send> {}
+impl Source for Box<dyn Source> {
+	fn rewind(&mut self) -> std::io::Result<bool> {
+		(**self).rewind()
+	}
+
+	fn seek_forward(&mut self, bytes: u64) -> std::io::Result<bool> {
+		(**self).seek_forward(bytes)
+	}
+
+	fn format_hint(&self) -> Option<InputFormat> {
+		(**self).format_hint()
+	}
+
+	fn len_hint(&self) -> Option<u64> {
+		(**self).len_hint()
+	}
+
+	fn description(&self) -> String {
+		(**self).description()
+	}
+}
+impl Source for std::fs::File {
+	fn rewind(&mut self) -> std::io::Result<bool> {
+		self.seek(SeekFrom::Start(0)).map(|_| true)
+	}
+
+	fn seek_forward(&mut self, bytes: u64) -> std::io::Result<bool> {
+		if bytes > i64::MAX as u64 { return Ok(false) }
+		self.seek(SeekFrom::Current(bytes as i64)).map(|_| true)
+	}
+
+	fn len_hint(&self) -> Option<u64> {
+		let length = self.metadata().ok()?.len();
+		let position = (&*self).stream_position().ok()?;
+		Some(length.saturating_sub(position))
+	}
+}
+impl Source for std::io::Stdin {
+	fn description(&self) -> String {
+		"the standard input".to_owned()
+	}
+}
+
+/** Function that opens a single item of a [`ConcatSource`]. */
+pub type Opener = Box<dyn FnMut() -> std::io::Result<Box<dyn Source>> + Send>;
+
+/** What a [`ConcatSource`] does once an item or the whole list is over. */
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Repeat {
+	/** Play every item once and stop at the end of the list. */
+	None,
+	/** Start over from the top once the end of the list is reached. */
+	All,
+	/** Play the same item over and over. */
+	One,
+}
+
+/** Parameters for the crossfade between consecutive items of a
+ * [`ConcatSource`]. */
+#[derive(Debug, Copy, Clone)]
+pub struct Crossfade {
+	/** Length of the overlap between two items, in bytes. */
+	pub length: usize,
+	/** Size of a single frame, in bytes. */
+	pub frame: usize,
+	/** Format of the samples being played. */
+	pub format: cpal::SampleFormat,
+	/** Endianness of the samples being played. */
+	pub endian: Endianness,
+}
+
+/** A source that plays a list of items back to back, as if they were a single,
+ * continuous stream of data. */
+pub struct ConcatSource {
+	/** Names and openers of all the items in the list. */
+	items: Vec<(String, Opener)>,
+	/** Order in which the items get played. */
+	order: Vec<usize>,
+	/** Position in the play order of the item we are going to open next. */
+	next: usize,
+	/** Reader for the item currently being played, if any. */
+	current: Option<Box<dyn Source>>,
+	/** Whether the current item has produced any data so far. */
+	fed: bool,
+	/** Whether any item has produced any data since the list was started. */
+	productive: bool,
+	/** Whether failing to open an item should abort playback, rather than just
+	 * skipping over to the next item in the list. */
+	strict: bool,
+	/** What to do once an item or the list is over. */
+	repeat: Repeat,
+	/** Whether to announce every item as it starts playing. */
+	progress: bool,
+	/** State of the random number generator used for shuffling, if the list
+	 * is to be played in a random order. */
+	shuffle: Option<u64>,
+	/** Crossfade between items, if any. */
+	fade: Option<(Crossfade, Quantizer)>,
+	/** Data read ahead from the current item, which will be faded out if the
+	 * item turns out to end within it. */
+	tail: VecDeque<u8>,
+	/** Data that's ready to be handed out. */
+	out: VecDeque<u8>,
+}
+impl ConcatSource {
+	/** Creates a new source that will play the given items in order. */
+	pub fn new(items: Vec<(String, Opener)>, strict: bool) -> Self {
+		Self {
+			order: (0..items.len()).collect(),
+			items,
+			next: 0,
+			current: None,
+			fed: false,
+			productive: false,
+			strict,
+			repeat: Repeat::None,
+			progress: true,
+			shuffle: None,
+			fade: None,
+			tail: VecDeque::new(),
+			out: VecDeque::new()
+		}
+	}
+
+	/** Overlaps the end of every item with the start of the next one. */
+	pub fn crossfade(mut self, fade: Crossfade) -> Self {
+		if fade.length >= fade.frame && fade.frame > 0 {
+			self.fade = Some((fade, Quantizer::new(Dither::None)));
+		}
+		self
+	}
+
+	/** Sets what happens once an item or the whole list is over. */
+	pub fn repeat(mut self, repeat: Repeat) -> Self {
+		self.repeat = repeat;
+		self
+	}
+
+	/** Sets whether every item gets announced as it starts playing. */
+	pub fn progress(mut self, progress: bool) -> Self {
+		self.progress = progress;
+		self
+	}
+
+	/** Plays the items in a random order, derived from the given seed. When
+	 * the whole list gets repeated, it is shuffled again every time. */
+	pub fn shuffle(mut self, seed: u64) -> Self {
+		self.shuffle = Some(seed);
+		self.reshuffle();
+		self
+	}
+
+	/** Shuffles the play order, if shuffling is enabled. */
+	fn reshuffle(&mut self) {
+		let state = match self.shuffle.as_mut() {
+			Some(state) => state,
+			None => return
+		};
+
+		/* Fisher-Yates over a SplitMix64 generator. Both are simple enough and
+		 * fully deterministic for a given seed. */
+		for i in (1..self.order.len()).rev() {
+			*state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+			let mut z = *state;
+			z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+			z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+			z ^= z >> 31;
+
+			let j = (z % (i as u64 + 1)) as usize;
+			self.order.swap(i, j);
+		}
+	}
+
+	/** Opens the next item in the list that can be opened, skipping over the
+	 * ones that can't, unless we're in strict mode. Returns whether there was
+	 * an item left to be opened. */
+	fn advance(&mut self) -> std::io::Result<bool> {
+		self.current = None;
+		self.fed = false;
+
+		let count = self.items.len();
+		loop {
+			if self.next >= count {
+				/* Only start over if the last pass actually played something,
+				 * otherwise we would be spinning over a list of broken items
+				 * forever. */
+				if self.repeat != Repeat::All || !self.productive {
+					return Ok(false)
+				}
+
+				self.productive = false;
+				self.next = 0;
+				self.reshuffle();
+			}
+
+			let position = self.next;
+			self.next += 1;
+
+			let (name, open) = &mut self.items[self.order[position]];
+			match open() {
+				Ok(reader) => {
+					if self.progress {
+						eprintln!("{}/{}: {}", position + 1, count, name);
+					}
+					self.current = Some(reader);
+
+					return Ok(true)
+				},
+				Err(what) => if self.strict {
+					return Err(Error::new(
+						what.kind(),
+						format!("could not open {}: {}", name, what)))
+				} else {
+					eprintln!("warning: skipping {}: {}", name, what);
+				}
+			}
+		}
+	}
+}
+impl ConcatSource {
+	/** Reads from the current item, keeping track of whether it's producing
+	 * any data. */
+	fn read_current(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		/* An item that got cut short is just over, unless we're in strict
+		 * mode, like one that can't be opened. */
+		let read = match self.current.as_mut().unwrap().read(buf) {
+			Ok(read) => read,
+			Err(what) if crate::error::Error::is_truncation(&what) && !self.strict => {
+				eprintln!("warning: {}", what);
+				0
+			},
+			Err(what) => return Err(what)
 		};
+		if read > 0 {
+			self.fed = true;
+			self.productive = true;
+		}
+
+		Ok(read)
+	}
+
+	/** Closes the current item, which just reached its end. */
+	fn finish_current(&mut self) -> std::io::Result<()> {
+		/* When repeating the item, have it start over, unless it's empty, in
+		 * which case repeating it is pointless. Going back to the start of the
+		 * item is seamless, and keeps playing the same file even if it gets
+		 * replaced in the meantime, so it's preferred over opening it again. */
+		if self.repeat == Repeat::One && self.fed {
+			if self.current.as_mut().unwrap().rewind()? {
+				self.fed = false;
+				return Ok(())
+			}
+			self.next -= 1;
+		}
+		self.current = None;
+
+		Ok(())
+	}
+
+	/** Reads from the list, crossfading between items. */
+	fn read_crossfaded(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		let fade = self.fade.as_ref().unwrap().0;
+
+		while self.out.is_empty() {
+			if self.current.is_none() && !self.advance()? {
+				/* We've run out of items, so whatever is left of the last one
+				 * gets played as it is. */
+				self.out.extend(self.tail.drain(..));
+				break
+			}
+
+			/* Keep the last `length` bytes of the item read ahead, so that we
+			 * have them at hand if the item ends. */
+			let mut chunk = [0; 4096];
+			let read = self.read_current(&mut chunk)?;
+			if read > 0 {
+				self.tail.extend(&chunk[..read]);
+				if self.tail.len() > fade.length {
+					let excess = self.tail.len() - fade.length;
+					self.out.extend(self.tail.drain(..excess));
+				}
+				continue
+			}
+			self.finish_current()?;
+
+			/* The item is over. Start the next one and read as much of it as
+			 * we have left of this one. */
+			if self.current.is_none() && !self.advance()? {
+				self.out.extend(self.tail.drain(..));
+				break
+			}
+
+			let mut head = vec![0; self.tail.len()];
+			let mut filled = 0;
+			while filled < head.len() {
+				let read = self.read_current(&mut head[filled..])?;
+				if read == 0 { break }
+				filled += read;
+			}
+			head.truncate(filled);
+
+			/* Items shorter than the crossfade just get a shorter fade. */
+			let overlap = usize::min(head.len(), self.tail.len());
+			let overlap = overlap - overlap % fade.frame;
+
+			let solo = self.tail.len() - overlap;
+			self.out.extend(self.tail.drain(..solo));
+
+			let outgoing = self.tail.drain(..).collect::<Vec<_>>();
+			let mut mixed = vec![0; overlap];
+			let frames = overlap / fade.frame;
+			let size = fade.format.sample_size();
+			let quantizer = &mut self.fade.as_mut().unwrap().1;
+			for frame in 0..frames {
+				/* Complementary linear ramps. */
+				let gain = (frame as f32 + 0.5) / frames as f32;
+
+				let start = frame * fade.frame;
+				for offset in (start..start + fade.frame).step_by(size) {
+					let a = crate::convert::decode(fade.format, fade.endian, &outgoing[offset..]);
+					let b = crate::convert::decode(fade.format, fade.endian, &head[offset..]);
+					crate::convert::encode(
+						quantizer,
+						fade.format,
+						fade.endian,
+						a * (1.0 - gain) + b * gain,
+						&mut mixed[offset..]);
+				}
+			}
+			self.out.extend(&mixed);
+
+			/* Whatever is left of the head belongs to the new item. */
+			self.tail.extend(&head[overlap..]);
+		}
+
+		let len = usize::min(buf.len(), self.out.len());
+		for (target, source) in buf.iter_mut().zip(self.out.drain(..len)) {
+			*target = source;
+		}
+
+		Ok(len)
+	}
+}
+impl Read for ConcatSource {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		if buf.is_empty() { return Ok(0) }
+		if self.fade.is_some() { return self.read_crossfaded(buf) }
+
+		loop {
+			if self.current.is_none() && !self.advance()? {
+				/* We've run out of items. */
+				return Ok(0)
+			}
+
+			let read = self.read_current(buf)?;
+			if read > 0 { return Ok(read) }
+
+			/* This item is over, move on to the next one. */
+			self.finish_current()?;
+		}
+	}
+}
+
+/** A source that converts samples in one format into another, so that an item
+ * of a list can be played along with items in a different format. Surround
+ * gets downmixed into stereo and anything else into mono through a mix matrix.
+ * Otherwise, mono gets spread over every channel going out, other channels are
+ * kept in order, with the ones left over dropped and the ones missing silent.
+ * The sample rate is converted by a [`Resampler`]. */
+pub struct Conform<R> {
+	source: R,
+	/** Format of the samples coming in. */
+	from: InputFormat,
+	/** Format of the samples going out. */
+	to: InputFormat,
+	/** Matrix the channels coming in get mixed down through, if they do. */
+	matrix: Option<crate::mix::Matrix>,
+	resampler: Resampler,
+	quantizer: Quantizer,
+	/** Bytes of a frame that has only been partially read. */
+	input: Vec<u8>,
+	/** Whether the source is over. */
+	over: bool,
+	/** Converted frames that have yet to be handed out. */
+	output: Vec<u8>,
+	/** How much of the converted frames has been handed out already. */
+	offset: usize,
+}
+impl<R> Conform<R> {
+	/** Creates a new source converting samples in the given format into the
+	 * other given format. */
+	pub fn new(source: R, from: InputFormat, to: InputFormat) -> Self {
+		Self {
+			source,
+			from,
+			to,
+			matrix: conform_matrix(from.channels, to.channels, false),
+			resampler: Resampler::new(from.sample_rate, to.sample_rate, to.channels),
+			quantizer: Quantizer::new(Dither::None),
+			input: Vec::new(),
+			over: false,
+			output: Vec::new(),
+			offset: 0
+		}
+	}
+
+	/** Filters frames with a low-pass filter of the given order before going
+	 * down in rate, rather than with the default one. An order of zero leaves
+	 * the filter out. */
+	pub fn anti_alias(mut self, order: usize) -> Self {
+		self.resampler = self.resampler.anti_alias(order);
+		self
+	}
+
+	/** Folds the LFE into both sides when surround gets downmixed into stereo,
+	 * rather than leaving it out. */
+	pub fn keep_lfe(mut self, lfe: bool) -> Self {
+		self.matrix = conform_matrix(self.from.channels, self.to.channels, lfe);
+		self
+	}
+
+	/** Decodes all the whole frames that have been read so far and hands them
+	 * over to the resampler. */
+	fn decode(&mut self) {
+		let size = self.from.sample_format.sample_size();
+		let frame = size * usize::from(self.from.channels);
+		let whole = self.input.len() - self.input.len() % frame;
+
+		let mut decoded = Vec::with_capacity(usize::from(self.from.channels));
+		let mut mixed = Vec::with_capacity(usize::from(self.to.channels));
+		for frame in self.input[..whole].chunks_exact(frame) {
+			decoded.clear();
+			decoded.extend(frame.chunks_exact(size).map(|sample| crate::convert::decode(
+				self.from.sample_format,
+				self.from.endian,
+				sample)));
+
+			mixed.clear();
+			match &self.matrix {
+				Some(matrix) => matrix.mix_decoded(&decoded, &mut mixed),
+				None => for channel in 0..usize::from(self.to.channels) {
+					let channel = if self.from.channels == 1 { 0 } else { channel };
+					mixed.push(decoded.get(channel).copied().unwrap_or(0.0));
+				}
+			}
+			self.resampler.push(&mixed);
+		}
+		self.input.drain(..whole);
+	}
+
+	/** Encodes as many frames as the resampler can put out. */
+	fn encode(&mut self) {
+		let mut frames = Vec::new();
+		self.resampler.pull(self.over, &mut frames);
+
+		let size = self.to.sample_format.sample_size();
+		for sample in frames {
+			let start = self.output.len();
+			self.output.resize(start + size, 0);
+			crate::convert::encode(
+				&mut self.quantizer,
+				self.to.sample_format,
+				self.to.endian,
+				sample,
+				&mut self.output[start..]);
+		}
+	}
+}
+/** The matrix channels have to be mixed down through going from one number
+ * of them into the other, if they do. */
+fn conform_matrix(inputs: u16, outputs: u16, lfe: bool) -> Option<crate::mix::Matrix> {
+	match crate::mix::Matrix::surround(inputs, outputs, lfe) {
+		Some(matrix) => Some(matrix),
+		None if outputs == 1 && inputs > 1 => Some(crate::mix::Matrix::average(inputs)),
+		None => None
+	}
+}
+impl<R> Source for Conform<R>
+	where R: Source {
+
+	fn rewind(&mut self) -> std::io::Result<bool> {
+		if !self.source.rewind()? { return Ok(false) }
+
+		self.resampler.reset();
+		self.input.clear();
+		self.over = false;
+		self.output.clear();
+		self.offset = 0;
+
+		Ok(true)
+	}
+
+	fn format_hint(&self) -> Option<InputFormat> {
+		Some(self.to)
+	}
+
+	fn description(&self) -> String {
+		self.source.description()
+	}
+}
+impl<R> Read for Conform<R>
+	where R: Read {
+
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		if buf.is_empty() { return Ok(0) }
+		while self.offset == self.output.len() {
+			self.output.clear();
+			self.offset = 0;
+			if self.over { return Ok(0) }
+
+			/* Read about as many frames as the caller wants. */
+			let frame = self.from.sample_format.sample_size() * usize::from(self.from.channels);
+			let out = self.to.sample_format.sample_size() * usize::from(self.to.channels);
+			let frames = usize::max(buf.len() / out, 1);
+			let mut chunk = vec![0; frames * frame];
+			let read = self.source.read(&mut chunk)?;
+			if read == 0 {
+				self.over = true;
+			} else {
+				self.input.extend(&chunk[..read]);
+				self.decode();
+			}
+
+			self.encode();
+		}
+
+		let len = usize::min(buf.len(), self.output.len() - self.offset);
+		buf[..len].copy_from_slice(&self.output[self.offset..self.offset + len]);
+		self.offset += len;
+
+		Ok(len)
+	}
+}
+
+/** A source that forwards every byte read from it to a given sink, such that
+ * the data can be passed along to someone else while it gets played.
+ *
+ * Writes happen on a separate thread, so that a slow sink can't stall the
+ * playback. If the sink can't keep up, the data it couldn't take in time gets
+ * dropped instead. */
+pub struct Tee<R> {
+	source: R,
+	/** Channel through which data gets to the writer thread. This is `None` if
+	 * the sink has been closed. */
+	sender: Option<SyncSender<Vec<u8>>>,
+	thread: Option<JoinHandle<()>>,
+	/** Number of bytes that had to be dropped because the sink was full. */
+	dropped: usize,
+}
+impl<R> Tee<R> {
+	/** Creates a new source forwarding data read from the given source into
+	 * the given sink, holding at most `capacity` pending reads in memory. */
+	pub fn new<W>(source: R, mut sink: W, capacity: usize) -> Self
+		where W: Write + Send + 'static {
+
+		let (sender, receiver) = std::sync::mpsc::sync_channel::<Vec<u8>>(capacity);
+		let thread = std::thread::spawn(move || {
+			for chunk in receiver {
+				if sink.write_all(&chunk).is_err() { break }
+			}
+			let _ = sink.flush();
+		});
+
+		Self {
+			source,
+			sender: Some(sender),
+			thread: Some(thread),
+			dropped: 0
+		}
+	}
+}
+impl<R> Read for Tee<R>
+	where R: Read {
+
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		let read = self.source.read(buf)?;
+		if read == 0 { return Ok(0) }
+
+		if let Some(sender) = self.sender.as_ref() {
+			match sender.try_send(buf[..read].to_vec()) {
+				Ok(_) => {},
+				Err(TrySendError::Full(_)) =>
+					self.dropped += read,
+				Err(TrySendError::Disconnected(_)) => {
+					/* The sink has been closed. Keep on playing. */
+					self.sender = None;
+				}
+			}
+		}
+
+		Ok(read)
+	}
+}
+impl<R> Drop for Tee<R> {
+	fn drop(&mut self) {
+		/* Let the writer thread drain whatever is left and finish. */
+		self.sender = None;
+		if let Some(thread) = self.thread.take() {
+			let _ = thread.join();
+		}
+
+		if self.dropped > 0 {
+			eprintln!("warning: {} bytes could not be forwarded in time and were dropped",
+				self.dropped);
+		}
+	}
+}
+
+/** A source that turns frames with one number of channels into frames with
+ * another. Channels both sides have in common are copied over as they are. A
+ * single input channel goes to every output channel, other input channels the
+ * output doesn't have are left out, and output channels with nothing to go in
+ * them get silence. */
+pub struct Remap<R> {
+	source: R,
+	/** Number of channels coming in. */
+	inputs: usize,
+	/** Number of channels going out. */
+	outputs: usize,
+	/** Size of a single sample, in bytes. */
+	size: usize,
+	/** Format of the samples. */
+	format: cpal::SampleFormat,
+	/** Byte order of the samples. */
+	endian: Endianness,
+	/** A single silent sample. */
+	silence: Vec<u8>,
+	/** Matrix the frames get mixed through, rather than having their channels
+	 * copied over one by one. */
+	downmix: Option<crate::mix::Matrix>,
+	/** Quantizer integer samples mixed through the matrix go through. */
+	quantizer: Quantizer,
+	/** Bytes of a frame that has only been partially read. */
+	input: Vec<u8>,
+	/** Remapped frames that have yet to be handed out. */
+	output: Vec<u8>,
+	/** How much of the remapped frames has been handed out already. */
+	offset: usize,
+}
+impl<R> Remap<R> {
+	/** Creates a new source remapping samples in the given format. */
+	pub fn new(
+		source: R,
+		inputs: u16,
+		outputs: u16,
+		format: cpal::SampleFormat,
+		endian: Endianness) -> Self {
 
-		/* Copy the data over. */
-		let mut copied = 0;
-		let (a, b) = lock.as_slices();
+		let mut silence = vec![0; format.sample_size()];
+		crate::convert::silence(format, endian, &mut silence);
 
-		let len = usize::min(buf.len(), b.len());
-		let off_t = buf.len() - len;
-		let off_s = b.len() - len;
-		if len > 0 {
-			buf[off_t..].copy_from_slice(&b[off_s..]);
+		Self {
+			source,
+			inputs: usize::from(inputs),
+			outputs: usize::from(outputs),
+			size: format.sample_size(),
+			format,
+			endian,
+			silence,
+			downmix: None,
+			quantizer: Quantizer::new(Dither::None),
+			input: Vec::new(),
+			output: Vec::new(),
+			offset: 0
 		}
+	}
+
+	/** Mixes frames through the given matrix, which must take in and put out
+	 * as many channels as this source does. */
+	pub fn downmix(mut self, matrix: crate::mix::Matrix) -> Self {
+		self.downmix = Some(matrix);
+		self
+	}
+
+	/** Applies the given dither to integer samples mixed through the matrix. */
+	pub fn dither(mut self, dither: Dither) -> Self {
+		self.quantizer = Quantizer::new(dither);
+		self
+	}
+}
+impl<R> Read for Remap<R>
+	where R: Read {
+
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		if buf.is_empty() { return Ok(0) }
+		while self.offset == self.output.len() {
+			self.output.clear();
+			self.offset = 0;
+
+			/* Read about as many frames as the caller wants. */
+			let frames = usize::max(buf.len() / (self.size * self.outputs), 1);
+			let mut chunk = vec![0; frames * self.size * self.inputs];
+			let read = self.source.read(&mut chunk)?;
+			if read == 0 { return Ok(0) }
 
-		copied += len;
-		if copied == b.len() {
-			let len = usize::min(buf.len() - copied, a.len());
-			let off_t = buf.len() - copied - len;
-			let off_s = a.len() - len;
+			self.input.extend(&chunk[..read]);
+			let frame = self.size * self.inputs;
+			let whole = self.input.len() - self.input.len() % frame;
+			for frame in self.input[..whole].chunks_exact(frame) {
+				if let Some(matrix) = &self.downmix {
+					matrix.mix(&mut self.quantizer, self.format, self.endian, frame, &mut self.output);
+					continue
+				}
 
-			if len > 0 {
-				buf[off_t..off_t + len].copy_from_slice(&a[off_s..]);
+				for channel in 0..self.outputs {
+					let sample = if self.inputs == 1 {
+						&frame[..self.size]
+					} else if channel < self.inputs {
+						&frame[channel * self.size..(channel + 1) * self.size]
+					} else {
+						&self.silence[..]
+					};
+					self.output.extend(sample);
+				}
 			}
+			self.input.drain(..whole);
+		}
+
+		let len = usize::min(buf.len(), self.output.len() - self.offset);
+		buf[..len].copy_from_slice(&self.output[self.offset..self.offset + len]);
+		self.offset += len;
+
+		Ok(len)
+	}
+}
+
+/** A source that unpacks raw samples laid out as given into native single
+ * precision samples. */
+pub struct Unpack<R> {
+	source: R,
+	packing: crate::arg::Packing,
+	/** Bytes of a sample that has only been partially read. */
+	input: Vec<u8>,
+	/** Unpacked samples that have yet to be handed out. */
+	output: Vec<u8>,
+	/** How much of the unpacked samples has been handed out already. */
+	offset: usize,
+}
+impl<R> Unpack<R> {
+	pub fn new(source: R, packing: crate::arg::Packing) -> Self {
+		Self {
+			source,
+			packing,
+			input: Vec::with_capacity(packing.size),
+			output: Vec::new(),
+			offset: 0
+		}
+	}
+}
+impl<R> Read for Unpack<R>
+	where R: Read {
 
-			copied += len;
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		if buf.is_empty() { return Ok(0) }
+		while self.offset == self.output.len() {
+			self.output.clear();
+			self.offset = 0;
+
+			/* Read about as many samples as the caller wants. */
+			let samples = usize::max(buf.len() / 4, 1);
+			let mut chunk = vec![0; samples * self.packing.size];
+			let read = self.source.read(&mut chunk)?;
+			if read == 0 { return Ok(0) }
+
+			self.input.extend(&chunk[..read]);
+			let whole = self.input.len() - self.input.len() % self.packing.size;
+			for sample in self.input[..whole].chunks_exact(self.packing.size) {
+				let sample = crate::convert::unpack(
+					sample,
+					self.packing.endian,
+					self.packing.valid_bits);
+				self.output.extend(&sample.to_ne_bytes());
+			}
+			self.input.drain(..whole);
 		}
 
-		Ok(copied)
+		let len = usize::min(buf.len(), self.output.len() - self.offset);
+		buf[..len].copy_from_slice(&self.output[self.offset..self.offset + len]);
+		self.offset += len;
+
+		Ok(len)
+	}
+}
+impl<R> Source for Unpack<R>
+	where R: Source {
+
+	fn rewind(&mut self) -> std::io::Result<bool> {
+		if !self.source.rewind()? { return Ok(false) }
+
+		self.input.clear();
+		self.output.clear();
+		self.offset = 0;
+
+		Ok(true)
+	}
+
+	fn description(&self) -> String {
+		self.source.description()
+	}
+}
+
+/** A source that plays silence whenever the source it reads from has no data
+ * at hand, which it says by failing with [`std::io::ErrorKind::WouldBlock`],
+ * rather than holding up playback. How long every such gap lasted gets
+ * reported once the data comes back. */
+pub struct FillGaps<R> {
+	source: R,
+	/** A single silent frame. */
+	silence: Vec<u8>,
+	/** Number of frames played every second. */
+	rate: u32,
+	/** Position within its frame of the next byte to be handed out. */
+	position: usize,
+	/** Number of silent bytes played so far in the current gap. */
+	gap: u64,
+}
+impl<R> FillGaps<R> {
+	/** Creates a new source filling gaps with silence in the given format. */
+	pub fn new(
+		source: R,
+		channels: u16,
+		rate: u32,
+		format: cpal::SampleFormat,
+		endian: Endianness) -> Self {
+
+		let mut silence = vec![0; format.sample_size() * usize::from(channels)];
+		crate::convert::silence(format, endian, &mut silence);
+
+		Self {
+			source,
+			silence,
+			rate,
+			position: 0,
+			gap: 0
+		}
 	}
 }
+impl<R> Read for FillGaps<R>
+	where R: Read {
+
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		match self.source.read(buf) {
+			Ok(read) => {
+				if self.gap > 0 {
+					let frames = self.gap / self.silence.len() as u64;
+					eprintln!("warning: played {:.3}s of silence while the input was unavailable",
+						frames as f64 / f64::from(self.rate));
+					self.gap = 0;
+				}
+
+				self.position = (self.position + read) % self.silence.len();
+				Ok(read)
+			},
+			Err(what) if what.kind() == std::io::ErrorKind::WouldBlock => {
+				/* Silence picks up wherever the data left off within its
+				 * frame, so that whatever comes after it is still aligned. */
+				for byte in buf.iter_mut() {
+					*byte = self.silence[self.position];
+					self.position = (self.position + 1) % self.silence.len();
+				}
+				self.gap += buf.len() as u64;
 
+				Ok(buf.len())
+			},
+			Err(what) => Err(what)
+		}
+	}
+}
+
+/** How often a [`HoldOpen`] checks whether more data has come in, once its
+ * source has run out. */
+const HOLD_OPEN_POLL: Duration = Duration::from_millis(50);
+
+/** A source that doesn't take its source running out for the end of it, and
+ * keeps checking for more data instead. Giving up on it is left to whoever
+ * keeps an eye on the input, through a [`Watch`].
+ *
+ * While there's none, reads either fail with
+ * [`std::io::ErrorKind::WouldBlock`], for the gap to be filled in with silence,
+ * or, when blocking, hold off until there is some. */
+pub struct HoldOpen<R> {
+	source: R,
+	/** Whether reads wait for more data, rather than failing. */
+	blocking: bool,
+	/** When the source ran out, if it's out at the moment. */
+	idle_since: Option<Instant>,
+	/** When the source was last checked for more data. */
+	polled: Instant,
+}
+impl<R> HoldOpen<R> {
+	/** Creates a new source waiting on the given one. */
+	pub fn new(source: R, blocking: bool) -> Self {
+		Self {
+			source,
+			blocking,
+			idle_since: None,
+			polled: Instant::now()
+		}
+	}
+}
+impl<R> Read for HoldOpen<R>
+	where R: Read {
+
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		loop {
+			/* Sources that have run out tend to stay that way for a while, so
+			 * there's no use in asking them all the time. */
+			if self.idle_since.is_some() && self.polled.elapsed() < HOLD_OPEN_POLL {
+				if !self.blocking {
+					return Err(std::io::ErrorKind::WouldBlock.into())
+				}
+				std::thread::sleep(HOLD_OPEN_POLL - self.polled.elapsed().min(HOLD_OPEN_POLL));
+			}
+
+			self.polled = Instant::now();
+			let read = self.source.read(buf)?;
+			if read > 0 || buf.is_empty() {
+				self.idle_since = None;
+				return Ok(read)
+			}
+
+			self.idle_since.get_or_insert(self.polled);
+			if !self.blocking {
+				return Err(std::io::ErrorKind::WouldBlock.into())
+			}
+		}
+	}
+}
+
+/** Keeps track of when data last came in from a source, through a [`Watch`]
+ * on it. Clones all keep track of the same source. */
+#[derive(Clone)]
+pub struct Activity(Arc<Mutex<ActivityState>>);
+
+/** What an [`Activity`] knows about its source. */
+struct ActivityState {
+	/** When data last came in, or when it was first asked for, if none has
+	 * come in yet. Nothing, before it's first asked for. */
+	last: Option<Instant>,
+}
+impl Activity {
+	pub fn new() -> Self {
+		Self(Arc::new(Mutex::new(ActivityState {
+			last: None
+		})))
+	}
+
+	/** How long it's been since data last came in. Sources that nobody has
+	 * asked for data yet, such as while the output is being delayed, aren't
+	 * idle, however long they go untouched. */
+	pub fn idle(&self) -> Option<Duration> {
+		self.0.lock().unwrap().last.map(|last| last.elapsed())
+	}
+
+	/** Forgets about when data last came in, so that time spent with the
+	 * source untouched on purpose, such as while paused, doesn't count. */
+	pub fn reset(&self) {
+		self.0.lock().unwrap().last = None;
+	}
+}
+
+/** A source that keeps its [`Activity`] up to date with its own source. */
+pub struct Watch<R> {
+	source: R,
+	activity: Activity,
+}
+impl<R> Watch<R> {
+	pub fn new(source: R, activity: Activity) -> Self {
+		Self { source, activity }
+	}
+}
+impl<R> Read for Watch<R>
+	where R: Read {
+
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		self.activity.0.lock().unwrap().last.get_or_insert_with(Instant::now);
+
+		let result = self.source.read(buf);
+
+		let mut state = self.activity.0.lock().unwrap();
+		if let Ok(read) = result {
+			if read > 0 {
+				state.last = Some(Instant::now());
+			}
+		}
+		result
+	}
+}
+
+/** A source whose reads happen on a thread of their own, one at a time and
+ * only as they're asked for, so that whoever's reading from it can be let go
+ * of in the middle of a read, through the [`Release`] it comes with.
+ *
+ * Nothing can cut a read short, as blocking reads from pipes, terminals and
+ * sockets can't be cancelled from another thread. A read that's let go of
+ * keeps its thread, and the source along with it, around until it comes back,
+ * at which point the thread drops the source and finishes on its own. */
+pub struct Detach {
+	shared: Arc<(Mutex<DetachState>, Condvar)>,
+	thread: Option<JoinHandle<()>>,
+}
+
+/** What a [`Detach`] and its thread share. */
+struct DetachState {
+	/** Number of bytes wanted by a read the thread has yet to take up. */
+	request: Option<usize>,
+	/** How many bytes the last read got, or how it failed, until handed out. */
+	result: Option<std::io::Result<usize>>,
+	/** Bytes the last read got. */
+	data: Vec<u8>,
+	/** Whether the thread is in the middle of a read. */
+	reading: bool,
+	/** Whether reads have been let go of. */
+	released: bool,
+}
+
+/** Lets go of the reads of a [`Detach`]. */
+#[derive(Clone)]
+pub struct Release(Arc<(Mutex<DetachState>, Condvar)>);
+impl Release {
+	/** Has the read under way, if any, and every read after it come back as
+	 * the end of the input right away. */
+	pub fn release(&self) {
+		self.0.0.lock().unwrap().released = true;
+		self.0.1.notify_all();
+	}
+}
+
+impl Detach {
+	pub fn new<R>(source: R) -> (Self, Release)
+		where R: Read + Send + 'static {
+
+		let shared = Arc::new((Mutex::new(DetachState {
+			request: None,
+			result: None,
+			data: Vec::new(),
+			reading: false,
+			released: false
+		}), Condvar::new()));
+
+		let shared1 = shared.clone();
+		let thread = std::thread::spawn(move || Self::handle(source, shared1));
+
+		(Self { shared: shared.clone(), thread: Some(thread) }, Release(shared))
+	}
+
+	/** Whether reads have been let go of, rather than the input having run
+	 * out on its own. */
+	pub fn released(&self) -> bool {
+		self.shared.0.lock().unwrap().released
+	}
+
+	fn handle<R>(mut source: R, shared: Arc<(Mutex<DetachState>, Condvar)>)
+		where R: Read {
+
+		let (lock, cond) = &*shared;
+		let mut buffer = Vec::new();
+		loop {
+			let len = {
+				let mut state = lock.lock().unwrap();
+				loop {
+					if state.released { return }
+					if let Some(len) = state.request.take() {
+						state.reading = true;
+						break len
+					}
+					state = cond.wait(state).unwrap();
+				}
+			};
+
+			buffer.resize(len, 0);
+			let result = source.read(&mut buffer);
+
+			let mut state = lock.lock().unwrap();
+			if let Ok(read) = result {
+				state.data.clear();
+				state.data.extend_from_slice(&buffer[..read]);
+			}
+			state.result = Some(result);
+			state.reading = false;
+			cond.notify_all();
+		}
+	}
+}
+impl Drop for Detach {
+	fn drop(&mut self) {
+		let reading = {
+			let mut state = self.shared.0.lock().unwrap();
+			state.released = true;
+			state.reading
+		};
+		self.shared.1.notify_all();
+
+		/* A thread that isn't reading finishes right away. One that is gets
+		 * left to finish whenever its read comes back. */
+		if let Some(thread) = self.thread.take().filter(|_| !reading) {
+			let _ = thread.join();
+		}
+	}
+}
+impl Read for Detach {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		if buf.is_empty() { return Ok(0) }
+
+		let (lock, cond) = &*self.shared;
+		let mut state = lock.lock().unwrap();
+		if state.released { return Ok(0) }
+
+		state.request = Some(buf.len());
+		cond.notify_all();
+		loop {
+			if state.released { return Ok(0) }
+			if let Some(result) = state.result.take() {
+				return result.inspect(|read| buf[..*read].copy_from_slice(&state.data[..*read]))
+			}
+			state = cond.wait(state).unwrap();
+		}
+	}
+}
+
+/** A source that plays a given amount of silence before anything from the
+ * source it reads from. The silence takes no memory to speak of, however long
+ * it is, but a source that keeps producing data in real time is held back for
+ * as long, so whatever it produces in the meantime has to be buffered up by
+ * whoever is producing it. */
+pub struct Delay<R> {
+	source: R,
+	/** A single silent frame. */
+	silence: Vec<u8>,
+	/** Number of silent bytes still to be played. */
+	remaining: usize,
+}
+impl<R> Delay<R> {
+	/** Creates a new source delayed by the given number of frames of silence
+	 * in the given format. */
+	pub fn new(
+		source: R,
+		frames: usize,
+		channels: u16,
+		format: cpal::SampleFormat,
+		endian: Endianness) -> Self {
+
+		let mut silence = vec![0; format.sample_size() * usize::from(channels)];
+		crate::convert::silence(format, endian, &mut silence);
+
+		Self {
+			source,
+			remaining: frames * silence.len(),
+			silence
+		}
+	}
+}
+impl<R> Read for Delay<R>
+	where R: Read {
+
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		if self.remaining == 0 {
+			return self.source.read(buf)
+		}
+
+		/* The silence is made up of whole frames, so it ends on one too. */
+		let len = usize::min(buf.len(), self.remaining);
+		let played = self.silence.len() - self.remaining % self.silence.len();
+		for (index, byte) in buf[..len].iter_mut().enumerate() {
+			*byte = self.silence[(played + index) % self.silence.len()];
+		}
+		self.remaining -= len;
+
+		Ok(len)
+	}
+}
+
+/** A reader that keeps a copy of everything read from it, so that it can be
+ * played again from memory once it's over. This is how sources that can't go
+ * back by themselves, such as pipes, get repeated. The copy is only allowed to
+ * grow up to a given size, past which reading fails. */
+pub struct Replay<R> {
+	source: R,
+	/** Everything read from the source so far. */
+	buffer: Vec<u8>,
+	/** Largest the copy is allowed to grow, in bytes. */
+	limit: usize,
+	/** Where in the copy we are, once we've started playing from it. */
+	position: Option<usize>,
+}
+impl<R> Replay<R> {
+	/** Creates a new reader keeping up to the given number of bytes. */
+	pub fn new(source: R, limit: usize) -> Self {
+		Self {
+			source,
+			buffer: Vec::new(),
+			limit,
+			position: None
+		}
+	}
+}
+impl<R> Read for Replay<R>
+	where R: Read {
+
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		if let Some(position) = self.position.as_mut() {
+			let len = usize::min(buf.len(), self.buffer.len() - *position);
+			buf[..len].copy_from_slice(&self.buffer[*position..*position + len]);
+			*position += len;
+
+			return Ok(len)
+		}
+
+		let read = self.source.read(buf)?;
+		if self.buffer.len() + read > self.limit {
+			return Err(Error::other(
+				format!("the input is longer than the loop buffer of {} bytes", self.limit)))
+		}
+		self.buffer.extend_from_slice(&buf[..read]);
+
+		Ok(read)
+	}
+}
+impl<R> Source for Replay<R>
+	where R: Read + Send {
+
+	/** Goes back to the start of the copy. Only meant to be called once the
+	 * source is over, as whatever is left of it never gets read otherwise. */
+	fn rewind(&mut self) -> std::io::Result<bool> {
+		self.position = Some(0);
+		Ok(true)
+	}
+}
+
+/** Parameters for trimming the silence off both ends of a source. */
+#[derive(Debug, Copy, Clone)]
+pub struct Trim {
+	/** Level, as a linear amplitude, at or below which samples are considered
+	 * to be silent. */
+	pub threshold: f32,
+	/** Size of a single frame, in bytes. */
+	pub frame: usize,
+	/** Format of the samples in the source. */
+	pub format: cpal::SampleFormat,
+	/** Endianness of the samples in the source. */
+	pub endian: Endianness,
+}
+
+/** Finds the range of frames in the given source holding audible samples, as
+ * the index of the first audible frame and of the one after the last audible
+ * frame. Returns `None` if the whole source is silent. */
+pub fn audible_range<R>(source: &mut R, trim: &Trim) -> std::io::Result<Option<(u64, u64)>>
+	where R: Read {
+
+	let size = trim.format.sample_size();
+	let mut buffer = vec![0; trim.frame * 1024];
+	let mut filled = 0;
+
+	let mut index = 0u64;
+	let mut range = None;
+	loop {
+		let read = source.read(&mut buffer[filled..])?;
+		if read == 0 { break }
+		filled += read;
+
+		let whole = filled - filled % trim.frame;
+		for frame in buffer[..whole].chunks_exact(trim.frame) {
+			let audible = frame.chunks_exact(size)
+				.map(|sample| crate::convert::decode(trim.format, trim.endian, sample))
+				.any(|sample| sample.abs() > trim.threshold);
+			if audible {
+				range = match range {
+					Some((first, _)) => Some((first, index + 1)),
+					None => Some((index, index + 1))
+				};
+			}
+			index += 1;
+		}
+
+		buffer.copy_within(whole..filled, 0);
+		filled -= whole;
+	}
+
+	Ok(range)
+}
+
+/** A stretch of the input to be played, in bytes from its start. */
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Segment {
+	/** Offset of the first byte in the segment. */
+	pub start: u64,
+	/** Offset of the first byte past the end of the segment. */
+	pub end: u64,
+	/** How the segment was given, for the user's benefit. */
+	pub name: String,
+}
+
+/** A source that only plays the given segments of the source it reads from,
+ * back to back. Segments have to be in order and can't overlap, so getting
+ * from one to the next only ever takes moving forward, which sources that
+ * can't seek do by reading through whatever is in between. */
+pub struct Segments<R> {
+	source: R,
+	segments: Vec<Segment>,
+	/** Index of the segment being played. */
+	index: usize,
+	/** Offset of the next byte of the source to be read. */
+	position: u64,
+	/** Number of segments that have been announced so far. */
+	announced: usize,
+	/** Whether to announce every segment as it starts playing. */
+	progress: bool,
+}
+impl<R> Segments<R>
+	where R: Source {
+
+	/** Creates a new source playing the given segments of the given source,
+	 * which must be in order and must not overlap. Sources that know their
+	 * length get their segments checked against it. */
+	pub fn new(source: R, segments: Vec<Segment>, progress: bool) -> std::io::Result<Self> {
+		if let Some(length) = source.len_hint() {
+			if let Some(segment) = segments.iter().find(|segment| segment.start >= length) {
+				return Err(Error::new(
+					std::io::ErrorKind::InvalidInput,
+					format!("segment {} starts past the end of {}", segment.name, source.description())))
+			}
+			if let Some(segment) = segments.iter().find(|segment| segment.end > length) {
+				eprintln!("warning: segment {} ends past the end of {}, playing up to its end",
+					segment.name,
+					source.description());
+			}
+		}
+
+		Ok(Self {
+			source,
+			segments,
+			index: 0,
+			position: 0,
+			announced: 0,
+			progress
+		})
+	}
+
+	/** Moves forward in the source by the given number of bytes. Returns
+	 * whether there were that many bytes to be moved past. */
+	fn skip(&mut self, bytes: u64) -> std::io::Result<bool> {
+		if self.source.seek_forward(bytes)? {
+			return Ok(true)
+		}
+
+		let skipped = std::io::copy(&mut (&mut self.source).take(bytes), &mut std::io::sink())?;
+		Ok(skipped == bytes)
+	}
+}
+impl<R> Read for Segments<R>
+	where R: Source {
+
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		loop {
+			let segment = match self.segments.get(self.index) {
+				Some(segment) => segment,
+				None => return Ok(0)
+			};
+			if self.position >= segment.end {
+				self.index += 1;
+				continue
+			}
+
+			if self.position < segment.start {
+				let (start, name) = (segment.start, segment.name.clone());
+				if !self.skip(start - self.position)? {
+					eprintln!("warning: the input ends before segment {}", name);
+					self.index = self.segments.len();
+					return Ok(0)
+				}
+				self.position = start;
+				continue
+			}
+
+			if self.announced <= self.index {
+				if self.progress {
+					eprintln!("segment {}/{}: {}", self.index + 1, self.segments.len(), segment.name);
+				}
+				self.announced = self.index + 1;
+			}
+
+			let len = u64::min(buf.len() as u64, segment.end - self.position) as usize;
+			let read = self.source.read(&mut buf[..len])?;
+			if read == 0 && len > 0 {
+				/* The source ran out within the segment. Whatever is left of
+				 * it, as well as the segments after it, can't be played. */
+				self.index = self.segments.len();
+				return Ok(0)
+			}
+			self.position += read as u64;
+
+			return Ok(read)
+		}
+	}
+}
+impl<R> Source for Segments<R>
+	where R: Source {
+
+	fn rewind(&mut self) -> std::io::Result<bool> {
+		if !self.source.rewind()? { return Ok(false) }
+
+		self.index = 0;
+		self.position = 0;
+		self.announced = 0;
+		Ok(true)
+	}
+
+	fn description(&self) -> String {
+		self.source.description()
+	}
+}
+
+/** A region of the input that gets played over and over once it's reached, in
+ * frames from the start of the input. */
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct LoopPoints {
+	/** First frame in the loop. */
+	pub start: u64,
+	/** First frame past the end of the loop. */
+	pub end: u64,
+}
+
+/** Largest loop that gets kept in memory to be played over and over, in bytes.
+ * Loops in instrument samples tend to be a lot shorter than this. */
+const MAX_LOOP: u64 = 64 * 1024 * 1024;
+
+/** A source that plays the source it reads from up to the end of a loop, then
+ * plays the loop over and over, either for as long as it's being read from or
+ * for a given number of times, after which the rest of the source is played.
+ * The loop is kept in memory as it gets played the first time around, so the
+ * source doesn't have to be able to seek. */
+pub struct LoopRegion<R> {
+	source: R,
+	/** Offset of the first byte in the loop. */
+	start: u64,
+	/** Offset of the first byte past the end of the loop. */
+	end: u64,
+	/** Offset of the next byte of the source to be read. */
+	position: u64,
+	/** Data in the loop, as it's been read so far. */
+	recorded: Vec<u8>,
+	/** Position in the recorded loop, once it's being played from memory. */
+	replay: Option<usize>,
+	/** Number of times the loop is left to be played after the current one,
+	 * if it's not to be played forever. */
+	left: Option<u64>,
+}
+impl<R> LoopRegion<R> {
+	/** Creates a new source looping over the given region of the given source,
+	 * for frames of the given size, the given number of times in total, or
+	 * forever. Loops too long to be kept in memory are played once, with a
+	 * warning. */
+	pub fn new(source: R, points: LoopPoints, frame: usize, times: Option<u64>) -> Self {
+		let frame = frame as u64;
+		let (start, end) = if (points.end - points.start).saturating_mul(frame) > MAX_LOOP {
+			eprintln!("warning: the loop is longer than {} bytes, playing it only once", MAX_LOOP);
+			(u64::MAX, u64::MAX)
+		} else {
+			(points.start.saturating_mul(frame), points.end.saturating_mul(frame))
+		};
+
+		Self {
+			source,
+			start,
+			end,
+			position: 0,
+			recorded: Vec::new(),
+			replay: None,
+			left: times.map(|times| times.saturating_sub(1))
+		}
+	}
+
+	/** Counts one more time through the loop. Once it's been played as many
+	 * times as it was meant to, the source gets played through past it. */
+	fn pass(&mut self) -> bool {
+		match self.left.as_mut() {
+			Some(0) => {},
+			Some(left) => {
+				*left -= 1;
+				return true
+			},
+			None => return true
+		}
+
+		self.start = u64::MAX;
+		self.end = u64::MAX;
+		self.recorded = Vec::new();
+		self.replay = None;
+		false
+	}
+}
+impl<R> Read for LoopRegion<R>
+	where R: Read {
+
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		if let Some(offset) = self.replay.as_mut() {
+			let len = usize::min(buf.len(), self.recorded.len() - *offset);
+			buf[..len].copy_from_slice(&self.recorded[*offset..*offset + len]);
+			*offset = (*offset + len) % self.recorded.len();
+			if *offset == 0 {
+				self.pass();
+			}
+
+			return Ok(len)
+		}
+
+		let len = u64::min(buf.len() as u64, self.end - self.position) as usize;
+		let read = self.source.read(&mut buf[..len])?;
+		if read == 0 && len > 0 {
+			if self.position > self.start {
+				eprintln!("warning: the input ends within its loop, playing it only once");
+			}
+			return Ok(0)
+		}
+
+		/* Keep whatever falls within the loop. */
+		let first = self.position;
+		self.position += read as u64;
+		if self.position > self.start {
+			let from = self.start.saturating_sub(first) as usize;
+			self.recorded.extend(&buf[from..read]);
+		}
+
+		if self.position == self.end && !self.recorded.is_empty() && self.pass() {
+			self.replay = Some(0);
+		}
+		Ok(read)
+	}
+}
+impl<R> Source for LoopRegion<R>
+	where R: Read + Send {}
+
+/** A source that plays a single segment of the source it reads from over and
+ * over, going back to its start by rewinding the source and moving forward to
+ * the segment again, so the source has to be able to rewind. It does so either
+ * for as long as it's being read from or for a given number of times. */
+pub struct AbLoop<R> {
+	segments: Segments<R>,
+	/** Number of times the segment is left to be played after the current
+	 * one, if it's not to be played forever. */
+	left: Option<u64>,
+	/** Number of the time through the segment being played, from one. */
+	pass: u64,
+	/** Whether anything was played from the segment this time through. */
+	played: bool,
+	/** Whether to announce every time through the segment. */
+	progress: bool,
+}
+impl<R> AbLoop<R>
+	where R: Source {
+
+	/** Creates a new source playing the given segment of the given source the
+	 * given number of times, or forever. Sources that can't rewind are
+	 * refused right away, rather than once the segment is over. */
+	pub fn new(mut source: R, segment: Segment, times: Option<u64>, progress: bool)
+		-> std::io::Result<Self> {
+
+		if !source.rewind()? {
+			return Err(Error::new(
+				std::io::ErrorKind::InvalidInput,
+				format!("{} can't be seeked through, so it can't be looped over", source.description())))
+		}
+		if progress {
+			eprintln!("looping over {}", segment.name);
+		}
+
+		Ok(Self {
+			segments: Segments::new(source, vec![segment], false)?,
+			left: times.map(|times| times.saturating_sub(1)),
+			pass: 1,
+			played: false,
+			progress
+		})
+	}
+}
+impl<R> Read for AbLoop<R>
+	where R: Source {
+
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		loop {
+			let read = self.segments.read(buf)?;
+			if read > 0 || buf.is_empty() {
+				self.played |= read > 0;
+				return Ok(read)
+			}
+
+			/* A segment with nothing in it would otherwise have us going
+			 * around in circles forever. */
+			if !self.played { return Ok(0) }
+			match self.left.as_mut() {
+				Some(0) => return Ok(0),
+				Some(left) => *left -= 1,
+				None => {}
+			}
+			if !self.segments.rewind()? { return Ok(0) }
+
+			self.pass += 1;
+			self.played = false;
+			if self.progress {
+				eprintln!("loop {}", self.pass);
+			}
+		}
+	}
+}
+impl<R> Source for AbLoop<R>
+	where R: Source {
+
+	fn description(&self) -> String {
+		self.segments.description()
+	}
+}
+
+/** A source that plays the source it reads from up to a given number of bytes
+ * before its end. When the length of the source is known, that's where it
+ * stops. Otherwise, that many bytes are held back at all times, so output lags
+ * behind the source by that much, and whatever is still held back once the
+ * source is over is never played. */
+pub struct TrimEnd<R> {
+	source: R,
+	/** Number of bytes to be left out at the end of the source. */
+	trim: u64,
+	/** Number of bytes left to be played, for sources of known length. */
+	left: Option<u64>,
+	/** Bytes read from the source but not played yet, for sources of unknown
+	 * length. */
+	held: VecDeque<u8>,
+	/** Whether the source of unknown length is over. */
+	over: bool,
+}
+impl<R> TrimEnd<R>
+	where R: Source {
+
+	/** Creates a new source leaving the given number of bytes out at the end
+	 * of the given source. */
+	pub fn new(source: R, trim: u64) -> Self {
+		let left = source.len_hint().map(|length| length.saturating_sub(trim));
+		Self {
+			source,
+			trim,
+			left,
+			held: VecDeque::new(),
+			over: false
+		}
+	}
+}
+impl<R> Read for TrimEnd<R>
+	where R: Source {
+
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		if let Some(left) = self.left.as_mut() {
+			let len = u64::min(buf.len() as u64, *left) as usize;
+			let read = self.source.read(&mut buf[..len])?;
+			*left -= read as u64;
+
+			return Ok(read)
+		}
+
+		loop {
+			let ready = (self.held.len() as u64).saturating_sub(self.trim) as usize;
+			if ready > 0 || buf.is_empty() || self.over {
+				let len = usize::min(buf.len(), ready);
+				for (byte, held) in buf[..len].iter_mut().zip(self.held.drain(..len)) {
+					*byte = held;
+				}
+				return Ok(len)
+			}
+
+			/* The buffer doubles as scratch space, as nothing has been put in
+			 * it yet. */
+			let read = self.source.read(buf)?;
+			if read == 0 {
+				self.over = true;
+				continue
+			}
+			self.held.extend(&buf[..read]);
+		}
+	}
+}
+impl<R> Source for TrimEnd<R>
+	where R: Source {
+
+	fn rewind(&mut self) -> std::io::Result<bool> {
+		if !self.source.rewind()? { return Ok(false) }
+
+		self.left = self.source.len_hint().map(|length| length.saturating_sub(self.trim));
+		self.held.clear();
+		self.over = false;
+		Ok(true)
+	}
+
+	fn seek_forward(&mut self, bytes: u64) -> std::io::Result<bool> {
+		/* Skipping over held back bytes would need them to be read first. */
+		let left = match self.left {
+			Some(left) if bytes <= left => left,
+			_ => return Ok(false)
+		};
+		if !self.source.seek_forward(bytes)? { return Ok(false) }
+
+		self.left = Some(left - bytes);
+		Ok(true)
+	}
+
+	fn format_hint(&self) -> Option<InputFormat> {
+		self.source.format_hint()
+	}
+
+	fn len_hint(&self) -> Option<u64> {
+		self.left
+	}
+
+	fn description(&self) -> String {
+		self.source.description()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Cursor;
+
+	/** Reads the given source all the way through. */
+	fn drain<R: Read>(mut source: R) -> Vec<u8> {
+		let mut data = Vec::new();
+		source.read_to_end(&mut data).unwrap();
+		data
+	}
+
+	/** Items holding a single byte each, their position in the list. */
+	fn numbered(count: u8) -> Vec<(String, Opener)> {
+		let data = (0..count).map(|i| vec![i]).collect::<Vec<_>>();
+		items(&data.iter().map(|data| &data[..]).collect::<Vec<_>>())
+	}
+
+	#[test]
+	fn shuffle_is_deterministic_for_a_seed() {
+		let play = |seed| drain(ConcatSource::new(numbered(16), true)
+			.progress(false)
+			.shuffle(seed));
+
+		let order = play(42);
+		assert_eq!(order, play(42));
+		assert_ne!(order, play(43));
+		assert_ne!(order, (0..16).collect::<Vec<u8>>());
+
+		let mut sorted = order.clone();
+		sorted.sort();
+		assert_eq!(sorted, (0..16).collect::<Vec<u8>>());
+	}
+
+	#[test]
+	fn repeat_all_reshuffles_every_pass() {
+		let mut source = ConcatSource::new(numbered(8), true)
+			.progress(false)
+			.repeat(Repeat::All)
+			.shuffle(7);
+
+		let mut played = [0; 24];
+		source.read_exact(&mut played).unwrap();
+		for pass in played.chunks(8) {
+			let mut sorted = pass.to_vec();
+			sorted.sort();
+			assert_eq!(sorted, (0..8).collect::<Vec<u8>>());
+		}
+		assert!(played[..8] != played[8..16] || played[8..16] != played[16..]);
+	}
+
+	#[test]
+	fn repeat_one_plays_the_same_item() {
+		let mut source = ConcatSource::new(items(&[b"ab", b"cd"]), true)
+			.progress(false)
+			.repeat(Repeat::One);
+
+		let mut played = [0; 6];
+		source.read_exact(&mut played).unwrap();
+		assert_eq!(&played, b"ababab");
+	}
+
+	#[test]
+	fn repeating_broken_items_ends() {
+		let open: Opener = Box::new(|| Err(std::io::Error::other("broken")));
+		let source = ConcatSource::new(vec![("broken".to_owned(), open)], false)
+			.progress(false)
+			.repeat(Repeat::All);
+
+		assert!(drain(source).is_empty());
+	}
+
+	/** Mono single precision samples, all of the given value. */
+	fn constant(value: f32, frames: usize) -> Vec<u8> {
+		(0..frames).flat_map(|_| value.to_le_bytes()).collect()
+	}
+
+	fn crossfade(frames: usize) -> Crossfade {
+		Crossfade {
+			length: frames * 4,
+			frame: 4,
+			format: cpal::SampleFormat::F32,
+			endian: Endianness::Little
+		}
+	}
+
+	#[test]
+	fn crossfade_overlaps_items() {
+		let (a, b, c) = (constant(0.5, 100), constant(-0.5, 100), constant(0.5, 100));
+		let output = drain(ConcatSource::new(items(&[&a, &b, &c]), true)
+			.progress(false)
+			.crossfade(crossfade(20)));
+
+		/* Every item but the first one starts where the one before it is
+		 * still fading out. */
+		assert_eq!(output.len(), (300 - 2 * 20) * 4);
+
+		let samples = output.chunks_exact(4)
+			.map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+			.collect::<Vec<_>>();
+		assert_eq!(samples[79], 0.5);
+		assert_eq!(samples[80], 0.5 * (1.0 - 0.5 / 20.0) - 0.5 * (0.5 / 20.0));
+		assert!(samples[80..100].windows(2).all(|pair| pair[1] < pair[0]));
+		assert_eq!(samples[100], -0.5);
+	}
+
+	#[test]
+	fn crossfade_is_cut_short_by_short_items() {
+		let (a, b) = (constant(0.5, 100), constant(-0.5, 5));
+		let output = drain(ConcatSource::new(items(&[&a, &b]), true)
+			.progress(false)
+			.crossfade(crossfade(20)));
+
+		assert_eq!(output.len(), (100 + 5 - 5) * 4);
+	}
+
+	#[test]
+	fn conform_doubles_rate_and_channels() {
+		let from = InputFormat {
+			channels: 1,
+			sample_rate: 24000,
+			sample_format: cpal::SampleFormat::F32,
+			endian: Endianness::Little
+		};
+		let to = InputFormat {
+			channels: 2,
+			sample_rate: 48000,
+			..from
+		};
+		let samples = [0.0f32, 0.5, -0.5, 0.25];
+		let data = samples.iter().flat_map(|sample| sample.to_le_bytes()).collect::<Vec<_>>();
+
+		let output = drain(Conform::new(Cursor::new(data), from, to));
+		let output = output.chunks_exact(4)
+			.map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+			.collect::<Vec<_>>();
+
+		let expected = [0.0, 0.25, 0.5, 0.0, -0.5, -0.125, 0.25, 0.25];
+		let expected = expected.iter().flat_map(|sample| [*sample, *sample]).collect::<Vec<_>>();
+		assert_eq!(output, expected);
+	}
+
+	#[test]
+	fn conform_converts_sample_format_and_endianness() {
+		let from = InputFormat {
+			channels: 2,
+			sample_rate: 44100,
+			sample_format: cpal::SampleFormat::I16,
+			endian: Endianness::Big
+		};
+		let to = InputFormat {
+			sample_format: cpal::SampleFormat::F32,
+			endian: Endianness::Little,
+			..from
+		};
+		let data = [0x40, 0x00, 0xc0, 0x00, 0x00, 0x00, 0x7f, 0xff];
+
+		let output = drain(Conform::new(Cursor::new(data.to_vec()), from, to));
+		let output = output.chunks_exact(4)
+			.map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+			.collect::<Vec<_>>();
+		assert_eq!(output.len(), 4);
+		assert_eq!(output[0], 0.5);
+		assert_eq!(output[1], -0.5);
+		assert_eq!(output[2], 0.0);
+		assert!((output[3] - 1.0).abs() < 1e-4);
+	}
+
+	#[test]
+	fn conform_mixes_channels_down_rather_than_dropping_them() {
+		let from = InputFormat {
+			channels: 6,
+			sample_rate: 48000,
+			sample_format: cpal::SampleFormat::F32,
+			endian: Endianness::Little
+		};
+		let floats = |output: Vec<u8>| output.chunks_exact(4)
+			.map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+			.collect::<Vec<_>>();
+
+		/* The center goes into both sides, the LFE into neither. */
+		let samples = [0.0f32, 0.0, 1.0, 1.0, 0.0, 0.0];
+		let data = samples.iter().flat_map(|sample| sample.to_le_bytes()).collect::<Vec<_>>();
+		let to = InputFormat { channels: 2, ..from };
+		let output = floats(drain(Conform::new(Cursor::new(data.clone()), from, to)));
+		let center = std::f32::consts::FRAC_1_SQRT_2 / (1.0 + 2.0 * std::f32::consts::FRAC_1_SQRT_2);
+		assert_eq!(output.len(), 2);
+		assert!((output[0] - center).abs() < 1e-6);
+		assert!((output[1] - center).abs() < 1e-6);
+
+		/* Kept, the LFE goes into both sides just like the center. */
+		let conform = Conform::new(Cursor::new(data.clone()), from, to).keep_lfe(true);
+		let output = floats(drain(conform));
+		let both = 2.0 * std::f32::consts::FRAC_1_SQRT_2 / (1.0 + 3.0 * std::f32::consts::FRAC_1_SQRT_2);
+		assert!((output[0] - both).abs() < 1e-6);
+		assert!((output[1] - both).abs() < 1e-6);
+
+		/* Into mono, every channel counts the same. */
+		let to = InputFormat { channels: 1, ..from };
+		let output = floats(drain(Conform::new(Cursor::new(data), from, to)));
+		assert_eq!(output.len(), 1);
+		assert!((output[0] - 2.0 / 6.0).abs() < 1e-6);
+	}
+
+	#[test]
+	fn conform_converts_rate_down_in_small_reads() {
+		let from = InputFormat {
+			channels: 1,
+			sample_rate: 48000,
+			sample_format: cpal::SampleFormat::I16,
+			endian: Endianness::Little
+		};
+		let to = InputFormat {
+			sample_rate: 44100,
+			..from
+		};
+		let data = vec![0; 48000 * 2];
+
+		/* Reads smaller than a frame have to work all the same. */
+		let mut conform = Conform::new(Cursor::new(data), from, to);
+		let mut output = Vec::<u8>::new();
+		let mut buffer = [0; 3];
+		loop {
+			let read = conform.read(&mut buffer).unwrap();
+			if read == 0 { break }
+			output.extend(&buffer[..read]);
+		}
+		assert_eq!(output.len(), 44100 * 2);
+	}
+
+	/** Bytes that don't repeat for a good while, so that any of them showing
+	 * up out of place gets noticed. */
+	fn pattern(len: usize) -> Vec<u8> {
+		(0..len).map(|i| (i * 7 % 251) as u8).collect()
+	}
+
+	/** Items of a list, each one holding the given bytes. */
+	fn items(data: &[&[u8]]) -> Vec<(String, Opener)> {
+		data.iter()
+			.enumerate()
+			.map(|(i, data)| {
+				let data = data.to_vec();
+				let open: Opener = Box::new(move || {
+					let reader: Box<dyn Read + Send> = Box::new(Cursor::new(data.clone()));
+					Ok(Box::new(reader))
+				});
+				(format!("item {}", i), open)
+			})
+			.collect()
+	}
+
+	/** An in-memory source that can rewind and skip ahead, and knows its
+	 * length, like a file does. */
+	struct Seekable(Cursor<Vec<u8>>);
+	impl Read for Seekable {
+		fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+			self.0.read(buf)
+		}
+	}
+	impl Source for Seekable {
+		fn rewind(&mut self) -> std::io::Result<bool> {
+			self.0.set_position(0);
+			Ok(true)
+		}
+
+		fn seek_forward(&mut self, bytes: u64) -> std::io::Result<bool> {
+			self.0.set_position(self.0.position() + bytes);
+			Ok(true)
+		}
+
+		fn len_hint(&self) -> Option<u64> {
+			Some(self.0.get_ref().len() as u64).map(|len| len.saturating_sub(self.0.position()))
+		}
+	}
+
+	fn segment(start: u64, end: u64) -> Segment {
+		Segment {
+			start,
+			end,
+			name: format!("{}-{}", start, end)
+		}
+	}
+
+	#[test]
+	fn ab_loop_plays_the_window_the_given_number_of_times() {
+		let data = pattern(100);
+		let source = Seekable(Cursor::new(data.clone()));
+		let looped = drain(AbLoop::new(source, segment(20, 36), Some(3), false).unwrap());
+
+		assert_eq!(looped.len(), 3 * 16);
+		for pass in looped.chunks(16) {
+			assert_eq!(pass, &data[20..36]);
+		}
+	}
+
+	#[test]
+	fn ab_loop_goes_on_forever_without_a_count() {
+		let data = pattern(100);
+		let source = Seekable(Cursor::new(data.clone()));
+		let mut looped = Vec::new();
+		AbLoop::new(source, segment(90, 100), None, false).unwrap()
+			.take(1000)
+			.read_to_end(&mut looped)
+			.unwrap();
+
+		assert_eq!(looped.len(), 1000);
+		assert!(looped.chunks(10).all(|pass| pass == &data[90..]));
+	}
+
+	#[test]
+	fn ab_loop_refuses_sources_that_cant_rewind() {
+		let source: Box<dyn Read + Send> = Box::new(Cursor::new(pattern(100)));
+		let error = AbLoop::new(source, segment(0, 10), Some(2), false).err().unwrap();
+		assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+	}
+
+	#[test]
+	fn ab_loop_past_the_end_is_refused() {
+		let source = Seekable(Cursor::new(pattern(100)));
+		assert!(AbLoop::new(source, segment(100, 110), None, false).is_err());
+	}
+
+	#[test]
+	fn segments_play_back_to_back() {
+		let data = pattern(100);
+		let segments = || vec![segment(10, 20), segment(30, 45), segment(90, 95)];
+		let expected = [&data[10..20], &data[30..45], &data[90..95]].concat();
+		assert_eq!(expected.len(), 10 + 15 + 5);
+
+		/* Sources that can seek get moved forward to every segment, and
+		 * those that can't get read through up to it. */
+		let source = Seekable(Cursor::new(data.clone()));
+		assert_eq!(drain_by(Segments::new(source, segments(), false).unwrap(), 7), expected);
+
+		let source: Box<dyn Read + Send> = Box::new(Cursor::new(data.clone()));
+		assert_eq!(drain_by(Segments::new(source, segments(), false).unwrap(), 7), expected);
+	}
+
+	#[test]
+	fn segments_past_the_end_play_what_there_is() {
+		/* Ending past the end of the input only gets a warning, and plays up
+		 * to the end of it. */
+		let data = pattern(100);
+		let source = Seekable(Cursor::new(data.clone()));
+		let played = drain(Segments::new(source, vec![segment(90, 120)], false).unwrap());
+		assert_eq!(played, &data[90..]);
+
+		/* Sources that don't know how long they are find out on the way,
+		 * which ends whatever segments come after. */
+		let source: Box<dyn Read + Send> = Box::new(Cursor::new(data.clone()));
+		let played = drain(Segments::new(source, vec![segment(90, 120), segment(130, 140)], false).unwrap());
+		assert_eq!(played, &data[90..]);
+		let source: Box<dyn Read + Send> = Box::new(Cursor::new(data.clone()));
+		assert!(drain(Segments::new(source, vec![segment(110, 120)], false).unwrap()).is_empty());
+	}
+
+	#[test]
+	fn segments_starting_past_the_end_are_refused() {
+		let source = Seekable(Cursor::new(pattern(100)));
+		let error = Segments::new(source, vec![segment(10, 20), segment(100, 110)], false).err().unwrap();
+		assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+	}
+
+	/** Frames 10 up to 18 of two byte frames. */
+	const LOOP: LoopPoints = LoopPoints { start: 10, end: 18 };
+
+	#[test]
+	fn loop_region_plays_the_loop_the_given_number_of_times() {
+		let data = pattern(100);
+		let source = Cursor::new(data.clone());
+
+		/* Reads that don't line up with the loop get it all the same. */
+		let looped = drain_by(LoopRegion::new(source, LOOP, 2, Some(3)), 7);
+		let expected = [&data[..36], &data[20..36], &data[20..36], &data[36..]].concat();
+		assert_eq!(looped, expected);
+	}
+
+	#[test]
+	fn loop_region_goes_on_forever_without_a_count() {
+		let data = pattern(100);
+		let source = Cursor::new(data.clone());
+		let mut looped = Vec::new();
+		LoopRegion::new(source, LOOP, 2, None)
+			.take(36 + 16 * 50)
+			.read_to_end(&mut looped)
+			.unwrap();
+
+		assert_eq!(looped[..36], data[..36]);
+		assert!(looped[36..].chunks(16).all(|pass| pass == &data[20..36]));
+	}
+
+	#[test]
+	fn loop_region_plays_input_ending_within_it_once() {
+		let data = pattern(30);
+		let source = Cursor::new(data.clone());
+		assert_eq!(drain_by(LoopRegion::new(source, LOOP, 2, Some(3)), 7), data);
+	}
+
+	/** A source handing out a few bytes at a time, slower than anyone
+	 * reading from it. */
+	struct Trickle {
+		data: Vec<u8>,
+		position: usize,
+	}
+	impl Read for Trickle {
+		fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+			std::thread::sleep(Duration::from_micros(200));
+			let len = usize::min(usize::min(buf.len(), 7), self.data.len() - self.position);
+			buf[..len].copy_from_slice(&self.data[self.position..self.position + len]);
+			self.position += len;
+			Ok(len)
+		}
+	}
+
+	/** Reads everything out of the given source a few bytes at a time. */
+	fn drain_by<R: Read>(mut source: R, size: usize) -> Vec<u8> {
+		let mut data = Vec::new();
+		let mut buffer = vec![0; size];
+		loop {
+			match source.read(&mut buffer).unwrap() {
+				0 => return data,
+				len => data.extend_from_slice(&buffer[..len])
+			}
+		}
+	}
+
+	#[test]
+	fn trim_end_cuts_seekable_sources_up_front() {
+		let data = pattern(1000);
+		let trimmed = TrimEnd::new(Seekable(Cursor::new(data.clone())), 123);
+		assert_eq!(trimmed.len_hint(), Some(877));
+		assert_eq!(drain_by(trimmed, 100), &data[..877]);
+
+		let trimmed = TrimEnd::new(Seekable(Cursor::new(data.clone())), 5000);
+		assert!(drain(trimmed).is_empty());
+	}
+
+	#[test]
+	fn trim_end_holds_the_tail_of_streams_back() {
+		let data = pattern(1000);
+		for &size in &[1, 7, 100, 123, 124, 4096] {
+			let source: Box<dyn Read + Send> = Box::new(Cursor::new(data.clone()));
+			let trimmed = TrimEnd::new(source, 123);
+			assert_eq!(trimmed.len_hint(), None);
+			assert_eq!(drain_by(trimmed, size), &data[..877], "reads of {} bytes", size);
+		}
+
+		let source = Trickle { data: data.clone(), position: 0 };
+		assert_eq!(drain(TrimEnd::new(Box::new(source) as Box<dyn Read + Send>, 123)), &data[..877]);
+
+		let source: Box<dyn Read + Send> = Box::new(Cursor::new(data));
+		assert!(drain(TrimEnd::new(source, 5000)).is_empty());
+	}
+
+	#[test]
+	fn trim_end_starts_over_when_rewound() {
+		let data = pattern(1000);
+		let mut trimmed = TrimEnd::new(Seekable(Cursor::new(data.clone())), 100);
+		assert!(trimmed.seek_forward(850).unwrap());
+		assert_eq!(drain(&mut trimmed), &data[850..900]);
+
+		/* Nothing past the cut can be skipped to. */
+		assert!(!trimmed.seek_forward(1).unwrap());
+
+		assert!(trimmed.rewind().unwrap());
+		assert_eq!(drain(trimmed), &data[..900]);
+	}
+
+	/** Waits for the skipper to have read everything in its source, so that
+	 * reading from it plays the part of a consumer that's fallen behind. */
+	fn wait_done<R>(skipper: &Skipper<R>) {
+		while !skipper.done.load(Ordering::Relaxed) {
+			std::thread::sleep(Duration::from_millis(1));
+		}
+	}
+
+	#[test]
+	fn skipper_keeps_the_newest_bytes_for_a_slow_consumer() {
+		let data = pattern(10_000);
+		let mut skipper = Skipper::new_with_chunk(Cursor::new(data.clone()), 1000, 64);
+		wait_done(&skipper);
+
+		let mut read = Vec::new();
+		skipper.read_to_end(&mut read).unwrap();
+		assert_eq!(read, &data[data.len() - 1000..]);
+	}
+
+	#[test]
+	fn skipper_drops_the_start_of_reads_larger_than_capacity() {
+		let data = pattern(3 * 4096 + 100);
+		let mut skipper = Skipper::new_with_chunk(Cursor::new(data.clone()), 1000, 4096);
+		wait_done(&skipper);
+
+		let mut read = Vec::new();
+		skipper.read_to_end(&mut read).unwrap();
+		assert_eq!(read, &data[data.len() - 1000..]);
+	}
+
+	#[test]
+	fn skipper_drops_nothing_until_capacity_is_exceeded() {
+		let data = pattern(100);
+		let mut skipper = Skipper::new_with_chunk(Cursor::new(data.clone()), 100, 50);
+		wait_done(&skipper);
+
+		let mut read = Vec::new();
+		skipper.read_to_end(&mut read).unwrap();
+		assert_eq!(read, data);
+
+		/* One byte past capacity drops exactly the oldest one. */
+		let data = pattern(101);
+		let mut skipper = Skipper::new_with_chunk(Cursor::new(data.clone()), 100, 50);
+		wait_done(&skipper);
+
+		let mut read = Vec::new();
+		skipper.read_to_end(&mut read).unwrap();
+		assert_eq!(read, &data[1..]);
+	}
+
+	#[test]
+	fn skipper_hands_every_byte_out_once_to_a_fast_consumer() {
+		let data = pattern(2000);
+		let source = Trickle { data: data.clone(), position: 0 };
+		let mut skipper = Skipper::new_with_chunk(source, 1 << 20, 64);
+
+		let mut read = Vec::new();
+		let mut buffer = [0; 16];
+		loop {
+			match skipper.read(&mut buffer).unwrap() {
+				0 => break,
+				len => read.extend_from_slice(&buffer[..len])
+			}
+		}
+		assert_eq!(read, data);
+	}
+
+	#[test]
+	fn skipper_hands_every_byte_out_at_any_chunk_size() {
+		let data = pattern(3 * SKIPPER_CHUNK + 5);
+		for &chunk in &[1, 7, 16, 1000, SKIPPER_CHUNK, 4 * SKIPPER_CHUNK] {
+			let mut skipper = Skipper::new_with_chunk(
+				Cursor::new(data.clone()),
+				data.len(),
+				chunk);
+
+			/* Reads smaller than the chunks, so that they're never handed out
+			 * whole. */
+			let mut read = Vec::new();
+			let mut buffer = [0; 16];
+			loop {
+				match skipper.read(&mut buffer).unwrap() {
+					0 => break,
+					len => read.extend_from_slice(&buffer[..len])
+				}
+			}
+			assert_eq!(read, data, "chunks of {} bytes", chunk);
+		}
+	}
+
+	#[test]
+	fn skipper_chunks_are_never_empty() {
+		let data = pattern(100);
+		let mut skipper = Skipper::new_with_chunk(Cursor::new(data.clone()), 100, 0);
+
+		let mut read = Vec::new();
+		skipper.read_to_end(&mut read).unwrap();
+		assert_eq!(read, data);
+	}
+
+	/** A source that blocks every read until it's let go of, keeping track of
+	 * whether it's been dropped. */
+	struct Blocked {
+		wait: std::sync::mpsc::Receiver<()>,
+		dropped: Arc<AtomicBool>,
+	}
+	impl Read for Blocked {
+		fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+			let _ = self.wait.recv();
+			Ok(0)
+		}
+	}
+	impl Drop for Blocked {
+		fn drop(&mut self) {
+			self.dropped.store(true, Ordering::Relaxed);
+		}
+	}
+
+	fn blocked() -> (Blocked, std::sync::mpsc::Sender<()>, Arc<AtomicBool>) {
+		let (send, wait) = std::sync::mpsc::channel();
+		let dropped = Arc::new(AtomicBool::new(false));
+		(Blocked { wait, dropped: dropped.clone() }, send, dropped)
+	}
+
+	/** Waits a while for the given flag to be raised. */
+	fn raised(flag: &AtomicBool) -> bool {
+		let deadline = Instant::now() + Duration::from_secs(5);
+		while !flag.load(Ordering::Relaxed) && Instant::now() < deadline {
+			std::thread::sleep(Duration::from_millis(1));
+		}
+		flag.load(Ordering::Relaxed)
+	}
+
+	#[test]
+	fn skipper_drop_joins_a_read_that_comes_back() {
+		let (source, send, dropped) = blocked();
+		let skipper = Skipper::new_with_chunk(source, 1000, 64);
+
+		/* Let the read go shortly after the drop starts waiting on it. */
+		let release = std::thread::spawn(move || {
+			std::thread::sleep(Duration::from_millis(50));
+			let _ = send.send(());
+		});
+		std::mem::drop(skipper);
+		assert!(dropped.load(Ordering::Relaxed));
+		release.join().unwrap();
+	}
+
+	#[test]
+	fn skipper_drop_leaves_a_stuck_read_behind() {
+		let (source, send, dropped) = blocked();
+		let skipper = Skipper::new_with_chunk(source, 1000, 64);
+		std::thread::sleep(Duration::from_millis(10));
+
+		let dropping = Instant::now();
+		std::mem::drop(skipper);
+		assert!(dropping.elapsed() < SKIPPER_GRACE * 4);
+		assert!(!dropped.load(Ordering::Relaxed));
+
+		/* The thread drops the source once the read comes back. */
+		std::mem::drop(send);
+		assert!(raised(&dropped));
+	}
+
+	#[test]
+	fn detach_hands_reads_through() {
+		let data = pattern(10_000);
+		let (mut detach, _) = Detach::new(Cursor::new(data.clone()));
+
+		let mut read = Vec::new();
+		detach.read_to_end(&mut read).unwrap();
+		assert_eq!(read, data);
+	}
+
+	#[test]
+	fn detach_release_ends_a_stuck_read() {
+		let (source, send, dropped) = blocked();
+		let (mut detach, release) = Detach::new(source);
+
+		let reading = std::thread::spawn(move || {
+			let result = detach.read(&mut [0; 16]).unwrap();
+			std::mem::drop(detach);
+			result
+		});
+		std::thread::sleep(Duration::from_millis(50));
+		release.release();
+		assert_eq!(reading.join().unwrap(), 0);
+		assert!(!dropped.load(Ordering::Relaxed));
+
+		std::mem::drop(send);
+		assert!(raised(&dropped));
+	}
+}
@@ -0,0 +1,1095 @@
+use crate::arg::Endianness;
+use crate::convert::{Dither, Quantizer};
+use crate::error::Error;
+use std::f64::consts::PI;
+use std::io::Read;
+use std::sync::mpsc::SyncSender;
+use std::time::Instant;
+
+/** Level sweeps are played at when none is given, in dBFS, a good 6dB below
+ * full scale, so that they can be played through whatever gain is in the way
+ * without clipping. */
+pub const DEFAULT_SWEEP_LEVEL: f64 = -6.0;
+
+/** What a sweep goes through, as given on the command line. */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SweepSpec {
+	/** Frequency the sweep starts at, in Hz. */
+	pub start: f64,
+	/** Frequency the sweep ends at, in Hz. */
+	pub end: f64,
+	/** How long the sweep lasts, in seconds. */
+	pub duration: f64,
+	/** Whether the frequency changes linearly, rather than exponentially. */
+	pub linear: bool,
+}
+
+/** Parses a sweep given as its starting and ending frequencies, in Hz, and its
+ * duration, in seconds, separated by colons, such as `20:20000:10`. */
+pub fn parse_sweep(value: &str) -> Result<SweepSpec, Error> {
+	let malformed = |what: &str| Error::MalformedSweep {
+		value: value.to_owned(),
+		what: what.to_owned()
+	};
+
+	let parts = value.split(':').collect::<Vec<_>>();
+	if parts.len() != 3 {
+		return Err(malformed("expected a starting frequency, an ending frequency and a duration"))
+	}
+	let number = |part: &str| part.trim().parse::<f64>()
+		.ok()
+		.filter(|number| number.is_finite() && *number > 0.0);
+
+	let start = number(parts[0]).ok_or_else(|| malformed("the starting frequency must be a positive number"))?;
+	let end = number(parts[1]).ok_or_else(|| malformed("the ending frequency must be a positive number"))?;
+	let duration = number(parts[2]).ok_or_else(|| malformed("the duration must be a positive number"))?;
+
+	Ok(SweepSpec { start, end, duration, linear: false })
+}
+
+/** Phase of the given sweep at the given time, in radians. The frequency of
+ * the sweep rises, or falls, exponentially, as `f0 * (f1 / f0) ^ (t / T)`, so
+ * that every octave takes the same amount of time to go through, or linearly,
+ * as `f0 + (f1 - f0) * t / T`. The phase is the integral of that from the
+ * start of the sweep, which, unlike accumulating the frequency sample by
+ * sample, doesn't drift no matter how long it is. */
+pub fn phase(spec: &SweepSpec, time: f64) -> f64 {
+	if spec.linear {
+		return 2.0 * PI * (spec.start * time
+			+ (spec.end - spec.start) * time * time / (2.0 * spec.duration))
+	}
+
+	let ratio = (spec.end / spec.start).ln();
+
+	/* A sweep that goes nowhere is a steady tone. */
+	if ratio.abs() < f64::EPSILON {
+		return 2.0 * PI * spec.start * time
+	}
+
+	2.0 * PI * spec.start * spec.duration / ratio
+		* ((time / spec.duration * ratio).exp() - 1.0)
+}
+
+/** A source playing a sine sweep on every channel, optionally with silence
+ * around it, ending once the sweep is over. */
+pub struct Sweep {
+	spec: SweepSpec,
+	/** Number of frames played every second. */
+	rate: u32,
+	/** Peak level of the sweep, as a linear amplitude. */
+	amplitude: f64,
+	/** Number of frames of silence played before the sweep. */
+	before: u64,
+	/** Number of frames in the sweep itself, the last of which lands right
+	 * on its end. */
+	sweep: u64,
+	/** Number of frames in the whole source, silence included. */
+	frames: u64,
+	/** Index of the next frame to be generated. */
+	next: u64,
+	format: cpal::SampleFormat,
+	endian: Endianness,
+	quantizer: Quantizer,
+	/** The frame currently being handed out. */
+	frame: Vec<u8>,
+	/** How much of the current frame has been handed out already. */
+	position: usize,
+}
+impl Sweep {
+	/** Creates a new sweep in the given format. */
+	pub fn new(
+		spec: SweepSpec,
+		channels: u16,
+		rate: u32,
+		format: cpal::SampleFormat,
+		endian: Endianness) -> Self {
+
+		let frame = vec![0; format.sample_size() * usize::from(channels)];
+		let position = frame.len();
+
+		let sweep = (spec.duration * f64::from(rate)).round() as u64 + 1;
+		Self {
+			spec,
+			rate,
+			amplitude: 10f64.powf(DEFAULT_SWEEP_LEVEL / 20.0),
+			before: 0,
+			sweep,
+			frames: sweep,
+			next: 0,
+			format,
+			endian,
+			quantizer: Quantizer::new(Dither::Triangular),
+			frame,
+			position
+		}
+	}
+
+	/** Plays the sweep at the given peak level, in dBFS. */
+	pub fn level(mut self, level: f64) -> Self {
+		self.amplitude = 10f64.powf(level / 20.0);
+		self
+	}
+
+	/** Plays the given number of seconds of silence before and after the
+	 * sweep. */
+	pub fn padding(mut self, seconds: f64) -> Self {
+		self.before = (seconds * f64::from(self.rate)).round() as u64;
+		self.frames = self.sweep + self.before * 2;
+		self
+	}
+
+	/** Fills the current frame in with the next sample of the sweep. */
+	fn generate(&mut self) {
+		let index = self.next.wrapping_sub(self.before);
+		if index >= self.sweep {
+			crate::convert::silence(self.format, self.endian, &mut self.frame);
+		} else {
+			let time = index as f64 / f64::from(self.rate);
+			let sample = (phase(&self.spec, time).sin() * self.amplitude) as f32;
+
+			let size = self.format.sample_size();
+			for bytes in self.frame.chunks_exact_mut(size) {
+				crate::convert::encode(&mut self.quantizer, self.format, self.endian, sample, bytes);
+			}
+		}
+
+		self.next += 1;
+		self.position = 0;
+	}
+}
+impl Read for Sweep {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		let mut written = 0;
+		while written < buf.len() {
+			if self.position == self.frame.len() {
+				if self.next == self.frames { break }
+				self.generate();
+			}
+
+			let len = usize::min(buf.len() - written, self.frame.len() - self.position);
+			buf[written..written + len]
+				.copy_from_slice(&self.frame[self.position..self.position + len]);
+			written += len;
+			self.position += len;
+		}
+
+		Ok(written)
+	}
+}
+impl crate::src::Source for Sweep {
+	fn len_hint(&self) -> Option<u64> {
+		let left = (self.frames - self.next) * self.frame.len() as u64;
+		Some(left + (self.frame.len() - self.position) as u64)
+	}
+
+	fn description(&self) -> String {
+		format!("a {} sweep from {}Hz to {}Hz",
+			if self.spec.linear { "linear" } else { "logarithmic" },
+			self.spec.start,
+			self.spec.end)
+	}
+}
+
+/** Level noise gets played at when none is given, in dBFS RMS, which leaves
+ * plenty of room for its peaks. */
+pub const DEFAULT_NOISE_LEVEL: f64 = -20.0;
+
+/** Poles of the filter white noise is shaped into pink noise with. These, and
+ * the gains below, are Paul Kellet's economy filter, which stays within half
+ * a decibel of a -3dB per octave slope from around 10Hz up, at 44.1kHz. */
+const PINK_POLES: [f64; 3] = [0.99765, 0.96300, 0.57000];
+/** Gains of the white noise fed into each of the poles of the pink filter. */
+const PINK_GAINS: [f64; 3] = [0.0990460, 0.2965164, 1.0526913];
+/** Gain of the white noise that goes through the pink filter untouched. */
+const PINK_DIRECT: f64 = 0.1848;
+
+/** Color of the noise to be generated. */
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum NoiseColor {
+	/** The same power at every frequency. */
+	White,
+	/** Power falling off by 3dB every octave, so the same in every octave. */
+	Pink,
+}
+
+/** Parses the color of the noise, as given on the command line. */
+pub fn parse_color(value: &str) -> Option<NoiseColor> {
+	match value {
+		"white" => Some(NoiseColor::White),
+		"pink" => Some(NoiseColor::Pink),
+		_ => None
+	}
+}
+
+/** RMS level of what comes out of the pink filter, for white noise of unit RMS
+ * level going in. The filter is a sum of one-pole sections and a direct path,
+ * all fed the same noise, so its power works out to
+ * `d² + 2d Σ cᵢ + Σᵢ Σⱼ cᵢ cⱼ / (1 - pᵢ pⱼ)`. */
+pub fn pink_gain() -> f64 {
+	let mut power = PINK_DIRECT * PINK_DIRECT
+		+ 2.0 * PINK_DIRECT * PINK_GAINS.iter().sum::<f64>();
+	for i in 0..PINK_POLES.len() {
+		for j in 0..PINK_POLES.len() {
+			power += PINK_GAINS[i] * PINK_GAINS[j] / (1.0 - PINK_POLES[i] * PINK_POLES[j]);
+		}
+	}
+
+	power.sqrt()
+}
+
+/** A generator of noise of unit RMS level, fully determined by its seed. */
+#[derive(Debug, Clone)]
+pub struct NoiseGenerator {
+	color: NoiseColor,
+	/** State of the SplitMix64 generator the noise comes from. */
+	state: u64,
+	/** Second of the pair of normally distributed values last generated. */
+	spare: Option<f64>,
+	/** State of every section of the pink filter. */
+	pink: [f64; 3],
+	/** Gain bringing the pink filter back to unit RMS level. */
+	pink_scale: f64,
+}
+impl NoiseGenerator {
+	/** Creates a new generator of noise of the given color, starting from the
+	 * given seed. */
+	pub fn new(color: NoiseColor, seed: u64) -> Self {
+		Self {
+			color,
+			state: seed,
+			spare: None,
+			pink: [0.0; 3],
+			pink_scale: 1.0 / pink_gain()
+		}
+	}
+
+	/** Next number out of the SplitMix64 generator. */
+	fn next_u64(&mut self) -> u64 {
+		self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+		let mut z = self.state;
+		z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+		z ^ (z >> 31)
+	}
+
+	/** Next normally distributed number, with a mean of zero and a standard
+	 * deviation of one, through the Box-Muller transform. */
+	fn gaussian(&mut self) -> f64 {
+		if let Some(spare) = self.spare.take() {
+			return spare
+		}
+
+		/* The top 53 bits make for a uniform number in (0, 1], which keeps
+		 * the logarithm finite. */
+		let u = ((self.next_u64() >> 11) + 1) as f64 / (1u64 << 53) as f64;
+		let v = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+		let radius = (-2.0 * u.ln()).sqrt();
+		let angle = 2.0 * PI * v;
+
+		self.spare = Some(radius * angle.sin());
+		radius * angle.cos()
+	}
+
+	/** Next sample of the noise. */
+	pub fn next(&mut self) -> f64 {
+		let white = self.gaussian();
+		match self.color {
+			NoiseColor::White => white,
+			NoiseColor::Pink => {
+				let mut pink = white * PINK_DIRECT;
+				for ((state, pole), gain) in self.pink.iter_mut().zip(&PINK_POLES).zip(&PINK_GAINS) {
+					*state = pole * *state + white * gain;
+					pink += *state;
+				}
+				pink * self.pink_scale
+			}
+		}
+	}
+}
+
+/** A source playing noise on every channel, either for a given number of
+ * frames or for as long as it's read from. */
+pub struct Noise {
+	/** Generators for every channel or, when every channel gets the same
+	 * noise, a single one. */
+	generators: Vec<NoiseGenerator>,
+	/** RMS level of the noise, as a linear amplitude. */
+	level: f64,
+	/** Number of frames to be played, if there's an end to it. */
+	frames: Option<u64>,
+	/** Index of the next frame to be generated. */
+	next: u64,
+	format: cpal::SampleFormat,
+	endian: Endianness,
+	quantizer: Quantizer,
+	/** The frame currently being handed out. */
+	frame: Vec<u8>,
+	/** How much of the current frame has been handed out already. */
+	position: usize,
+}
+impl Noise {
+	/** Creates a new source of noise in the given format, at the given level,
+	 * in dBFS RMS. With decorrelation, every channel gets noise of its own,
+	 * seeded apart from the others. Otherwise, they all play the same. */
+	#[allow(clippy::too_many_arguments)]
+	pub fn new(
+		color: NoiseColor,
+		level: f64,
+		seed: u64,
+		decorrelate: bool,
+		frames: Option<u64>,
+		channels: u16,
+		format: cpal::SampleFormat,
+		endian: Endianness) -> Self {
+
+		let count = if decorrelate { usize::from(channels) } else { 1 };
+		let generators = (0..count as u64)
+			.map(|channel| {
+				/* Seeds get scrambled, rather than just offset, so that the
+				 * channels don't end up with shifted copies of one another. */
+				let mut seeder = NoiseGenerator::new(color, seed.wrapping_add(channel));
+				NoiseGenerator::new(color, seeder.next_u64())
+			})
+			.collect();
+
+		let frame = vec![0; format.sample_size() * usize::from(channels)];
+		let position = frame.len();
+
+		Self {
+			generators,
+			level: 10f64.powf(level / 20.0),
+			frames,
+			next: 0,
+			format,
+			endian,
+			quantizer: Quantizer::new(Dither::Triangular),
+			frame,
+			position
+		}
+	}
+
+	/** Fills the current frame in with the next sample of the noise. Peaks
+	 * past full scale get clipped. */
+	fn generate(&mut self) {
+		let size = self.format.sample_size();
+		let mut sample = 0.0;
+		for (channel, bytes) in self.frame.chunks_exact_mut(size).enumerate() {
+			if let Some(generator) = self.generators.get_mut(channel) {
+				sample = (generator.next() * self.level).clamp(-1.0, 1.0) as f32;
+			}
+			crate::convert::encode(&mut self.quantizer, self.format, self.endian, sample, bytes);
+		}
+
+		self.next += 1;
+		self.position = 0;
+	}
+}
+impl Read for Noise {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		let mut written = 0;
+		while written < buf.len() {
+			if self.position == self.frame.len() {
+				if Some(self.next) == self.frames { break }
+				self.generate();
+			}
+
+			let len = usize::min(buf.len() - written, self.frame.len() - self.position);
+			buf[written..written + len]
+				.copy_from_slice(&self.frame[self.position..self.position + len]);
+			written += len;
+			self.position += len;
+		}
+
+		Ok(written)
+	}
+}
+impl crate::src::Source for Noise {
+	fn len_hint(&self) -> Option<u64> {
+		let frames = self.frames?;
+		let left = (frames - self.next) * self.frame.len() as u64;
+		Some(left + (self.frame.len() - self.position) as u64)
+	}
+
+	fn description(&self) -> String {
+		match self.generators[0].color {
+			NoiseColor::White => "white noise".to_owned(),
+			NoiseColor::Pink => "pink noise".to_owned()
+		}
+	}
+}
+
+/** Frequency of the bursts played to tell channels apart, in Hz. */
+const BURST_FREQUENCY: f64 = 1000.0;
+/** Length of the fades at both ends of a burst, in milliseconds, which keep
+ * it from clicking as it starts and stops. */
+const BURST_FADE_MS: f64 = 5.0;
+
+/** A source playing a burst of tone on every channel in turn, with the other
+ * channels silent and a gap of silence after every burst, going around the
+ * channels a given number of times or until interrupted. The channel being
+ * played can be announced as it's heard. */
+pub struct ChannelId {
+	channels: u16,
+	/** Number of frames played every second. */
+	rate: u32,
+	/** Peak level of the bursts, as a linear amplitude. */
+	amplitude: f64,
+	/** Number of frames in a burst. */
+	burst: u64,
+	/** Number of frames in the gap after a burst. */
+	gap: u64,
+	/** How many times to go around the channels, if not until interrupted. */
+	rounds: Option<u64>,
+	/** Index of the next frame to be generated. */
+	next: u64,
+	format: cpal::SampleFormat,
+	endian: Endianness,
+	quantizer: Quantizer,
+	/** The frame currently being handed out. */
+	frame: Vec<u8>,
+	/** How much of the current frame has been handed out already. */
+	position: usize,
+	/** Where the channels get sent to be announced, along with when they
+	 * start being heard. */
+	announcer: Option<SyncSender<(Instant, u16)>>,
+}
+impl ChannelId {
+	/** Creates a new source of bursts in the given format, with bursts and
+	 * gaps of the given lengths, in frames, and bursts at the given peak
+	 * level, in dBFS. Leaving the number of rounds out has it go around the
+	 * channels until interrupted. */
+	#[allow(clippy::too_many_arguments)]
+	pub fn new(
+		channels: u16,
+		rate: u32,
+		level: f64,
+		burst: u64,
+		gap: u64,
+		rounds: Option<u64>,
+		announce: bool,
+		format: cpal::SampleFormat,
+		endian: Endianness) -> Self {
+
+		/* Announcements wait on a thread of their own for the bursts to come
+		 * out of the device, as they get generated well ahead of that. The
+		 * thread goes away along with the source. */
+		let announcer = if announce {
+			let (sender, receiver) = std::sync::mpsc::sync_channel::<(Instant, u16)>(16);
+			std::thread::spawn(move || for (at, channel) in receiver {
+				if let Some(wait) = at.checked_duration_since(Instant::now()) {
+					std::thread::sleep(wait);
+				}
+				eprintln!("now playing: channel {}", channel);
+			});
+			Some(sender)
+		} else {
+			None
+		};
+
+		let frame = vec![0; format.sample_size() * usize::from(channels)];
+		let position = frame.len();
+
+		Self {
+			channels,
+			rate,
+			amplitude: 10f64.powf(level / 20.0),
+			burst: burst.max(1),
+			gap,
+			rounds,
+			next: 0,
+			format,
+			endian,
+			quantizer: Quantizer::new(Dither::Triangular),
+			frame,
+			position,
+			announcer
+		}
+	}
+
+	/** Number of frames in all of the rounds, if there's an end to them. */
+	fn length(&self) -> Option<u64> {
+		self.rounds.map(|rounds| rounds * (self.burst + self.gap) * u64::from(self.channels))
+	}
+
+	/** Fills the current frame in with the next frame of the bursts. */
+	fn generate(&mut self) {
+		let period = self.burst + self.gap;
+		let index = self.next % period;
+		let playing = ((self.next / period) % u64::from(self.channels)) as usize;
+
+		let sample = if index < self.burst {
+			let time = index as f64 / f64::from(self.rate);
+			let fade = BURST_FADE_MS / 1000.0 * f64::from(self.rate);
+			let distance = index.min(self.burst - 1 - index) as f64;
+			let envelope = if distance < fade {
+				0.5 - 0.5 * (PI * distance / fade).cos()
+			} else {
+				1.0
+			};
+			(2.0 * PI * BURST_FREQUENCY * time).sin() * envelope * self.amplitude
+		} else {
+			0.0
+		};
+
+		let size = self.format.sample_size();
+		for (channel, bytes) in self.frame.chunks_exact_mut(size).enumerate() {
+			if channel == playing && index < self.burst {
+				crate::convert::encode(&mut self.quantizer, self.format, self.endian, sample as f32, bytes);
+			} else {
+				crate::convert::silence(self.format, self.endian, bytes);
+			}
+		}
+
+		self.next += 1;
+		self.position = 0;
+	}
+
+	/** Announces the burst starting with the next frame, if one does, for
+	 * when it's heard. Whatever is read gets played once the device is done
+	 * with what it has already, which is taken to be as much as gets read at
+	 * a time, so the burst is heard that long after its place in the data
+	 * being read. */
+	fn announce(&self, offset: usize, length: usize) {
+		let announcer = match &self.announcer {
+			Some(announcer) => announcer,
+			None => return
+		};
+		let period = self.burst + self.gap;
+		if !self.next.is_multiple_of(period) { return }
+
+		let frame = self.frame.len();
+		let frames = (offset + length) / frame;
+		let at = Instant::now()
+			+ std::time::Duration::from_secs_f64(frames as f64 / f64::from(self.rate));
+		let channel = ((self.next / period) % u64::from(self.channels)) as u16;
+
+		/* Falling behind on announcements is better than holding up the
+		 * output over them. */
+		let _ = announcer.try_send((at, channel));
+	}
+}
+impl Read for ChannelId {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		let mut written = 0;
+		while written < buf.len() {
+			if self.position == self.frame.len() {
+				if Some(self.next) == self.length() { break }
+				self.announce(written, buf.len());
+				self.generate();
+			}
+
+			let len = usize::min(buf.len() - written, self.frame.len() - self.position);
+			buf[written..written + len]
+				.copy_from_slice(&self.frame[self.position..self.position + len]);
+			written += len;
+			self.position += len;
+		}
+
+		Ok(written)
+	}
+}
+impl crate::src::Source for ChannelId {
+	fn len_hint(&self) -> Option<u64> {
+		let left = (self.length()? - self.next) * self.frame.len() as u64;
+		Some(left + (self.frame.len() - self.position) as u64)
+	}
+
+	fn description(&self) -> String {
+		format!("bursts on each of {} channels", self.channels)
+	}
+}
+
+/** Frequency of the clicks played on every beat, in Hz. */
+const CLICK_FREQUENCY: f64 = 1000.0;
+/** Frequency of the clicks played on accented beats, in Hz, high enough above
+ * the others to be told apart from them. */
+const ACCENT_FREQUENCY: f64 = 1500.0;
+/** Time it takes a click to decay by a factor of e, in milliseconds. */
+const CLICK_DECAY_MS: f64 = 3.0;
+/** Length of a click, in milliseconds, by which point it's decayed 60dB. */
+const CLICK_MS: f64 = 21.0;
+
+/** A source playing a click on every beat at a given tempo, with every so
+ * many beats accented, either for a given number of frames or for as long as
+ * it's read from. Beats start on frames computed from the tempo with integer
+ * arithmetic, so they stay on the exact frame they belong on however long it
+ * plays for. */
+pub struct Click {
+	/** Number of frames played every second. */
+	rate: u32,
+	/** Tempo, in thousandths of a beat per minute. */
+	tempo: u64,
+	/** Every how many beats one is accented, if any are. */
+	accent: Option<u64>,
+	/** Peak level of the clicks, as a linear amplitude. */
+	amplitude: f64,
+	/** Number of frames in a click. */
+	click: u64,
+	/** Number of frames to be played, if there's an end to it. */
+	frames: Option<u64>,
+	/** Index of the next frame to be generated. */
+	next: u64,
+	/** Index of the beat the next frame falls in. */
+	beat: u64,
+	format: cpal::SampleFormat,
+	endian: Endianness,
+	quantizer: Quantizer,
+	/** The frame currently being handed out. */
+	frame: Vec<u8>,
+	/** How much of the current frame has been handed out already. */
+	position: usize,
+}
+impl Click {
+	/** Creates a new source of clicks in the given format, at the given tempo,
+	 * in beats per minute, and at the given peak level, in dBFS. */
+	#[allow(clippy::too_many_arguments)]
+	pub fn new(
+		bpm: f64,
+		accent: Option<u64>,
+		level: f64,
+		frames: Option<u64>,
+		channels: u16,
+		rate: u32,
+		format: cpal::SampleFormat,
+		endian: Endianness) -> Self {
+
+		let frame = vec![0; format.sample_size() * usize::from(channels)];
+		let position = frame.len();
+
+		Self {
+			rate,
+			tempo: ((bpm * 1000.0).round() as u64).max(1),
+			accent: accent.filter(|accent| *accent > 1),
+			amplitude: 10f64.powf(level / 20.0),
+			click: (CLICK_MS / 1000.0 * f64::from(rate)).round() as u64,
+			frames,
+			next: 0,
+			beat: 0,
+			format,
+			endian,
+			quantizer: Quantizer::new(Dither::Triangular),
+			frame,
+			position
+		}
+	}
+
+	/** Index of the frame the given beat starts on. */
+	pub fn start(&self, beat: u64) -> u64 {
+		let frames = u128::from(beat) * 60_000 * u128::from(self.rate) / u128::from(self.tempo);
+		frames as u64
+	}
+
+	/** Fills the current frame in with the next frame of clicks. Everything
+	 * between the clicks is exact silence. */
+	fn generate(&mut self) {
+		while self.start(self.beat + 1) <= self.next {
+			self.beat += 1;
+		}
+
+		let index = self.next - self.start(self.beat);
+		if index < self.click {
+			let accented = self.accent
+				.map(|accent| self.beat.is_multiple_of(accent))
+				.unwrap_or(false);
+			let frequency = if accented { ACCENT_FREQUENCY } else { CLICK_FREQUENCY };
+
+			let time = index as f64 / f64::from(self.rate);
+			let envelope = (-time * 1000.0 / CLICK_DECAY_MS).exp();
+			let sample = ((2.0 * PI * frequency * time).sin() * envelope * self.amplitude) as f32;
+
+			let size = self.format.sample_size();
+			for bytes in self.frame.chunks_exact_mut(size) {
+				crate::convert::encode(&mut self.quantizer, self.format, self.endian, sample, bytes);
+			}
+		} else {
+			crate::convert::silence(self.format, self.endian, &mut self.frame);
+		}
+
+		self.next += 1;
+		self.position = 0;
+	}
+}
+impl Read for Click {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		let mut written = 0;
+		while written < buf.len() {
+			if self.position == self.frame.len() {
+				if Some(self.next) == self.frames { break }
+				self.generate();
+			}
+
+			let len = usize::min(buf.len() - written, self.frame.len() - self.position);
+			buf[written..written + len]
+				.copy_from_slice(&self.frame[self.position..self.position + len]);
+			written += len;
+			self.position += len;
+		}
+
+		Ok(written)
+	}
+}
+impl crate::src::Source for Click {
+	fn len_hint(&self) -> Option<u64> {
+		let frames = self.frames?;
+		let left = (frames - self.next) * self.frame.len() as u64;
+		Some(left + (self.frame.len() - self.position) as u64)
+	}
+
+	fn description(&self) -> String {
+		format!("clicks at {}bpm", self.tempo as f64 / 1000.0)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/** Everything in the given source, as single precision samples. */
+	fn floats<R: Read>(mut source: R) -> Vec<f32> {
+		let mut data = Vec::new();
+		source.read_to_end(&mut data).unwrap();
+		data.chunks_exact(4)
+			.map(|sample| f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]))
+			.collect()
+	}
+
+	fn rms<I>(samples: I) -> f64
+		where I: IntoIterator<Item = f64> {
+
+		let (sum, count) = samples.into_iter()
+			.fold((0.0, 0), |(sum, count), sample| (sum + sample * sample, count + 1));
+		(sum / f64::from(count)).sqrt()
+	}
+
+	/** Magnitude of the response of the pink filter at the given frequency, in
+	 * dB, at 44.1kHz. */
+	fn pink_response(frequency: f64) -> f64 {
+		let omega = 2.0 * PI * frequency / 44100.0;
+		let (mut re, mut im) = (PINK_DIRECT, 0.0);
+		for (pole, gain) in PINK_POLES.iter().zip(&PINK_GAINS) {
+			/* gain / (1 - pole e^-jω) */
+			let (dr, di) = (1.0 - pole * omega.cos(), pole * omega.sin());
+			let norm = dr * dr + di * di;
+			re += gain * dr / norm;
+			im -= gain * di / norm;
+		}
+		10.0 * (re * re + im * im).log10()
+	}
+
+	#[test]
+	fn pink_filter_falls_by_3db_every_octave() {
+		/* Taking the ideal slope out leaves a flat line, within half a
+		 * decibel either way, across the whole audible range. */
+		let flattened = (0..=110)
+			.map(|step| 10.0 * 2f64.powf(f64::from(step) / 10.0))
+			.map(|frequency| pink_response(frequency) + 10.0 * frequency.log10())
+			.collect::<Vec<_>>();
+		let mean = flattened.iter().sum::<f64>() / flattened.len() as f64;
+		for (step, level) in flattened.iter().enumerate() {
+			assert!((level - mean).abs() < 0.5, "{}dB off at step {}", level - mean, step);
+		}
+
+		/* Every octave has the same power, over a few of them at a time. */
+		for &frequency in &[20.0, 100.0, 1000.0] {
+			let slope = (pink_response(frequency * 16.0) - pink_response(frequency)) / 4.0;
+			assert!((slope + 3.0).abs() < 0.25, "{}dB per octave from {}Hz up", slope, frequency);
+		}
+	}
+
+	#[test]
+	fn noise_has_unit_rms_level() {
+		for &color in &[NoiseColor::White, NoiseColor::Pink] {
+			let mut generator = NoiseGenerator::new(color, 1);
+			let samples = (0..200_000).map(|_| generator.next()).collect::<Vec<_>>();
+			let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+
+			assert!((rms(samples.iter().copied()) - 1.0).abs() < 0.03, "{:?}", color);
+			assert!(mean.abs() < 0.05, "{:?}", color);
+		}
+	}
+
+	#[test]
+	fn noise_is_fixed_by_its_seed() {
+		/* Changing any of these changes every recording made with a seed. */
+		let mut white = NoiseGenerator::new(NoiseColor::White, 42);
+		let golden = [0.4147197504315305, 0.6526812221519427, -0.8918862136277562, 1.3268335628141064];
+		for expected in golden.iter() {
+			assert!((white.next() - expected).abs() < 1e-12);
+		}
+		let mut pink = NoiseGenerator::new(NoiseColor::Pink, 42);
+		let golden = [0.22734352365442342, 0.49483162794959934, -0.17362727408354944, 0.6671092291054154];
+		for expected in golden.iter() {
+			assert!((pink.next() - expected).abs() < 1e-12);
+		}
+
+		let noise = |seed| floats(Noise::new(
+			NoiseColor::Pink, -20.0, seed, true, Some(1000), 2,
+			cpal::SampleFormat::F32, Endianness::Little));
+		assert_eq!(noise(7), noise(7));
+		assert_ne!(noise(7), noise(8));
+	}
+
+	#[test]
+	fn noise_plays_at_its_level_for_its_duration() {
+		let noise = Noise::new(
+			NoiseColor::White, -20.0, 3, false, Some(48000), 2,
+			cpal::SampleFormat::F32, Endianness::Little);
+		assert_eq!(crate::src::Source::len_hint(&noise), Some(48000 * 2 * 4));
+
+		let samples = floats(noise);
+		assert_eq!(samples.len(), 48000 * 2);
+		let level = 20.0 * rms(samples.iter().map(|sample| f64::from(*sample))).log10();
+		assert!((level + 20.0).abs() < 0.2, "{}dBFS", level);
+	}
+
+	#[test]
+	fn noise_is_decorrelated_only_when_asked() {
+		let noise = |decorrelate| floats(Noise::new(
+			NoiseColor::White, -10.0, 5, decorrelate, Some(10_000), 2,
+			cpal::SampleFormat::F32, Endianness::Little));
+
+		let same = noise(false);
+		assert!(same.chunks_exact(2).all(|frame| frame[0] == frame[1]));
+
+		/* Independent channels are barely correlated at all. */
+		let apart = noise(true);
+		let (mut both, mut left, mut right) = (0.0, 0.0, 0.0);
+		for frame in apart.chunks_exact(2) {
+			let (l, r) = (f64::from(frame[0]), f64::from(frame[1]));
+			both += l * r;
+			left += l * l;
+			right += r * r;
+		}
+		assert!((both / (left * right).sqrt()).abs() < 0.05);
+	}
+
+	/** Frequency of the given mono signal around the given time, in Hz, from
+	 * the zero crossings within a few milliseconds of it. */
+	fn frequency_at(samples: &[f32], rate: u32, time: f64) -> f64 {
+		let rate = f64::from(rate);
+		let window = (0.005 * rate) as usize;
+		let center = (time * rate) as usize;
+		let range = center.saturating_sub(window)..usize::min(center + window, samples.len() - 1);
+
+		/* Crossings are placed in between samples, by linear interpolation. */
+		let crossings = range
+			.filter(|&i| (samples[i] < 0.0) != (samples[i + 1] < 0.0))
+			.map(|i| {
+				let (a, b) = (f64::from(samples[i]), f64::from(samples[i + 1]));
+				(i as f64 + a / (a - b)) / rate
+			})
+			.collect::<Vec<_>>();
+
+		let span = crossings[crossings.len() - 1] - crossings[0];
+		(crossings.len() - 1) as f64 / (2.0 * span)
+	}
+
+	fn sweep(start: f64, end: f64, linear: bool) -> Vec<f32> {
+		let spec = SweepSpec { start, end, duration: 2.0, linear };
+		floats(Sweep::new(spec, 1, 48000, cpal::SampleFormat::F32, Endianness::Little))
+	}
+
+	#[test]
+	fn logarithmic_sweeps_spend_the_same_time_on_every_octave() {
+		let samples = sweep(100.0, 6400.0, false);
+		for &(time, expected) in &[(0.05, 100.0 * 2f64.powf(0.15)), (0.5, 283.0), (1.0, 800.0), (1.5, 2263.0)] {
+			let measured = frequency_at(&samples, 48000, time);
+			assert!((measured / expected - 1.0).abs() < 0.02, "{}Hz at {}s, not {}Hz", measured, time, expected);
+		}
+
+		/* Falling sweeps go through the same frequencies backwards. */
+		let samples = sweep(6400.0, 100.0, false);
+		let measured = frequency_at(&samples, 48000, 1.0);
+		assert!((measured / 800.0 - 1.0).abs() < 0.02, "{}Hz", measured);
+	}
+
+	#[test]
+	fn linear_sweeps_spend_the_same_time_on_every_hertz() {
+		let samples = sweep(100.0, 4100.0, true);
+		for &(time, expected) in &[(0.1, 300.0), (0.5, 1100.0), (1.0, 2100.0), (1.5, 3100.0)] {
+			let measured = frequency_at(&samples, 48000, time);
+			assert!((measured / expected - 1.0).abs() < 0.02, "{}Hz at {}s, not {}Hz", measured, time, expected);
+		}
+	}
+
+	#[test]
+	fn sweeps_end_right_on_their_end_frequency() {
+		for &linear in &[false, true] {
+			let spec = SweepSpec { start: 20.0, end: 20000.0, duration: 10.0, linear };
+
+			/* The phase rises at the end frequency at the very end. */
+			let step = 1e-6;
+			let frequency = (phase(&spec, 10.0) - phase(&spec, 10.0 - step)) / (2.0 * PI * step);
+			assert!((frequency - 20000.0).abs() < 0.1, "{}Hz", frequency);
+
+			/* The last frame falls on the end of the sweep, and none before
+			 * it jumps in phase by more than the end frequency allows. */
+			let samples = floats(Sweep::new(spec, 1, 48000, cpal::SampleFormat::F32, Endianness::Little));
+			assert_eq!(samples.len(), 10 * 48000 + 1);
+			let expected = (phase(&spec, 10.0).sin() * 10f64.powf(DEFAULT_SWEEP_LEVEL / 20.0)) as f32;
+			assert!((samples[samples.len() - 1] - expected).abs() < 1e-6);
+
+			let largest = 2.0 * PI * 20000.0 / 48000.0;
+			let amplitude = 10f64.powf(DEFAULT_SWEEP_LEVEL / 20.0);
+			for pair in samples.windows(2) {
+				let jump = f64::from(pair[1] - pair[0]).abs();
+				assert!(jump <= largest * amplitude + 1e-6);
+			}
+		}
+	}
+
+	#[test]
+	fn sweeps_are_padded_with_silence() {
+		let spec = SweepSpec { start: 100.0, end: 200.0, duration: 1.0, linear: false };
+		let sweep = Sweep::new(spec, 2, 1000, cpal::SampleFormat::F32, Endianness::Little)
+			.level(-12.0)
+			.padding(0.5);
+		assert_eq!(crate::src::Source::len_hint(&sweep), Some((500 + 1001 + 500) * 2 * 4));
+
+		let samples = floats(sweep);
+		assert_eq!(samples.len(), (500 + 1001 + 500) * 2);
+		assert!(samples[..1000].iter().all(|sample| *sample == 0.0));
+		assert!(samples[samples.len() - 1000..].iter().all(|sample| *sample == 0.0));
+
+		let peak = samples.iter().fold(0f32, |peak, sample| peak.max(sample.abs()));
+		assert!((f64::from(peak) - 10f64.powf(-12.0 / 20.0)).abs() < 0.01);
+	}
+
+	#[test]
+	fn sweeps_parse_from_the_command_line() {
+		assert_eq!(parse_sweep("20:20000:10").unwrap(),
+			SweepSpec { start: 20.0, end: 20000.0, duration: 10.0, linear: false });
+		assert!(parse_sweep("20:20000").is_err());
+		assert!(parse_sweep("0:20000:10").is_err());
+		assert!(parse_sweep("20:20000:-1").is_err());
+		assert!(parse_sweep("20:inf:10").is_err());
+	}
+
+	fn channel_id(rounds: Option<u64>) -> ChannelId {
+		ChannelId::new(4, 8000, -6.0, 400, 200, rounds, false, cpal::SampleFormat::F32, Endianness::Little)
+	}
+
+	#[test]
+	fn channel_id_plays_on_one_channel_at_a_time() {
+		let mut source = channel_id(Some(2));
+		assert_eq!(crate::src::Source::len_hint(&source), Some(2 * 4 * 600 * 4 * 4));
+
+		/* Reads of odd sizes, so frames get split across them. */
+		let mut data = Vec::new();
+		let mut buffer = [0; 1001];
+		loop {
+			match source.read(&mut buffer).unwrap() {
+				0 => break,
+				len => data.extend_from_slice(&buffer[..len])
+			}
+		}
+		let samples = data.chunks_exact(4)
+			.map(|sample| f64::from(f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]])))
+			.collect::<Vec<_>>();
+		assert_eq!(samples.len(), 2 * 4 * 600 * 4);
+
+		for (window, frames) in samples.chunks_exact(600 * 4).enumerate() {
+			let (burst, gap) = frames.split_at(400 * 4);
+			let energy = |channel: usize| burst.iter()
+				.skip(channel)
+				.step_by(4)
+				.map(|sample| sample * sample)
+				.sum::<f64>();
+
+			let playing = window % 4;
+			assert!(energy(playing) > 40.0, "no burst on channel {} in window {}", playing, window);
+			for channel in (0..4).filter(|channel| *channel != playing) {
+				assert_eq!(energy(channel), 0.0, "channel {} heard in window {}", channel, window);
+			}
+			assert!(gap.iter().all(|sample| *sample == 0.0), "noise in the gap of window {}", window);
+		}
+	}
+
+	#[test]
+	fn channel_id_bursts_fade_in_and_out() {
+		let samples = floats(channel_id(Some(1)));
+		let burst = samples.iter().step_by(4).take(400).copied().collect::<Vec<_>>();
+		let peak = 10f32.powf(-6.0 / 20.0);
+
+		assert_eq!(burst[0], 0.0);
+		assert!(burst[399].abs() < 0.01);
+		assert!(burst[200..].iter().take(8).any(|sample| (sample.abs() - peak).abs() < 0.01));
+	}
+
+	#[test]
+	fn channel_id_announces_channels_in_order() {
+		let mut source = channel_id(Some(2));
+		let (sender, receiver) = std::sync::mpsc::sync_channel(16);
+		source.announcer = Some(sender);
+
+		let start = Instant::now();
+		let mut buffer = vec![0; 600 * 4 * 4];
+		while source.read(&mut buffer).unwrap() > 0 {}
+		drop(source);
+
+		let announced = receiver.iter().collect::<Vec<_>>();
+		assert_eq!(announced.iter().map(|(_, channel)| *channel).collect::<Vec<_>>(), [0, 1, 2, 3, 0, 1, 2, 3]);
+
+		/* Every burst is heard no sooner than a buffer after it was read. */
+		assert!(announced.iter().all(|(at, _)| *at >= start + std::time::Duration::from_millis(75)));
+	}
+
+	#[test]
+	fn channel_id_goes_around_until_interrupted() {
+		let mut source = channel_id(None);
+		assert_eq!(crate::src::Source::len_hint(&source), None);
+
+		let mut data = Vec::new();
+		(&mut source).take(10 * 4 * 600 * 4 * 4).read_to_end(&mut data).unwrap();
+		assert_eq!(data.len(), 10 * 4 * 600 * 4 * 4);
+	}
+
+	#[test]
+	fn clicks_land_on_their_exact_frames() {
+		/* 133bpm at 8kHz is 3609.02... frames a beat, which any step added up
+		 * beat after beat would drift away from over five minutes. */
+		let click = Click::new(133.0, None, -6.0, Some(5 * 60 * 8000), 1, 8000,
+			cpal::SampleFormat::F32, Endianness::Little);
+		let samples = floats(click);
+		assert_eq!(samples.len(), 5 * 60 * 8000);
+
+		/* Clicks are sines, so they start from zero, and are heard from the
+		 * frame after the one they start on. */
+		let onsets = (1..samples.len())
+			.filter(|&i| samples[i] != 0.0 && samples[i - 1] == 0.0)
+			.map(|i| i as u64 - 1)
+			.collect::<Vec<_>>();
+		let expected = (0..665u64)
+			.map(|beat| beat * 60 * 8000 * 1000 / 133_000)
+			.collect::<Vec<_>>();
+		assert_eq!(onsets, expected);
+	}
+
+	#[test]
+	fn accented_clicks_are_higher() {
+		let click = Click::new(120.0, Some(4), -6.0, Some(8 * 4000), 1, 8000,
+			cpal::SampleFormat::F32, Endianness::Little);
+		let samples = floats(click);
+
+		/* The second frame of every click tells the frequencies apart. */
+		let amplitude = 10f64.powf(-6.0 / 20.0) * (-1.0 / 8.0 / CLICK_DECAY_MS).exp();
+		let second = |frequency: f64| (amplitude * (2.0 * PI * frequency / 8000.0).sin()) as f32;
+		for beat in 0..8 {
+			let expected = if beat % 4 == 0 { second(ACCENT_FREQUENCY) } else { second(CLICK_FREQUENCY) };
+			assert!((samples[beat * 4000 + 1] - expected).abs() < 1e-6, "beat {}", beat);
+		}
+	}
+
+	#[test]
+	fn clicks_are_short_and_silent_in_between() {
+		let click = Click::new(60.0, None, -6.0, Some(2 * 8000), 2, 8000,
+			cpal::SampleFormat::F32, Endianness::Little);
+		let samples = floats(click);
+		let length = (CLICK_MS / 1000.0 * 8000.0).round() as usize;
+
+		for beat in samples.chunks_exact(2 * 8000) {
+			assert!(beat[..2 * length].iter().any(|sample| *sample != 0.0));
+			assert!(beat[2 * length..].iter().all(|sample| *sample == 0.0));
+		}
+	}
+}
@@ -0,0 +1,272 @@
+use crate::arg::{Endianness, InputFormat};
+use crate::error::Error;
+use std::io::{ErrorKind, Read};
+use std::sync::Mutex;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{Decoder, DecoderOptions};
+use symphonia::core::errors::Error as DecodeError;
+use symphonia::core::formats::{FormatOptions, FormatReader};
+use symphonia::core::io::{MediaSourceStream, ReadOnlySource};
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+/** Checks whether the given bytes look like the start of something the decoder
+ * is worth trying on, returning the name of the container if they do.
+ * Everything else is left to the hand-written readers, as whatever the decoder
+ * rejects after having read from a stream can't be played as raw samples
+ * anymore. */
+pub fn recognize(bytes: &[u8]) -> Option<&'static str> {
+	let at = |offset: usize, magic: &[u8]| bytes.get(offset..offset + magic.len()) == Some(magic);
+
+	if at(0, b"RIFF") && at(8, b"WAVE") {
+		Some("WAV")
+	} else if at(0, b"fLaC") {
+		Some("FLAC")
+	} else if at(0, b"OggS") {
+		Some("Ogg")
+	} else if at(4, b"ftyp") {
+		Some("MP4")
+	} else if at(0, &[0x1a, 0x45, 0xdf, 0xa3]) {
+		Some("Matroska")
+	} else {
+		None
+	}
+}
+
+/** Adapter that lets a reader that's only safe to send across threads be used
+ * where one that's safe to share between them is wanted. */
+struct Shared<R>(Mutex<R>);
+impl<R> Read for Shared<R>
+	where R: Read {
+
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		self.0.get_mut().unwrap().read(buf)
+	}
+}
+
+/** Maps errors from the decoder into I/O errors, keeping their text. */
+fn io_error(what: DecodeError) -> std::io::Error {
+	match what {
+		DecodeError::IoError(what) => what,
+		what => std::io::Error::new(ErrorKind::InvalidData, what.to_string())
+	}
+}
+
+/** Whether the given error from the decoder just means the stream is over. */
+fn is_end(what: &DecodeError) -> bool {
+	match what {
+		DecodeError::IoError(what) => what.kind() == ErrorKind::UnexpectedEof,
+		_ => false
+	}
+}
+
+/** A reader that decodes the packets of the default track of whatever the
+ * decoder found in the input as they're needed, handing out their samples
+ * interleaved, as single precision floating point, in native byte order. */
+pub struct UniversalSource {
+	format: Box<dyn FormatReader>,
+	decoder: Box<dyn Decoder>,
+	/** Identifier of the track being played. */
+	track: u32,
+	/** Number of channels the track started out with. */
+	channels: usize,
+	/** Decoded samples that have yet to be handed out. */
+	output: Vec<u8>,
+	/** How much of the decoded samples has been handed out already. */
+	offset: usize,
+	/** Number of frames the container says the track holds, along with how
+	 * many bytes every one of them takes up in it, when both are known. */
+	expected: Option<(u64, u64)>,
+	/** Number of frames decoded so far. */
+	decoded: u64,
+}
+impl UniversalSource {
+	/** Decodes the next packet of the track into the output buffer. Returns
+	 * whether there was any packet left to decode. */
+	fn decode(&mut self) -> std::io::Result<bool> {
+		loop {
+			let packet = match self.format.next_packet() {
+				Ok(packet) => packet,
+				Err(what) if is_end(&what) => return self.end(),
+				Err(what) => return Err(io_error(what))
+			};
+			if packet.track_id() != self.track { continue }
+
+			let decoded = match self.decoder.decode(&packet) {
+				Ok(decoded) => decoded,
+				/* A single corrupt packet isn't worth stopping over. */
+				Err(DecodeError::DecodeError(what)) => {
+					eprintln!("warning: skipping a corrupt packet: {}", what);
+					continue
+				},
+				Err(what) => return Err(io_error(what))
+			};
+
+			self.decoded += decoded.frames() as u64;
+			let spec = *decoded.spec();
+			if spec.channels.count() != self.channels {
+				return Err(std::io::Error::new(
+					ErrorKind::InvalidData,
+					format!("the track changes from {} to {} channels",
+						self.channels,
+						spec.channels.count())))
+			}
+
+			let mut samples = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+			samples.copy_interleaved_ref(decoded);
+
+			self.output.clear();
+			self.offset = 0;
+			for sample in samples.samples() {
+				self.output.extend(&sample.to_ne_bytes());
+			}
+
+			return Ok(true)
+		}
+	}
+
+	/** Checks whether the track really is over once the input runs out, which
+	 * it isn't when the container said it held more frames than could be
+	 * decoded from it. */
+	fn end(&self) -> std::io::Result<bool> {
+		match self.expected {
+			Some((frames, bytes)) if self.decoded < frames =>
+				Err(std::io::Error::new(
+					ErrorKind::UnexpectedEof,
+					Error::TruncatedData {
+						expected: frames * bytes,
+						got: self.decoded * bytes
+					})),
+			_ => Ok(false)
+		}
+	}
+}
+impl Read for UniversalSource {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		if buf.is_empty() { return Ok(0) }
+		while self.offset == self.output.len() {
+			if !self.decode()? { return Ok(0) }
+		}
+
+		let len = usize::min(buf.len(), self.output.len() - self.offset);
+		buf[..len].copy_from_slice(&self.output[self.offset..self.offset + len]);
+		self.offset += len;
+
+		Ok(len)
+	}
+}
+
+/** Opens whatever the decoder can make of the given source, from its very
+ * start. The source is only ever read forward, so this works for pipes as well
+ * as it does for files. Returns a reader over the decoded samples of its
+ * default track, along with their format. */
+pub fn open<R>(source: R) -> Result<(Box<dyn Read + Send>, InputFormat), Error>
+	where R: Read + Send + 'static {
+
+	let stream = MediaSourceStream::new(
+		Box::new(ReadOnlySource::new(Shared(Mutex::new(source)))),
+		Default::default());
+	let probed = symphonia::default::get_probe()
+		.format(
+			&Hint::new(),
+			stream,
+			&FormatOptions::default(),
+			&MetadataOptions::default())
+		.map_err(|what| Error::UnsupportedDecode(what.to_string()))?;
+
+	let format = probed.format;
+	let track = format.default_track()
+		.ok_or(Error::UnsupportedDecode("the input has no audio tracks".to_owned()))?;
+	let decoder = symphonia::default::get_codecs()
+		.make(&track.codec_params, &DecoderOptions::default())
+		.map_err(|what| Error::UnsupportedDecode(what.to_string()))?;
+
+	let channels = track.codec_params.channels
+		.map(|channels| channels.count())
+		.unwrap_or(0);
+	let sample_rate = track.codec_params.sample_rate.unwrap_or(0);
+	/* Only containers of raw samples say how many bits they take up, which is
+	 * what lets a shortfall in frames be told in bytes. */
+	let expected = track.codec_params.n_frames
+		.zip(track.codec_params.bits_per_coded_sample)
+		.map(|(frames, bits)| (frames, channels as u64 * u64::from(bits) / 8));
+	if channels == 0 || channels > usize::from(u16::MAX) || sample_rate == 0 {
+		return Err(Error::UnsupportedDecode(
+			"the track doesn't say how many channels it has or at which rate".to_owned()))
+	}
+
+	let input = InputFormat {
+		channels: channels as u16,
+		sample_rate,
+		sample_format: cpal::SampleFormat::F32,
+		endian: Endianness::Native
+	};
+	let source = UniversalSource {
+		track: track.id,
+		format,
+		decoder,
+		channels,
+		output: Vec::new(),
+		offset: 0,
+		expected,
+		decoded: 0
+	};
+
+	Ok((Box::new(source), input))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Cursor;
+
+	/** A mono 16-bit WAV file at 8000Hz whose data chunk claims to hold the
+	 * given number of bytes, with only the given samples in it. */
+	fn wav(claimed: u32, samples: &[i16]) -> Vec<u8> {
+		let mut file = Vec::new();
+		file.extend(b"RIFF");
+		file.extend(&(36 + claimed).to_le_bytes());
+		file.extend(b"WAVEfmt ");
+		file.extend(&16u32.to_le_bytes());
+		file.extend(&1u16.to_le_bytes());
+		file.extend(&1u16.to_le_bytes());
+		file.extend(&8000u32.to_le_bytes());
+		file.extend(&16000u32.to_le_bytes());
+		file.extend(&2u16.to_le_bytes());
+		file.extend(&16u16.to_le_bytes());
+		file.extend(b"data");
+		file.extend(&claimed.to_le_bytes());
+		for sample in samples {
+			file.extend(&sample.to_le_bytes());
+		}
+		file
+	}
+
+	#[test]
+	fn whole_files_end_cleanly() {
+		let (mut source, format) = open(Cursor::new(wav(8, &[0, 16384, -16384, 0]))).unwrap();
+		assert_eq!(format.channels, 1);
+		assert_eq!(format.sample_rate, 8000);
+
+		let mut samples = Vec::new();
+		source.read_to_end(&mut samples).unwrap();
+		assert_eq!(samples.len(), 4 * 4);
+	}
+
+	#[test]
+	fn truncated_files_are_told_apart_from_their_end() {
+		let (mut source, _) = open(Cursor::new(wav(1000, &[0; 50]))).unwrap();
+
+		let mut samples = Vec::new();
+		let what = source.read_to_end(&mut samples).err().unwrap();
+		assert!(Error::is_truncation(&what));
+		match Error::read_failed(what) {
+			Error::TruncatedData { expected, got } => {
+				assert_eq!(expected, 1000);
+				assert!(got < expected);
+				assert_eq!(got, samples.len() as u64 / 4 * 2);
+			},
+			what => panic!("unexpected error: {}", what)
+		}
+	}
+}
@@ -0,0 +1,151 @@
+use crate::arg::{Endianness, InputFormat};
+use crate::error::Error;
+use lewton::inside_ogg::OggStreamReader;
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+
+/** Adapter giving a source that can't seek the seeking interface the decoder
+ * insists on. Only ever asking for the current position works, which is all the
+ * decoder needs for playing a stream from start to end. */
+struct Unseekable<R> {
+	source: R,
+	/** Number of bytes read from the source so far. */
+	position: u64,
+}
+impl<R> Read for Unseekable<R>
+	where R: Read {
+
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		let read = self.source.read(buf)?;
+		self.position += read as u64;
+
+		Ok(read)
+	}
+}
+impl<R> Seek for Unseekable<R> {
+	fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+		match pos {
+			SeekFrom::Current(0) => Ok(self.position),
+			_ => Err(std::io::Error::other(
+				"the input can't be seeked"))
+		}
+	}
+}
+
+/** A reader that decodes the packets of an Ogg Vorbis stream as they're needed,
+ * handing out their samples interleaved and in native byte order. */
+pub struct VorbisSource<R>
+	where R: Read {
+
+	reader: OggStreamReader<Unseekable<R>>,
+	/** Serial number of the logical stream being played. */
+	serial: u32,
+	/** Decoded samples that have yet to be handed out. */
+	output: Vec<u8>,
+	/** How much of the decoded samples has been handed out already. */
+	offset: usize,
+}
+impl<R> VorbisSource<R>
+	where R: Read {
+
+	/** Decodes the next packet of the stream into the output buffer. Returns
+	 * whether there was any packet left to decode. */
+	fn decode(&mut self) -> std::io::Result<bool> {
+		let samples = self.reader.read_dec_packet_itl()
+			.map_err(|what| std::io::Error::new(ErrorKind::InvalidData, what.to_string()))?;
+		let samples = match samples {
+			Some(samples) => samples,
+			None => return Ok(false)
+		};
+
+		/* Further logical streams, be they chained after this one or
+		 * multiplexed with it, may well have a different format, which we have
+		 * no way to switch to. Stop rather than playing them as garbage. */
+		if self.reader.stream_serial() != self.serial {
+			return Err(std::io::Error::new(
+				ErrorKind::InvalidData,
+				"chained or multiplexed Ogg streams are not supported"))
+		}
+
+		self.output.clear();
+		self.offset = 0;
+		for sample in samples {
+			self.output.extend(&sample.to_ne_bytes());
+		}
+
+		Ok(true)
+	}
+}
+impl<R> Read for VorbisSource<R>
+	where R: Read {
+
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		if buf.is_empty() { return Ok(0) }
+		while self.offset == self.output.len() {
+			if !self.decode()? { return Ok(0) }
+		}
+
+		let len = usize::min(buf.len(), self.output.len() - self.offset);
+		buf[..len].copy_from_slice(&self.output[self.offset..self.offset + len]);
+		self.offset += len;
+
+		Ok(len)
+	}
+}
+
+/** Opens the Ogg Vorbis stream in the given source, magic number included, as
+ * Ogg streams are always read from the very start. Returns a reader over the
+ * decoded samples, along with their format. */
+pub fn open<R>(source: R) -> Result<(Box<dyn Read + Send>, InputFormat), Error>
+	where R: Read + Send + 'static {
+
+	let reader = OggStreamReader::new(Unseekable { source, position: 0 })
+		.map_err(|what| Error::MalformedVorbis(what.to_string()))?;
+
+	let channels = reader.ident_hdr.audio_channels;
+	if channels == 0 {
+		return Err(Error::MalformedVorbis("the stream has no channels".to_owned()))
+	}
+
+	let input = InputFormat {
+		channels: u16::from(channels),
+		sample_rate: reader.ident_hdr.audio_sample_rate,
+		sample_format: cpal::SampleFormat::I16,
+		endian: Endianness::Native
+	};
+	let source = VorbisSource {
+		serial: reader.stream_serial(),
+		reader,
+		output: Vec::new(),
+		offset: 0
+	};
+
+	Ok((Box::new(source), input))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/** One second of stereo silence at 8000Hz, in short blocks over two pages,
+	 * the last of which cuts the final block short. */
+	const SILENCE: &[u8] = include_bytes!("../fixtures/silence.ogg");
+
+	#[test]
+	fn packets_get_decoded_up_to_the_end_of_the_stream() {
+		let (mut reader, format) = open(SILENCE).unwrap();
+		assert_eq!(format.channels, 2);
+		assert_eq!(format.sample_rate, 8000);
+		assert_eq!(format.sample_format, cpal::SampleFormat::I16);
+
+		let mut data = Vec::new();
+		reader.read_to_end(&mut data).unwrap();
+		assert_eq!(data.len(), 8000 * 2 * 2);
+		assert!(data.iter().all(|byte| *byte == 0));
+	}
+
+	#[test]
+	fn other_streams_are_refused() {
+		assert!(matches!(open(&SILENCE[..64]), Err(Error::MalformedVorbis(_))));
+		assert!(matches!(open(&b"fLaC\0\0\0\x22"[..]), Err(Error::MalformedVorbis(_))));
+	}
+}
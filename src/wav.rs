@@ -0,0 +1,1081 @@
+use crate::arg::{Endianness, InputFormat};
+use crate::error::Error;
+use crate::detect::{Seeker, Skip, Span};
+use crate::src::{LoopPoints, Source};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+
+/** Format tag for integer PCM data. */
+const TAG_PCM: u16 = 1;
+/** Format tag for IEEE floating point data. */
+const TAG_FLOAT: u16 = 3;
+/** Format tag for the extensible format, in which the actual format is given
+ * by a sub-format GUID. */
+const TAG_EXTENSIBLE: u16 = 0xfffe;
+
+/** All of the sub-format GUIDs we know of share these last 14 bytes, with the
+ * first two bytes holding the equivalent format tag. */
+const GUID_SUFFIX: [u8; 14] = [
+	0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00,
+	0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71
+];
+
+/** Names of the compressed codecs we know the format tags of. */
+const CODECS: [(u16, &'static str); 13] = [
+	(0x0002, "Microsoft ADPCM"),
+	(0x0006, "A-law"),
+	(0x0007, "mu-law"),
+	(0x0011, "IMA ADPCM"),
+	(0x0031, "GSM 6.10"),
+	(0x0050, "MPEG"),
+	(0x0055, "MP3"),
+	(0x0092, "AC-3 over S/PDIF"),
+	(0x00ff, "AAC"),
+	(0x0161, "WMA"),
+	(0x2000, "AC-3"),
+	(0x2001, "DTS"),
+	(0xf1ac, "FLAC"),
+];
+
+/** Maximum number of bytes we're willing to go through looking for the `data`
+ * chunk before giving up on the file. */
+const MAX_HEADER: u64 = 16 * 1024 * 1024;
+/** Maximum length of a `fmt ` chunk we're willing to read. Real ones are never
+ * longer than a few tens of bytes. */
+const MAX_FMT: u64 = 1024;
+
+/** Maximum length of a `smpl` chunk we're willing to read. Every loop takes up
+ * 24 bytes, and real files rarely have more than a handful of them. */
+const MAX_SMPL: u64 = 64 * 1024;
+/** Length of the part of a `smpl` chunk that comes before its loops. */
+const SMPL_HEADER: usize = 36;
+/** Length of every loop in a `smpl` chunk. */
+const SMPL_LOOP: usize = 24;
+
+/** Names of the speaker positions in a channel mask, from the lowest bit up. */
+const SPEAKERS: [&'static str; 18] = [
+	"FL", "FR", "FC", "LFE", "BL", "BR", "FLC", "FRC", "BC",
+	"SL", "SR", "TC", "TFL", "TFC", "TFR", "TBL", "TBC", "TBR"
+];
+
+/** Format of the data in a WAV file, as described by its `fmt ` chunk. */
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct WavFormat {
+	/** The `wFormatTag` field, telling how the samples are encoded. For the
+	 * extensible format, this is the tag equivalent to its sub-format. */
+	pub tag: u16,
+	/** Number of interleaved channels. */
+	pub channels: u16,
+	/** Number of frames per second. */
+	pub sample_rate: u32,
+	/** Number of bits in every sample. */
+	pub bits: u16,
+	/** Number of bits in every sample that actually carry data. */
+	pub valid_bits: u16,
+	/** Which speaker every channel goes to, if the file says so. */
+	pub channel_mask: Option<u32>,
+}
+impl WavFormat {
+	/** Description of this format for the purposes of playback, if it's one we
+	 * know how to play. */
+	pub fn input_format(&self) -> Result<InputFormat, Error> {
+		if self.valid_bits > self.bits {
+			return Err(Error::MalformedWav(
+				format!("{} valid bits don't fit in {} bit samples", self.valid_bits, self.bits)))
+		}
+
+		let sample_format = match (self.tag, self.bits) {
+			(TAG_PCM, 16)   => cpal::SampleFormat::I16,
+			/* Double precision samples get narrowed down as they're read. */
+			(TAG_FLOAT, 32) => cpal::SampleFormat::F32,
+			(TAG_FLOAT, 64) => cpal::SampleFormat::F32,
+			(TAG_FLOAT, bits) =>
+				return Err(Error::MalformedWav(
+					format!("floating point samples can't have {} bits", bits))),
+			/* Compressed data would only ever play as noise. */
+			(tag, _) if tag != TAG_PCM =>
+				return Err(Error::UnsupportedWavCodec {
+					tag,
+					name: CODECS.iter()
+						.find(|(known, _)| *known == tag)
+						.map(|(_, name)| *name)
+				}),
+			(tag, bits) =>
+				return Err(Error::UnsupportedWav { tag, bits })
+		};
+
+		Ok(InputFormat {
+			channels: self.channels,
+			sample_rate: self.sample_rate,
+			sample_format,
+			endian: Endianness::Little
+		})
+	}
+}
+
+/** Names of the speakers in the given channel mask, in channel order. */
+pub fn speakers(mask: u32) -> Vec<&'static str> {
+	SPEAKERS.iter()
+		.enumerate()
+		.filter(|(i, _)| mask & (1 << i) != 0)
+		.map(|(_, name)| *name)
+		.collect()
+}
+
+/** Formats a GUID in its usual textual representation. */
+fn format_guid(guid: &[u8]) -> String {
+	format!("{{{:02x}{:02x}{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}}}",
+		guid[3], guid[2], guid[1], guid[0],
+		guid[5], guid[4],
+		guid[7], guid[6],
+		guid[8], guid[9],
+		guid[10], guid[11], guid[12], guid[13], guid[14], guid[15])
+}
+
+/** Reads the first loop out of the contents of a `smpl` chunk, if it has any.
+ * The chunk gives its end as the last frame in the loop, which gets turned into
+ * the first one past it. */
+fn parse_smpl(chunk: &[u8]) -> Result<Option<LoopPoints>, Error> {
+	if chunk.len() < SMPL_HEADER {
+		return Err(Error::MalformedWav(
+			format!("the smpl chunk is too short ({} bytes)", chunk.len())))
+	}
+
+	let u32_at = |i: usize| u32::from_le_bytes([
+		chunk[i],
+		chunk[i + 1],
+		chunk[i + 2],
+		chunk[i + 3]]);
+	let loops = u32_at(28) as usize;
+	if loops == 0 { return Ok(None) }
+	if chunk.len() < SMPL_HEADER + SMPL_LOOP {
+		return Err(Error::MalformedWav(
+			format!("the smpl chunk claims {} loops, but has no room for any", loops)))
+	}
+
+	let start = u64::from(u32_at(SMPL_HEADER + 8));
+	let end = u64::from(u32_at(SMPL_HEADER + 12)) + 1;
+	if end <= start {
+		eprintln!("warning: ignoring the loop embedded in the file, as it ends before it starts");
+		return Ok(None)
+	}
+
+	Ok(Some(LoopPoints { start, end }))
+}
+
+/** Looks for a `smpl` chunk past the `data` chunk, which is where most editors
+ * put them, starting at the given offset. The source is left wherever it was
+ * before looking. Nothing is found if anything goes wrong along the way, as
+ * this is only ever a nicety. */
+fn trailing_smpl<R>(source: &mut R, seek: Seeker<R>, from: u64) -> Option<LoopPoints>
+	where R: Read {
+
+	let back = seek(source, SeekFrom::Current(0)).ok()?;
+	let mut position = from;
+	let mut found = None;
+	for _ in 0..64 {
+		if seek(source, SeekFrom::Start(position)).is_err() { break }
+
+		let mut header = [0; 8];
+		if source.read_exact(&mut header).is_err() { break }
+		let size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+
+		if &header[0..4] == b"smpl" && u64::from(size) <= MAX_SMPL {
+			let mut chunk = vec![0; size as usize];
+			if source.read_exact(&mut chunk).is_err() { break }
+
+			found = parse_smpl(&chunk).ok().flatten();
+			break
+		}
+		position += 8 + u64::from(size) + u64::from(size & 1);
+	}
+
+	seek(source, SeekFrom::Start(back)).ok()?;
+	found
+}
+
+/** A reader over the samples in the `data` chunk of a WAV file. */
+pub struct WavReader<R> {
+	/** Data in the `data` chunk. */
+	data: Span<R>,
+	/** Length of the `data` chunk, as given in its header, if it gives one. */
+	length: Option<u64>,
+	/** First loop given by the `smpl` chunk, if there's one. */
+	loop_points: Option<LoopPoints>,
+}
+impl<R> WavReader<R>
+	where R: Read {
+
+	/** Reads the header of a WAV file from the given source, up to the start of
+	 * its `data` chunk, skipping over chunks with the given function. The magic
+	 * number is expected to have been consumed already. */
+	fn new(mut source: R, skip: Skip<R>, seek: Option<Seeker<R>>)
+		-> Result<(Self, WavFormat), Error> {
+
+		let mut format = None;
+		let mut loop_points = None;
+		let mut scanned = 12u64;
+		loop {
+			if scanned > MAX_HEADER {
+				return Err(Error::MalformedWav(
+					format!("no data chunk was found within the first {} bytes", MAX_HEADER)))
+			}
+
+			let mut header = [0; 8];
+			read_header(&mut source, &mut header, "before the data chunk")?;
+
+			let id = [header[0], header[1], header[2], header[3]];
+			let size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+
+			/* Chunks are always aligned to two bytes, so the ones with an odd
+			 * length are followed by a padding byte. */
+			let padded = u64::from(size) + u64::from(size & 1);
+			scanned += 8 + padded;
+
+			match &id {
+				b"fmt " => {
+					if size < 16 {
+						return Err(Error::MalformedWav(
+							format!("the fmt chunk is too short ({} bytes)", size)))
+					}
+					if u64::from(size) > MAX_FMT {
+						return Err(Error::MalformedWav(
+							format!("the fmt chunk is implausibly long ({} bytes)", size)))
+					}
+
+					let mut chunk = vec![0; padded as usize];
+					read_header(&mut source, &mut chunk, "within the fmt chunk")?;
+
+					let u16_at = |i: usize| u16::from_le_bytes([chunk[i], chunk[i + 1]]);
+					let u32_at = |i: usize| u32::from_le_bytes([
+						chunk[i],
+						chunk[i + 1],
+						chunk[i + 2],
+						chunk[i + 3]]);
+
+					let mut parsed = WavFormat {
+						tag: u16_at(0),
+						channels: u16_at(2),
+						sample_rate: u32_at(4),
+						bits: u16_at(14),
+						valid_bits: u16_at(14),
+						channel_mask: None
+					};
+					if parsed.tag == TAG_EXTENSIBLE {
+						if size < 40 {
+							return Err(Error::MalformedWav(
+								format!("the extensible fmt chunk is too short ({} bytes)", size)))
+						}
+
+						let guid = &chunk[24..40];
+						if guid[2..] != GUID_SUFFIX {
+							return Err(Error::UnsupportedWavSubFormat {
+								guid: format_guid(guid)
+							})
+						}
+
+						parsed.tag = u16_at(24);
+						parsed.valid_bits = u16_at(18);
+						parsed.channel_mask = Some(u32_at(20));
+					}
+
+					format = Some(parsed);
+				},
+				b"data" => {
+					let format = format.ok_or(Error::MalformedWav(
+						"the data chunk comes before the fmt chunk".to_owned()))?;
+
+					/* Only files we can move around in can have a look past
+					 * their samples. */
+					if let Some(seek) = seek.filter(|_| loop_points.is_none() && size != 0 && size != u32::MAX) {
+						let data = seek(&mut source, SeekFrom::Current(0)).map_err(Error::InputError)?;
+						loop_points = trailing_smpl(
+							&mut source,
+							seek,
+							data + u64::from(size) + u64::from(size & 1));
+					}
+
+					/* Files that were written as a stream don't know how long
+					 * their data is going to be, and leave these placeholder
+					 * lengths in. Just read those until the end. */
+					let length = if size == 0 || size == u32::MAX {
+						None
+					} else {
+						Some(u64::from(size))
+					};
+
+					return Ok((
+						Self {
+							data: Span::new(source, length.unwrap_or(u64::MAX), seek)
+								.map_err(Error::InputError)?,
+							length,
+							loop_points
+						},
+						format
+					))
+				},
+				b"smpl" if u64::from(size) <= MAX_SMPL => {
+					let mut chunk = vec![0; padded as usize];
+					read_header(&mut source, &mut chunk, "within the smpl chunk")?;
+
+					loop_points = parse_smpl(&chunk[..size as usize])?;
+				},
+				_ => {
+					/* We don't care about any other chunks. */
+					skip(&mut source, padded)
+						.map_err(|what| if what.kind() == ErrorKind::UnexpectedEof {
+							Error::MalformedWav(format!(
+								"the file ends within the \"{}\" chunk",
+								String::from_utf8_lossy(&id)))
+						} else {
+							Error::InputError(what)
+						})?;
+				}
+			}
+		}
+	}
+}
+impl<R> Read for WavReader<R>
+	where R: Read {
+
+	/** The file running out before the `data` chunk does means it got cut
+	 * short, which is told apart from it simply being over by failing with
+	 * [`Error::TruncatedData`]. */
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		let read = self.data.read(buf)?;
+		if read == 0 && !buf.is_empty() {
+			let remaining = self.data.remaining();
+			if let Some(expected) = self.length.filter(|_| remaining > 0) {
+				return Err(std::io::Error::new(ErrorKind::UnexpectedEof, Error::TruncatedData {
+					expected,
+					got: expected - remaining
+				}))
+			}
+		}
+
+		Ok(read)
+	}
+}
+impl<R> Source for WavReader<R>
+	where R: Read + Send {
+
+	fn rewind(&mut self) -> std::io::Result<bool> {
+		self.data.rewind()
+	}
+
+	fn seek_forward(&mut self, bytes: u64) -> std::io::Result<bool> {
+		self.data.seek_forward(bytes)
+	}
+
+	/** Files that don't give the length of their data don't know how much
+	 * of it is left either. */
+	fn len_hint(&self) -> Option<u64> {
+		self.length.and(self.data.len_hint())
+	}
+}
+
+/** A reader that narrows little endian, double precision samples down into
+ * little endian, single precision samples. */
+struct Narrow<R> {
+	source: R,
+	/** Bytes of a double precision sample that has only been partially read. */
+	input: Vec<u8>,
+	/** Narrowed samples that have yet to be handed out. */
+	output: Vec<u8>,
+	/** How much of the narrowed samples has been handed out already. */
+	offset: usize,
+}
+impl<R> Narrow<R> {
+	fn new(source: R) -> Self {
+		Self {
+			source,
+			input: Vec::with_capacity(8),
+			output: Vec::new(),
+			offset: 0
+		}
+	}
+}
+impl<R> Read for Narrow<R>
+	where R: Read {
+
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		if buf.is_empty() { return Ok(0) }
+		while self.offset == self.output.len() {
+			self.output.clear();
+			self.offset = 0;
+
+			/* Read about as many samples as the caller wants. */
+			let mut chunk = vec![0; usize::max(buf.len() * 2, 8)];
+			let read = self.source.read(&mut chunk)?;
+			if read == 0 { return Ok(0) }
+
+			self.input.extend(&chunk[..read]);
+			let whole = self.input.len() - self.input.len() % 8;
+			for sample in self.input[..whole].chunks_exact(8) {
+				let mut bytes = [0; 8];
+				bytes.copy_from_slice(sample);
+
+				let sample = crate::convert::narrow(f64::from_le_bytes(bytes));
+				self.output.extend(&sample.to_le_bytes());
+			}
+			self.input.drain(..whole);
+		}
+
+		let len = usize::min(buf.len(), self.output.len() - self.offset);
+		buf[..len].copy_from_slice(&self.output[self.offset..self.offset + len]);
+		self.offset += len;
+
+		Ok(len)
+	}
+}
+impl<R> Source for Narrow<R>
+	where R: Source {
+
+	fn rewind(&mut self) -> std::io::Result<bool> {
+		if !self.source.rewind()? { return Ok(false) }
+
+		self.input.clear();
+		self.output.clear();
+		self.offset = 0;
+
+		Ok(true)
+	}
+}
+
+/** Opens the WAV file in the given source, whose magic number has already been
+ * read, skipping over chunks with the given function. Sources that can seek
+ * give a reader that can go back to the start of the samples. Returns a reader
+ * over the samples in the file, along with their format and the first loop
+ * embedded in the file, if any. */
+pub fn open<R>(source: R, skip: Skip<R>, seek: Option<Seeker<R>>)
+	-> crate::registry::Opened
+	where R: Read + Send + 'static {
+
+	let (reader, format) = WavReader::new(source, skip, seek)?;
+	let input = format.input_format()?;
+	let loop_points = reader.loop_points;
+	if let Some(points) = loop_points {
+		eprintln!("embedded loop: frames {} to {}", points.start, points.end);
+	}
+
+	/* A mask of zero means the channels don't go to any speaker in
+	 * particular. Masks with more speakers than there are channels are fine,
+	 * the extra ones are ignored. */
+	if let Some(mask) = format.channel_mask.filter(|mask| *mask != 0) {
+		let speakers = speakers(mask);
+		if speakers.len() >= usize::from(format.channels) {
+			eprintln!("channel layout: {}",
+				speakers[..usize::from(format.channels)].join(" "));
+		} else {
+			eprintln!("warning: the channel mask of the file ({:#x}) does not match its {} channels",
+				mask,
+				format.channels);
+		}
+	}
+
+	if format.tag == TAG_FLOAT && format.bits == 64 {
+		Ok((Box::new(Narrow::new(reader)), input, loop_points))
+	} else {
+		Ok((Box::new(reader), input, loop_points))
+	}
+}
+
+/** Length of the header written by [`WavWriter`], up to its samples. */
+const WRITTEN_HEADER: u32 = 12 + 8 + 18 + 8;
+
+/** A writer of WAV files holding samples in the given format, which must be
+ * little endian. The lengths in the header are only known once every sample
+ * has been written, so they get filled in at the end, which takes a sink that
+ * can seek. */
+pub struct WavWriter<W> {
+	sink: W,
+	/** Number of bytes of samples written so far. */
+	written: u64,
+}
+impl<W> WavWriter<W>
+	where W: Write + Seek {
+
+	/** Starts a WAV file in the given sink, writing out its header, with the
+	 * lengths in it left empty for now. Unsigned samples have no WAV format of
+	 * their own. */
+	pub fn new(mut sink: W, format: InputFormat) -> std::io::Result<Self> {
+		let (tag, bits) = match format.sample_format {
+			cpal::SampleFormat::I16 => (TAG_PCM, 16u16),
+			cpal::SampleFormat::F32 => (TAG_FLOAT, 32u16),
+			cpal::SampleFormat::U16 => return Err(std::io::Error::new(
+				ErrorKind::InvalidInput,
+				"WAV files can't hold unsigned 16 bit samples"))
+		};
+		let align = format.channels * (bits / 8);
+
+		let mut header = Vec::with_capacity(WRITTEN_HEADER as usize);
+		header.extend_from_slice(b"RIFF");
+		header.extend_from_slice(&0u32.to_le_bytes());
+		header.extend_from_slice(b"WAVE");
+		header.extend_from_slice(b"fmt ");
+		header.extend_from_slice(&18u32.to_le_bytes());
+		header.extend_from_slice(&tag.to_le_bytes());
+		header.extend_from_slice(&format.channels.to_le_bytes());
+		header.extend_from_slice(&format.sample_rate.to_le_bytes());
+		header.extend_from_slice(&(format.sample_rate * u32::from(align)).to_le_bytes());
+		header.extend_from_slice(&align.to_le_bytes());
+		header.extend_from_slice(&bits.to_le_bytes());
+		header.extend_from_slice(&0u16.to_le_bytes());
+		header.extend_from_slice(b"data");
+		header.extend_from_slice(&0u32.to_le_bytes());
+		sink.write_all(&header)?;
+
+		Ok(Self {
+			sink,
+			written: 0
+		})
+	}
+
+	/** Fills the lengths in the header in, now that every sample has been
+	 * written, and gives the sink back. */
+	pub fn finish(mut self) -> std::io::Result<W> {
+		use std::convert::TryFrom;
+
+		let data = u32::try_from(self.written)
+			.ok()
+			.filter(|data| data.checked_add(WRITTEN_HEADER).is_some())
+			.ok_or_else(|| std::io::Error::new(
+				ErrorKind::InvalidInput,
+				"the samples don't fit in a WAV file, which tops out at 4GiB"))?;
+
+		/* The data chunk gets padded out to an even length. */
+		if data & 1 != 0 {
+			self.sink.write_all(&[0])?;
+		}
+		let riff = WRITTEN_HEADER - 8 + data + (data & 1);
+
+		self.sink.seek(SeekFrom::Start(4))?;
+		self.sink.write_all(&riff.to_le_bytes())?;
+		self.sink.seek(SeekFrom::Start(u64::from(WRITTEN_HEADER) - 4))?;
+		self.sink.write_all(&data.to_le_bytes())?;
+		self.sink.seek(SeekFrom::End(0))?;
+		self.sink.flush()?;
+
+		Ok(self.sink)
+	}
+}
+impl<W> Write for WavWriter<W>
+	where W: Write {
+
+	fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+		let written = self.sink.write(buf)?;
+		self.written += written as u64;
+
+		Ok(written)
+	}
+
+	fn flush(&mut self) -> std::io::Result<()> {
+		self.sink.flush()
+	}
+}
+
+/** Fills the given buffer with header data, with the given description of
+ * where in the header we are in case the file ends early. */
+fn read_header<R>(source: &mut R, buf: &mut [u8], place: &str) -> Result<(), Error>
+	where R: Read {
+
+	source.read_exact(buf)
+		.map_err(|what| if what.kind() == ErrorKind::UnexpectedEof {
+			Error::MalformedWav(format!("the file ends {}", place))
+		} else {
+			Error::InputError(what)
+		})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::{Cursor, Seek, SeekFrom};
+
+	/** A chunk with the given ID and contents, padded out to an even length. */
+	fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+		let mut chunk = id.to_vec();
+		chunk.extend(&(data.len() as u32).to_le_bytes());
+		chunk.extend(data);
+		if data.len() & 1 != 0 { chunk.push(0) }
+		chunk
+	}
+
+	/** Contents of a plain `fmt ` chunk. */
+	fn fmt(tag: u16, channels: u16, sample_rate: u32, bits: u16) -> Vec<u8> {
+		let align = channels * (bits / 8);
+		let mut fmt = tag.to_le_bytes().to_vec();
+		fmt.extend(&channels.to_le_bytes());
+		fmt.extend(&sample_rate.to_le_bytes());
+		fmt.extend(&(sample_rate * u32::from(align)).to_le_bytes());
+		fmt.extend(&align.to_le_bytes());
+		fmt.extend(&bits.to_le_bytes());
+		fmt
+	}
+
+	/** A WAV file made up of the given chunks. */
+	fn riff(chunks: &[Vec<u8>]) -> Vec<u8> {
+		let body = chunks.concat();
+		let mut file = b"RIFF".to_vec();
+		file.extend(&(body.len() as u32 + 4).to_le_bytes());
+		file.extend(b"WAVE");
+		file.extend(body);
+		file
+	}
+
+	fn skip(source: &mut Cursor<Vec<u8>>, count: u64) -> std::io::Result<()> {
+		source.seek(SeekFrom::Current(count as i64)).map(|_| ())
+	}
+
+	/** Opens the given file the way detection does, past its magic number. */
+	fn open_file(file: Vec<u8>) -> crate::registry::Opened {
+		let mut source = Cursor::new(file);
+		source.set_position(12);
+		open(source, skip, Some(<Cursor<Vec<u8>> as Seek>::seek))
+	}
+
+	/** Samples of the given file. */
+	fn samples(file: Vec<u8>) -> Vec<u8> {
+		let (mut reader, _, _) = open_file(file).unwrap();
+		let mut data = Vec::new();
+		reader.read_to_end(&mut data).unwrap();
+		data
+	}
+
+	#[test]
+	fn pcm_header_gives_the_format() {
+		let file = riff(&[chunk(b"fmt ", &fmt(TAG_PCM, 2, 44100, 16)), chunk(b"data", b"abcd")]);
+		let (_, format, loop_points) = open_file(file.clone()).unwrap();
+		assert_eq!(format, InputFormat {
+			channels: 2,
+			sample_rate: 44100,
+			sample_format: cpal::SampleFormat::I16,
+			endian: Endianness::Little
+		});
+		assert_eq!(loop_points, None);
+		assert_eq!(samples(file), b"abcd");
+	}
+
+	#[test]
+	fn data_must_come_after_fmt() {
+		let file = riff(&[chunk(b"data", b"abcd"), chunk(b"fmt ", &fmt(TAG_PCM, 2, 44100, 16))]);
+		assert!(matches!(open_file(file).err().unwrap(), Error::MalformedWav(_)));
+	}
+
+	#[test]
+	fn short_fmt_is_rejected() {
+		let file = riff(&[chunk(b"fmt ", &fmt(TAG_PCM, 2, 44100, 16)[..14]), chunk(b"data", b"abcd")]);
+		assert!(matches!(open_file(file).err().unwrap(), Error::MalformedWav(_)));
+	}
+
+	#[test]
+	fn unsupported_bit_depths_are_rejected() {
+		let file = riff(&[chunk(b"fmt ", &fmt(TAG_PCM, 2, 44100, 24)), chunk(b"data", b"abcdef")]);
+		assert!(matches!(
+			open_file(file).err().unwrap(),
+			Error::UnsupportedWav { tag: TAG_PCM, bits: 24 }));
+	}
+
+	/** Contents of an extensible `fmt ` chunk for the given sub-format. */
+	fn extensible(channels: u16, bits: u16, valid_bits: u16, mask: u32, guid: [u8; 16]) -> Vec<u8> {
+		let mut fmt = fmt(TAG_EXTENSIBLE, channels, 48000, bits);
+		fmt.extend(&22u16.to_le_bytes());
+		fmt.extend(&valid_bits.to_le_bytes());
+		fmt.extend(&mask.to_le_bytes());
+		fmt.extend(&guid);
+		fmt
+	}
+
+	/** GUID of the sub-format equivalent to the given format tag. */
+	fn guid(tag: u16) -> [u8; 16] {
+		let mut guid = [0; 16];
+		guid[..2].copy_from_slice(&tag.to_le_bytes());
+		guid[2..].copy_from_slice(&GUID_SUFFIX);
+		guid
+	}
+
+	#[test]
+	fn extensible_header_gives_the_sub_format() {
+		let file = riff(&[
+			chunk(b"fmt ", &extensible(6, 16, 16, 0x3f, guid(TAG_PCM))),
+			chunk(b"data", &[0; 12])
+		]);
+		let (_, format, _) = open_file(file).unwrap();
+		assert_eq!(format.channels, 6);
+		assert_eq!(format.sample_rate, 48000);
+		assert_eq!(format.sample_format, cpal::SampleFormat::I16);
+	}
+
+	#[test]
+	fn unknown_sub_formats_are_rejected() {
+		let mut unknown = guid(TAG_PCM);
+		unknown[15] ^= 0xff;
+		let file = riff(&[
+			chunk(b"fmt ", &extensible(2, 16, 16, 0x3, unknown)),
+			chunk(b"data", &[0; 4])
+		]);
+		assert!(matches!(
+			open_file(file).err().unwrap(),
+			Error::UnsupportedWavSubFormat { .. }));
+	}
+
+	#[test]
+	fn short_extensible_fmt_is_rejected() {
+		let fmt = extensible(2, 16, 16, 0x3, guid(TAG_PCM));
+		let file = riff(&[chunk(b"fmt ", &fmt[..30]), chunk(b"data", &[0; 4])]);
+		assert!(matches!(open_file(file).err().unwrap(), Error::MalformedWav(_)));
+	}
+
+	#[test]
+	fn valid_bits_must_fit_in_the_samples() {
+		let file = riff(&[
+			chunk(b"fmt ", &extensible(2, 16, 20, 0x3, guid(TAG_PCM))),
+			chunk(b"data", &[0; 4])
+		]);
+		assert!(matches!(open_file(file).err().unwrap(), Error::MalformedWav(_)));
+	}
+
+	#[test]
+	fn channel_masks_name_their_speakers() {
+		assert_eq!(speakers(0x3f), ["FL", "FR", "FC", "LFE", "BL", "BR"]);
+		assert_eq!(speakers(0x603), ["FL", "FR", "SL", "SR"]);
+		assert!(speakers(0).is_empty());
+	}
+
+	#[test]
+	fn single_precision_samples_are_played_as_they_are() {
+		let data = [0.25f32, -1.0].iter().flat_map(|sample| sample.to_le_bytes()).collect::<Vec<_>>();
+		let file = riff(&[chunk(b"fmt ", &fmt(TAG_FLOAT, 2, 48000, 32)), chunk(b"data", &data)]);
+
+		let (_, format, _) = open_file(file.clone()).unwrap();
+		assert_eq!(format.sample_format, cpal::SampleFormat::F32);
+		assert_eq!(samples(file), data);
+	}
+
+	#[test]
+	fn double_precision_samples_get_narrowed() {
+		let data = [0.25f64, -1.0, 3.0].iter().flat_map(|sample| sample.to_le_bytes()).collect::<Vec<_>>();
+		let file = riff(&[chunk(b"fmt ", &fmt(TAG_FLOAT, 1, 48000, 64)), chunk(b"data", &data)]);
+
+		let (_, format, _) = open_file(file.clone()).unwrap();
+		assert_eq!(format.sample_format, cpal::SampleFormat::F32);
+
+		/* Samples out of range get clamped as they're narrowed. */
+		let expected = [0.25f32, -1.0, 1.0].iter().flat_map(|sample| sample.to_le_bytes()).collect::<Vec<_>>();
+		assert_eq!(samples(file), expected);
+	}
+
+	#[test]
+	fn odd_float_widths_are_rejected() {
+		let file = riff(&[chunk(b"fmt ", &fmt(TAG_FLOAT, 1, 48000, 16)), chunk(b"data", &[0; 2])]);
+		assert!(matches!(open_file(file).err().unwrap(), Error::MalformedWav(_)));
+	}
+
+	#[test]
+	fn unknown_chunks_are_skipped_with_their_padding() {
+		let file = riff(&[
+			chunk(b"LIST", b"odd"),
+			chunk(b"fmt ", &fmt(TAG_PCM, 1, 8000, 16)),
+			chunk(b"junk", &[0xff; 7]),
+			chunk(b"data", b"abcd")
+		]);
+		assert_eq!(samples(file.clone()), b"abcd");
+
+		/* Streams get the chunks read through instead. */
+		let skip = |source: &mut Cursor<Vec<u8>>, count: u64| -> std::io::Result<()> {
+			std::io::copy(&mut source.take(count), &mut std::io::sink()).map(|_| ())
+		};
+		let mut source = Cursor::new(file);
+		source.set_position(12);
+		let (mut reader, _, _) = open(source, skip, None).unwrap();
+		let mut data = Vec::new();
+		reader.read_to_end(&mut data).unwrap();
+		assert_eq!(data, b"abcd");
+	}
+
+	#[test]
+	fn files_ending_within_a_chunk_are_rejected() {
+		let mut file = riff(&[chunk(b"fmt ", &fmt(TAG_PCM, 1, 8000, 16))]);
+		file.extend(b"LIST");
+		file.extend(&100u32.to_le_bytes());
+		file.extend(&[0; 10]);
+
+		let mut source = Cursor::new(file);
+		source.set_position(12);
+		let skip = |source: &mut Cursor<Vec<u8>>, count: u64| -> std::io::Result<()> {
+			let skipped = std::io::copy(&mut source.take(count), &mut std::io::sink())?;
+			if skipped < count { Err(ErrorKind::UnexpectedEof.into()) } else { Ok(()) }
+		};
+		assert!(matches!(open(source, skip, None).err().unwrap(), Error::MalformedWav(_)));
+	}
+
+	#[test]
+	fn implausibly_long_fmt_is_rejected() {
+		let mut fmt = fmt(TAG_PCM, 1, 8000, 16);
+		fmt.resize(MAX_FMT as usize + 2, 0);
+		let file = riff(&[chunk(b"fmt ", &fmt), chunk(b"data", b"abcd")]);
+		assert!(matches!(open_file(file).err().unwrap(), Error::MalformedWav(_)));
+	}
+
+	#[test]
+	fn data_chunk_is_looked_for_only_so_far() {
+		let mut file = riff(&[chunk(b"fmt ", &fmt(TAG_PCM, 1, 8000, 16))]);
+		file.extend(b"junk");
+		file.extend(&(MAX_HEADER as u32 + 2).to_le_bytes());
+		assert!(matches!(open_file(file).err().unwrap(), Error::MalformedWav(_)));
+	}
+
+	#[test]
+	fn placeholder_data_lengths_are_read_to_the_end() {
+		for length in [0, u32::MAX] {
+			let mut file = riff(&[chunk(b"fmt ", &fmt(TAG_PCM, 1, 8000, 16))]);
+			file.extend(b"data");
+			file.extend(&length.to_le_bytes());
+			file.extend(b"abcdef");
+
+			let (reader, _, _) = open_file(file.clone()).unwrap();
+			assert_eq!(reader.len_hint(), None);
+			assert_eq!(samples(file), b"abcdef");
+		}
+	}
+
+	/** Contents of the `fmt ` chunk of a stereo Microsoft ADPCM file, as
+	 * written by most encoders, with the standard coefficient table. */
+	fn adpcm() -> Vec<u8> {
+		let mut fmt = 0x0002u16.to_le_bytes().to_vec();
+		fmt.extend(&2u16.to_le_bytes());
+		fmt.extend(&44100u32.to_le_bytes());
+		fmt.extend(&44359u32.to_le_bytes());
+		fmt.extend(&2048u16.to_le_bytes());
+		fmt.extend(&4u16.to_le_bytes());
+		fmt.extend(&32u16.to_le_bytes());
+		fmt.extend(&2036u16.to_le_bytes());
+		fmt.extend(&7u16.to_le_bytes());
+		for (a, b) in &[(256i16, 0i16), (512, -256), (0, 0), (192, 64), (240, 0), (460, -208), (392, -232)] {
+			fmt.extend(&a.to_le_bytes());
+			fmt.extend(&b.to_le_bytes());
+		}
+		fmt
+	}
+
+	#[test]
+	fn compressed_samples_name_their_codec() {
+		let file = riff(&[chunk(b"fmt ", &adpcm()), chunk(b"data", &[0; 2048])]);
+		let error = open_file(file).err().unwrap();
+		assert!(matches!(
+			error,
+			Error::UnsupportedWavCodec { tag: 0x0002, name: Some("Microsoft ADPCM") }));
+		assert!(error.to_string().contains("compressed with Microsoft ADPCM (format tag 0x0002)"));
+	}
+
+	#[test]
+	fn unknown_codecs_are_rejected_by_their_tag() {
+		let file = riff(&[chunk(b"fmt ", &fmt(0x1234, 2, 44100, 16)), chunk(b"data", b"abcd")]);
+		let error = open_file(file).err().unwrap();
+		assert!(matches!(error, Error::UnsupportedWavCodec { tag: 0x1234, name: None }));
+		assert!(error.to_string().contains("unknown codec (format tag 0x1234)"));
+	}
+
+	/** Contents of a `smpl` chunk with the given loops, each given by its first
+	 * and last frames. */
+	fn smpl(loops: &[(u32, u32)]) -> Vec<u8> {
+		let mut smpl = vec![0; SMPL_HEADER];
+		smpl[28..32].copy_from_slice(&(loops.len() as u32).to_le_bytes());
+		for (i, (start, end)) in loops.iter().enumerate() {
+			smpl.extend(&(i as u32).to_le_bytes());
+			smpl.extend(&0u32.to_le_bytes());
+			smpl.extend(&start.to_le_bytes());
+			smpl.extend(&end.to_le_bytes());
+			smpl.extend(&[0; 8]);
+		}
+		smpl
+	}
+
+	/** Loop points of the given file. */
+	fn loop_points(file: Vec<u8>) -> Option<LoopPoints> {
+		let (_, _, loop_points) = open_file(file).unwrap();
+		loop_points
+	}
+
+	#[test]
+	fn smpl_gives_the_first_loop() {
+		let file = riff(&[
+			chunk(b"fmt ", &fmt(TAG_PCM, 1, 8000, 16)),
+			chunk(b"smpl", &smpl(&[(100, 199), (300, 399)])),
+			chunk(b"data", &[0; 8])
+		]);
+		assert_eq!(loop_points(file.clone()), Some(LoopPoints { start: 100, end: 200 }));
+		assert_eq!(samples(file), [0; 8]);
+	}
+
+	#[test]
+	fn smpl_past_the_data_is_found_in_seekable_files() {
+		let file = riff(&[
+			chunk(b"fmt ", &fmt(TAG_PCM, 1, 8000, 16)),
+			chunk(b"data", b"abc"),
+			chunk(b"LIST", b"x"),
+			chunk(b"smpl", &smpl(&[(2, 5)]))
+		]);
+		assert_eq!(loop_points(file.clone()), Some(LoopPoints { start: 2, end: 6 }));
+
+		/* Looking for it leaves the samples where they were. */
+		assert_eq!(samples(file.clone()), b"abc");
+
+		/* Streams can't look that far ahead. */
+		let mut source = Cursor::new(file);
+		source.set_position(12);
+		let (_, _, loop_points) = open(source, skip, None).unwrap();
+		assert_eq!(loop_points, None);
+	}
+
+	#[test]
+	fn files_without_loops_have_no_loop_points() {
+		let file = riff(&[chunk(b"fmt ", &fmt(TAG_PCM, 1, 8000, 16)), chunk(b"data", b"ab")]);
+		assert_eq!(loop_points(file), None);
+
+		let file = riff(&[
+			chunk(b"fmt ", &fmt(TAG_PCM, 1, 8000, 16)),
+			chunk(b"smpl", &smpl(&[])),
+			chunk(b"data", b"ab")
+		]);
+		assert_eq!(loop_points(file), None);
+	}
+
+	#[test]
+	fn backwards_loops_are_ignored() {
+		let file = riff(&[
+			chunk(b"fmt ", &fmt(TAG_PCM, 1, 8000, 16)),
+			chunk(b"smpl", &smpl(&[(10, 5)])),
+			chunk(b"data", b"ab")
+		]);
+		assert_eq!(loop_points(file), None);
+	}
+
+	#[test]
+	fn malformed_smpl_is_rejected() {
+		let file = riff(&[
+			chunk(b"fmt ", &fmt(TAG_PCM, 1, 8000, 16)),
+			chunk(b"smpl", &[0; SMPL_HEADER - 4]),
+			chunk(b"data", b"ab")
+		]);
+		assert!(matches!(open_file(file).err().unwrap(), Error::MalformedWav(_)));
+
+		/* A loop count with no loops following it. */
+		let file = riff(&[
+			chunk(b"fmt ", &fmt(TAG_PCM, 1, 8000, 16)),
+			chunk(b"smpl", &smpl(&[(1, 2)])[..SMPL_HEADER]),
+			chunk(b"data", b"ab")
+		]);
+		assert!(matches!(open_file(file).err().unwrap(), Error::MalformedWav(_)));
+	}
+
+	/** Renders the given source into a WAV file in memory, the way --render
+	 * does. */
+	fn render<R: Read>(mut source: R, format: InputFormat) -> Vec<u8> {
+		let mut writer = WavWriter::new(Cursor::new(Vec::new()), format).unwrap();
+		std::io::copy(&mut source, &mut writer).unwrap();
+		writer.finish().unwrap().into_inner()
+	}
+
+	#[test]
+	fn rendered_sweeps_last_and_peak_as_asked() {
+		let spec = crate::tone::SweepSpec { start: 100.0, end: 1000.0, duration: 3.0, linear: false };
+		for &sample_format in &[cpal::SampleFormat::I16, cpal::SampleFormat::F32] {
+			let format = InputFormat {
+				channels: 2,
+				sample_rate: 8000,
+				sample_format,
+				endian: Endianness::Little
+			};
+			let sweep = crate::tone::Sweep::new(spec, 2, 8000, sample_format, Endianness::Little)
+				.level(-6.0)
+				.padding(0.5);
+			let file = render(sweep, format);
+
+			/* The RIFF length covers everything past itself. */
+			assert_eq!(u32::from_le_bytes([file[4], file[5], file[6], file[7]]) as usize, file.len() - 8);
+
+			let (mut reader, read, _) = open_file(file).unwrap();
+			assert_eq!(read, format);
+			let frames = 3 * 8000 + 1 + 2 * 4000;
+			let size = sample_format.sample_size();
+			assert_eq!(reader.len_hint(), Some((frames * 2 * size) as u64));
+
+			let mut data = Vec::new();
+			reader.read_to_end(&mut data).unwrap();
+			let peak = data.chunks_exact(size)
+				.map(|sample| crate::convert::decode(sample_format, Endianness::Little, sample).abs())
+				.fold(0f32, f32::max);
+			let peak = 20.0 * f64::from(peak).log10();
+			assert!((peak + 6.0).abs() < 0.05, "peaked at {}dBFS", peak);
+		}
+	}
+
+	#[test]
+	fn odd_renders_are_padded() {
+		let format = InputFormat {
+			channels: 1,
+			sample_rate: 8000,
+			sample_format: cpal::SampleFormat::I16,
+			endian: Endianness::Little
+		};
+		let file = render(&b"abc"[..], format);
+		assert_eq!(file.len(), WRITTEN_HEADER as usize + 4);
+		assert_eq!(&file[file.len() - 8..file.len() - 4], &3u32.to_le_bytes());
+		assert_eq!(samples(file), b"abc");
+	}
+
+	#[test]
+	fn unsigned_samples_cant_be_rendered() {
+		let format = InputFormat {
+			channels: 1,
+			sample_rate: 8000,
+			sample_format: cpal::SampleFormat::U16,
+			endian: Endianness::Little
+		};
+		assert!(WavWriter::new(Cursor::new(Vec::new()), format).is_err());
+	}
+
+	/** A file whose data chunk claims 100 bytes, but which ends after six. */
+	fn truncated() -> Vec<u8> {
+		let mut file = riff(&[chunk(b"fmt ", &fmt(TAG_PCM, 1, 8000, 16))]);
+		file.extend(b"data");
+		file.extend(&100u32.to_le_bytes());
+		file.extend(b"abcdef");
+		file
+	}
+
+	#[test]
+	fn truncated_data_is_told_apart_from_its_end() {
+		let (mut reader, _, _) = open_file(truncated()).unwrap();
+		assert_eq!(reader.len_hint(), Some(100));
+
+		let mut data = Vec::new();
+		let what = reader.read_to_end(&mut data).err().unwrap();
+		assert_eq!(data, b"abcdef");
+		assert_eq!(what.kind(), ErrorKind::UnexpectedEof);
+		assert!(Error::is_truncation(&what));
+		assert!(matches!(Error::read_failed(what), Error::TruncatedData { expected: 100, got: 6 }));
+	}
+
+	#[test]
+	fn whole_data_ends_cleanly() {
+		let file = riff(&[chunk(b"fmt ", &fmt(TAG_PCM, 1, 8000, 16)), chunk(b"data", b"abcdef")]);
+		let (mut reader, _, _) = open_file(file).unwrap();
+
+		let mut data = Vec::new();
+		reader.read_to_end(&mut data).unwrap();
+		assert_eq!(data, b"abcdef");
+		assert_eq!(reader.read(&mut [0; 4]).unwrap(), 0);
+	}
+
+	#[test]
+	fn data_stops_at_the_length_of_its_chunk() {
+		/* Chunks past the data are none of its business. */
+		let file = riff(&[
+			chunk(b"fmt ", &fmt(TAG_PCM, 1, 8000, 16)),
+			chunk(b"data", b"abcd"),
+			chunk(b"LIST", b"trailing metadata")
+		]);
+		assert_eq!(samples(file), b"abcd");
+	}
+}
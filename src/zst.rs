@@ -0,0 +1,109 @@
+use std::io::{BufReader, Read};
+use zstd::stream::read::Decoder;
+
+/** Magic number every zstd frame starts with. */
+pub const MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/** Checks whether the given bytes start with a zstd frame, be it a regular one
+ * or a skippable one, which the decoder skips over on its own. */
+pub fn is_frame(bytes: &[u8]) -> bool {
+	if bytes.len() < 4 { return false }
+
+	bytes[..4] == MAGIC
+		|| (bytes[0] & 0xf0 == 0x50 && bytes[1..4] == [0x2a, 0x4d, 0x18])
+}
+
+/** Checks whether the regular frame at the start of the given bytes needs a
+ * dictionary to be decompressed, by looking at the dictionary ID flag of its
+ * frame header descriptor. */
+pub fn needs_dictionary(bytes: &[u8]) -> bool {
+	bytes.len() > 4 && bytes[..4] == MAGIC && bytes[4] & 0x03 != 0
+}
+
+/** A reader that decompresses zstd data as it's read, frame after frame. */
+pub struct Unzstd<R>
+	where R: Read {
+
+	decoder: Decoder<'static, BufReader<R>>,
+}
+impl<R> Unzstd<R>
+	where R: Read {
+
+	pub fn new(source: R) -> std::io::Result<Self> {
+		Ok(Self {
+			decoder: Decoder::new(source)?
+		})
+	}
+}
+impl<R> Read for Unzstd<R>
+	where R: Read {
+
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		self.decoder.read(buf)
+			.map_err(|what| std::io::Error::new(
+				what.kind(),
+				format!("zstd decompression failed: {}", what)))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::error::Error;
+	use std::io::Cursor;
+
+	/** Raw bytes going up from zero and wrapping around at 251, split in two
+	 * halves that got compressed into frames of their own. */
+	const FRAMES: &[u8] = include_bytes!("../fixtures/frames.raw.zst");
+
+	fn raw() -> Vec<u8> {
+		(0..2048).map(|i| (i % 251) as u8).collect()
+	}
+
+	#[test]
+	fn consecutive_frames_get_read_through() {
+		let mut data = Vec::new();
+		Unzstd::new(FRAMES).unwrap().read_to_end(&mut data).unwrap();
+		assert_eq!(data, raw());
+	}
+
+	#[test]
+	fn compressed_input_gets_detected_as_what_it_holds() {
+		let (mut reader, detected) = crate::detect::detect(Cursor::new(FRAMES.to_vec())).unwrap();
+		assert_eq!(detected, None);
+
+		let mut data = Vec::new();
+		reader.read_to_end(&mut data).unwrap();
+		assert_eq!(data, raw());
+	}
+
+	#[test]
+	fn skippable_frames_count_as_frames() {
+		assert!(is_frame(FRAMES));
+		assert!(is_frame(&[0x5e, 0x2a, 0x4d, 0x18]));
+		assert!(!is_frame(&[0x28, 0xb5, 0x2f]));
+		assert!(!is_frame(b"RIFF"));
+	}
+
+	#[test]
+	fn frames_needing_a_dictionary_are_refused() {
+		assert!(!needs_dictionary(FRAMES));
+
+		let mut input = MAGIC.to_vec();
+		input.extend(&[0x01, 0x00, 0x00, 0x00, 0x00]);
+		assert!(needs_dictionary(&input));
+		assert!(matches!(
+			crate::detect::detect(Cursor::new(input)),
+			Err(Error::UnsupportedContainer("dictionary compressed zstd"))));
+	}
+
+	#[test]
+	fn corrupted_input_fails_with_context() {
+		let mut corrupted = FRAMES.to_vec();
+		corrupted[12] ^= 0xff;
+
+		let mut data = Vec::new();
+		let what = Unzstd::new(&corrupted[..]).unwrap().read_to_end(&mut data).unwrap_err();
+		assert!(what.to_string().starts_with("zstd decompression failed: "), "{}", what);
+	}
+}